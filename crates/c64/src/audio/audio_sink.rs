@@ -0,0 +1,17 @@
+use std::io;
+
+/// Sample rate used when no other rate is requested
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Destination of the audio samples produced by the emulator.
+/// The SID pushes mono, signed 16-bit samples at the rate reported by the sink,
+/// so any backend (a file, a live sound device, a test buffer) only has to
+/// implement this trait.
+pub trait AudioSink: Send {
+    fn sample_rate(&self) -> u32;
+    fn write_sample(&mut self, sample: i16) -> io::Result<()>;
+
+    /// Makes sure all the samples written so far reached the destination.
+    /// It can be called multiple times (i.e. on every machine stop).
+    fn flush(&mut self) -> io::Result<()>;
+}
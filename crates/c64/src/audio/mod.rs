@@ -0,0 +1,4 @@
+mod audio_sink;
+mod wav_writer;
+
+pub use {audio_sink::*, wav_writer::*};
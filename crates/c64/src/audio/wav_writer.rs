@@ -0,0 +1,72 @@
+use super::AudioSink;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_SIZE: u32 = 44;
+
+/// Writes mono, 16-bit PCM samples into a WAV file.
+/// The header is written upfront with empty sizes that are patched
+/// on every flush, so the file stays valid even if the emulator
+/// is stopped more than once.
+/// See [WAVE PCM soundfile format](http://soundfile.sapp.org/doc/WaveFormat/)
+pub struct WavWriter {
+    out: BufWriter<File>,
+    sample_rate: u32,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        let mut writer = WavWriter {
+            out: BufWriter::new(File::create(path)?),
+            sample_rate,
+            data_len: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let byte_rate = self.sample_rate * 2;
+        self.out.write_all(b"RIFF")?;
+        self.out.write_all(&(HEADER_SIZE - 8 + self.data_len).to_le_bytes())?;
+        self.out.write_all(b"WAVE")?;
+        self.out.write_all(b"fmt ")?;
+        self.out.write_all(&16u32.to_le_bytes())?; // size of fmt chunk
+        self.out.write_all(&1u16.to_le_bytes())?; // PCM
+        self.out.write_all(&1u16.to_le_bytes())?; // mono
+        self.out.write_all(&self.sample_rate.to_le_bytes())?;
+        self.out.write_all(&byte_rate.to_le_bytes())?;
+        self.out.write_all(&2u16.to_le_bytes())?; // block align
+        self.out.write_all(&16u16.to_le_bytes())?; // bits per sample
+        self.out.write_all(b"data")?;
+        self.out.write_all(&self.data_len.to_le_bytes())
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write_sample(&mut self, sample: i16) -> io::Result<()> {
+        self.data_len = self.data_len.wrapping_add(2);
+        self.out.write_all(&sample.to_le_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Couldn't finalize WAV file: {e}");
+        }
+    }
+}
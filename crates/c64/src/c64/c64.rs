@@ -1,53 +1,190 @@
 #![allow(non_snake_case)]
 
-use super::{C64Memory, CIA1, CIA2, VIC_II};
-use crate::key_utils::C64KeyCode;
+use super::{
+    vsf, C64Memory, Chip, ControlPortDevice, Cpu6510, IOSlot, InputScript, Joystick, MachineModel,
+    Mouse1351, Paddles, RamPattern, RomSlot, SIDModel, TypeIn, TypeInMode, Vsf, CIA1, CIA2, SID,
+    VIC_II,
+};
+use crate::audio::{AudioSink, WavWriter, DEFAULT_SAMPLE_RATE};
+use crate::key_utils::{C64KeyCode, Keymap, KeymapMode};
+use chrono::Timelike;
+use keyboard_types::KeyState;
 use machine::{
-    cli::{FromProfile, Profile},
-    debugger::{DebugMachine, Debugger, DebuggerState},
+    cli::{get_file_as_byte_vec, FromProfile, Profile},
+    client::ClientEvent,
+    debugger::{DebugMachine, Debugger, DebuggerState, Rewind, TraceRecord, Tracer, WatchHit},
+    emulator::{
+        abstractions::{
+            is_same_device, Accessor, Addressable, Device, DeviceTrait, Scheduler, SharedDevice,
+            StateReader, StateWriter, CPU,
+        },
+        components::{MissingDevicePolicy, CIA_6526},
+        EmulatorError,
+    },
     impl_reg_setter,
     mos6502::{execute_operation, Operation, MOS6502},
-    Addr, Cycles, FromConfig, Machine, MachineConfig, MachineStatus, Memory, RegSetter, emulator::{abstractions::{Device, Accessor}, components::CIA_6526},
+    utils::lock,
+    Addr, Cycles, FromConfig, Machine, MachineConfig, MachineStatus, Memory, RegSetter, ResetKind,
+    Snapshot,
 };
 use std::num::Wrapping;
+use std::sync::{Arc, Mutex};
+
+/// Default delay of synthetic key release [ms]; long enough
+/// for KERNAL to notice the key (it scans keyboard every 1/60s)
+const DEFAULT_KEY_RELEASE: u64 = 100;
+
+/// Length of NMI pulse generated by RESTORE key (the 555 timer circuit)
+const RESTORE_PULSE_CYCLES: Cycles = 2000;
+
+/// Typed keys are held for two KERNAL's keyboard scans (1/60s each)
+/// and released for the same time, so repeated characters are noticed
+const TYPE_IN_KEYS_PER_SEC: Cycles = 30;
+
+/// KERNAL variables: keyboard buffer, number of characters in the buffer
+/// and size of the buffer (zero until KERNAL is initialized)
+const KEYBOARD_BUFFER: Addr = 0x0277;
+const KEYBOARD_BUFFER_COUNT: Addr = 0x00c6;
+const KEYBOARD_BUFFER_SIZE: Addr = 0x0289;
+
+/// SID measures the potentiometers (paddles, mouse) every 512 cycles
+const POT_SAMPLE_CYCLES: Cycles = 512;
+
+/// Checkpoints of the execution history are taken every 5 frames (1/10s)
+const REWIND_CHECKPOINT_FRAMES: Cycles = 5;
+
+/// I/O area (chips and colour RAM); devices are synced before the CPU accesses it
+const IO_AREA: std::ops::RangeInclusive<Addr> = 0xd000..=0xdfff;
+
+/// Timed events of the machine
+pub enum C64Event {
+    /// Release of keys pressed with `press_keys`
+    ReleaseKeys,
+    /// SID measures the potentiometers
+    SamplePots,
+    /// Devices are synced at the end of every frame (i.e. so SID's audio keeps flowing)
+    EndOfFrame,
+    Input(ClientEvent),
+    /// Input applied before, when the execution history is replayed (see `Rewind::seek`)
+    Replay(ClientEvent),
+}
 
 pub struct C64 {
     config: MachineConfig,
-    mos6510: MOS6502,
+    model: MachineModel,
+    ram_pattern: RamPattern,
+    /// The 6510 with the master clock and the timed events
+    cpu: Cpu6510<C64Event>,
     mem: C64Memory,
-    gpu: VIC_II,
+    gpu: Device<VIC_II>,
     cia1: Device<CIA1>,
     cia2: Device<CIA2>,
+    sid: Device<SID>,
+    /// Devices of the machine (but the PLA, see `C64Memory::devices`): they're
+    /// reset and saved in snapshots together, and clocked if they're `Clocked`
+    devices: Vec<SharedDevice>,
+    /// Expansion devices plugged into IO1 and IO2 pages (they're in `devices` too)
+    io_expansions: [Option<SharedDevice>; 2],
+    /// Chips taken out of the machine (see `remove_chip`)
+    removed_chips: Vec<Chip>,
     status: MachineStatus,
-    cycles: u64,
+    /// Interrupt lines of CIA1 (IRQ) and CIA2 (NMI) as of the last sync
+    cia_irq: bool,
+    cia_nmi: bool,
+    nmi_line: bool,
+    key_release: Cycles,
+    restore_pressed: bool,
+    restore_release_at: Option<Cycles>,
+    restore_pulse_until: Cycles,
+    keymap: Keymap,
+    type_in: TypeIn,
+    /// Execution history for reverse debugging
+    rewind: Rewind,
+    tracer: Option<Tracer>,
+    /// Whether the last instruction triggered a watchpoint (the machine pauses after it)
+    watch_triggered: bool,
     pub debugger_state: DebuggerState,
     pub last_op: Operation,
 }
 
 impl C64 {
     pub fn new(config: MachineConfig) -> Self {
+        let model = match &config.model {
+            Some(name) => MachineModel::from_name(name).unwrap_or_else(|| {
+                log::error!("Unknown machine model: {name} (expected pal, ntsc, ntsc-old or drean)");
+                MachineModel::default()
+            }),
+            None => MachineModel::default(),
+        };
+        let ram_pattern = match &config.ram_pattern {
+            Some(name) => RamPattern::from_name(name, config.seed).unwrap_or_else(|| {
+                log::error!("Unknown RAM pattern: {name} (expected stripes, zero or random)");
+                RamPattern::default()
+            }),
+            None => config.seed.map_or(RamPattern::default(), RamPattern::Random),
+        };
+        let clock = model.clock_frequency();
         let cia1 = Device::from(CIA1::new());
         let cia2 = Device::from(CIA2::new());
+        let sid = Device::from(SID::new(SIDModel::default()));
+        sid.lock().set_clock(clock);
+        cia1.lock().state_mut().set_tod_period(clock / model.power_line_hz());
+        cia2.lock().state_mut().set_tod_period(clock / model.power_line_hz());
+        let gpu = Device::from(VIC_II::new(model));
+        let mut mem = C64Memory::new(&gpu, &cia1, &cia2, &sid);
+        mem.power_on(ram_pattern);
+        let mut devices = mem.devices();
+        devices.extend([gpu.mutex() as SharedDevice, cia1.mutex(), cia2.mutex(), sid.mutex()]);
+        let mut scheduler = Scheduler::new();
+        devices.iter().for_each(|dev| scheduler.register(dev.clone()));
+        scheduler.schedule(POT_SAMPLE_CYCLES, C64Event::SamplePots);
+        scheduler.schedule(model.frame_cycles(), C64Event::EndOfFrame);
+        let rewind = Rewind::new(
+            config.rewind_budget.unwrap_or(0) << 20,
+            model.frame_cycles() * REWIND_CHECKPOINT_FRAMES,
+        );
+        let tracer = Tracer::from_config(&config);
         C64 {
             config,
-            mos6510: MOS6502::new(),
-            mem: C64Memory::new(&cia1, &cia2),
-            gpu: VIC_II::new(),
+            model,
+            ram_pattern,
+            cpu: Cpu6510::new(scheduler),
+            mem,
+            gpu,
             cia1,
             cia2,
+            sid,
+            devices,
+            io_expansions: Default::default(),
+            removed_chips: Vec::new(),
             status: MachineStatus::Stopped,
-            cycles: 0,
+            cia_irq: false,
+            cia_nmi: false,
+            nmi_line: false,
+            key_release: DEFAULT_KEY_RELEASE * clock as u64 / 1000,
+            restore_pressed: false,
+            restore_release_at: None,
+            restore_pulse_until: 0,
+            keymap: Keymap::default(),
+            type_in: TypeIn::new(),
+            rewind,
+            tracer,
+            watch_triggered: false,
             debugger_state: DebuggerState::default(),
             last_op: Operation::default(),
         }
     }
 
+    pub fn model(&self) -> MachineModel {
+        self.model
+    }
+
     pub fn load(&mut self, progmem: &[u8], addr: u16) {
         self.memory_mut().write(addr, progmem);
     }
 
     pub fn print_screen(&self) {
-        self.gpu.print_screen(&self.memory());
+        self.gpu.lock().print_screen(self.memory());
     }
 
     pub fn get_screen_memory(&self) -> Vec<u8> {
@@ -56,21 +193,69 @@ impl C64 {
 
     pub fn key_down(&mut self, ck: C64KeyCode) {
         self.cia1.lock().keyboard.key_down(ck as u8);
+    }
 
-        // let sc = VIC_II::ascii_to_petscii(ch);
-        // self.memory_mut().write_byte(0x0277, sc);
-        // self.memory_mut().write_byte(0x00c6, 1); // number of keys in the keyboard buffer
-        // self.memory_mut().write_byte(0xffe4, 22);
-
-        // self.machine.memory_mut().write_byte(0xc5, 2);
-        // self.machine.memory_mut().write_byte(0xcb, 3);
+    /// Matrix codes of keys pressed at the moment
+    pub fn pressed_keys(&self) -> Vec<u8> {
+        self.cia1.lock().keyboard.pressed_keys()
     }
 
     pub fn key_up(&mut self, ck: C64KeyCode) {
         self.cia1.lock().keyboard.key_up(ck as u8);
     }
 
-    pub fn send_keys(&mut self, vec: &Vec<C64KeyCode>, is_down: bool) {
+    /// Host keyboard mapping used by clients
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    /// Presses the keys, and - if synthetic release is enabled - releases
+    /// them after the configured delay. Meant for clients that can't
+    /// report key releases (i.e. terminals).
+    pub fn press_keys(&mut self, vec: &[C64KeyCode]) {
+        if self.key_release == 0 {
+            return self.send_keys(vec, true);
+        }
+        let release_at = self.get_cycles() + self.key_release;
+        {
+            let mut cia = self.cia1.lock();
+            vec.iter()
+                .for_each(|kc| cia.keyboard.key_down_until(*kc as u8, release_at));
+        }
+        self.cpu.scheduler.schedule(release_at, C64Event::ReleaseKeys);
+    }
+
+    /// RESTORE key is not a part of the keyboard matrix: pressing it triggers
+    /// a single NMI pulse. Holding it (or bouncing while the pulse lasts)
+    /// doesn't trigger another one.
+    pub fn set_restore_key(&mut self, pressed: bool) {
+        let cycles = self.get_cycles();
+        if pressed && !self.restore_pressed && cycles >= self.restore_pulse_until {
+            self.restore_pulse_until = cycles + RESTORE_PULSE_CYCLES;
+        }
+        self.restore_pressed = pressed;
+        self.restore_release_at = None;
+    }
+
+    /// Presses RESTORE key, releasing it as other keys in `press_keys`
+    pub fn press_restore(&mut self) {
+        self.set_restore_key(true);
+        if self.key_release > 0 {
+            self.restore_release_at = Some(self.get_cycles() + self.key_release);
+        }
+    }
+
+    /// Sets delay of synthetic key release in (emulated) milliseconds.
+    /// Zero disables it, so the keys are held until key-up.
+    pub fn set_key_release(&mut self, ms: u64) {
+        self.key_release = ms * self.model.clock_frequency() as u64 / 1000;
+    }
+
+    pub fn send_keys(&mut self, vec: &[C64KeyCode], is_down: bool) {
         vec.iter().for_each(|kc: &C64KeyCode| {
             if is_down {
                 self.key_down(*kc)
@@ -80,9 +265,453 @@ impl C64 {
         });
     }
 
+    /// Types the text in (see `text_to_petscii` for the format),
+    /// after already queued text. It starts when KERNAL is initialized.
+    pub fn type_text(&mut self, text: &str) {
+        self.type_in.push(text);
+    }
+
+    pub fn set_type_in_mode(&mut self, mode: TypeInMode) {
+        self.type_in.mode = mode;
+    }
+
+    fn type_in_next(&mut self) {
+        // the keyboard buffer is checked once per frame
+        let buffer_check_at = self.get_cycles() + self.model.frame_cycles();
+        let buffer_size = self.read_byte(KEYBOARD_BUFFER_SIZE).min(10);
+        if buffer_size == 0 {
+            self.type_in.wait_until(buffer_check_at);
+            return;
+        }
+        match self.type_in.mode {
+            TypeInMode::Matrix => {
+                // the next key is pressed when KERNAL has room for it in the buffer,
+                // otherwise it would be lost (i.e. when BASIC executes a line)
+                let count = self.read_byte(KEYBOARD_BUFFER_COUNT);
+                if !self.type_in.is_holding() && count >= buffer_size {
+                    self.type_in.wait_until(buffer_check_at);
+                    return;
+                }
+                let (keys, is_down) = self.type_in.next_keys();
+                self.send_keys(&keys, is_down);
+                let key_cycles = self.model.clock_frequency() as Cycles / TYPE_IN_KEYS_PER_SEC;
+                self.type_in.wait_until(self.get_cycles() + key_cycles);
+            }
+            TypeInMode::Buffer => {
+                if self.read_byte(KEYBOARD_BUFFER_COUNT) == 0 {
+                    let chars = self.type_in.next_chars(buffer_size as usize);
+                    for (i, ch) in chars.iter().enumerate() {
+                        self.write_byte(KEYBOARD_BUFFER + i as Addr, *ch);
+                    }
+                    self.write_byte(KEYBOARD_BUFFER_COUNT, chars.len() as u8);
+                }
+                self.type_in.wait_until(buffer_check_at);
+            }
+        }
+    }
+
+    /// Applies input event (keys, typed text, controllers) after the current
+    /// instruction, so it's journaled in the execution history (see `Rewind`).
+    /// Other events are ignored - they are handled by the client.
+    pub fn handle_input(&mut self, event: &ClientEvent) {
+        self.schedule_input(self.get_cycles(), event.clone());
+    }
+
+    fn apply_input(&mut self, event: &ClientEvent) {
+        match event {
+            ClientEvent::KeyPress(key_event) => {
+                log::debug!("Sending key {:?}", key_event);
+                let keys = self.keymap.map_event(key_event);
+                if key_event.state == KeyState::Up {
+                    self.send_keys(&keys, false);
+                } else {
+                    // released automatically, unless the synthetic release is disabled
+                    self.press_keys(&keys);
+                }
+            }
+            ClientEvent::MatrixKeys { keys, state } => {
+                let keys: Vec<C64KeyCode> =
+                    keys.iter().map(|kc| C64KeyCode::from(*kc & 0x3f)).collect();
+                match state {
+                    KeyState::Down => self.press_keys(&keys),
+                    KeyState::Up => self.send_keys(&keys, false),
+                }
+            }
+            ClientEvent::Restore(KeyState::Down) => self.press_restore(),
+            ClientEvent::Restore(KeyState::Up) => self.set_restore_key(false),
+            ClientEvent::Joystick { port, state } => self.set_joystick(*port, *state),
+            ClientEvent::Paddles { port, x, y, buttons } => {
+                self.set_paddles(*port, *x, *y, *buttons)
+            }
+            ClientEvent::Mouse { port, dx, dy, buttons } => {
+                self.move_mouse(*port, *dx, *dy, *buttons)
+            }
+            ClientEvent::TypeText(text) => self.type_text(text),
+            _ => {}
+        }
+    }
+
+    /// Applies input event when the machine reaches given cycle
+    /// (so runs with the same inputs are repeatable)
+    pub fn schedule_input(&mut self, at: Cycles, event: ClientEvent) {
+        self.cpu.scheduler.schedule(at, C64Event::Input(event));
+    }
+
+    fn handle_event(&mut self, event: C64Event) {
+        match event {
+            C64Event::ReleaseKeys => {
+                let cycles = self.get_cycles();
+                self.cia1.lock().keyboard.release_keys(cycles);
+            }
+            C64Event::SamplePots => {
+                let (x, y) = self.cia1.lock().selected_pots();
+                self.sid.lock().set_pots(x, y);
+                self.cpu.scheduler.schedule_in(POT_SAMPLE_CYCLES, C64Event::SamplePots);
+            }
+            C64Event::EndOfFrame => {
+                self.sync_devices();
+                self.cpu.scheduler.schedule_in(self.model.frame_cycles(), C64Event::EndOfFrame);
+            }
+            C64Event::Input(event) | C64Event::Replay(event) => {
+                self.rewind.record_input(self.get_cycles(), &event);
+                self.apply_input(&event);
+            }
+        }
+    }
+
+    /// Brings all the devices up to the master clock. Devices are synced
+    /// when the CPU accesses I/O area, so this is needed only when they're
+    /// accessed directly (i.e. `cia1()`).
+    pub fn sync_devices(&mut self) {
+        self.cpu.scheduler.sync();
+        self.cia_irq = !self.removed_chips.contains(&Chip::CIA1) && self.cia1.lock().interrupt();
+        self.cia_nmi = !self.removed_chips.contains(&Chip::CIA2) && self.cia2.lock().interrupt();
+    }
+
+    /// Visits all the devices of the machine (i.e. for debugger introspection)
+    pub fn for_each_device(&self, mut f: impl FnMut(&dyn DeviceTrait)) {
+        f(self.mem.pla());
+        self.devices.iter().for_each(|dev| f(&*lock(dev)));
+    }
+
+    pub fn for_each_device_mut(&mut self, mut f: impl FnMut(&mut dyn DeviceTrait)) {
+        f(self.mem.pla_mut());
+        self.devices.iter().for_each(|dev| f(&mut *lock(dev)));
+    }
+
+    /// Connects the device to the machine: from now on it's reset, saved
+    /// in snapshots (under its name) and clocked, if it's `Clocked`
+    fn add_device(&mut self, device: SharedDevice) {
+        self.cpu.scheduler.register(device.clone());
+        self.devices.push(device);
+    }
+
+    fn remove_device(&mut self, device: &SharedDevice) {
+        self.cpu.scheduler.unregister(device);
+        self.devices.retain(|dev| !is_same_device(dev, device));
+    }
+
+    /// Power cycle of all the devices (the CPU is reset with `Machine::reset`)
+    pub fn hard_reset(&mut self) {
+        self.sync_devices();
+        self.for_each_device_mut(|dev| dev.hard_reset());
+        self.mem.power_on(self.ram_pattern);
+        self.nmi_line = false;
+        self.sync_devices();
+    }
+
+    /// RESET line pulled: the chips are reset, but memory keeps its content
+    pub fn soft_reset(&mut self) {
+        self.sync_devices();
+        self.for_each_device_mut(|dev| dev.soft_reset());
+        self.nmi_line = false;
+        self.sync_devices();
+    }
+
+    /// Moves the clock to the cycle of a loaded snapshot. The periodic events
+    /// and the release of the pressed keys are rescheduled. The input scheduled
+    /// for later cycles (i.e. by a script) is kept, but the replayed one is dropped
+    /// (`Rewind::seek` schedules it again).
+    fn restore_clock(&mut self, cycles: Cycles) {
+        self.cpu.scheduler.set_clock(cycles);
+        self.cpu.scheduler
+            .retain_events(|at, event| matches!(event, C64Event::Input(_)) && at >= cycles);
+        let next = |period: Cycles| (cycles / period + 1) * period;
+        self.cpu.scheduler.schedule(next(POT_SAMPLE_CYCLES), C64Event::SamplePots);
+        self.cpu.scheduler.schedule(next(self.model.frame_cycles()), C64Event::EndOfFrame);
+        if let Some(at) = self.cia1.lock().keyboard.next_release() {
+            self.cpu.scheduler.schedule(at, C64Event::ReleaseKeys);
+        }
+    }
+
+    /// Saves the state of the input devices (keyboard, control ports,
+    /// RESTORE key) and of the type-in
+    fn save_input(&self, w: &mut StateWriter) {
+        let cia = self.cia1.lock();
+        cia.keyboard.save(w);
+        cia.port1.save(w);
+        cia.port2.save(w);
+        w.bool(self.restore_pressed)
+            .u64(self.restore_release_at.unwrap_or(Cycles::MAX));
+        self.type_in.save(w);
+    }
+
+    fn load_input(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        {
+            let mut cia = self.cia1.lock();
+            cia.keyboard.load(r)?;
+            cia.port1.load(r)?;
+            cia.port2.load(r)?;
+        }
+        self.restore_pressed = r.bool()?;
+        self.restore_release_at = Some(r.u64()?).filter(|at| *at != Cycles::MAX);
+        self.type_in.load(r)
+    }
+
+    /// Loads the sections of the snapshot (see `save_snapshot`), returning its clock
+    fn load_sections(&mut self, snapshot: &Snapshot) -> Result<Cycles, EmulatorError> {
+        let mut r = StateReader::new(snapshot.section("machine")?);
+        let model = r.str()?;
+        if model != format!("{:?}", self.model) {
+            return Err(EmulatorError::InvalidState(format!(
+                "snapshot of {model} model can't be loaded into {:?}",
+                self.model
+            )));
+        }
+        let cycles = r.u64()?;
+        self.status.load(&mut r)?;
+        self.cia_irq = r.bool()?;
+        self.cia_nmi = r.bool()?;
+        self.nmi_line = r.bool()?;
+        self.restore_pulse_until = r.u64()?;
+        r.finish()?;
+
+        let mut r = StateReader::new(snapshot.section("CPU")?);
+        self.cpu.core.registers.load(&mut r)?;
+        r.finish()?;
+
+        let mut result = Ok(());
+        self.for_each_device_mut(|dev| {
+            if result.is_ok() {
+                result = snapshot.section(dev.name()).and_then(|state| dev.load_state(state));
+            }
+        });
+        result?;
+
+        let mut r = StateReader::new(snapshot.section("media")?);
+        self.mem.load_media(&mut r)?;
+        r.finish()?;
+
+        let mut r = StateReader::new(snapshot.section("input")?);
+        self.load_input(&mut r)?;
+        r.finish()?;
+
+        let mut r = StateReader::new(snapshot.section("debugger")?);
+        self.debugger_state.load(&mut r)?;
+        r.finish()?;
+
+        Ok(cycles)
+    }
+
+    /// Imports a snapshot of VICE's x64 (CPU, RAM and the processor port, CIAs,
+    /// VIC-II and SID). Returns the modules that were skipped (not supported ones,
+    /// i.e. drives, cartridges, or of unsupported version); the devices keep then
+    /// their state. All the modules are read before any is applied, so the machine
+    /// keeps its state if one of them is broken.
+    pub fn import_vsf(&mut self, vsf: &Vsf) -> Result<Vec<String>, EmulatorError> {
+        if vsf.machine != "C64" {
+            return Err(EmulatorError::InvalidState(format!(
+                "VICE snapshot of {} can't be loaded into C64 (only the ones of x64 are supported)",
+                vsf.machine
+            )));
+        }
+        self.sync_devices();
+        let mut cpu = None;
+        let mut ram = None;
+        let mut cia1 = None;
+        let mut cia2 = None;
+        let mut vic = None;
+        let mut sid = None;
+        let mut skipped = Vec::new();
+        for module in vsf.modules.iter() {
+            if let Err(reason) = module.check() {
+                log::warn!("Module of the VICE snapshot skipped: {reason}");
+                skipped.push(reason);
+                continue;
+            }
+            match module.name.as_str() {
+                "MAINCPU" => cpu = Some(vsf::read_cpu(module)?),
+                "C64MEM" => ram = Some(vsf::read_memory(module)?),
+                "CIA1" => {
+                    let mut state = self.cia1.lock().state().clone();
+                    vsf::read_cia(module, &mut state)?;
+                    cia1 = Some(state);
+                }
+                "CIA2" => {
+                    let mut state = self.cia2.lock().state().clone();
+                    vsf::read_cia(module, &mut state)?;
+                    cia2 = Some(state);
+                }
+                "VIC-II" => vic = Some(vsf::read_vic(module)?),
+                "SID" => sid = vsf::read_sid(module)?,
+                _ => unreachable!(),
+            }
+        }
+        let (registers, cycles) = cpu
+            .ok_or_else(|| EmulatorError::InvalidState("no MAINCPU in the VICE snapshot".to_string()))?;
+
+        self.cpu.core.registers = registers;
+        if let Some(ram) = ram {
+            self.mem.set_ram(&ram);
+        }
+        if let Some(state) = cia1 {
+            *self.cia1.lock().state_mut() = state;
+        }
+        if let Some(state) = cia2 {
+            *self.cia2.lock().state_mut() = state;
+        }
+        if let Some(state) = vic {
+            state.apply(&mut self.gpu.lock(), &mut self.mem);
+        }
+        if let Some(registers) = sid {
+            vsf::set_sid_registers(&mut self.sid.lock(), &registers);
+        }
+        // VICE doesn't save the keys, so the live input is dropped
+        self.cia1.lock().keyboard.release_all();
+        self.type_in.clear();
+        self.restore_pressed = false;
+        self.restore_release_at = None;
+        self.restore_clock(cycles);
+        self.sync_devices();
+        self.nmi_line = self.cia_nmi;
+        self.restore_pulse_until = 0;
+        Ok(skipped)
+    }
+
+    /// Exports the state in VICE's snapshot format (the modules read by `import_vsf`),
+    /// i.e. to cross-check the emulation with VICE
+    pub fn export_vsf(&mut self) -> Vsf {
+        self.sync_devices();
+        let mut vsf = Vsf::new("C64");
+        vsf.modules.push(vsf::cpu_module(&self.cpu.core.registers, self.get_cycles()));
+        vsf.modules.push(vsf::memory_module(&self.mem));
+        vsf.modules.push(vsf::cia_module("CIA1", self.cia1.lock().state()));
+        vsf.modules.push(vsf::cia_module("CIA2", self.cia2.lock().state()));
+        vsf.modules.push(vsf::vic_module(&self.gpu.lock(), &self.mem));
+        vsf.modules.push(vsf::sid_module(&self.sid.lock()));
+        vsf
+    }
+
+    /// Sets state of joystick connected to control port 1 or 2
+    /// (connects the joystick if there is another device in the port)
+    pub fn set_joystick(&mut self, port: u8, state: u8) {
+        self.with_control_port(port, |device| {
+            if !matches!(device, ControlPortDevice::Joystick(_)) {
+                log::info!("Joystick connected to port {port}");
+                *device = ControlPortDevice::Joystick(Joystick::default());
+            }
+            if let ControlPortDevice::Joystick(joy) = device {
+                joy.set_state(state);
+            }
+        });
+    }
+
+    /// Sets positions and buttons of paddles connected to control port 1 or 2
+    pub fn set_paddles(&mut self, port: u8, x: u8, y: u8, buttons: u8) {
+        self.with_control_port(port, |device| {
+            if !matches!(device, ControlPortDevice::Paddles(_)) {
+                log::info!("Paddles connected to port {port}");
+            }
+            *device = ControlPortDevice::Paddles(Paddles { x, y, buttons });
+        });
+    }
+
+    /// Moves 1351 mouse connected to control port 1 or 2 and sets its buttons
+    pub fn move_mouse(&mut self, port: u8, dx: i16, dy: i16, buttons: u8) {
+        self.with_control_port(port, |device| {
+            if !matches!(device, ControlPortDevice::Mouse(_)) {
+                log::info!("Mouse connected to port {port}");
+                *device = ControlPortDevice::Mouse(Mouse1351::default());
+            }
+            if let ControlPortDevice::Mouse(mouse) = device {
+                mouse.move_by(dx, dy);
+                mouse.buttons = buttons;
+            }
+        });
+    }
+
+    fn with_control_port(&mut self, port: u8, f: impl FnOnce(&mut ControlPortDevice)) {
+        match self.cia1.lock().control_port_mut(port) {
+            Some(device) => f(device),
+            None => log::error!("There is no control port {port}"),
+        }
+    }
+
+    pub fn cia1(&self) -> &Device<CIA1> {
+        &self.cia1
+    }
+
+    /// Takes the chip out of the machine. Reads from its
+    /// address space follow then `MissingDevicePolicy`; the chip
+    /// is not clocked, reset or saved anymore, and raises no interrupts.
+    pub fn remove_chip(&mut self, chip: Chip) {
+        self.sync_devices();
+        self.mem.remove_chip(chip);
+        let device: SharedDevice = match chip {
+            Chip::CIA1 => self.cia1.mutex(),
+            Chip::CIA2 => self.cia2.mutex(),
+            Chip::SID => self.sid.mutex(),
+            Chip::Rom(_) => return,
+        };
+        self.remove_device(&device);
+        if !self.removed_chips.contains(&chip) {
+            self.removed_chips.push(chip);
+        }
+        self.sync_devices();
+    }
+
+    /// Plugs expansion device (i.e. cartridge's registers) into IO1 ($DE00)
+    /// or IO2 ($DF00) page, in place of the device plugged there
+    pub fn claim_io<T>(&mut self, slot: IOSlot, device: Arc<Mutex<T>>)
+    where
+        T: Addressable + DeviceTrait + Send + 'static,
+    {
+        self.release_io(slot);
+        self.mem.claim_io(slot, Some(device.clone()));
+        self.io_expansions[slot as usize] = Some(device.clone());
+        self.add_device(device);
+    }
+
+    /// Unplugs the device from IO1 or IO2 page
+    pub fn release_io(&mut self, slot: IOSlot) {
+        if let Some(device) = self.io_expansions[slot as usize].take() {
+            self.mem.claim_io(slot, None);
+            self.remove_device(&device);
+        }
+    }
+
+    /// Sets time of day clocks of both CIAs (in 24h format)
+    pub fn set_tod(&mut self, hours: u8, minutes: u8, seconds: u8) {
+        self.cia1.lock().state_mut().tod.set_time(hours, minutes, seconds, 0);
+        self.cia2.lock().state_mut().tod.set_time(hours, minutes, seconds, 0);
+    }
+
+    pub fn set_sid_model(&mut self, model: SIDModel) {
+        self.sid.lock().set_model(model);
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sid.lock().set_audio_sink(sink);
+    }
+
+    /// Replaces the tracer created from the config (`None` stops tracing)
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
     pub fn is_io(&self, addr: Addr) -> bool {
         let flag = self.memory().read_byte(1) & 0b00000111;
-        flag & 0b100 > 0 && flag & 11 > 0 && addr >= 0xdc00 && addr <= 0xdc0f
+        flag & 0b100 > 0 && flag & 11 > 0 && (0xdc00..=0xdc0f).contains(&addr)
     }
 }
 
@@ -100,11 +729,11 @@ impl Machine for C64 {
     }
 
     fn cpu(&self) -> &MOS6502 {
-        &self.mos6510
+        &self.cpu.core
     }
 
     fn cpu_mut(&mut self) -> &mut MOS6502 {
-        &mut self.mos6510
+        &mut self.cpu.core
     }
 
     fn get_config(&self) -> &MachineConfig {
@@ -120,33 +749,203 @@ impl Machine for C64 {
     }
 
     fn get_cycles(&self) -> Cycles {
-        self.cycles
+        self.cpu.cycles()
+    }
+
+    /// Writes are recorded in the execution history
+    fn write_byte(&mut self, addr: Addr, val: u8) {
+        self.rewind.record_write(addr, val);
+        self.mem.write_byte(addr, val);
     }
 
     fn advance_cycles(&mut self, cycles: u8) {
-        self.cycles = self.cycles.wrapping_add(cycles.into());
+        if let Err(err) = self.cpu.advance_cycles(cycles) {
+            log::error!("{err}");
+            self.stop();
+        }
+    }
+
+    fn clock_frequency(&self) -> u32 {
+        self.model.clock_frequency()
+    }
+
+    fn frame_cycles(&self) -> Cycles {
+        self.model.frame_cycles()
+    }
+
+    fn stop(&mut self) {
+        self.set_status(MachineStatus::Stopped);
+        self.sync_devices();
+        self.sid.lock().flush_audio();
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush();
+        }
+    }
+
+    fn reset(&mut self, kind: ResetKind) {
+        if self.get_status() != MachineStatus::Stopped {
+            self.stop();
+        }
+        self.rewind.clear();
+        match kind {
+            ResetKind::Hard => self.hard_reset(),
+            ResetKind::Soft => self.soft_reset(),
+        }
+        self.cpu.core.reset(kind);
+        self.start();
+    }
+
+    /// Sections: machine (model, clock, status and interrupt lines), CPU,
+    /// every device (by its name), media (ROMs), input (keys, controllers
+    /// and the type-in) and debugger
+    fn save_snapshot(&mut self) -> Snapshot {
+        self.sync_devices();
+        let mut snapshot = Snapshot::new("C64");
+        let mut w = StateWriter::new();
+        w.str(&format!("{:?}", self.model)).u64(self.get_cycles());
+        self.status.save(&mut w);
+        w.bool(self.cia_irq)
+            .bool(self.cia_nmi)
+            .bool(self.nmi_line)
+            .u64(self.restore_pulse_until);
+        snapshot.add("machine", w.finish());
+
+        let mut w = StateWriter::new();
+        self.cpu.core.registers.save(&mut w);
+        snapshot.add("CPU", w.finish());
+
+        self.for_each_device(|dev| snapshot.add(dev.name(), dev.save_state()));
+
+        let mut w = StateWriter::new();
+        self.mem.save_media(&mut w);
+        snapshot.add("media", w.finish());
+
+        let mut w = StateWriter::new();
+        self.save_input(&mut w);
+        snapshot.add("input", w.finish());
+
+        let mut w = StateWriter::new();
+        self.debugger_state.save(&mut w);
+        snapshot.add("debugger", w.finish());
+        snapshot
+    }
+
+    /// The execution history is dropped (see `Debugger::seek` to move within it).
+    /// If any section can't be loaded, the machine keeps its state.
+    fn load_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), EmulatorError> {
+        snapshot.check_machine("C64")?;
+        let backup = self.save_snapshot();
+        let cycles = match self.load_sections(snapshot) {
+            Ok(cycles) => cycles,
+            Err(err) => {
+                // the clock and the events are not touched until all the sections are loaded
+                self.load_sections(&backup)?;
+                return Err(err);
+            }
+        };
+        self.rewind.clear();
+        self.restore_clock(cycles);
+        Ok(())
+    }
+
+    fn replay_input(&mut self, at: Cycles, event: ClientEvent) {
+        self.cpu.scheduler.schedule(at, C64Event::Replay(event));
+    }
+
+    /// The interrupts come from the CIAs (IRQ from CIA1, NMI from CIA2; raster
+    /// interrupts of VIC-II are not emulated), as long as one of them is present.
+    /// With both taken out, the runtime emulates the jiffy IRQ.
+    fn has_interrupt_sources(&self) -> bool {
+        [Chip::CIA1, Chip::CIA2]
+            .iter()
+            .any(|chip| !self.removed_chips.contains(chip))
     }
 
     fn execute_operation(&mut self, op: &Operation) -> u8 {
-        let res = execute_operation(&op, self);
+        let io_access = op
+            .address
+            .is_some_and(|addr| IO_AREA.contains(&addr) || self.mem.reads_open_bus(addr));
+        if io_access {
+            self.sync_devices();
+        }
+        let mut registers = self.cpu.core.registers.clone();
+        registers.counter = registers.counter.wrapping_sub(op.def.len().into());
+        self.rewind.record_instruction(&registers, op.def.opcode, self.get_cycles());
+        // the accesses to the bus are logged for the tracer and watchpoints
+        let tracing = self.tracer.is_some() && !self.rewind.is_replaying();
+        let watching = !self.debugger_state.watchpoints.is_empty() && !self.rewind.is_replaying();
+        if tracing || watching {
+            self.mem.start_bus_log();
+        }
+        let cycles = self.get_cycles();
+        let res = execute_operation(op, self);
+        if io_access {
+            // the access may have changed the interrupt lines or started timers
+            self.sync_devices();
+        }
+        if tracing || watching {
+            let accesses = self.mem.take_bus_log();
+            if watching {
+                let hit = WatchHit::find(&self.debugger_state.watchpoints, registers.counter, &accesses);
+                if let Some(hit) = hit {
+                    log::info!("Watchpoint {}: {hit}", hit.watchpoint);
+                    self.debugger_state.watch_hit = Some(hit);
+                    self.watch_triggered = true;
+                }
+            }
+            if tracing {
+                let rom = self.mem.is_rom(registers.counter);
+                let record = TraceRecord::new(cycles, registers, op, rom, accesses);
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.record(&record);
+                }
+            }
+        }
         self.last_op = op.clone();
         res
     }
 
+    /// Instructions are traced after they're executed, with their bus accesses (see `Tracer`)
+    fn trace(&mut self, _op: &Operation) {}
+
     fn post_next(&mut self, op: &Operation) {
-        // FIXME this is an ugly workaround to fix screen scannig
-        // value at 0d012 represents currently scanned line
-        // if not updated  - screen won't be refreshed
-        // it should be implemented at VIC level
-        self.write_byte(0xd012, (self.cycles % 255) as u8);
+        // devices are ticked only when they're due (i.e. a timer underflows)
+        if self.cpu.scheduler.is_due() {
+            self.sync_devices();
+        }
+        while let Some(event) = self.cpu.scheduler.next_event() {
+            self.handle_event(event);
+        }
+        let cycles = self.get_cycles();
+        if self.type_in.is_due(cycles) {
+            self.type_in_next();
+        }
 
-        self.cia1.lock().tick_times(op.def.len());
-        self.cia2.lock().tick_times(op.def.len());
+        // IRQ is level-triggered: it's served as long as CIA1 keeps the line low
+        if self.cia_irq {
+            self.irq();
+        }
+        // NMI is edge-triggered: the line (shared by CIA2 and RESTORE key)
+        // must be released before next NMI
+        if self.restore_release_at.is_some_and(|at| cycles >= at) {
+            self.set_restore_key(false);
+        }
+        let nmi = self.cia_nmi || cycles < self.restore_pulse_until;
+        if nmi && !self.nmi_line {
+            self.nmi();
+        }
+        self.nmi_line = nmi;
 
-        if self.get_status() == MachineStatus::Running && self.should_pause(op) {
+        let watch_triggered = std::mem::take(&mut self.watch_triggered);
+        if self.get_status() == MachineStatus::Running && (watch_triggered || self.should_pause(op)) {
             self.start_debugging();
         }
         self.update_debugger_state();
+
+        if self.rewind.is_checkpoint_due(cycles) {
+            let snapshot = self.save_snapshot();
+            self.rewind.add_checkpoint(cycles, &snapshot);
+        }
     }
 }
 
@@ -163,10 +962,52 @@ impl Debugger for C64 {
     fn machine(&self) -> &C64 {
         self
     }
+
+    fn machine_mut(&mut self) -> &mut C64 {
+        self
+    }
+
+    fn rewind(&self) -> &Rewind {
+        &self.rewind
+    }
+
+    fn rewind_mut(&mut self) -> &mut Rewind {
+        &mut self.rewind
+    }
 }
 
 impl DebugMachine for C64 {}
 
+/// The machine as stepped by its CPU, which drives the master clock
+/// (see `Cpu6510`)
+impl machine::emulator::abstractions::Machine for C64 {
+    type CPU = Cpu6510<C64Event>;
+
+    fn cpu(&self) -> &Cpu6510<C64Event> {
+        &self.cpu
+    }
+
+    fn cpu_mut(&mut self) -> &mut Cpu6510<C64Event> {
+        &mut self.cpu
+    }
+
+    fn start(&mut self) {
+        Machine::start(self);
+    }
+
+    fn stop(&mut self) {
+        Machine::stop(self);
+    }
+
+    fn reset(&mut self) {
+        Machine::reset(self, ResetKind::Soft);
+    }
+
+    fn step(&mut self) {
+        Machine::next(self);
+    }
+}
+
 impl FromConfig for C64 {
     fn from_config(config: MachineConfig) -> Self {
         C64::new(config)
@@ -176,9 +1017,519 @@ impl FromConfig for C64 {
 impl FromProfile for C64 {
     fn from_profile(profile: &Profile) -> Self {
         let mut c64 = C64::new((&profile.config).into());
+        if let Some(name) = &profile.config.sid_model {
+            match SIDModel::from_name(name) {
+                Some(model) => c64.set_sid_model(model),
+                None => log::error!("Unknown SID model: {name} (expected 6581 or 8580)"),
+            }
+        }
+        if let Some(path) = &profile.config.audio_out {
+            match WavWriter::create(path, DEFAULT_SAMPLE_RATE) {
+                Ok(wav) => c64.set_audio_sink(Box::new(wav)),
+                Err(e) => log::error!("Couldn't create audio output {:?}: {e}", path),
+            }
+        }
+        if let Some(name) = &profile.config.keymap {
+            match Keymap::load(name) {
+                Ok(keymap) => c64.set_keymap(keymap),
+                Err(e) => log::error!("Couldn't load keymap {name}: {e}"),
+            }
+        }
+        if let Some(name) = &profile.config.keymap_mode {
+            match KeymapMode::from_name(name) {
+                Some(mode) => c64.keymap.mode = mode,
+                None => log::error!("Unknown keymap mode: {name} (expected symbolic or positional)"),
+            }
+        }
+        if let Some(ms) = profile.config.key_release {
+            c64.set_key_release(ms);
+        }
+        if let Some(name) = &profile.config.type_mode {
+            match TypeInMode::from_name(name) {
+                Some(mode) => c64.set_type_in_mode(mode),
+                None => log::error!("Unknown type-in mode: {name} (expected matrix or buffer)"),
+            }
+        }
+        if let Some(text) = &profile.config.type_text {
+            // allows new lines in command line argument
+            c64.type_text(&text.replace("\\n", "\n"));
+        }
+        if let Some(path) = &profile.config.input {
+            match InputScript::from_file(path) {
+                Ok(script) => script
+                    .events
+                    .into_iter()
+                    .for_each(|(at, event)| c64.schedule_input(at, event)),
+                Err(e) => log::error!("Couldn't load input script {:?}: {e}", path),
+            }
+        }
+        if let Some(tod) = &profile.config.tod {
+            if tod == "now" && c64.config.deterministic {
+                log::error!("Host's time of day can't be used in deterministic mode");
+            } else {
+                match parse_tod(tod) {
+                    Some((h, m, s)) => c64.set_tod(h, m, s),
+                    None => log::error!("Invalid time of day: {tod} (expected HH:MM[:SS] or now)"),
+                }
+            }
+        }
+        if let Some(name) = &profile.config.missing_device {
+            match MissingDevicePolicy::from_name(name) {
+                Some(policy) => c64.mem.set_missing_device_policy(policy),
+                None => log::error!("Unknown missing device policy: {name} (expected ram, zero or open-bus)"),
+            }
+        }
         if let Some(dc) = &profile.debug {
             c64.debugger_state = DebuggerState::from(dc);
         }
         c64
     }
+
+    fn images_loaded(&mut self, profile: &Profile) -> anyhow::Result<()> {
+        let config = &profile.config;
+        let roms = [
+            (RomSlot::Basic, &config.basic_rom),
+            (RomSlot::Kernal, &config.kernal_rom),
+            (RomSlot::CartridgeLo, &config.cart_lo_rom),
+            (RomSlot::CartridgeHi, &config.cart_hi_rom),
+        ];
+        for (slot, path) in roms {
+            if let Some(path) = path {
+                self.mem.load_rom(slot, &get_file_as_byte_vec(path)?);
+            }
+        }
+        // chips are removed at the end, so they're not put back with a ROM image
+        if let Some(names) = &config.remove_chips {
+            for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+                match Chip::from_name(name) {
+                    Some(chip) => self.remove_chip(chip),
+                    None => log::error!(
+                        "Unknown chip: {name} (expected cia1, cia2, sid, basic, kernal, chargen, cart-lo or cart-hi)"
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses time given as `HH:MM[:SS]` (24h) or `now` (host's local time)
+fn parse_tod(val: &str) -> Option<(u8, u8, u8)> {
+    if val == "now" {
+        let now = chrono::Local::now().time();
+        return Some((now.hour() as u8, now.minute() as u8, now.second() as u8));
+    }
+    let parts = val
+        .split(':')
+        .map(|part| part.parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    match parts[..] {
+        [h, m] if h < 24 && m < 60 => Some((h, m, 0)),
+        [h, m, s] if h < 24 && m < 60 && s < 60 => Some((h, m, s)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64::{JOY_FIRE, PAL_FRAME_CYCLES};
+    use machine::debugger::{TraceFilter, TraceFormat};
+    use std::sync::{Arc, Mutex};
+
+    /// Machine with RAM under KERNAL, looping at $C000. NMI handler
+    /// at $C100 counts the interrupts at $02; IRQ handler just returns.
+    fn test_machine() -> C64 {
+        test_machine_with(MachineConfig::new())
+    }
+
+    fn test_machine_with(config: MachineConfig) -> C64 {
+        let mut c64 = C64::new(config);
+        c64.write_byte(0x0001, 0x35);
+        c64.load(&[0x4c, 0x00, 0xc0], 0xc000); // JMP $C000
+        c64.load(&[0xe6, 0x02, 0x40, 0x40], 0xc100); // INC $02, RTI, RTI
+        c64.load(&[0x00, 0xc1, 0x00, 0xc0, 0x03, 0xc1], 0xfffa);
+        c64.write_byte(0x0002, 0);
+        c64.set_PC(0xc000);
+        c64
+    }
+
+    fn run(c64: &mut C64, cycles: Cycles) {
+        let end = c64.get_cycles() + cycles;
+        while c64.get_cycles() < end {
+            c64.next();
+        }
+    }
+
+    /// Trace output kept in memory
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// RAM and binary trace after a run with random power-on RAM and scripted input
+    fn deterministic_run(cycles: Cycles) -> (Vec<u8>, Vec<u8>) {
+        let mut config = MachineConfig::new();
+        config.deterministic = true;
+        config.seed = Some(6502);
+        let mut c64 = test_machine_with(config);
+        let trace = SharedBuffer::default();
+        let filter = TraceFilter::default();
+        let tracer = Tracer::new(Box::new(trace.clone()), TraceFormat::Binary, filter, true);
+        c64.set_tracer(Some(tracer));
+        // stores the keyboard rows mixed with the raster line into the screen
+        // LDA $DC01, EOR $D012, STA $0400,X, INX, JMP $C000
+        let program = [0xad, 0x01, 0xdc, 0x4d, 0x12, 0xd0, 0x9d, 0x00, 0x04, 0xe8, 0x4c, 0x00, 0xc0];
+        c64.load(&program, 0xc000);
+        let key = |state| ClientEvent::MatrixKeys { keys: vec![C64KeyCode::Key_A as u8], state };
+        c64.schedule_input(1000, key(KeyState::Down));
+        c64.schedule_input(20000, key(KeyState::Up));
+        c64.schedule_input(30000, ClientEvent::Joystick { port: 2, state: JOY_FIRE });
+        c64.schedule_input(50000, ClientEvent::Restore(KeyState::Down));
+        run(&mut c64, cycles);
+        c64.stop();
+        let trace = trace.0.lock().unwrap().clone();
+        (c64.mem.ram(), trace)
+    }
+
+    #[test]
+    fn test_deterministic_runs_are_identical() {
+        let (ram, trace) = deterministic_run(100_000);
+        assert!(trace.len() > 100_000);
+        assert_eq!(deterministic_run(100_000), (ram, trace));
+    }
+
+    /// Machine storing the raster line mixed with timer A of CIA2 into the screen
+    fn busy_machine() -> C64 {
+        let mut c64 = test_machine();
+        // LDA $D012, EOR $DD04, STA $0400,X, INX, JMP $C000
+        let program = [0xad, 0x12, 0xd0, 0x4d, 0x04, 0xdd, 0x9d, 0x00, 0x04, 0xe8, 0x4c, 0x00, 0xc0];
+        c64.load(&program, 0xc000);
+        c64.write_byte(0xdd04, 0x37);
+        c64.write_byte(0xdd05, 0);
+        c64.write_byte(0xdd0e, 0x11);
+        c64.sync_devices();
+        c64
+    }
+
+    #[test]
+    fn test_cpu_step_drives_the_clock() {
+        use machine::emulator::abstractions::Machine as _;
+        let mut c64 = busy_machine();
+        let timer = |c64: &mut C64| {
+            c64.sync_devices();
+            u16::from_le_bytes([c64.read_byte(0xdd04), c64.read_byte(0xdd05)]) as Cycles
+        };
+        // timer A of CIA2 runs from $FFFF, so it doesn't underflow meanwhile
+        c64.write_byte(0xdd04, 0xff);
+        c64.write_byte(0xdd05, 0xff);
+        c64.write_byte(0xdd0e, 0x11);
+        let (start, clock) = (timer(&mut c64), c64.cpu.cycles());
+        for _ in 0..1000 {
+            c64.step();
+        }
+        assert_eq!(c64.cpu.cycles(), c64.get_cycles());
+        assert_eq!(start - timer(&mut c64), c64.cpu.cycles() - clock);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut c64 = busy_machine();
+        run(&mut c64, 10_000);
+        let snapshot = c64.save_snapshot();
+        run(&mut c64, 50_000);
+        let registers = c64.cpu().registers.clone();
+        let ram = c64.mem.ram();
+        let state = c64.save_snapshot();
+
+        c64.load_snapshot(&snapshot).unwrap();
+        run(&mut c64, 50_000);
+        assert_eq!(c64.cpu().registers, registers);
+        assert_eq!(c64.mem.ram(), ram);
+        assert_eq!(c64.save_snapshot(), state);
+    }
+
+    #[test]
+    fn test_failed_snapshot_load_keeps_state() {
+        let mut c64 = busy_machine();
+        run(&mut c64, 10_000);
+        let mut snapshot = c64.save_snapshot();
+        snapshot.add("SID", vec![0]);
+        run(&mut c64, 10_000);
+        let state = c64.save_snapshot();
+        assert!(c64.load_snapshot(&snapshot).is_err());
+        assert_eq!(c64.save_snapshot(), state);
+    }
+
+    #[test]
+    fn test_step_back_and_forward_replays_input() {
+        let mut config = MachineConfig::new();
+        config.rewind_budget = Some(16);
+        let mut c64 = test_machine_with(config);
+        // stores the keyboard rows and joystick 2 mixed with the raster line into the screen
+        // LDA $DC01, EOR $DC00, EOR $D012, STA $0400,X, INX, JMP $C000
+        let program = [
+            0xad, 0x01, 0xdc, 0x4d, 0x00, 0xdc, 0x4d, 0x12, 0xd0, 0x9d, 0x00, 0x04, 0xe8, 0x4c,
+            0x00, 0xc0,
+        ];
+        c64.load(&program, 0xc000);
+        c64.set_key_release(20);
+        c64.write_byte(KEYBOARD_BUFFER_SIZE, 10);
+        let keys = ClientEvent::MatrixKeys { keys: vec![C64KeyCode::Key_A as u8], state: KeyState::Down };
+        // the key is still held when the second checkpoint is taken
+        c64.schedule_input(95_000, keys);
+        c64.schedule_input(150_000, ClientEvent::Joystick { port: 2, state: JOY_FIRE });
+        c64.schedule_input(180_000, ClientEvent::TypeText("run".to_string()));
+        run(&mut c64, 250_000);
+        c64.handle_input(&ClientEvent::Joystick { port: 2, state: 0 });
+        c64.handle_input(&ClientEvent::Restore(KeyState::Down));
+        run(&mut c64, 50_000);
+        let position = c64.rewind().position();
+        let registers = c64.cpu().registers.clone();
+        let ram = c64.mem.ram();
+        let state = c64.save_snapshot();
+
+        const STEPS: u64 = 60_000;
+        c64.seek(position - STEPS + 1).unwrap();
+        c64.step_back().unwrap();
+        assert!(c64.get_cycles() < 95_000);
+        for _ in 0..STEPS {
+            c64.next();
+        }
+        assert_eq!(c64.rewind().position(), position);
+        assert_eq!(c64.cpu().registers, registers);
+        assert_eq!(c64.mem.ram(), ram);
+        // going back pauses the machine
+        assert_eq!(c64.get_status(), MachineStatus::Debug);
+        c64.set_status(MachineStatus::Stopped);
+        assert_eq!(c64.save_snapshot(), state);
+    }
+
+    #[test]
+    fn test_restore_gives_single_nmi() {
+        let mut c64 = test_machine();
+        c64.set_restore_key(true);
+        run(&mut c64, 3 * RESTORE_PULSE_CYCLES);
+        assert_eq!(c64.read_byte(0x0002), 1);
+
+        // next press gives next NMI, but bouncing contacts
+        // (release and press while the pulse lasts) don't
+        c64.set_restore_key(false);
+        run(&mut c64, 100);
+        c64.set_restore_key(true);
+        run(&mut c64, 100);
+        c64.set_restore_key(false);
+        c64.set_restore_key(true);
+        run(&mut c64, 3 * RESTORE_PULSE_CYCLES);
+        assert_eq!(c64.read_byte(0x0002), 2);
+    }
+
+    #[test]
+    fn test_restore_while_cia2_holds_nmi() {
+        let mut c64 = test_machine();
+        // timer A of CIA2 underflows after 100 cycles; the handler doesn't
+        // acknowledge the interrupt, so CIA2 keeps NMI line low
+        c64.write_byte(0xdd04, 100);
+        c64.write_byte(0xdd05, 0);
+        c64.write_byte(0xdd0d, 0x81);
+        c64.write_byte(0xdd0e, 0x09);
+        c64.sync_devices();
+        run(&mut c64, 1000);
+        assert_eq!(c64.read_byte(0x0002), 1);
+
+        c64.set_restore_key(true);
+        run(&mut c64, 3 * RESTORE_PULSE_CYCLES);
+        assert_eq!(c64.read_byte(0x0002), 1);
+    }
+
+    #[test]
+    fn test_type_in_waits_for_keyboard_buffer() {
+        let mut c64 = test_machine();
+        c64.write_byte(KEYBOARD_BUFFER_SIZE, 10);
+        c64.write_byte(KEYBOARD_BUFFER_COUNT, 10);
+        c64.type_text("a");
+        c64.type_in_next();
+        assert!(c64.pressed_keys().is_empty());
+
+        c64.write_byte(KEYBOARD_BUFFER_COUNT, 9);
+        run(&mut c64, PAL_FRAME_CYCLES);
+        assert_eq!(c64.pressed_keys(), [C64KeyCode::Key_A as u8]);
+    }
+
+    /// Register of an expansion device
+    struct Latch(u8);
+
+    impl Addressable for Latch {
+        fn read_byte(&self, _addr: Addr) -> u8 {
+            self.0
+        }
+
+        fn write_byte(&mut self, _addr: Addr, value: u8) {
+            self.0 = value;
+        }
+
+        fn address_width(&self) -> u16 {
+            8
+        }
+    }
+
+    impl DeviceTrait for Latch {
+        fn name(&self) -> &str {
+            "latch"
+        }
+
+        fn hard_reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn save_state(&self) -> Vec<u8> {
+            vec![self.0]
+        }
+
+        fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+            let mut r = StateReader::new(state);
+            self.0 = r.u8()?;
+            r.finish()
+        }
+    }
+
+    #[test]
+    fn test_io_expansion_is_reset_and_saved() {
+        let mut c64 = test_machine();
+        let latch = Arc::new(Mutex::new(Latch(0)));
+        c64.claim_io(IOSlot::IO1, latch.clone());
+        c64.write_byte(0xde00, 0x42);
+        let snapshot = c64.save_snapshot();
+        c64.hard_reset();
+        assert_eq!(latch.lock().unwrap().0, 0);
+        c64.load_snapshot(&snapshot).unwrap();
+        assert_eq!(latch.lock().unwrap().0, 0x42);
+
+        c64.release_io(IOSlot::IO1);
+        c64.write_byte(0x0001, 0x35);
+        c64.write_byte(0xde00, 0x43);
+        c64.hard_reset();
+        assert_eq!(latch.lock().unwrap().0, 0x42);
+    }
+
+    #[test]
+    fn test_removed_cia_is_not_clocked() {
+        let mut c64 = test_machine();
+        // IRQ handler at $C104 counts the interrupts at $03 and acknowledges them
+        c64.load(&[0xe6, 0x03, 0xad, 0x0d, 0xdc, 0x40], 0xc104);
+        c64.load(&[0x04, 0xc1], 0xfffe);
+        c64.load(&[0x58, 0x4c, 0x01, 0xc0], 0xc000); // CLI, JMP $C001
+        c64.write_byte(0x0003, 0);
+        // timer A of CIA1 underflows every 100 cycles
+        c64.write_byte(0xdc04, 100);
+        c64.write_byte(0xdc05, 0);
+        c64.write_byte(0xdc0d, 0x81);
+        c64.write_byte(0xdc0e, 0x11);
+        c64.sync_devices();
+        run(&mut c64, 1000);
+        assert!(c64.read_byte(0x0003) > 5);
+
+        c64.remove_chip(Chip::CIA1);
+        let irqs = c64.read_byte(0x0003);
+        // the timer is programmed again to underflow after 50 cycles, through
+        // the bus (the writes don't reach the chip) and on the chip itself
+        for (reg, val) in [(0x04, 50), (0x05, 0), (0x0d, 0x81), (0x0e, 0x11)] {
+            c64.write_byte(0xdc00 + reg, val);
+            Addressable::write_byte(&mut *c64.cia1().lock(), reg, val);
+        }
+        let timer = c64.cia1().lock().peek_register(0x04);
+        assert_eq!(timer, Some(50));
+        run(&mut c64, 1000);
+        assert_eq!(c64.read_byte(0x0003), irqs);
+        assert_eq!(c64.cia1().lock().peek_register(0x04), timer);
+
+        // with both CIAs out, nothing raises interrupts on its own
+        c64.remove_chip(Chip::CIA2);
+        assert!(!c64.has_interrupt_sources());
+    }
+
+    fn vsf_module(name: &str, version: (u8, u8), data: &[u8]) -> Vec<u8> {
+        let mut module = name.as_bytes().to_vec();
+        module.resize(16, 0);
+        module.extend([version.0, version.1]);
+        module.extend(((data.len() + 22) as u32).to_le_bytes());
+        module.extend(data);
+        module
+    }
+
+    /// Snapshot laid out as x64 of VICE 3.7 writes it: the CPU (with
+    /// the clock beyond 32 bits), RAM, and a module of a disk drive
+    fn vice_snapshot(machine: &str, cia2: &[u8]) -> Vec<u8> {
+        let mut vsf = b"VICE Snapshot File\x1a\x02\x00".to_vec();
+        let mut name = machine.as_bytes().to_vec();
+        name.resize(16, 0);
+        vsf.extend(name);
+        vsf.extend(b"VICE Version\x1a\x03\x07\x00\x00\x8f\xa6\x00\x00");
+        // clock, A, X, Y, SP, PC, status, the last opcode and interrupt state
+        let mut cpu = 0x1_0000_1234u64.to_le_bytes().to_vec();
+        cpu.extend([0x11, 0x22, 0x33, 0xf0, 0x00, 0xc0, 0x24]);
+        cpu.extend([0; 12]);
+        vsf.extend(vsf_module("MAINCPU", (1, 1), &cpu));
+        // processor port (data, direction), EXROM, GAME, RAM and the port's capacitors
+        let mut mem = vec![0x35, 0x2f, 0, 0];
+        mem.extend((0..0x10000).map(|addr| (addr >> 8) as u8));
+        mem.extend([0; 13]);
+        vsf.extend(vsf_module("C64MEM", (0, 0), &mem));
+        vsf.extend(vsf_module("CIA2", (2, 2), cia2));
+        vsf.extend(vsf_module("DRIVE8", (4, 2), &[0; 16]));
+        vsf
+    }
+
+    #[test]
+    fn test_import_vice_snapshot() {
+        let mut c64 = test_machine();
+        let cia2 = vsf::cia_module("CIA2", c64.cia2.lock().state()).data;
+        let vsf = Vsf::from_bytes(&vice_snapshot("C64", &cia2)).unwrap();
+        let skipped = c64.import_vsf(&vsf).unwrap();
+        assert_eq!(skipped, ["DRIVE8 (not supported)"]);
+        assert_eq!(c64.get_cycles(), 0x1_0000_1234);
+        assert_eq!(c64.cpu().registers.counter, 0xc000);
+        assert_eq!(c64.cpu().registers.accumulator.0, 0x11);
+        assert_eq!(c64.cpu().registers.stack.0, 0xf0);
+        let ram = c64.mem.ram();
+        assert_eq!(ram[..2], [0x2f, 0x35]);
+        assert_eq!((ram[0x1234], ram[0xfffe]), (0x12, 0xff));
+    }
+
+    #[test]
+    fn test_import_vice_snapshot_is_atomic() {
+        let mut c64 = test_machine();
+        let state = c64.save_snapshot();
+        let cia2 = vsf::cia_module("CIA2", c64.cia2.lock().state()).data;
+        let broken = Vsf::from_bytes(&vice_snapshot("C64", &cia2[..10])).unwrap();
+        assert!(c64.import_vsf(&broken).is_err());
+        assert_eq!(c64.save_snapshot(), state);
+
+        // VIC-II module of x64sc has another layout
+        let x64sc = Vsf::from_bytes(&vice_snapshot("C64SC", &cia2)).unwrap();
+        assert!(c64.import_vsf(&x64sc).is_err());
+        assert_eq!(c64.save_snapshot(), state);
+    }
+
+    #[test]
+    fn test_pots_selected_by_cia1() {
+        let mut c64 = C64::new(MachineConfig::new());
+        c64.set_paddles(1, 0x10, 0x20, 0);
+        c64.set_paddles(2, 0x30, 0x40, 0);
+        // PA6 and PA7 of CIA1 (outputs after reset) select the port
+        for (select, pots) in [(0x40, (0x10, 0x20)), (0x80, (0x30, 0x40))] {
+            c64.write_byte(0xdc00, select);
+            c64.handle_event(C64Event::SamplePots);
+            assert_eq!((c64.read_byte(0xd419), c64.read_byte(0xd41a)), pots);
+        }
+    }
 }
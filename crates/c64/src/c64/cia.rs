@@ -1,62 +1,94 @@
-use super::keyboard::Keyboard;
+use super::{ControlPortDevice, Keyboard};
 use machine::emulator::{
-    abstractions::{Addr, Addressable, DeviceTrait},
-    components::CIA_6526,
+    abstractions::{
+        Addr, Addressable, CPUCycles, Clocked, DeviceTrait, RegisterInfo, StateReader,
+        StateWriter,
+    },
+    components::{CIAState, CIA_6526, CIA_REGISTERS},
+    EmulatorError,
 };
 
 // -----------------------------------------
 // CIA1
 
 pub struct CIA1 {
-    data: [u8; 16],
-    t_a: u16,
-    t_b: u16,
+    state: CIAState,
     pub keyboard: Keyboard,
+    pub port1: ControlPortDevice,
+    pub port2: ControlPortDevice,
+}
+
+impl Default for CIA1 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CIA1 {
     pub fn new() -> CIA1 {
-        let mut data = [0u8; 16];
-        data[2] = 0xff;
-        CIA1 {
-            data,
-            t_a: 0,
-            t_b: 0,
+        let mut cia = CIA1 {
+            state: CIAState::default(),
             keyboard: Keyboard::new(),
-        }
+            port1: ControlPortDevice::default(),
+            port2: ControlPortDevice::default(),
+        };
+        cia.hard_reset();
+        cia
     }
-}
 
-impl CIA_6526 for CIA1 {
-    fn mem(&self) -> &[u8] {
-        &self.data
+    pub fn control_port_mut(&mut self, port: u8) -> Option<&mut ControlPortDevice> {
+        match port {
+            1 => Some(&mut self.port1),
+            2 => Some(&mut self.port2),
+            _ => None,
+        }
     }
 
-    fn mem_mut(&mut self) -> &mut [u8] {
-        &mut self.data
+    /// Port lines (columns, rows) pulled low through the keyboard matrix.
+    /// Lines are driven low by CIA outputs set to 0 and by control port devices.
+    fn keyboard_lines(&self) -> (u8, u8) {
+        let s = &self.state;
+        let columns = (s.ddra & !s.pra) | !(self.port2.port_value() | 0xe0);
+        let rows = (s.ddrb & !s.prb) | !(self.port1.port_value() | 0xe0);
+        self.keyboard.scan(columns, rows)
     }
 
-    fn write_byte(&mut self, addr: Addr, val: u8) {
-        if addr == 0x00 {
-            self.data[0] = val;
-            let code = self.keyboard.scan(val, self.data[1]); // self.read_byte(0xdc01));
-            self.data[1] = code;
-            return ();
+    /// POT lines connected to SID: CIA1 lines PA6 and PA7
+    /// select control port 1 and 2 respectively
+    pub fn selected_pots(&self) -> (u8, u8) {
+        let select = self.state.pra | !self.state.ddra;
+        let (x1, y1) = self.port1.pots();
+        let (x2, y2) = self.port2.pots();
+        match (select & 0x40 > 0, select & 0x80 > 0) {
+            (true, false) => (x1, y1),
+            (false, true) => (x2, y2),
+            // both ports connected: the faster charging line wins
+            (true, true) => (x1.min(x2), y1.min(y2)),
+            (false, false) => (0xff, 0xff),
         }
-        self.mem_mut()[addr as usize] = val;
+    }
+}
+
+impl CIA_6526 for CIA1 {
+    fn state(&self) -> &CIAState {
+        &self.state
     }
 
-    fn timer_a(&self) -> u16 {
-        self.t_a
+    fn state_mut(&mut self) -> &mut CIAState {
+        &mut self.state
     }
 
-    fn timer_b(&self) -> u16 {
-        self.t_b
+    // control port 2 shares lines with keyboard columns
+    fn read_port_a(&self) -> u8 {
+        let s = &self.state;
+        (s.pra | !s.ddra) & !self.keyboard_lines().0
     }
 
-    fn tick(&mut self) {
-        self.t_a = self.t_a.wrapping_sub(1);
-        self.t_b = self.t_b.wrapping_sub(1);
+    // control port 1 shares lines with keyboard rows, so joystick moves
+    // are seen by the keyboard scan (and vice versa)
+    fn read_port_b(&self) -> u8 {
+        let s = &self.state;
+        s.with_timer_outputs((s.prb | !s.ddrb) & !self.keyboard_lines().1)
     }
 }
 
@@ -75,49 +107,80 @@ impl Addressable for CIA1 {
     }
 }
 
-impl DeviceTrait for CIA1 {}
+impl DeviceTrait for CIA1 {
+    fn name(&self) -> &str {
+        "CIA1"
+    }
+
+    /// Unlike the datasheet says, port A (keyboard columns) stays an output:
+    /// HelloOS scans the keyboard without setting its direction
+    /// (the KERNAL sets it anyway)
+    fn hard_reset(&mut self) {
+        self.state.reset();
+        self.state.ddra = 0xff;
+    }
+
+    fn registers(&self) -> &'static [RegisterInfo] {
+        &CIA_REGISTERS
+    }
+
+    fn peek_register(&self, offset: Addr) -> Option<u8> {
+        Some(self.peek_byte(offset))
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        save_state(&self.state)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        load_state(&mut self.state, state)
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        Some(self)
+    }
+}
+
+impl Clocked for CIA1 {
+    fn tick(&mut self, cycles: CPUCycles) {
+        for _ in 0..cycles {
+            self.state.tick();
+        }
+    }
+
+    fn next_event(&self) -> Option<CPUCycles> {
+        self.state.cycles_until_event()
+    }
+}
 
 // -----------------------------------------
 // CIA2
 
 pub struct CIA2 {
-    data: [u8; 16],
-    t_a: u16,
-    t_b: u16,
+    state: CIAState,
+}
+
+impl Default for CIA2 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CIA2 {
     pub fn new() -> CIA2 {
-        let mut data = [0u8; 16];
-        data[2] = 0xff;
         CIA2 {
-            data,
-            t_a: 0,
-            t_b: 0,
+            state: power_on_state(),
         }
     }
 }
 
 impl CIA_6526 for CIA2 {
-    fn mem(&self) -> &[u8] {
-        &self.data
-    }
-
-    fn mem_mut(&mut self) -> &mut [u8] {
-        &mut self.data
-    }
-
-    fn timer_a(&self) -> u16 {
-        self.t_a
-    }
-
-    fn timer_b(&self) -> u16 {
-        self.t_b
+    fn state(&self) -> &CIAState {
+        &self.state
     }
 
-    fn tick(&mut self) {
-        self.t_a = self.t_a.wrapping_sub(1);
-        self.t_b = self.t_b.wrapping_sub(1);
+    fn state_mut(&mut self) -> &mut CIAState {
+        &mut self.state
     }
 }
 
@@ -136,4 +199,124 @@ impl Addressable for CIA2 {
     }
 }
 
-impl DeviceTrait for CIA2 {}
+impl DeviceTrait for CIA2 {
+    fn name(&self) -> &str {
+        "CIA2"
+    }
+
+    fn hard_reset(&mut self) {
+        self.state.reset();
+    }
+
+    fn registers(&self) -> &'static [RegisterInfo] {
+        &CIA_REGISTERS
+    }
+
+    fn peek_register(&self, offset: Addr) -> Option<u8> {
+        Some(self.peek_byte(offset))
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        save_state(&self.state)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        load_state(&mut self.state, state)
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        Some(self)
+    }
+}
+
+impl Clocked for CIA2 {
+    fn tick(&mut self, cycles: CPUCycles) {
+        for _ in 0..cycles {
+            self.state.tick();
+        }
+    }
+
+    fn next_event(&self) -> Option<CPUCycles> {
+        self.state.cycles_until_event()
+    }
+}
+
+// -----------------------------------------
+// common for both CIAs
+
+fn power_on_state() -> CIAState {
+    let mut state = CIAState::default();
+    state.reset();
+    state
+}
+
+fn save_state(state: &CIAState) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    state.save(&mut w);
+    w.finish()
+}
+
+fn load_state(state: &mut CIAState, data: &[u8]) -> Result<(), EmulatorError> {
+    let mut r = StateReader::new(data);
+    state.load(&mut r)?;
+    r.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64::{Joystick, JOY_FIRE, JOY_UP};
+
+    fn joystick(state: u8) -> ControlPortDevice {
+        let mut joy = Joystick::default();
+        joy.set_state(state);
+        ControlPortDevice::Joystick(joy)
+    }
+
+    #[test]
+    fn test_joystick_and_keyboard() {
+        let mut cia = CIA1::new();
+        // KERNAL's scan: columns (port A) are outputs, rows (port B) inputs
+        Addressable::write_byte(&mut cia, 0x02, 0xff);
+        Addressable::write_byte(&mut cia, 0x03, 0x00);
+        Addressable::write_byte(&mut cia, 0x00, 0x7f);
+        cia.keyboard.key_down(4 * 8 + 2);
+        assert_eq!(Addressable::read_byte(&cia, 0x01), 0xff);
+
+        // fire of joystick 2 pulls column 4 low, as if it was selected
+        cia.port2 = joystick(JOY_FIRE);
+        assert_eq!(Addressable::read_byte(&cia, 0x00), 0x6f);
+        assert_eq!(Addressable::read_byte(&cia, 0x01), 0xfb);
+
+        // joystick 1 pulls row 0 low, so a key in that row is seen in port A
+        cia.port2 = joystick(0);
+        cia.port1 = joystick(JOY_UP);
+        cia.keyboard.key_down(3 * 8);
+        Addressable::write_byte(&mut cia, 0x00, 0xff);
+        assert_eq!(Addressable::read_byte(&cia, 0x01), 0xfe);
+        assert_eq!(Addressable::read_byte(&cia, 0x00), 0xf7);
+    }
+
+    #[test]
+    fn test_reset_and_state() {
+        let mut cia = CIA2::new();
+        cia.state_mut().set_tod_period(100);
+        Addressable::write_byte(&mut cia, 0x04, 0x10);
+        Addressable::write_byte(&mut cia, 0x05, 0x00);
+        Addressable::write_byte(&mut cia, 0x0e, 0x11);
+        Clocked::tick(&mut cia, 0x20);
+        let state = cia.save_state();
+
+        cia.hard_reset();
+        assert_eq!(cia.peek_register(0x0e), Some(0));
+        assert_eq!(cia.peek_register(0x02), Some(0));
+        assert_eq!(cia.peek_register(0x05), Some(0xff)); // timer latches are set to ones
+        assert_eq!(cia.state().cycles_until_event(), Some(100));
+
+        cia.load_state(&state).unwrap();
+        assert_eq!(cia.timer_a(), 0x10 - (0x20 - 0x11));
+        assert_eq!(cia.peek_register(0x0d), Some(0x01));
+        assert_eq!(Addressable::read_byte(&cia, 0x0d), 0x01);
+        assert_eq!(cia.peek_register(0x0d), Some(0));
+    }
+}
@@ -0,0 +1,80 @@
+use super::{Joystick, Mouse1351, Paddles};
+use machine::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+/// Device plugged into a control port
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlPortDevice {
+    Joystick(Joystick),
+    Paddles(Paddles),
+    Mouse(Mouse1351),
+}
+
+impl Default for ControlPortDevice {
+    fn default() -> Self {
+        ControlPortDevice::Joystick(Joystick::default())
+    }
+}
+
+impl ControlPortDevice {
+    /// State of lines 0-4, as seen by CIA1
+    pub fn port_value(&self) -> u8 {
+        match self {
+            Self::Joystick(joy) => joy.port_value(),
+            Self::Paddles(paddles) => paddles.port_value(),
+            Self::Mouse(mouse) => mouse.port_value(),
+        }
+    }
+
+    /// Saves the kind of the device and its state (see `C64::save_snapshot`)
+    pub fn save(&self, w: &mut StateWriter) {
+        match self {
+            Self::Joystick(joy) => {
+                w.u8(0).u8(joy.state());
+            }
+            Self::Paddles(paddles) => {
+                w.u8(1).u8(paddles.x).u8(paddles.y).u8(paddles.buttons);
+            }
+            Self::Mouse(mouse) => {
+                w.u8(2);
+                mouse.save(w);
+            }
+        }
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        *self = match r.u8()? {
+            0 => {
+                let mut joy = Joystick::default();
+                joy.set_state(r.u8()?);
+                Self::Joystick(joy)
+            }
+            1 => Self::Paddles(Paddles {
+                x: r.u8()?,
+                y: r.u8()?,
+                buttons: r.u8()?,
+            }),
+            2 => {
+                let mut mouse = Mouse1351::default();
+                mouse.load(r)?;
+                Self::Mouse(mouse)
+            }
+            kind => {
+                return Err(EmulatorError::InvalidState(format!("control port device {kind}")))
+            }
+        };
+        Ok(())
+    }
+
+    /// Values of POTX and POTY lines, as measured by SID.
+    /// Not connected lines read as $FF.
+    pub fn pots(&self) -> (u8, u8) {
+        match self {
+            Self::Joystick(_) => (0xff, 0xff),
+            Self::Paddles(paddles) => paddles.pots(),
+            Self::Mouse(mouse) => mouse.pots(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use machine::{
+    emulator::{
+        abstractions::{CPUCycles, Scheduler, CPU},
+        EmulatorError,
+    },
+    mos6502::MOS6502,
+};
+
+/// 6510 of the C64 with the master clock it drives: every executed instruction
+/// advances the clock of the scheduler, which keeps the timed events `E`
+/// (the devices catch up with the clock when one of them is due, see `Scheduler`)
+pub struct Cpu6510<E> {
+    pub core: MOS6502,
+    pub scheduler: Scheduler<E>,
+}
+
+impl<E> Cpu6510<E> {
+    pub fn new(scheduler: Scheduler<E>) -> Self {
+        Cpu6510 {
+            core: MOS6502::new(),
+            scheduler,
+        }
+    }
+}
+
+impl<E> CPU for Cpu6510<E> {
+    fn cycles(&self) -> CPUCycles {
+        self.scheduler.clock()
+    }
+
+    fn advance_cycles(&mut self, cycles: u8) -> Result<(), EmulatorError> {
+        if self.scheduler.clock().checked_add(cycles.into()).is_none() {
+            return Err(EmulatorError::InvalidState("master clock overflow".to_string()));
+        }
+        self.scheduler.advance(cycles.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_cycles() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(5, "event");
+        let mut cpu = Cpu6510::new(scheduler);
+        cpu.advance_cycles(4).unwrap();
+        assert_eq!(cpu.scheduler.next_event(), None);
+        cpu.advance_cycles(2).unwrap();
+        assert_eq!(cpu.cycles(), 6);
+        assert_eq!(cpu.scheduler.next_event(), Some("event"));
+
+        cpu.scheduler.set_clock(CPUCycles::MAX);
+        assert!(cpu.advance_cycles(1).is_err());
+        assert_eq!(cpu.cycles(), CPUCycles::MAX);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::key_utils::C64KeyCode;
+use anyhow::{anyhow, bail, Result};
+use keyboard_types::KeyState;
+use machine::{client::ClientEvent, Cycles};
+use std::path::Path;
+
+/// Input events timestamped in emulated cycles, so runs can be repeated
+/// exactly. Each line of the script is `<cycle> <event> [arguments]`:
+///
+/// ```text
+/// # comments and empty lines are skipped
+/// 2500000 type load"*",8,1\n
+/// 3000000 press LShift+Stop
+/// 3100000 release LShift+Stop
+/// 3200000 restore
+/// 4000000 joystick 2 16
+/// 4100000 paddles 1 128 128 0
+/// 4200000 mouse 1 -5 10 1
+/// ```
+///
+/// Keys are given by their names (as in keymaps, i.e. `A`, `Return`); pressed keys
+/// are released after the synthetic release delay (if enabled). Controller events take
+/// the same arguments as `ClientEvent`. The events are sorted by the cycle.
+pub struct InputScript {
+    pub events: Vec<(Cycles, ClientEvent)>,
+}
+
+impl InputScript {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        InputScript::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut events = Vec::new();
+        for (num, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = parse_line(line).map_err(|e| anyhow!("line {}: {e}", num + 1))?;
+            events.push(event);
+        }
+        // the sort is stable, so events at the same cycle keep their order
+        events.sort_by_key(|(at, _)| *at);
+        Ok(InputScript { events })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(Cycles, ClientEvent)> {
+    let (at, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let at = at.parse::<Cycles>().map_err(|_| anyhow!("invalid cycle: {at}"))?;
+    let (name, args) = rest.trim_start().split_once(' ').unwrap_or((rest.trim(), ""));
+    let event = match name {
+        // allows new lines, as in --type argument
+        "type" => ClientEvent::TypeText(args.replace("\\n", "\n")),
+        "press" => ClientEvent::MatrixKeys { keys: parse_keys(args)?, state: KeyState::Down },
+        "release" => ClientEvent::MatrixKeys { keys: parse_keys(args)?, state: KeyState::Up },
+        "restore" => ClientEvent::Restore(KeyState::Down),
+        "joystick" => match parse_numbers::<u8>(args)?[..] {
+            [port, state] => ClientEvent::Joystick { port, state },
+            _ => bail!("expected: joystick <port> <state>"),
+        },
+        "paddles" => match parse_numbers::<u8>(args)?[..] {
+            [port, x, y, buttons] => ClientEvent::Paddles { port, x, y, buttons },
+            _ => bail!("expected: paddles <port> <x> <y> <buttons>"),
+        },
+        "mouse" => match parse_numbers::<i16>(args)?[..] {
+            [port, dx, dy, buttons] => ClientEvent::Mouse {
+                port: port as u8,
+                dx,
+                dy,
+                buttons: buttons as u8,
+            },
+            _ => bail!("expected: mouse <port> <dx> <dy> <buttons>"),
+        },
+        _ => bail!("unknown event: {name}"),
+    };
+    Ok((at, event))
+}
+
+fn parse_keys(args: &str) -> Result<Vec<u8>> {
+    args.trim()
+        .split('+')
+        .map(|name| {
+            C64KeyCode::from_name(name)
+                .map(|kc| kc as u8)
+                .ok_or(anyhow!("unknown key: {name}"))
+        })
+        .collect()
+}
+
+fn parse_numbers<T: std::str::FromStr>(args: &str) -> Result<Vec<T>> {
+    args.split_whitespace()
+        .map(|val| val.parse::<T>().map_err(|_| anyhow!("invalid number: {val}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script() {
+        let script = InputScript::parse(
+            "# boot\n\n300 joystick 2 16\n100 type run\\n\n200 press LShift+Stop\n",
+        )
+        .unwrap();
+        assert_eq!(
+            script.events,
+            vec![
+                (100, ClientEvent::TypeText("run\n".to_string())),
+                (
+                    200,
+                    ClientEvent::MatrixKeys {
+                        keys: vec![C64KeyCode::LShift as u8, C64KeyCode::Stop as u8],
+                        state: KeyState::Down
+                    }
+                ),
+                (300, ClientEvent::Joystick { port: 2, state: 16 }),
+            ]
+        );
+        assert!(InputScript::parse("100 jump").is_err());
+        assert!(InputScript::parse("100 press Foo").is_err());
+    }
+}
@@ -1,46 +1,329 @@
+use std::cell::Cell;
 use std::sync::{Arc, Mutex};
 
 use machine::{
-    emulator::abstractions::{Addr, Addressable, ArrayMemory, DeviceTrait},
+    emulator::abstractions::{
+        Addr, Addressable, ArrayMemory, DeviceTrait, OpenBusSource, StateReader, StateWriter,
+    },
+    emulator::{components::MissingDevicePolicy, EmulatorError},
     utils::lock,
 };
 
-use super::{CIA1, CIA2};
+use super::{CIA1, CIA2, SID, VIC_II};
 
-pub struct C64IO {
-    pub cia1: Arc<Mutex<CIA1>>,
-    pub cia2: Arc<Mutex<CIA2>>,
-    // FIXME RAM is just a fallback. Remove when all devies will be in place
-    pub ram: Arc<Mutex<ArrayMemory>>,
+/// Device plugged into the expansion port, claiming IO1 or IO2 page.
+/// It's addressed relative to the page (0x00-0xff).
+pub type IOExpansion = Arc<Mutex<dyn Addressable + Send>>;
+
+/// Expansion port pages of the I/O area
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IOSlot {
+    /// $DE00-$DEFF
+    IO1,
+    /// $DF00-$DFFF
+    IO2,
 }
 
-impl Addressable for C64IO {
-    // addr is relative to $D000
-    fn read_byte(&self, addr: Addr) -> u8 {
-        // The CIA registers are mirrored each 16 bytes
-        // so 0xdc10 is same as 0xdc00
-        if (0xc00..=0xcff).contains(&addr) {
-            lock::<CIA1>(&self.cia1).read_byte((addr - 0xc00) & 0x000f)
-        } else if (0xd00..=0xdff).contains(&addr) {
-            lock::<CIA2>(&self.cia2).read_byte((addr - 0xd00) & 0x000f)
+/// Decodes chip-select regions of the I/O area ($D000-$DFFF):
+///
+/// ```text
+/// $D000-$D3FF VIC-II (registers mirrored every 64 bytes)
+/// $D400-$D7FF SID (mirrored every 32 bytes)
+/// $D800-$DBFF Colour RAM (1024 nibbles; upper bits are not connected)
+/// $DC00-$DCFF CIA1 (mirrored every 16 bytes)
+/// $DD00-$DDFF CIA2 (mirrored every 16 bytes)
+/// $DE00-$DEFF IO1 (expansion port)
+/// $DF00-$DFFF IO2 (expansion port)
+/// ```
+///
+/// Reads of unclaimed IO1/IO2 pages and of the missing colour RAM bits
+/// return the value left on the data bus (open bus). Reads of removed
+/// chips (SID, CIAs) follow `MissingDevicePolicy`.
+pub struct IODispatcher {
+    vic: Arc<Mutex<VIC_II>>,
+    sid: Option<Arc<Mutex<SID>>>,
+    cia1: Option<Arc<Mutex<CIA1>>>,
+    cia2: Option<Arc<Mutex<CIA2>>>,
+    colour_ram: Box<[u8; 0x400]>,
+    io1: Option<IOExpansion>,
+    io2: Option<IOExpansion>,
+    /// Last value transferred over the data bus through the I/O area
+    /// (used when there is no `open_bus` source)
+    bus: Cell<u8>,
+    open_bus: Option<OpenBusSource>,
+    missing_device: MissingDevicePolicy,
+    /// RAM underneath the I/O area, seen in place of removed chips (see `MissingDevicePolicy`)
+    ram: Arc<Mutex<ArrayMemory>>,
+}
+
+impl IODispatcher {
+    pub fn new(
+        vic: Arc<Mutex<VIC_II>>,
+        sid: Arc<Mutex<SID>>,
+        cia1: Arc<Mutex<CIA1>>,
+        cia2: Arc<Mutex<CIA2>>,
+        ram: Arc<Mutex<ArrayMemory>>,
+    ) -> Self {
+        IODispatcher {
+            vic,
+            sid: Some(sid),
+            cia1: Some(cia1),
+            cia2: Some(cia2),
+            colour_ram: Box::new([0; 0x400]),
+            io1: None,
+            io2: None,
+            bus: Cell::new(0),
+            open_bus: None,
+            missing_device: MissingDevicePolicy::default(),
+            ram,
+        }
+    }
+
+    /// Disconnects the chip, as if it was taken out of its socket
+    pub fn remove_sid(&mut self) {
+        self.sid = None;
+    }
+
+    pub fn remove_cia1(&mut self) {
+        self.cia1 = None;
+    }
+
+    pub fn remove_cia2(&mut self) {
+        self.cia2 = None;
+    }
+
+    pub fn set_missing_device_policy(&mut self, policy: MissingDevicePolicy) {
+        self.missing_device = policy;
+    }
+
+    /// Connects the source of the value floating on the data bus (VIC-II fetches)
+    pub fn link_open_bus(&mut self, src: OpenBusSource) {
+        self.open_bus = Some(src);
+    }
+
+    /// Connects the device to the slot (or disconnects the slot with None)
+    pub fn claim(&mut self, slot: IOSlot, device: Option<IOExpansion>) {
+        match slot {
+            IOSlot::IO1 => self.io1 = device,
+            IOSlot::IO2 => self.io2 = device,
+        }
+    }
+
+    pub fn is_claimed(&self, slot: IOSlot) -> bool {
+        match slot {
+            IOSlot::IO1 => self.io1.is_some(),
+            IOSlot::IO2 => self.io2.is_some(),
+        }
+    }
+
+    pub fn colour_ram(&self) -> &[u8; 0x400] {
+        &self.colour_ram
+    }
+
+    pub fn colour_ram_mut(&mut self) -> &mut [u8; 0x400] {
+        &mut self.colour_ram
+    }
+
+    /// Value read from an address no device responds to
+    pub fn open_bus(&self) -> u8 {
+        match &self.open_bus {
+            Some(src) => lock(src).open_bus(),
+            None => self.bus.get(),
+        }
+    }
+
+    /// Name of the chip at the address (relative to $D000), as seen in the bus log
+    pub fn device_name(&self, addr: Addr) -> &'static str {
+        let addr = addr & 0x0fff;
+        match addr {
+            0x000..=0x3ff => "VIC-II",
+            0x400..=0x7ff if self.sid.is_some() => "SID",
+            0x800..=0xbff => "colour RAM",
+            0xc00..=0xcff if self.cia1.is_some() => "CIA1",
+            0xd00..=0xdff if self.cia2.is_some() => "CIA2",
+            0xe00..=0xfff if self.expansion(addr).is_some() => "I/O expansion",
+            0xe00..=0xfff => "open bus",
+            _ => "none",
+        }
+    }
+
+    fn expansion(&self, addr: Addr) -> Option<&IOExpansion> {
+        if addr < 0xf00 {
+            self.io1.as_ref()
         } else {
-            lock::<ArrayMemory>(&self.ram).read_byte(addr + 0xd000)
+            self.io2.as_ref()
         }
     }
 
+    fn read(&self, addr: Addr) -> u8 {
+        match addr {
+            0x000..=0x3ff => lock::<VIC_II>(&self.vic).read_byte(addr & 0x3f),
+            0x400..=0x7ff => match &self.sid {
+                Some(sid) => lock::<SID>(sid).read_byte(addr & 0x1f),
+                None => self.read_missing(addr),
+            },
+            0x800..=0xbff => self.open_bus() & 0xf0 | self.colour_ram[(addr & 0x3ff) as usize],
+            0xc00..=0xcff => match &self.cia1 {
+                Some(cia) => lock::<CIA1>(cia).read_byte(addr & 0x0f),
+                None => self.read_missing(addr),
+            },
+            0xd00..=0xdff => match &self.cia2 {
+                Some(cia) => lock::<CIA2>(cia).read_byte(addr & 0x0f),
+                None => self.read_missing(addr),
+            },
+            _ => match self.expansion(addr) {
+                Some(dev) => lock(dev).read_byte(addr & 0xff),
+                None => self.open_bus(),
+            },
+        }
+    }
+
+    fn read_missing(&self, addr: Addr) -> u8 {
+        match self.missing_device {
+            MissingDevicePolicy::Ram => lock::<ArrayMemory>(&self.ram).read_byte(addr + 0xd000),
+            MissingDevicePolicy::Zero => 0,
+            MissingDevicePolicy::OpenBus => self.open_bus(),
+        }
+    }
+}
+
+impl Addressable for IODispatcher {
+    // addr is relative to $D000
+    fn read_byte(&self, addr: Addr) -> u8 {
+        let value = self.read(addr & 0x0fff);
+        self.bus.set(value);
+        value
+    }
+
     fn write_byte(&mut self, addr: Addr, value: u8) {
-        if (0xc00..=0xc0f).contains(&addr) {
-            return lock::<CIA1>(&self.cia1).write_byte((addr - 0xc00) & 0x000f, value);
-        } else if (0xc00..=0xc0f).contains(&addr) {
-            return lock::<CIA2>(&self.cia2).write_byte((addr - 0xd00) & 0x000f, value);
-        } else {
-            lock::<ArrayMemory>(&self.ram).write_byte(addr + 0xd000, value);
+        self.bus.set(value);
+        let addr = addr & 0x0fff;
+        match addr {
+            0x000..=0x3ff => lock::<VIC_II>(&self.vic).write_byte(addr & 0x3f, value),
+            0x400..=0x7ff => {
+                if let Some(sid) = &self.sid {
+                    lock::<SID>(sid).write_byte(addr & 0x1f, value);
+                }
+            }
+            0x800..=0xbff => self.colour_ram[(addr & 0x3ff) as usize] = value & 0x0f,
+            0xc00..=0xcff => {
+                if let Some(cia) = &self.cia1 {
+                    lock::<CIA1>(cia).write_byte(addr & 0x0f, value);
+                }
+            }
+            0xd00..=0xdff => {
+                if let Some(cia) = &self.cia2 {
+                    lock::<CIA2>(cia).write_byte(addr & 0x0f, value);
+                }
+            }
+            _ => {
+                if let Some(dev) = self.expansion(addr) {
+                    lock(dev).write_byte(addr & 0xff, value);
+                }
+            }
         }
     }
 
     fn address_width(&self) -> u16 {
-        16
+        12
+    }
+}
+
+/// The chips are reset on their own, the dispatcher keeps only colour RAM
+impl DeviceTrait for IODispatcher {
+    fn name(&self) -> &str {
+        "I/O"
+    }
+
+    fn hard_reset(&mut self) {
+        self.colour_ram.fill(0);
+        self.bus.set(0);
+    }
+
+    /// Colour RAM keeps its content
+    fn soft_reset(&mut self) {}
+
+    fn peek_register(&self, addr: Addr) -> Option<u8> {
+        let addr = addr & 0x0fff;
+        match (addr, &self.sid, &self.cia1, &self.cia2) {
+            (0x000..=0x3ff, ..) => lock::<VIC_II>(&self.vic).peek_register(addr & 0x3f),
+            (0x400..=0x7ff, Some(sid), _, _) => lock::<SID>(sid).peek_register(addr & 0x1f),
+            (0xc00..=0xcff, _, Some(cia), _) => lock::<CIA1>(cia).peek_register(addr & 0x0f),
+            (0xd00..=0xdff, _, _, Some(cia)) => lock::<CIA2>(cia).peek_register(addr & 0x0f),
+            // colour RAM, expansion devices and missing chips have no side effects of reading
+            _ => Some(self.read(addr)),
+        }
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&self.colour_ram[..]).u8(self.bus.get());
+        w.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = StateReader::new(state);
+        self.colour_ram.copy_from_slice(r.bytes(0x400)?);
+        self.bus.set(r.u8()?);
+        r.finish()
     }
 }
 
-impl DeviceTrait for C64IO {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64::{MachineModel, SIDModel};
+
+    fn dispatcher() -> IODispatcher {
+        IODispatcher::new(
+            Arc::new(Mutex::new(VIC_II::new(MachineModel::PAL))),
+            Arc::new(Mutex::new(SID::new(SIDModel::default()))),
+            Arc::new(Mutex::new(CIA1::new())),
+            Arc::new(Mutex::new(CIA2::new())),
+            Arc::new(Mutex::new(ArrayMemory::new(0x10000, 16))),
+        )
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut io = dispatcher();
+        io.write_byte(0x0060, 0x05); // VIC $D020 mirrored at $D060
+        assert_eq!(io.read_byte(0x0020), 0xf5);
+        io.write_byte(0x0d12, 0x34); // CIA2 $DD02 mirrored at $DD12
+        assert_eq!(io.read_byte(0x0d02), 0x34);
+        assert_eq!(io.read_byte(0x0c02), 0xff); // CIA1 is not affected
+        assert_eq!(io.read_byte(0x003f), 0xff); // unused VIC register
+
+        // upper bits of colour RAM come from the bus
+        io.write_byte(0x0bff, 0xab);
+        assert_eq!(io.read_byte(0x0bff), 0xab);
+        io.read_byte(0x0d02);
+        assert_eq!(io.read_byte(0x0bff), 0x3b);
+    }
+
+    #[test]
+    fn test_expansion_slots() {
+        let mut io = dispatcher();
+        io.write_byte(0x0800, 0x0e);
+        assert_eq!(io.read_byte(0x0e10), 0x0e); // open bus
+        io.claim(IOSlot::IO1, Some(Arc::new(Mutex::new(ArrayMemory::new(0x100, 8)))));
+        io.write_byte(0x0e10, 0x42);
+        assert_eq!(io.read_byte(0x0e10), 0x42);
+        assert!(!io.is_claimed(IOSlot::IO2));
+        io.write_byte(0x0f10, 0x43);
+        assert_eq!(io.read_byte(0x0f10), 0x43); // the value left on the bus
+        assert_eq!(io.read_byte(0x0e10), 0x42);
+    }
+
+    #[test]
+    fn test_removed_chip() {
+        let mut io = dispatcher();
+        io.remove_cia1();
+        io.ram.lock().unwrap().write_byte(0xdc0d, 0x81);
+        io.write_byte(0x0c0d, 0x7f); // ignored
+        assert_eq!(io.read_byte(0x0c0d), 0x81);
+        io.set_missing_device_policy(MissingDevicePolicy::Zero);
+        assert_eq!(io.read_byte(0x0c0d), 0);
+        assert_eq!(io.read_byte(0x0d05), 0xff); // CIA2 is still there (timer A)
+    }
+}
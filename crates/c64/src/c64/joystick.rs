@@ -0,0 +1,39 @@
+/// Joystick switches (bits of both joystick state and CIA1 port)
+pub const JOY_UP: u8 = 0b0000_0001;
+pub const JOY_DOWN: u8 = 0b0000_0010;
+pub const JOY_LEFT: u8 = 0b0000_0100;
+pub const JOY_RIGHT: u8 = 0b0000_1000;
+pub const JOY_FIRE: u8 = 0b0001_0000;
+
+/// Digital joystick connected to one of the control ports.
+/// Joystick in port 2 is wired to CIA1 port A, and in port 1 to CIA1 port B,
+/// so - as on the real hardware - both interfere with the keyboard matrix.
+/// See [Joystick at C64 Wiki](https://www.c64-wiki.com/wiki/Joystick)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Joystick {
+    state: u8,
+}
+
+impl Joystick {
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
+    /// Sets the switches (active-high, see `JOY_*` constants).
+    /// Contradictory directions can't be pressed at once.
+    pub fn set_state(&mut self, state: u8) {
+        let mut state = state & 0x1f;
+        if state & (JOY_UP | JOY_DOWN) == JOY_UP | JOY_DOWN {
+            state &= !JOY_DOWN;
+        }
+        if state & (JOY_LEFT | JOY_RIGHT) == JOY_LEFT | JOY_RIGHT {
+            state &= !JOY_RIGHT;
+        }
+        self.state = state;
+    }
+
+    /// Value of the port lines: closed switches pull them low
+    pub fn port_value(&self) -> u8 {
+        !self.state
+    }
+}
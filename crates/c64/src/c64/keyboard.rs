@@ -1,80 +1,151 @@
-use crate::key_utils::C64_SCAN_CODES;
-
-pub struct Keyboard {
-    last_keys: Vec<u8>,
-    cycle: u8,
-}
+use machine::{
+    emulator::{
+        abstractions::{StateReader, StateWriter},
+        EmulatorError,
+    },
+    Cycles,
+};
 
+/// C64 keyboard as 8x8 matrix of switches between CIA1 port A lines
+/// (columns) and port B lines (rows). Key code (see `C64KeyCode`) is
+/// `column * 8 + row`. Keys stay pressed until released.
 // see
 // https://c64os.com/post/howthekeyboardworks
 // https://www.c64-wiki.com/wiki/Keyboard
+pub struct Keyboard {
+    /// Pressed keys: bit `row` of `matrix[column]`
+    matrix: [u8; 8],
+    /// Keys to be released automatically, with the release cycle
+    releases: Vec<(u8, Cycles)>,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keyboard {
     pub fn new() -> Self {
         Keyboard {
-            last_keys: Vec::with_capacity(5),
-            cycle: 8,
+            matrix: [0; 8],
+            releases: Vec::with_capacity(8),
         }
     }
 
     pub fn key_down(&mut self, ck: u8) {
-        if !self.last_keys.contains(&ck) {
-            self.last_keys.push(ck);
-        }
+        self.matrix[(ck >> 3) as usize & 7] |= 1 << (ck & 7);
+        self.releases.retain(|(key, _)| *key != ck);
     }
 
     pub fn key_up(&mut self, ck: u8) {
-        if let Some(idx) = self.last_keys.iter().position(|x| *x == ck) {
-            self.last_keys.remove(idx);
+        self.matrix[(ck >> 3) as usize & 7] &= !(1 << (ck & 7));
+        self.releases.retain(|(key, _)| *key != ck);
+    }
+
+    /// Presses the key and releases it at given cycle
+    pub fn key_down_until(&mut self, ck: u8, release_at: Cycles) {
+        self.key_down(ck);
+        self.releases.push((ck, release_at));
+    }
+
+    /// Releases the keys pressed with `key_down_until` that are due
+    pub fn release_keys(&mut self, cycles: Cycles) {
+        let (due, pending) = self.releases.iter().partition(|(_, at)| *at <= cycles);
+        self.releases = pending;
+        for (key, _) in due as Vec<(u8, Cycles)> {
+            self.matrix[(key >> 3) as usize & 7] &= !(1 << (key & 7));
         }
     }
 
-    fn is_column_scan(&self, c: u8) -> bool {
-        c == 0xfe
-            || c == 0xfd
-            || c == 0xfb
-            || c == 0xf7
-            || c == 0xef
-            || c == 0xdf
-            || c == 0xbf
-            || c == 0x7f
+    /// Releases all the keys (also the ones to be released automatically)
+    pub fn release_all(&mut self) {
+        self.matrix = [0; 8];
+        self.releases.clear();
     }
 
-    fn get_row_code(&self, ch: u8, col: u8) -> u8 {
-        C64_SCAN_CODES
-            .iter()
-            .find(|sc| sc.key as u8 == ch && sc.col == col)
-            .map_or(0xff, |sc| sc.row)
+    /// The earliest cycle a key is to be released at automatically
+    pub fn next_release(&self) -> Option<Cycles> {
+        self.releases.iter().map(|(_, at)| *at).min()
     }
 
-    pub fn scan(&mut self, dc00: u8, dc01: u8) -> u8 {
-        if self.last_keys.is_empty() {
-            return 0xff;
+    /// Saves the pressed keys with their release cycles (see `C64::save_snapshot`)
+    pub fn save(&self, w: &mut StateWriter) {
+        w.bytes(&self.matrix).u8(self.releases.len() as u8);
+        for (key, at) in self.releases.iter() {
+            w.u8(*key).u64(*at);
         }
+    }
 
-        if dc00 == 0 {
-            self.cycle = 8;
-            return 0;
-        };
-        if !self.is_column_scan(dc00) {
-            return dc01;
-        };
-        let val = if self.last_keys.len() < 2 {
-            self.get_row_code(self.last_keys[0], dc00)
-        } else {
-            self.last_keys // when more than one key pressed at the same time
-                .iter()
-                .map(|x| self.get_row_code(*x, dc00))
-                .reduce(|x, y| x & y)
-                .unwrap()
-        };
-        if self.cycle > 0 {
-            self.cycle -= 1;
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.matrix = r.array()?;
+        let count = r.u8()?;
+        self.releases.clear();
+        for _ in 0..count {
+            self.releases.push((r.u8()?, r.u64()?));
         }
-        if self.cycle == 0 {
-            self.last_keys.remove(0);
-            // self.cycle = 3;
+        Ok(())
+    }
+
+    pub fn is_pressed(&self, ck: u8) -> bool {
+        self.matrix[(ck >> 3) as usize & 7] & (1 << (ck & 7)) > 0
+    }
+
+    pub fn pressed_keys(&self) -> Vec<u8> {
+        (0..64).filter(|ck| self.is_pressed(*ck)).collect()
+    }
+
+    /// Finds which lines are pulled low, given the columns and rows driven low
+    /// by CIA1 (or joysticks). The signal goes through pressed keys in both
+    /// directions, so it can also flow back through other keys. This is what
+    /// causes ghosting: when three corners of a rectangle are pressed,
+    /// the fourth one is seen as pressed as well.
+    /// Returns (columns, rows) - 1 means the line is low.
+    pub fn scan(&self, columns: u8, rows: u8) -> (u8, u8) {
+        let (mut columns, mut rows) = (columns, rows);
+        loop {
+            let new_rows = (0..8)
+                .filter(|col| columns & (1 << col) > 0)
+                .fold(rows, |acc, col| acc | self.matrix[col]);
+            let new_columns = (0..8)
+                .filter(|col| self.matrix[*col] & new_rows > 0)
+                .fold(columns, |acc, col| acc | (1 << col));
+            if new_rows == rows && new_columns == columns {
+                return (columns, rows);
+            }
+            (columns, rows) = (new_columns, new_rows);
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_scan_and_ghosting() {
+        let mut kb = Keyboard::new();
+        kb.key_down(0x0a); // A: column 1, row 2
+        assert_eq!(kb.scan(0b10, 0), (0b10, 0b100));
+        assert_eq!(kb.scan(0, 0b100), (0b10, 0b100));
+        assert_eq!(kb.scan(0b01, 0), (0b01, 0));
+
+        // CTRL (7, 2) and Q (7, 6) make ghost key E (1, 6)
+        kb.key_down(0x3a);
+        kb.key_down(0x3e);
+        assert_eq!(kb.scan(0b10, 0).1, 0b0100_0100);
+
+        kb.key_up(0x3a);
+        assert_eq!(kb.scan(0b10, 0).1, 0b100);
+    }
 
-        val
+    #[test]
+    fn test_synthetic_release() {
+        let mut kb = Keyboard::new();
+        kb.key_down_until(0x01, 100);
+        kb.release_keys(99);
+        assert!(kb.is_pressed(0x01));
+        kb.release_keys(100);
+        assert!(!kb.is_pressed(0x01));
     }
 }
@@ -1,19 +1,105 @@
 use super::{
     cia::{CIA1, CIA2},
-    io::C64IO,
+    io::{IODispatcher, IOExpansion, IOSlot},
+    sid::SID,
+    vic_bus::VicBus,
+    vic_ii::VIC_II,
 };
 use machine::{
     emulator::{
-        abstractions::{Accessor, AddressResolver, Addressable, ArrayMemory, Device},
-        components::PLA_82S100,
+        abstractions::{
+            Accessor, AddressResolver, Addressable, ArrayMemory, BusAccess, Device, DeviceTrait,
+            SharedDevice, StateReader, StateWriter,
+        },
+        components::{MissingDevicePolicy, PLA_82S100},
+        EmulatorError,
     },
+    utils::XorShift,
     Addr, Memory,
 };
+use std::sync::{Arc, Mutex};
 
 // TODO consider better way of initializing the memory
 // see this: https://www.reddit.com/r/rust/comments/jzwwqb/about_creating_a_boxed_slice/
 // and this: https://www.reddit.com/r/rust/comments/c4zdue/newbie_question_array_in_a_struct/
 
+/// ROM sockets (and cartridge's ROMs) seen by the PLA
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RomSlot {
+    Basic,
+    Kernal,
+    Chargen,
+    /// ROML of a cartridge ($8000)
+    CartridgeLo,
+    /// ROMH of a cartridge ($A000, or $E000 in Ultimax mode)
+    CartridgeHi,
+}
+
+/// Chips that can be taken out of the machine (i.e. to test diagnostic cartridges)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chip {
+    CIA1,
+    CIA2,
+    SID,
+    Rom(RomSlot),
+}
+
+/// All the slots, in the order of `RomSlot` values
+const ROM_SLOTS: [RomSlot; 5] = [
+    RomSlot::Basic,
+    RomSlot::Kernal,
+    RomSlot::Chargen,
+    RomSlot::CartridgeLo,
+    RomSlot::CartridgeHi,
+];
+
+impl RomSlot {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "basic" => Some(RomSlot::Basic),
+            "kernal" => Some(RomSlot::Kernal),
+            "chargen" => Some(RomSlot::Chargen),
+            "cart-lo" => Some(RomSlot::CartridgeLo),
+            "cart-hi" => Some(RomSlot::CartridgeHi),
+            _ => None,
+        }
+    }
+}
+
+impl Chip {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cia1" => Some(Chip::CIA1),
+            "cia2" => Some(Chip::CIA2),
+            "sid" => Some(Chip::SID),
+            _ => RomSlot::from_name(name).map(Chip::Rom),
+        }
+    }
+}
+
+/// Content of RAM after power-on
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RamPattern {
+    /// Stripes of $00 and $FF bytes (64 bytes each), as seen on most C64s
+    #[default]
+    Stripes,
+    Zero,
+    /// Pseudo-random values (the same for the same seed)
+    Random(u64),
+}
+
+impl RamPattern {
+    /// Names: stripes, zero, random (random without a seed gives seed 0)
+    pub fn from_name(name: &str, seed: Option<u64>) -> Option<Self> {
+        match name {
+            "stripes" => Some(RamPattern::Stripes),
+            "zero" => Some(RamPattern::Zero),
+            "random" => Some(RamPattern::Random(seed.unwrap_or(0))),
+            _ => None,
+        }
+    }
+}
+
 /// Emulation of C64 memory.
 /// C64 uses 16-bit addressing, but it provides more memory than can be addressed with u16:
 /// 64kB RAM, ~20kB ROM, plus - optionally - extra ROM when a cartrige is used.
@@ -27,32 +113,213 @@ use machine::{
 /// The emulator provides 64kB of RAM and 64kB of ROM, but no extra memory for
 /// cartridges - it simply overrides ROM for cartridges (TBC whether such simplification
 /// is sufficient).
-
-// pub struct C64Memory {
-//     ram: Box<[u8]>,
-//     rom: Box<[u8]>,
-// }
-//
 pub struct C64Memory {
     pla: PLA_82S100,
-    ram: Device<ArrayMemory>
+    ram: Device<ArrayMemory>,
+    io: Device<IODispatcher>,
+    vic_bus: Arc<Mutex<VicBus>>,
+    /// Images of the ROMs in the slots (indexed by `RomSlot`)
+    roms: [Option<Vec<u8>>; 5],
 }
 impl C64Memory {
-    pub fn new(cia1: &Device<CIA1>, cia2: &Device<CIA2>) -> Self {
+    pub fn new(
+        vic: &Device<VIC_II>,
+        cia1: &Device<CIA1>,
+        cia2: &Device<CIA2>,
+        sid: &Device<SID>,
+    ) -> Self {
         let mut pla = PLA_82S100::default();
-        let ram = Device::from(ArrayMemory::new(0xffff, 16));
+        let ram = Device::from(ArrayMemory::new(0x10000, 16));
         pla.link_ram(ram.mutex());
 
-        let io = Device::from(C64IO {
-            ram: ram.mutex(),
-            cia1: cia1.mutex(),
-            cia2: cia2.mutex(),
-        });
+        let io = Device::from(IODispatcher::new(
+            vic.mutex(),
+            sid.mutex(),
+            cia1.mutex(),
+            cia2.mutex(),
+            ram.mutex(),
+        ));
 
         // FIXME careful - there is hardcoded address inside the PLA
         pla.link_io(io.mutex());
 
-        C64Memory { pla, ram }
+        let vic_bus = Arc::new(Mutex::new(VicBus::new(vic.mutex(), cia2.mutex(), ram.mutex())));
+        pla.link_open_bus(vic_bus.clone());
+        io.lock().link_open_bus(vic_bus.clone());
+
+        C64Memory {
+            pla,
+            ram,
+            io,
+            vic_bus,
+            roms: Default::default(),
+        }
+    }
+
+    /// Puts the ROM into the slot (replacing the current one)
+    pub fn load_rom(&mut self, slot: RomSlot, data: &[u8]) {
+        let rom = Device::from(ArrayMemory::from_data(data, 16)).mutex();
+        self.roms[slot as usize] = Some(data.to_vec());
+        match slot {
+            RomSlot::Basic => self.pla.link_basic(rom),
+            RomSlot::Kernal => self.pla.link_kernal(rom),
+            RomSlot::Chargen => {
+                self.vic_bus.lock().unwrap().set_chargen(Some(rom.clone()));
+                self.pla.link_chargen(rom)
+            }
+            RomSlot::CartridgeLo => self.pla.link_cartridge(rom),
+            RomSlot::CartridgeHi => self.pla.link_cartridge_hi(rom),
+        };
+    }
+
+    pub fn remove_chip(&mut self, chip: Chip) {
+        match chip {
+            Chip::CIA1 => self.io.lock().remove_cia1(),
+            Chip::CIA2 => {
+                self.io.lock().remove_cia2();
+                self.vic_bus.lock().unwrap().remove_cia2();
+            }
+            Chip::SID => self.io.lock().remove_sid(),
+            Chip::Rom(slot) => {
+                self.roms[slot as usize] = None;
+                match slot {
+                    RomSlot::Basic => self.pla.unlink_basic(),
+                    RomSlot::Kernal => self.pla.unlink_kernal(),
+                    RomSlot::Chargen => {
+                        self.vic_bus.lock().unwrap().set_chargen(None);
+                        self.pla.unlink_chargen()
+                    }
+                    RomSlot::CartridgeLo => self.pla.unlink_cartridge(),
+                    RomSlot::CartridgeHi => self.pla.unlink_cartridge_hi(),
+                };
+            }
+        }
+    }
+
+    /// What is read from removed chips (or ROMs)
+    pub fn set_missing_device_policy(&mut self, policy: MissingDevicePolicy) {
+        self.pla.set_missing_device_policy(policy);
+        self.io.lock().set_missing_device_policy(policy);
+    }
+
+    /// Whether reading the address returns the value floating on the data bus
+    /// (the value depends on the VIC-II cycle, so it must be in sync)
+    pub fn reads_open_bus(&self, addr: Addr) -> bool {
+        self.pla.reads_open_bus(addr)
+    }
+
+    /// Starts recording the accesses of the CPU to the bus (see `take_bus_log`)
+    pub fn start_bus_log(&mut self) {
+        self.pla.start_bus_log();
+    }
+
+    /// Accesses since `start_bus_log`, with the chips serving the I/O area
+    pub fn take_bus_log(&mut self) -> Vec<BusAccess> {
+        let mut log = self.pla.take_bus_log();
+        let io = self.io.lock();
+        for access in log.iter_mut().filter(|access| access.device == "I/O") {
+            access.device = io.device_name(access.addr).to_string();
+        }
+        log
+    }
+
+    /// Whether the address is served by a ROM (as mapped at the moment)
+    pub fn is_rom(&self, addr: Addr) -> bool {
+        self.pla.is_rom(addr)
+    }
+
+    /// Plugs expansion device into IO1 or IO2 page (None unplugs it)
+    pub fn claim_io(&mut self, slot: IOSlot, device: Option<IOExpansion>) {
+        self.io.lock().claim(slot, device);
+    }
+
+    /// Devices of the memory subsystem shared with the rest of the machine.
+    /// The PLA is owned by the memory, as it's on the path of every access
+    /// of the CPU (see `pla`); ROMs are skipped, as they have no state.
+    pub fn devices(&self) -> Vec<SharedDevice> {
+        vec![self.ram.mutex(), self.io.mutex()]
+    }
+
+    pub fn pla(&self) -> &PLA_82S100 {
+        &self.pla
+    }
+
+    pub fn pla_mut(&mut self) -> &mut PLA_82S100 {
+        &mut self.pla
+    }
+
+    /// Content of the whole RAM (also the parts hidden by ROMs and I/O)
+    pub fn ram(&self) -> Vec<u8> {
+        let ram = self.ram.lock();
+        (0..ram.len()).map(|addr| ram.read_byte(addr as Addr)).collect()
+    }
+
+    pub fn set_ram(&mut self, data: &[u8]) {
+        let mut ram = self.ram.lock();
+        for (addr, val) in data.iter().enumerate().take(ram.len()) {
+            ram.write_byte(addr as Addr, *val);
+        }
+    }
+
+    /// Colour RAM (the lower nibbles only)
+    pub fn colour_ram(&self) -> Vec<u8> {
+        self.io.lock().colour_ram().to_vec()
+    }
+
+    pub fn set_colour_ram(&mut self, data: &[u8]) {
+        let mut io = self.io.lock();
+        for (cell, val) in io.colour_ram_mut().iter_mut().zip(data) {
+            *cell = val & 0x0f;
+        }
+    }
+
+    /// Base address of the 16kB bank seen by VIC-II
+    pub fn vic_bank(&self) -> Addr {
+        self.vic_bus.lock().unwrap().bank()
+    }
+
+    /// Saves the ROMs in the slots (so a snapshot restores the same media)
+    pub fn save_media(&self, w: &mut StateWriter) {
+        for rom in self.roms.iter() {
+            match rom {
+                Some(data) => w.bool(true).u32(data.len() as u32).bytes(data),
+                None => w.bool(false),
+            };
+        }
+    }
+
+    /// Puts the saved ROMs into the slots (the empty slots are emptied)
+    pub fn load_media(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        for slot in ROM_SLOTS {
+            if r.bool()? {
+                let len = r.u32()? as usize;
+                let data = r.bytes(len)?;
+                self.load_rom(slot, data);
+            } else {
+                self.remove_chip(Chip::Rom(slot));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the whole RAM (also the parts hidden by ROMs and I/O)
+    /// and resets the processor port
+    pub fn power_on(&mut self, pattern: RamPattern) {
+        let mut rng = XorShift::new(match pattern {
+            RamPattern::Random(seed) => seed,
+            _ => 0,
+        });
+        let mut ram = self.ram.lock();
+        for addr in 0..ram.len() {
+            let value = match pattern {
+                RamPattern::Stripes if addr & 0x40 > 0 => 0xff,
+                RamPattern::Random(_) => rng.next_u8(),
+                _ => 0,
+            };
+            ram.write_byte(addr as Addr, value);
+        }
+        drop(ram);
+        self.pla.hard_reset();
     }
 }
 
@@ -68,22 +335,18 @@ impl Memory for C64Memory {
         let len = data.len();
         if len == 16384 {
             // the size of original rom
-            self.pla
-                .link_basic(Device::from(ArrayMemory::from_data(&data[..8192], 16)).mutex());
-            self.pla
-                .link_kernal(Device::from(ArrayMemory::from_data(&data[8192..], 16)).mutex());
+            self.load_rom(RomSlot::Basic, &data[..8192]);
+            self.load_rom(RomSlot::Kernal, &data[8192..]);
         } else {
             // custom rom
             // let addr: usize = 0x10000 - len;
             //self.init_rom_at_addr(addr as u16, data);
-            self.pla
-                .link_kernal(Device::from(ArrayMemory::from_data(&data, 16)).mutex());
+            self.load_rom(RomSlot::Kernal, data);
         }
     }
 
     fn init_rom_at_addr(&mut self, _addr: Addr, data: &[u8]) {
-        self.pla
-            .link_chargen(Device::from(ArrayMemory::from_data(&data, 16)).mutex());
+        self.load_rom(RomSlot::Chargen, data);
     }
     fn write_byte(&mut self, addr: Addr, val: u8) {
         self.pla.write_byte(addr, val);
@@ -1,8 +1,19 @@
 mod c64;
 mod cia;
+mod control_port;
+mod cpu;
+mod input_script;
+mod joystick;
 mod keyboard;
 mod memory;
+mod model;
+mod mouse_1351;
+mod paddles;
+mod type_in;
+mod vic_bus;
 mod vic_ii;
 mod io;
+mod sid;
+mod vsf;
 
-pub use {c64::C64, cia::*, keyboard::*, memory::C64Memory, vic_ii::VIC_II, io::*};
+pub use {c64::C64, cia::*, control_port::*, cpu::Cpu6510, input_script::*, joystick::*, keyboard::*, memory::{C64Memory, Chip, RamPattern, RomSlot}, model::*, mouse_1351::*, paddles::*, type_in::*, vic_bus::*, vic_ii::{VIC_II, PAL_FRAME_CYCLES}, io::*, sid::*, vsf::{Vsf, VsfModule}};
@@ -0,0 +1,129 @@
+use machine::Cycles;
+
+/// Colours of later VIC II revisions ("Pepto" palette)
+const PALETTE: [u32; 16] = [
+    0x000000, 0xffffff, 0x68372b, 0x70a4b2, 0x6f3d86, 0x588d43, 0x352879, 0xb8c76f, 0x6f4f25,
+    0x433900, 0x9a6759, 0x444444, 0x6c6c6c, 0x9ad284, 0x6c5eb5, 0x959595,
+];
+
+/// Luminance levels (out of 32) of the colours on later VIC II revisions
+const LUMA: [u8; 16] = [0, 32, 10, 20, 12, 16, 8, 24, 12, 8, 16, 10, 15, 24, 15, 20];
+
+/// First VIC II revisions (i.e. 6567R56A) have only five luminance levels
+const FIRST_REVISION_LUMA: [u8; 16] = [0, 32, 8, 24, 16, 16, 8, 24, 16, 8, 16, 16, 16, 24, 16, 24];
+
+/// C64 model, defined by its VIC II chip (which also provides the CPU clock)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MachineModel {
+    /// European C64 with 6569
+    #[default]
+    PAL,
+    /// American C64 with 6567R8
+    NTSC,
+    /// Early American C64 with 6567R56A
+    OldNTSC,
+    /// Argentinian Drean C64 with 6572 (PAL-N)
+    Drean,
+}
+
+impl MachineModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "pal" | "6569" => Some(MachineModel::PAL),
+            "ntsc" | "6567r8" => Some(MachineModel::NTSC),
+            "ntsc-old" | "6567r56a" => Some(MachineModel::OldNTSC),
+            "drean" | "6572" => Some(MachineModel::Drean),
+            _ => None,
+        }
+    }
+
+    /// CPU clock [Hz]
+    pub fn clock_frequency(&self) -> u32 {
+        match self {
+            MachineModel::PAL => 985_248,
+            MachineModel::NTSC | MachineModel::OldNTSC => 1_022_727,
+            MachineModel::Drean => 1_023_440,
+        }
+    }
+
+    pub fn cycles_per_line(&self) -> Cycles {
+        match self {
+            MachineModel::PAL => 63,
+            MachineModel::NTSC | MachineModel::Drean => 65,
+            MachineModel::OldNTSC => 64,
+        }
+    }
+
+    pub fn lines_per_frame(&self) -> Cycles {
+        match self {
+            MachineModel::PAL | MachineModel::Drean => 312,
+            MachineModel::NTSC => 263,
+            MachineModel::OldNTSC => 262,
+        }
+    }
+
+    pub fn frame_cycles(&self) -> Cycles {
+        self.cycles_per_line() * self.lines_per_frame()
+    }
+
+    /// Raster line scanned at given cycle
+    pub fn raster_line(&self, cycles: Cycles) -> u16 {
+        (cycles / self.cycles_per_line() % self.lines_per_frame()) as u16
+    }
+
+    /// Frequency of the power line, that drives TOD clocks [Hz]
+    pub fn power_line_hz(&self) -> u32 {
+        match self {
+            MachineModel::PAL | MachineModel::Drean => 50,
+            MachineModel::NTSC | MachineModel::OldNTSC => 60,
+        }
+    }
+
+    /// CIA1 timer value set by KERNAL for 1/60s interrupt
+    pub fn jiffy_timer(&self) -> u16 {
+        match self {
+            MachineModel::PAL | MachineModel::Drean => 0x4025,
+            MachineModel::NTSC | MachineModel::OldNTSC => 0x4295,
+        }
+    }
+
+    /// RGB values of the 16 colours. The palette is the same for all
+    /// video standards, but the first VIC II revision differs in luminance
+    /// (approximated by scaling the luma of later revisions).
+    pub fn palette(&self) -> [u32; 16] {
+        if *self != MachineModel::OldNTSC {
+            return PALETTE;
+        }
+        let mut palette = PALETTE;
+        for (i, rgb) in palette.iter_mut().enumerate() {
+            if LUMA[i] > 0 {
+                *rgb = scale_luma(*rgb, FIRST_REVISION_LUMA[i] as f32 / LUMA[i] as f32);
+            }
+        }
+        palette
+    }
+}
+
+/// Changes luminance of RGB colour, keeping its chrominance (in YUV space)
+fn scale_luma(rgb: u32, factor: f32) -> u32 {
+    let [_, r, g, b] = rgb.to_be_bytes().map(|c| c as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let delta = y * factor - y;
+    let [r, g, b] = [r, g, b].map(|c| (c + delta).round().clamp(0.0, 255.0) as u32);
+    (r << 16) | (g << 8) | b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_models() {
+        assert_eq!(MachineModel::PAL.frame_cycles(), 19656);
+        assert_eq!(MachineModel::NTSC.frame_cycles(), 17095);
+        assert_eq!(MachineModel::PAL.raster_line(63 * 311), 311);
+        assert_eq!(MachineModel::NTSC.raster_line(65 * 263), 0);
+        assert_eq!(MachineModel::OldNTSC.palette()[0], 0);
+        assert_ne!(MachineModel::OldNTSC.palette()[2], PALETTE[2]);
+    }
+}
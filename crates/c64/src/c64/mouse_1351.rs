@@ -0,0 +1,53 @@
+use machine::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+/// Commodore 1351 mouse in proportional mode. It reports its position
+/// (modulo 64) in bits 1-6 of POTX and POTY; software computes the movement
+/// from the difference between consecutive samples. The left button is
+/// wired to the fire line and the right one to the "up" line.
+/// See [1351 at C64 Wiki](https://www.c64-wiki.com/wiki/Mouse_1351)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Mouse1351 {
+    x: u8,
+    y: u8,
+    /// Bit 0 - left button, bit 1 - right button
+    pub buttons: u8,
+}
+
+impl Mouse1351 {
+    /// Moves the mouse by given number of (C64) pixels.
+    /// Positive `dy` moves down the screen.
+    pub fn move_by(&mut self, dx: i16, dy: i16) {
+        self.x = self.x.wrapping_add(dx as u8);
+        // vertical counter grows when moving the mouse up
+        self.y = self.y.wrapping_sub(dy as u8);
+    }
+
+    pub fn port_value(&self) -> u8 {
+        let mut val = 0xff;
+        if self.buttons & 0b01 > 0 {
+            val &= !0b0001_0000;
+        }
+        if self.buttons & 0b10 > 0 {
+            val &= !0b0000_0001;
+        }
+        val
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u8(self.x).u8(self.y).u8(self.buttons);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.x = r.u8()?;
+        self.y = r.u8()?;
+        self.buttons = r.u8()?;
+        Ok(())
+    }
+
+    pub fn pots(&self) -> (u8, u8) {
+        ((self.x & 0x3f) << 1, (self.y & 0x3f) << 1)
+    }
+}
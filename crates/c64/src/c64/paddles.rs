@@ -0,0 +1,31 @@
+/// Pair of paddles connected to one control port. Paddle positions
+/// are read by SID (POTX/POTY), and fire buttons are wired to
+/// lines 2 (paddle X) and 3 (paddle Y) of the CIA1 port.
+/// See [Paddle at C64 Wiki](https://www.c64-wiki.com/wiki/Paddle)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Paddles {
+    pub x: u8,
+    pub y: u8,
+    /// Bit 0 - fire of paddle X, bit 1 - fire of paddle Y
+    pub buttons: u8,
+}
+
+impl Default for Paddles {
+    fn default() -> Self {
+        Paddles {
+            x: 0x80,
+            y: 0x80,
+            buttons: 0,
+        }
+    }
+}
+
+impl Paddles {
+    pub fn port_value(&self) -> u8 {
+        !((self.buttons & 0b11) << 2)
+    }
+
+    pub fn pots(&self) -> (u8, u8) {
+        (self.x, self.y)
+    }
+}
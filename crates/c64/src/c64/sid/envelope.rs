@@ -0,0 +1,170 @@
+use machine::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+/// Number of cycles between envelope counter steps for each of 16 rate settings.
+/// The same periods are used by attack, decay and release - the two latter ones
+/// are additionally slowed down by the exponential counter.
+/// Source: [reSID](https://github.com/libsidplayfp/resid/blob/master/envelope.cc)
+const RATE_PERIODS: [u16; 16] = [
+    9, 32, 63, 95, 149, 220, 267, 313, 392, 977, 1954, 3126, 3907, 11720, 19532, 31251,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum EnvelopeState {
+    Attack,
+    DecaySustain,
+    #[default]
+    Release,
+}
+
+/// ADSR envelope generator of a single SID voice
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    pub attack: u8,
+    pub decay: u8,
+    pub sustain: u8,
+    pub release: u8,
+    pub state: EnvelopeState,
+    counter: u8,
+    rate_counter: u16,
+    exp_counter: u8,
+    gate: bool,
+}
+
+impl Envelope {
+    pub fn set_gate(&mut self, gate: bool) {
+        if gate && !self.gate {
+            self.state = EnvelopeState::Attack;
+        } else if !gate && self.gate {
+            self.state = EnvelopeState::Release;
+        }
+        self.gate = gate;
+    }
+
+    pub fn set_attack_decay(&mut self, val: u8) {
+        self.attack = val >> 4;
+        self.decay = val & 0x0f;
+    }
+
+    pub fn set_sustain_release(&mut self, val: u8) {
+        self.sustain = val >> 4;
+        self.release = val & 0x0f;
+    }
+
+    /// Current value of the envelope (readable as ENV3 for voice 3)
+    pub fn output(&self) -> u8 {
+        self.counter
+    }
+
+    pub fn clock(&mut self) {
+        let rate = match self.state {
+            EnvelopeState::Attack => self.attack,
+            EnvelopeState::DecaySustain => self.decay,
+            EnvelopeState::Release => self.release,
+        };
+
+        self.rate_counter += 1;
+        if self.rate_counter < RATE_PERIODS[rate as usize] {
+            return;
+        }
+        self.rate_counter = 0;
+
+        if self.state == EnvelopeState::Attack {
+            self.counter = self.counter.saturating_add(1);
+            if self.counter == 0xff {
+                self.state = EnvelopeState::DecaySustain;
+            }
+            return;
+        }
+
+        self.exp_counter += 1;
+        if self.exp_counter < self.exp_period() {
+            return;
+        }
+        self.exp_counter = 0;
+
+        match self.state {
+            EnvelopeState::DecaySustain => {
+                if self.counter != self.sustain * 0x11 {
+                    self.counter = self.counter.saturating_sub(1);
+                }
+            }
+            EnvelopeState::Release => self.counter = self.counter.saturating_sub(1),
+            EnvelopeState::Attack => {}
+        }
+    }
+
+    /// Emulates the piece-wise exponential decay and release of the real chip
+    fn exp_period(&self) -> u8 {
+        match self.counter {
+            0x5e..=0xff => 1,
+            0x37..=0x5d => 2,
+            0x1b..=0x36 => 4,
+            0x0f..=0x1a => 8,
+            0x07..=0x0e => 16,
+            0x01..=0x06 => 30,
+            0x00 => 1,
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u8(self.attack)
+            .u8(self.decay)
+            .u8(self.sustain)
+            .u8(self.release)
+            .u8(self.state as u8)
+            .u8(self.counter)
+            .u16(self.rate_counter)
+            .u8(self.exp_counter)
+            .bool(self.gate);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.attack = r.u8()? & 0x0f;
+        self.decay = r.u8()? & 0x0f;
+        self.sustain = r.u8()? & 0x0f;
+        self.release = r.u8()? & 0x0f;
+        self.state = match r.u8()? {
+            0 => EnvelopeState::Attack,
+            1 => EnvelopeState::DecaySustain,
+            _ => EnvelopeState::Release,
+        };
+        self.counter = r.u8()?;
+        self.rate_counter = r.u16()?;
+        self.exp_counter = r.u8()?;
+        self.gate = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attack_decay_sustain_release() {
+        let mut env = Envelope::default();
+        env.set_attack_decay(0x00);
+        env.set_sustain_release(0x80);
+
+        env.set_gate(true);
+        for _ in 0..(9 * 255) {
+            env.clock();
+        }
+        assert_eq!(0xff, env.output());
+        assert_eq!(EnvelopeState::DecaySustain, env.state);
+
+        for _ in 0..10_000 {
+            env.clock();
+        }
+        assert_eq!(0x88, env.output());
+
+        env.set_gate(false);
+        for _ in 0..100_000 {
+            env.clock();
+        }
+        assert_eq!(0, env.output());
+    }
+}
@@ -0,0 +1,142 @@
+use super::SIDModel;
+use machine::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+use std::f32::consts::PI;
+
+/// Multimode (low-, band-, high-pass) filter of the SID, emulated
+/// as a digital state variable filter running at the output sample rate.
+/// The cutoff curves are rough approximations of the measured ones:
+/// the 8580's curve is linear, while the 6581's one is steeper,
+/// starts higher and saturates earlier.
+/// See [Chamberlin's SVF](https://www.musicdsp.org/en/latest/Filters/23-state-variable.html)
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub cutoff: u16,
+    pub resonance: u8,
+    pub routing: u8,
+    pub mode: u8,
+    low: f32,
+    band: f32,
+}
+
+// mode bits (upper nibble of $D418)
+const LOW_PASS: u8 = 0x1;
+const BAND_PASS: u8 = 0x2;
+const HIGH_PASS: u8 = 0x4;
+
+impl Filter {
+    /// Checks whether voice (0-2) is routed through the filter
+    pub fn is_routed(&self, voice: usize) -> bool {
+        self.routing & (1 << voice) > 0
+    }
+
+    pub fn cutoff_frequency(&self, model: SIDModel) -> f32 {
+        let fc = self.cutoff as f32 / 2047.0;
+        match model {
+            SIDModel::MOS6581 => 220.0 + 18000.0 * fc.powf(1.6),
+            SIDModel::MOS8580 => 30.0 + 12000.0 * fc,
+        }
+    }
+
+    /// Filters single sample. `input` is the sum of all routed voices.
+    pub fn process(&mut self, input: f32, model: SIDModel, sample_rate: u32) -> f32 {
+        if self.mode & 0x7 == 0 {
+            return 0.0;
+        }
+
+        let fc = self.cutoff_frequency(model).min(sample_rate as f32 / 4.0);
+        let f = 2.0 * (PI * fc / sample_rate as f32).sin();
+        let damping = 1.0 / (0.707 + self.resonance as f32 / 15.0 * 1.5);
+
+        let high = input - self.low - damping * self.band;
+        self.band += f * high;
+        self.low += f * self.band;
+
+        let mut out = 0.0;
+        if self.mode & LOW_PASS > 0 {
+            out += self.low;
+        }
+        if self.mode & BAND_PASS > 0 {
+            out += self.band;
+        }
+        if self.mode & HIGH_PASS > 0 {
+            out += high;
+        }
+        out
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u16(self.cutoff)
+            .u8(self.resonance)
+            .u8(self.routing)
+            .u8(self.mode)
+            .f32(self.low)
+            .f32(self.band);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.cutoff = r.u16()? & 0x07ff;
+        self.resonance = r.u8()?;
+        self.routing = r.u8()?;
+        self.mode = r.u8()?;
+        self.low = r.f32()?;
+        self.band = r.f32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn settle(filter: &mut Filter, input: f32) -> f32 {
+        let mut out = 0.0;
+        for _ in 0..SAMPLE_RATE {
+            out = filter.process(input, SIDModel::MOS8580, SAMPLE_RATE);
+        }
+        out
+    }
+
+    #[test]
+    fn test_routing() {
+        let filter = Filter {
+            routing: 0x05,
+            ..Default::default()
+        };
+        assert!(filter.is_routed(0));
+        assert!(!filter.is_routed(1));
+        assert!(filter.is_routed(2));
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut filter = Filter {
+            cutoff: 0x100,
+            ..Default::default()
+        };
+        assert_eq!(0.0, settle(&mut filter, 1.0));
+
+        // DC passes through the low-pass only
+        filter.mode = LOW_PASS;
+        assert!((settle(&mut filter, 1.0) - 1.0).abs() < 0.01);
+        filter.mode = BAND_PASS;
+        assert!(settle(&mut filter, 1.0).abs() < 0.01);
+        filter.mode = HIGH_PASS;
+        assert!(settle(&mut filter, 1.0).abs() < 0.01);
+        filter.mode = LOW_PASS | HIGH_PASS;
+        assert!((settle(&mut filter, 1.0) - 1.0).abs() < 0.01);
+
+        // and the high frequencies through the high-pass
+        filter.mode = HIGH_PASS;
+        let mut peak: f32 = 0.0;
+        for i in 0..1000 {
+            let input = if i % 2 == 0 { 1.0 } else { -1.0 };
+            peak = peak.max(filter.process(input, SIDModel::MOS8580, SAMPLE_RATE));
+        }
+        assert!(peak > 0.9);
+    }
+}
@@ -0,0 +1,6 @@
+mod envelope;
+mod filter;
+mod sid;
+mod voice;
+
+pub use {envelope::*, filter::*, sid::*, voice::*};
@@ -0,0 +1,454 @@
+use super::{Filter, Voice};
+use crate::audio::AudioSink;
+use machine::emulator::{
+    abstractions::{
+        Addr, Addressable, CPUCycles, Clocked, DeviceTrait, StateReader, StateWriter,
+    },
+    EmulatorError,
+};
+
+/// PAL CPU clock [Hz]; SID is clocked with the same phi2 signal as the CPU
+pub const PAL_CLOCK: u32 = 985_248;
+
+/// DC offset of the 6581 output stage. It's the reason why writing
+/// to the volume register produces audible clicks (used by "digi" players).
+const DC_OFFSET_6581: f32 = 0.35;
+
+/// Max output of a voice: (waveform - 0x800) * envelope
+const VOICE_RANGE: f32 = (0x800 * 0xff) as f32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum SIDModel {
+    #[default]
+    MOS6581,
+    MOS8580,
+}
+
+impl SIDModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "6581" => Some(SIDModel::MOS6581),
+            "8580" => Some(SIDModel::MOS8580),
+            _ => None,
+        }
+    }
+}
+
+/// Emulation of MOS 6581/8580 Sound Interface Device.
+/// Three voices, the filter and the mixer are clocked with CPU cycles
+/// and (if an audio sink is attached) produce samples at the sink's rate.
+///
+/// Registers (relative to $D400, mirrored every 32 bytes)
+/// $00-$06 Voice 1 (frequency, pulse width, control, AD, SR)
+/// $07-$0D Voice 2
+/// $0E-$14 Voice 3
+/// $15-$16 Filter cutoff (11 bits)
+/// $17     Resonance and filter routing
+/// $18     Filter mode and volume
+/// $19-$1A POTX/POTY (read only)
+/// $1B     OSC3 - upper 8 bits of voice 3 waveform (read only)
+/// $1C     ENV3 - voice 3 envelope (read only)
+///
+/// See [SID at C64Wiki](https://www.c64-wiki.com/wiki/SID) and
+/// [MOS 6581 datasheet](http://archive.6502.org/datasheets/mos_6581_sid.pdf)
+pub struct SID {
+    model: SIDModel,
+    voices: [Voice; 3],
+    filter: Filter,
+    volume: u8,
+    registers: [u8; 0x20],
+    pot_x: u8,
+    pot_y: u8,
+    // last value written to the chip; returned when reading write-only registers
+    bus_value: u8,
+    clock_hz: u32,
+    audio: Option<Box<dyn AudioSink>>,
+    sample_clock: u32,
+    filtered_sum: f32,
+    direct_sum: f32,
+    sum_count: u32,
+    dc_block: (f32, f32),
+}
+
+impl SID {
+    pub fn new(model: SIDModel) -> Self {
+        SID {
+            model,
+            voices: Default::default(),
+            filter: Filter::default(),
+            volume: 0,
+            registers: [0; 0x20],
+            pot_x: 0xff,
+            pot_y: 0xff,
+            bus_value: 0,
+            clock_hz: PAL_CLOCK,
+            audio: None,
+            sample_clock: 0,
+            filtered_sum: 0.0,
+            direct_sum: 0.0,
+            sum_count: 0,
+            dc_block: (0.0, 0.0),
+        }
+    }
+
+    pub fn model(&self) -> SIDModel {
+        self.model
+    }
+
+    pub fn set_model(&mut self, model: SIDModel) {
+        self.model = model;
+    }
+
+    /// Sets frequency of the phi2 clock [Hz] (it differs between PAL and NTSC machines)
+    pub fn set_clock(&mut self, hz: u32) {
+        self.clock_hz = hz;
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio = Some(sink);
+    }
+
+    pub fn flush_audio(&mut self) {
+        if let Some(audio) = self.audio.as_mut() {
+            if let Err(e) = audio.flush() {
+                log::error!("Flushing audio output failed: {e}");
+            }
+        }
+    }
+
+    pub fn voice(&self, id: usize) -> &Voice {
+        &self.voices[id]
+    }
+
+    /// Advances the chip by given number of CPU cycles
+    pub fn clock(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.clock_cycle();
+        }
+    }
+
+    fn clock_cycle(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.clock();
+        }
+        // voice 1 is synced by voice 3, voice 2 by voice 1 and voice 3 by voice 2
+        let rising = self.voices.each_ref().map(|v| v.msb_rising());
+        for i in 0..3 {
+            self.voices[i].sync(rising[(i + 2) % 3]);
+        }
+
+        if self.audio.is_none() {
+            return;
+        }
+
+        for i in 0..3 {
+            if i == 2 && !self.filter.is_routed(2) && self.filter.mode & 0x8 > 0 {
+                continue; // 3OFF: voice 3 disconnected from the output
+            }
+            let out = self.voices[i].output(&self.voices[(i + 2) % 3]) as f32 / VOICE_RANGE;
+            if self.filter.is_routed(i) {
+                self.filtered_sum += out;
+            } else {
+                self.direct_sum += out;
+            }
+        }
+        self.sum_count += 1;
+
+        let rate = self.audio.as_ref().map_or(0, |a| a.sample_rate());
+        self.sample_clock += rate;
+        if self.sample_clock >= self.clock_hz {
+            self.sample_clock -= self.clock_hz;
+            self.produce_sample(rate);
+        }
+    }
+
+    fn produce_sample(&mut self, rate: u32) {
+        let n = self.sum_count.max(1) as f32;
+        let filtered = self.filter.process(self.filtered_sum / n, self.model, rate);
+        let direct = self.direct_sum / n;
+        self.filtered_sum = 0.0;
+        self.direct_sum = 0.0;
+        self.sum_count = 0;
+
+        let dc = if self.model == SIDModel::MOS6581 {
+            DC_OFFSET_6581
+        } else {
+            0.0
+        };
+        let mixed = (filtered + direct + dc) / 3.0 * (self.volume as f32 / 15.0);
+
+        // output capacitor of C64 acts as a high-pass filter removing DC
+        let (prev_in, prev_out) = self.dc_block;
+        let out = mixed - prev_in + 0.995 * prev_out;
+        self.dc_block = (mixed, out);
+
+        let sample = (out * 32767.0).clamp(-32768.0, 32767.0) as i16;
+        if let Some(audio) = self.audio.as_mut() {
+            if let Err(e) = audio.write_sample(sample) {
+                log::error!("Writing audio sample failed: {e}");
+                self.audio = None;
+            }
+        }
+    }
+
+    pub fn set_pots(&mut self, x: u8, y: u8) {
+        self.pot_x = x;
+        self.pot_y = y;
+    }
+}
+
+impl Addressable for SID {
+    fn read_byte(&self, addr: Addr) -> u8 {
+        match addr & 0x1f {
+            0x19 => self.pot_x,
+            0x1a => self.pot_y,
+            0x1b => (self.voices[2].waveform(&self.voices[1]) >> 4) as u8,
+            0x1c => self.voices[2].envelope.output(),
+            _ => self.bus_value,
+        }
+    }
+
+    fn write_byte(&mut self, addr: Addr, value: u8) {
+        let reg = (addr & 0x1f) as u8;
+        self.bus_value = value;
+        self.registers[reg as usize] = value;
+        match reg {
+            0x00..=0x14 => self.voices[(reg / 7) as usize].write_register(reg % 7, value),
+            0x15 => self.filter.cutoff = (self.filter.cutoff & 0x7f8) | (value & 0x07) as u16,
+            0x16 => self.filter.cutoff = (self.filter.cutoff & 0x007) | (value as u16) << 3,
+            0x17 => {
+                self.filter.resonance = value >> 4;
+                self.filter.routing = value & 0x0f;
+            }
+            0x18 => {
+                self.filter.mode = value >> 4;
+                self.volume = value & 0x0f;
+            }
+            _ => {}
+        }
+    }
+
+    fn address_width(&self) -> u16 {
+        5
+    }
+}
+
+impl DeviceTrait for SID {
+    fn name(&self) -> &str {
+        "SID"
+    }
+
+    /// RES pin clears all the registers (and silences the voices).
+    /// The model, the clock and the audio output are kept.
+    fn hard_reset(&mut self) {
+        self.voices = Default::default();
+        self.filter = Filter::default();
+        self.volume = 0;
+        self.registers = [0; 0x20];
+        self.bus_value = 0;
+    }
+
+    /// Write-only registers are peeked as last written
+    fn peek_register(&self, offset: Addr) -> Option<u8> {
+        Some(match offset & 0x1f {
+            reg @ 0x19..=0x1c => self.read_byte(reg),
+            reg => self.registers[reg as usize],
+        })
+    }
+
+    /// The model and the audio output are not a part of the state
+    fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&self.registers)
+            .u8(self.volume)
+            .u8(self.pot_x)
+            .u8(self.pot_y)
+            .u8(self.bus_value)
+            .f32(self.dc_block.0)
+            .f32(self.dc_block.1);
+        self.voices.iter().for_each(|voice| voice.save(&mut w));
+        self.filter.save(&mut w);
+        w.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = StateReader::new(state);
+        self.registers = r.array()?;
+        self.volume = r.u8()?;
+        self.pot_x = r.u8()?;
+        self.pot_y = r.u8()?;
+        self.bus_value = r.u8()?;
+        self.dc_block = (r.f32()?, r.f32()?);
+        for voice in self.voices.iter_mut() {
+            voice.load(&mut r)?;
+        }
+        self.filter.load(&mut r)?;
+        r.finish()
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        Some(self)
+    }
+}
+
+impl Clocked for SID {
+    fn tick(&mut self, cycles: CPUCycles) {
+        self.clock(cycles);
+    }
+
+    /// The chip doesn't raise interrupts, so it's enough to sync it before
+    /// it's accessed (and periodically, so the audio keeps flowing)
+    fn next_event(&self) -> Option<CPUCycles> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    struct TestSink(Arc<Mutex<Vec<i16>>>);
+
+    impl AudioSink for TestSink {
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+
+        fn write_sample(&mut self, sample: i16) -> io::Result<()> {
+            self.0.lock().unwrap().push(sample);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// SID with a sawtooth playing on the given voice
+    fn playing_sid(voice: u8) -> (SID, Arc<Mutex<Vec<i16>>>) {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let mut sid = SID::new(SIDModel::MOS8580);
+        sid.set_audio_sink(Box::new(TestSink(samples.clone())));
+        let base = voice * 7;
+        sid.write_byte((base + 1) as Addr, 0x10); // frequency
+        sid.write_byte((base + 6) as Addr, 0xf0); // sustain
+        sid.write_byte((base + 4) as Addr, 0x21); // sawtooth, gate on
+        (sid, samples)
+    }
+
+    /// Whether anything was heard in the next 1/10 s
+    fn is_audible(sid: &mut SID, samples: &Arc<Mutex<Vec<i16>>>) -> bool {
+        samples.lock().unwrap().clear();
+        sid.tick(PAL_CLOCK as u64 / 10);
+        samples.lock().unwrap().iter().any(|&s| s != 0)
+    }
+
+    #[test]
+    fn test_filter_routing() {
+        let (mut sid, samples) = playing_sid(0);
+        sid.write_byte(0x16, 0x10); // cutoff
+        sid.write_byte(0x18, 0x0f); // volume, no filter mode
+        assert!(is_audible(&mut sid, &samples));
+
+        // voice 1 goes through the filter, which has no mode selected
+        sid.write_byte(0x17, 0xf1);
+        is_audible(&mut sid, &samples); // the output capacitor discharges
+        assert!(!is_audible(&mut sid, &samples));
+
+        sid.write_byte(0x18, 0x1f); // low-pass
+        assert!(is_audible(&mut sid, &samples));
+
+        // voice 2 is routed, voice 1 goes directly to the output
+        sid.write_byte(0x17, 0xf2);
+        sid.write_byte(0x18, 0x0f);
+        assert!(is_audible(&mut sid, &samples));
+    }
+
+    #[test]
+    fn test_voice_3_off() {
+        let (mut sid, samples) = playing_sid(2);
+        sid.write_byte(0x18, 0x0f);
+        assert!(is_audible(&mut sid, &samples));
+
+        sid.write_byte(0x18, 0x8f); // 3OFF
+        is_audible(&mut sid, &samples); // the output capacitor discharges
+        assert!(!is_audible(&mut sid, &samples));
+
+        // 3OFF doesn't disconnect voice 3 routed through the filter
+        sid.write_byte(0x17, 0x04);
+        sid.write_byte(0x18, 0x9f);
+        assert!(is_audible(&mut sid, &samples));
+
+        // voice 3 still runs, so it can be read back (i.e. as a random generator)
+        assert_ne!(0, sid.read_byte(0x1b));
+    }
+
+    #[test]
+    fn test_voice_3_synced_by_voice_2() {
+        let mut sid = SID::new(SIDModel::MOS8580);
+        sid.write_byte(0x08, 0x80); // voice 2 MSB goes up every 256 cycles
+        sid.write_byte(0x0f, 0x10);
+        sid.write_byte(0x12, 0x22); // sawtooth, sync
+        sid.tick(0xff);
+        assert_eq!(0x0f, sid.read_byte(0x1b));
+        sid.tick(1);
+        assert_eq!(0, sid.read_byte(0x1b));
+
+        sid.write_byte(0x12, 0x20);
+        sid.tick(0x100);
+        assert_eq!(0x10, sid.read_byte(0x1b));
+    }
+
+    #[test]
+    fn test_read_only_registers() {
+        let mut sid = SID::new(SIDModel::MOS6581);
+        assert_eq!((0xff, 0xff), (sid.read_byte(0x19), sid.read_byte(0x1a)));
+        sid.set_pots(0x12, 0x34);
+        assert_eq!((0x12, 0x34), (sid.read_byte(0x19), sid.read_byte(0x1a)));
+        assert_eq!(0x12, sid.read_byte(0x39)); // mirror
+
+        // OSC3: upper 8 bits of the waveform, ENV3: the envelope
+        sid.write_byte(0x0f, 0x10);
+        sid.write_byte(0x13, 0x00); // fastest attack
+        sid.write_byte(0x14, 0xf0);
+        sid.write_byte(0x12, 0x21);
+        sid.tick(0x100);
+        assert_eq!(0x10, sid.read_byte(0x1b));
+        sid.tick(9 * 255);
+        assert_eq!(0xff, sid.read_byte(0x1c));
+
+        sid.write_byte(0x12, 0x41); // pulse with zero width is high
+        assert_eq!(0xff, sid.read_byte(0x1b));
+        sid.write_byte(0x12, 0x00);
+        assert_eq!(0, sid.read_byte(0x1b));
+        assert_eq!(0xff, sid.read_byte(0x1c)); // still releasing
+
+        // write-only registers read as the last value on the bus
+        assert_eq!(0x00, sid.read_byte(0x00));
+        sid.write_byte(0x05, 0xab);
+        assert_eq!(0xab, sid.read_byte(0x0f));
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut sid = SID::new(SIDModel::MOS8580);
+        sid.write_byte(0x0f, 0x25); // voice 3 frequency
+        sid.write_byte(0x14, 0xf0); // sustain
+        sid.write_byte(0x12, 0x21); // sawtooth, gate on
+        sid.write_byte(0x18, 0x1f); // volume, low-pass
+        sid.tick(1000);
+        let state = sid.save_state();
+
+        let mut loaded = SID::new(SIDModel::MOS8580);
+        loaded.load_state(&state).unwrap();
+        assert_eq!(loaded.save_state(), state);
+        sid.tick(100);
+        loaded.tick(100);
+        assert_eq!(loaded.read_byte(0x1c), sid.read_byte(0x1c));
+        assert!(loaded.load_state(&state[1..]).is_err());
+    }
+}
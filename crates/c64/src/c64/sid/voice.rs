@@ -0,0 +1,279 @@
+use super::Envelope;
+use machine::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+// control register bits
+const GATE: u8 = 0x01;
+const SYNC: u8 = 0x02;
+const RING_MOD: u8 = 0x04;
+const TEST: u8 = 0x08;
+const TRIANGLE: u8 = 0x10;
+const SAWTOOTH: u8 = 0x20;
+const PULSE: u8 = 0x40;
+const NOISE: u8 = 0x80;
+
+const NOISE_SEED: u32 = 0x7ffff8;
+
+/// A single SID voice: 24-bit phase accumulator (oscillator),
+/// waveform generator and the envelope.
+/// See [SID at C64Wiki](https://www.c64-wiki.com/wiki/SID) and
+/// [Inside the SID](https://www.waitingforfriday.com/?p=661)
+#[derive(Debug, Clone)]
+pub struct Voice {
+    pub frequency: u16,
+    pub pulse_width: u16,
+    pub control: u8,
+    pub envelope: Envelope,
+    accumulator: u32,
+    noise: u32,
+    msb_rising: bool,
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Voice {
+            frequency: 0,
+            pulse_width: 0,
+            control: 0,
+            envelope: Envelope::default(),
+            accumulator: 0,
+            noise: NOISE_SEED,
+            msb_rising: false,
+        }
+    }
+}
+
+impl Voice {
+    /// Handles writes to the 7 registers of the voice (relative address 0-6)
+    pub fn write_register(&mut self, reg: u8, val: u8) {
+        match reg {
+            0 => self.frequency = (self.frequency & 0xff00) | val as u16,
+            1 => self.frequency = (self.frequency & 0x00ff) | (val as u16) << 8,
+            2 => self.pulse_width = (self.pulse_width & 0x0f00) | val as u16,
+            3 => self.pulse_width = (self.pulse_width & 0x00ff) | ((val & 0x0f) as u16) << 8,
+            4 => self.set_control(val),
+            5 => self.envelope.set_attack_decay(val),
+            6 => self.envelope.set_sustain_release(val),
+            _ => {}
+        }
+    }
+
+    fn set_control(&mut self, val: u8) {
+        if val & TEST > 0 {
+            self.accumulator = 0;
+        } else if self.control & TEST > 0 {
+            // releasing the test bit resets the noise generator
+            self.noise = NOISE_SEED;
+        }
+        self.envelope.set_gate(val & GATE > 0);
+        self.control = val;
+    }
+
+    pub fn clock(&mut self) {
+        self.envelope.clock();
+        if self.control & TEST > 0 {
+            self.msb_rising = false;
+            return;
+        }
+
+        let prev = self.accumulator;
+        self.accumulator = (self.accumulator + self.frequency as u32) & 0xffffff;
+        let rising = !prev & self.accumulator;
+        self.msb_rising = rising & 0x800000 > 0;
+
+        // noise shift register is clocked by bit 19 of the accumulator
+        if rising & 0x080000 > 0 {
+            let bit = ((self.noise >> 22) ^ (self.noise >> 17)) & 1;
+            self.noise = ((self.noise << 1) | bit) & 0x7fffff;
+        }
+    }
+
+    /// Whether MSB of the accumulator went up in the last cycle
+    pub fn msb_rising(&self) -> bool {
+        self.msb_rising
+    }
+
+    /// Hard sync: resets the accumulator when the sync source's MSB goes up
+    pub fn sync(&mut self, source_msb_rising: bool) {
+        if self.control & SYNC > 0 && source_msb_rising {
+            self.accumulator = 0;
+        }
+    }
+
+    /// 12-bit output of the waveform generator.
+    /// Combined waveforms are approximated with bitwise AND of the selected
+    /// waveforms, which is close enough for most of tunes.
+    pub fn waveform(&self, ring_source: &Voice) -> u16 {
+        let mut out = 0xfffu16;
+        let wave = self.control & 0xf0;
+        if wave == 0 {
+            return 0;
+        }
+        if wave & TRIANGLE > 0 {
+            out &= self.triangle(ring_source);
+        }
+        if wave & SAWTOOTH > 0 {
+            out &= (self.accumulator >> 12) as u16;
+        }
+        if wave & PULSE > 0 {
+            let high = self.control & TEST > 0 || (self.accumulator >> 12) as u16 >= self.pulse_width;
+            out &= if high { 0xfff } else { 0 };
+        }
+        if wave & NOISE > 0 {
+            out &= self.noise_output();
+        }
+        out
+    }
+
+    /// Output of the waveform mixed with the envelope (signed, ~20 bits)
+    pub fn output(&self, ring_source: &Voice) -> i32 {
+        (self.waveform(ring_source) as i32 - 0x800) * self.envelope.output() as i32
+    }
+
+    fn triangle(&self, ring_source: &Voice) -> u16 {
+        let msb = if self.control & RING_MOD > 0 {
+            (self.accumulator ^ ring_source.accumulator) & 0x800000
+        } else {
+            self.accumulator & 0x800000
+        };
+        let acc = if msb > 0 {
+            !self.accumulator
+        } else {
+            self.accumulator
+        };
+        ((acc >> 11) & 0xfff) as u16
+    }
+
+    fn noise_output(&self) -> u16 {
+        let n = self.noise;
+        (((n >> 9) & 0x800)
+            | ((n >> 8) & 0x400)
+            | ((n >> 5) & 0x200)
+            | ((n >> 3) & 0x100)
+            | ((n >> 2) & 0x080)
+            | ((n << 1) & 0x040)
+            | ((n << 3) & 0x020)
+            | ((n << 4) & 0x010)) as u16
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u16(self.frequency)
+            .u16(self.pulse_width)
+            .u8(self.control)
+            .u32(self.accumulator)
+            .u32(self.noise)
+            .bool(self.msb_rising);
+        self.envelope.save(w);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.frequency = r.u16()?;
+        self.pulse_width = r.u16()? & 0x0fff;
+        self.control = r.u8()?;
+        self.accumulator = r.u32()? & 0xffffff;
+        self.noise = r.u32()? & 0x7fffff;
+        self.msb_rising = r.bool()?;
+        self.envelope.load(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(frequency: u16, control: u8, cycles: u32) -> Voice {
+        let mut voice = Voice {
+            frequency,
+            control,
+            ..Default::default()
+        };
+        for _ in 0..cycles {
+            voice.clock();
+        }
+        voice
+    }
+
+    #[test]
+    fn test_waveforms() {
+        let silent = Voice::default();
+        // accumulator is 0x300000 after 256 cycles
+        let mut v = voice(0x3000, SAWTOOTH, 0x100);
+        assert_eq!(0x300, v.waveform(&silent));
+
+        v.control = TRIANGLE;
+        assert_eq!(0x600, v.waveform(&silent));
+
+        v.control = PULSE;
+        v.pulse_width = 0x2ff;
+        assert_eq!(0xfff, v.waveform(&silent));
+        v.pulse_width = 0x301;
+        assert_eq!(0, v.waveform(&silent));
+
+        v.control = 0;
+        assert_eq!(0, v.waveform(&silent));
+
+        // second half of the triangle goes down
+        let v = voice(0x9000, TRIANGLE, 0x100);
+        assert_eq!(0xdff, v.waveform(&silent));
+
+        // noise is shifted when bit 19 of the accumulator goes up
+        let v = voice(0x8000, NOISE, 0);
+        assert_eq!(0xfc0, v.waveform(&silent));
+        let v = voice(0x8000, NOISE, 80);
+        assert_eq!(0xf80, v.waveform(&silent));
+    }
+
+    #[test]
+    fn test_combined_waveforms() {
+        let silent = Voice::default();
+        let mut v = voice(0x3000, SAWTOOTH | TRIANGLE, 0x100);
+        assert_eq!(0x300 & 0x600, v.waveform(&silent));
+
+        v.control = SAWTOOTH | PULSE;
+        v.pulse_width = 0x100;
+        assert_eq!(0x300, v.waveform(&silent));
+        v.pulse_width = 0x400;
+        assert_eq!(0, v.waveform(&silent));
+
+        v.control = SAWTOOTH | NOISE;
+        assert_eq!(0x300 & 0xfc0, v.waveform(&silent));
+    }
+
+    #[test]
+    fn test_ring_modulation() {
+        let low = voice(0x3000, 0, 0x100);
+        let high = voice(0x9000, 0, 0x100);
+
+        // MSB of the ring source inverts the triangle
+        let v = voice(0x3000, TRIANGLE | RING_MOD, 0x100);
+        assert_eq!(0x600, v.waveform(&low));
+        assert_eq!(0x9ff, v.waveform(&high));
+
+        // no effect on other waveforms
+        let v = voice(0x3000, SAWTOOTH | RING_MOD, 0x100);
+        assert_eq!(0x300, v.waveform(&high));
+    }
+
+    #[test]
+    fn test_hard_sync() {
+        let mut source = voice(0x8000, 0, 0xff);
+        assert!(!source.msb_rising());
+        source.clock();
+        assert!(source.msb_rising());
+        source.clock();
+        assert!(!source.msb_rising());
+
+        let silent = Voice::default();
+        let mut v = voice(0x3000, SAWTOOTH | SYNC, 0x100);
+        v.sync(false);
+        assert_eq!(0x300, v.waveform(&silent));
+        v.sync(true);
+        assert_eq!(0, v.waveform(&silent));
+
+        let mut v = voice(0x3000, SAWTOOTH, 0x100);
+        v.sync(true);
+        assert_eq!(0x300, v.waveform(&silent));
+    }
+}
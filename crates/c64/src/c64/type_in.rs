@@ -0,0 +1,126 @@
+use crate::key_utils::{petscii_to_keys, text_to_petscii, C64KeyCode};
+use machine::{
+    emulator::{
+        abstractions::{StateReader, StateWriter},
+        EmulatorError,
+    },
+    Cycles,
+};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeInMode {
+    /// Keys are pressed in the keyboard matrix, as typed by a human
+    Matrix,
+    /// Characters are put directly into KERNAL's keyboard buffer (faster)
+    Buffer,
+}
+
+impl TypeInMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "matrix" => Some(TypeInMode::Matrix),
+            "buffer" => Some(TypeInMode::Buffer),
+            _ => None,
+        }
+    }
+}
+
+/// Queue of text to be typed into the machine. It's fed by C64 on every
+/// instruction, so the timing depends on emulated (not host's) time.
+pub struct TypeIn {
+    pub mode: TypeInMode,
+    queue: VecDeque<u8>,
+    /// Keys currently held by the type-in
+    held: Vec<C64KeyCode>,
+    next_at: Cycles,
+}
+
+impl Default for TypeIn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeIn {
+    pub fn new() -> Self {
+        TypeIn {
+            mode: TypeInMode::Matrix,
+            queue: VecDeque::new(),
+            held: Vec::new(),
+            next_at: 0,
+        }
+    }
+
+    /// Adds the text (see `text_to_petscii`) to the queue
+    pub fn push(&mut self, text: &str) {
+        self.queue.extend(text_to_petscii(text));
+    }
+
+    /// Drops the text that wasn't typed yet (the held keys are forgotten)
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.held.clear();
+        self.next_at = 0;
+    }
+
+    /// Saves the text that wasn't typed yet and the held keys
+    /// (the mode is a part of the configuration)
+    pub fn save(&self, w: &mut StateWriter) {
+        let (front, back) = self.queue.as_slices();
+        w.u32(self.queue.len() as u32).bytes(front).bytes(back);
+        let held: Vec<u8> = self.held.iter().map(|kc| *kc as u8).collect();
+        w.u8(held.len() as u8).bytes(&held).u64(self.next_at);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        let len = r.u32()? as usize;
+        self.queue = r.bytes(len)?.iter().copied().collect();
+        let count = r.u8()? as usize;
+        self.held = r.bytes(count)?.iter().map(|kc| C64KeyCode::from(*kc)).collect();
+        self.next_at = r.u64()?;
+        Ok(())
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.queue.is_empty() || !self.held.is_empty()
+    }
+
+    /// Whether a typed key is held (it's released in the next step)
+    pub fn is_holding(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Whether it's time for the next step
+    pub fn is_due(&self, cycles: Cycles) -> bool {
+        cycles >= self.next_at && self.is_pending()
+    }
+
+    pub fn wait_until(&mut self, cycles: Cycles) {
+        self.next_at = cycles;
+    }
+
+    /// Next character for the matrix mode: keys to be released (if any)
+    /// or keys to be pressed
+    pub fn next_keys(&mut self) -> (Vec<C64KeyCode>, bool) {
+        if !self.held.is_empty() {
+            return (std::mem::take(&mut self.held), false);
+        }
+        while let Some(code) = self.queue.pop_front() {
+            match petscii_to_keys(code) {
+                Some(keys) => {
+                    self.held = keys.clone();
+                    return (keys, true);
+                }
+                None => log::warn!("PETSCII code {code:02x} can't be typed with keyboard"),
+            }
+        }
+        (Vec::new(), false)
+    }
+
+    /// Up to `count` characters for the buffer mode
+    pub fn next_chars(&mut self, count: usize) -> Vec<u8> {
+        let count = count.min(self.queue.len());
+        self.queue.drain(..count).collect()
+    }
+}
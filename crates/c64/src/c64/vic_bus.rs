@@ -0,0 +1,108 @@
+use super::{CIA2, VIC_II};
+use machine::{
+    emulator::{
+        abstractions::{Addr, Addressable, ArrayMemory, OpenBus},
+        components::CIA_6526,
+    },
+    utils::lock,
+};
+use std::sync::{Arc, Mutex};
+
+type Rom = Arc<Mutex<dyn Addressable + Send>>;
+
+/// Memory as seen by VIC-II: 16kB bank of RAM selected with CIA2 (PA0-PA1, inverted),
+/// with the character ROM at $1000-$1FFF of banks 0 and 2.
+/// It's the source of the open bus (see `VIC_II::fetched_byte`).
+pub struct VicBus {
+    vic: Arc<Mutex<VIC_II>>,
+    cia2: Option<Arc<Mutex<CIA2>>>,
+    ram: Arc<Mutex<ArrayMemory>>,
+    chargen: Option<Rom>,
+}
+
+impl VicBus {
+    pub fn new(
+        vic: Arc<Mutex<VIC_II>>,
+        cia2: Arc<Mutex<CIA2>>,
+        ram: Arc<Mutex<ArrayMemory>>,
+    ) -> Self {
+        VicBus {
+            vic,
+            cia2: Some(cia2),
+            ram,
+            chargen: None,
+        }
+    }
+
+    pub fn set_chargen(&mut self, chargen: Option<Rom>) {
+        self.chargen = chargen;
+    }
+
+    /// Without CIA2 the bank lines are pulled up (bank 0)
+    pub fn remove_cia2(&mut self) {
+        self.cia2 = None;
+    }
+
+    /// Start address of the bank
+    pub fn bank(&self) -> Addr {
+        let port = match &self.cia2 {
+            Some(cia2) => lock::<CIA2>(cia2).read_port_a(),
+            None => 0xff,
+        };
+        (3 - (port & 0b11) as Addr) * 0x4000
+    }
+
+    fn read(&self, bank: Addr, addr: Addr) -> u8 {
+        let addr = addr & 0x3fff;
+        match &self.chargen {
+            Some(rom) if bank & 0x4000 == 0 && (0x1000..=0x1fff).contains(&addr) => {
+                lock(rom).read_byte(addr - 0x1000)
+            }
+            _ => lock::<ArrayMemory>(&self.ram).read_byte(bank | addr),
+        }
+    }
+}
+
+impl OpenBus for VicBus {
+    fn open_bus(&self) -> u8 {
+        let bank = self.bank();
+        lock::<VIC_II>(&self.vic).fetched_byte(|addr| self.read(bank, addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64::MachineModel;
+    use machine::emulator::abstractions::Clocked;
+
+    #[test]
+    fn test_fetches() {
+        let vic = Arc::new(Mutex::new(VIC_II::new(MachineModel::PAL)));
+        let cia2 = Arc::new(Mutex::new(CIA2::new()));
+        let ram = Arc::new(Mutex::new(ArrayMemory::new(0x10000, 16)));
+        let chargen = ArrayMemory::from_data(&[0xc0; 0x1000], 16);
+        let mut bus = VicBus::new(vic.clone(), cia2.clone(), ram.clone());
+        bus.set_chargen(Some(Arc::new(Mutex::new(chargen))));
+        ram.lock().unwrap().write_byte(0xffff, 0x55);
+        ram.lock().unwrap().write_byte(0x3fff, 0x33);
+        assert_eq!(bus.bank(), 0x0000); // PA0-PA1 are inputs (pulled up) after reset
+
+        // idle fetch at $3FFF of the bank
+        vic.lock().unwrap().tick(20);
+        assert_eq!(bus.open_bus(), 0x33);
+        Addressable::write_byte(&mut *lock::<CIA2>(&cia2), 0x02, 0x03); // PA0-PA1 low
+        assert_eq!(bus.bank(), 0xc000);
+        assert_eq!(bus.open_bus(), 0x55);
+        Addressable::write_byte(&mut *lock::<CIA2>(&cia2), 0x00, 0x03);
+
+        // graphics fetch from the character ROM (screen at $0400, chars at $1000)
+        {
+            let mut vic = vic.lock().unwrap();
+            vic.write_byte(0x11, 0x1b);
+            vic.write_byte(0x18, 0x14);
+            vic.tick(0x33 * 63);
+        }
+        assert_eq!(bus.open_bus(), 0xc0);
+    }
+}
@@ -1,9 +1,21 @@
 #![allow(non_camel_case_types)]
 
-use super::C64Memory;
+use super::{C64Memory, MachineModel};
 use crate::key_utils::screen_code_to_ascii;
 use colored::*;
-use machine::Memory;
+use machine::{
+    emulator::{
+        abstractions::{
+            Addr, Addressable, CPUCycles, Clocked, DeviceTrait, RegisterField, RegisterInfo,
+            StateReader, StateWriter,
+        },
+        EmulatorError,
+    },
+    Cycles, Memory,
+};
+
+/// Cycles per frame of PAL C64 (312 raster lines, 63 cycles each)
+pub const PAL_FRAME_CYCLES: Cycles = 312 * 63;
 
 /*
  * RGB values of C64 colours
@@ -24,13 +36,204 @@ use machine::Memory;
  * #6C5EB5 mid blue
  * #959595 light grey
  */
+const fn register(offset: u16, name: &'static str, description: &'static str) -> RegisterInfo {
+    RegisterInfo { offset, name, description, fields: &[] }
+}
+
+/// Registers of VIC-II ($D000-$D02E)
+static VIC_REGISTERS: [RegisterInfo; 47] = [
+    register(0x00, "M0X", "Sprite 0 X position"),
+    register(0x01, "M0Y", "Sprite 0 Y position"),
+    register(0x02, "M1X", "Sprite 1 X position"),
+    register(0x03, "M1Y", "Sprite 1 Y position"),
+    register(0x04, "M2X", "Sprite 2 X position"),
+    register(0x05, "M2Y", "Sprite 2 Y position"),
+    register(0x06, "M3X", "Sprite 3 X position"),
+    register(0x07, "M3Y", "Sprite 3 Y position"),
+    register(0x08, "M4X", "Sprite 4 X position"),
+    register(0x09, "M4Y", "Sprite 4 Y position"),
+    register(0x0a, "M5X", "Sprite 5 X position"),
+    register(0x0b, "M5Y", "Sprite 5 Y position"),
+    register(0x0c, "M6X", "Sprite 6 X position"),
+    register(0x0d, "M6Y", "Sprite 6 Y position"),
+    register(0x0e, "M7X", "Sprite 7 X position"),
+    register(0x0f, "M7Y", "Sprite 7 Y position"),
+    register(0x10, "MX8", "Bit 8 of sprites' X positions"),
+    RegisterInfo {
+        offset: 0x11,
+        name: "CR1",
+        description: "Control register 1",
+        fields: &[
+            RegisterField { name: "YSCROLL", mask: 0x07, description: "Vertical scroll" },
+            RegisterField { name: "RSEL", mask: 0x08, description: "25 rows (or 24)" },
+            RegisterField { name: "DEN", mask: 0x10, description: "Display enabled" },
+            RegisterField { name: "BMM", mask: 0x20, description: "Bitmap mode" },
+            RegisterField { name: "ECM", mask: 0x40, description: "Extended colour mode" },
+            RegisterField { name: "RST8", mask: 0x80, description: "Bit 8 of the raster line" },
+        ],
+    },
+    register(0x12, "RASTER", "Raster line (read) / raster interrupt line (write)"),
+    register(0x13, "LPX", "Light pen X position"),
+    register(0x14, "LPY", "Light pen Y position"),
+    register(0x15, "ME", "Sprites enabled"),
+    RegisterInfo {
+        offset: 0x16,
+        name: "CR2",
+        description: "Control register 2",
+        fields: &[
+            RegisterField { name: "XSCROLL", mask: 0x07, description: "Horizontal scroll" },
+            RegisterField { name: "CSEL", mask: 0x08, description: "40 columns (or 38)" },
+            RegisterField { name: "MCM", mask: 0x10, description: "Multicolour mode" },
+            RegisterField { name: "RES", mask: 0x20, description: "Reset (no effect)" },
+        ],
+    },
+    register(0x17, "MYE", "Sprites expanded vertically"),
+    RegisterInfo {
+        offset: 0x18,
+        name: "MEMPTR",
+        description: "Memory pointers (within VIC bank)",
+        fields: &[
+            RegisterField { name: "CB", mask: 0x0e, description: "Character set / bitmap address" },
+            RegisterField { name: "VM", mask: 0xf0, description: "Screen memory address" },
+        ],
+    },
+    RegisterInfo {
+        offset: 0x19,
+        name: "IRR",
+        description: "Interrupt requests (write 1 to acknowledge)",
+        fields: &[
+            RegisterField { name: "RST", mask: 0x01, description: "Raster line reached" },
+            RegisterField { name: "MBC", mask: 0x02, description: "Sprite-background collision" },
+            RegisterField { name: "MMC", mask: 0x04, description: "Sprite-sprite collision" },
+            RegisterField { name: "LP", mask: 0x08, description: "Light pen" },
+            RegisterField { name: "IRQ", mask: 0x80, description: "Interrupt requested" },
+        ],
+    },
+    register(0x1a, "IMR", "Interrupts enabled (bits as in IRR)"),
+    register(0x1b, "MDP", "Sprites behind the background"),
+    register(0x1c, "MMC", "Multicolour sprites"),
+    register(0x1d, "MXE", "Sprites expanded horizontally"),
+    register(0x1e, "MM", "Sprite-sprite collisions (cleared on read)"),
+    register(0x1f, "MD", "Sprite-background collisions (cleared on read)"),
+    register(0x20, "EC", "Border colour"),
+    register(0x21, "B0C", "Background colour 0"),
+    register(0x22, "B1C", "Background colour 1"),
+    register(0x23, "B2C", "Background colour 2"),
+    register(0x24, "B3C", "Background colour 3"),
+    register(0x25, "MM0", "Sprite multicolour 0"),
+    register(0x26, "MM1", "Sprite multicolour 1"),
+    register(0x27, "M0C", "Sprite 0 colour"),
+    register(0x28, "M1C", "Sprite 1 colour"),
+    register(0x29, "M2C", "Sprite 2 colour"),
+    register(0x2a, "M3C", "Sprite 3 colour"),
+    register(0x2b, "M4C", "Sprite 4 colour"),
+    register(0x2c, "M5C", "Sprite 5 colour"),
+    register(0x2d, "M6C", "Sprite 6 colour"),
+    register(0x2e, "M7C", "Sprite 7 colour"),
+];
+
+/// Number of VIC's registers; the remaining ones (up to $3F) read as $FF
+const REGISTERS: usize = 0x2f;
+
 pub struct VIC_II {
-    char_set: u8,
+    registers: [u8; REGISTERS],
+    model: MachineModel,
+    /// Cycle of the current frame
+    frame_cycle: Cycles,
 }
 
 impl VIC_II {
-    pub fn new() -> Self {
-        VIC_II { char_set: 0x14 }
+    pub fn new(model: MachineModel) -> Self {
+        VIC_II {
+            registers: [0; REGISTERS],
+            model,
+            frame_cycle: 0,
+        }
+    }
+
+    /// Currently scanned line
+    pub fn raster_line(&self) -> u16 {
+        self.model.raster_line(self.frame_cycle)
+    }
+
+    /// Raster position: the line and the cycle of the line (counted from 0)
+    pub fn raster_position(&self) -> (u16, Cycles) {
+        (self.raster_line(), self.frame_cycle % self.model.cycles_per_line())
+    }
+
+    /// Moves the raster (i.e. when a snapshot of another emulator is imported)
+    pub fn set_raster_position(&mut self, line: u16, cycle: Cycles) {
+        let cycles_per_line = self.model.cycles_per_line();
+        let cycle = line as Cycles * cycles_per_line + cycle.min(cycles_per_line - 1);
+        self.frame_cycle = cycle % self.model.frame_cycles();
+    }
+
+    /// Value of the register as written (without unused bits and the raster)
+    pub fn raw_register(&self, reg: usize) -> u8 {
+        self.registers.get(reg).copied().unwrap_or(0xff)
+    }
+
+    /// Sets the register without the side effects of writing it
+    pub fn set_raw_register(&mut self, reg: usize, value: u8) {
+        if let Some(val) = self.registers.get_mut(reg) {
+            *val = value;
+        }
+    }
+
+    /// Byte fetched by the chip in the first phase of the current cycle. Nothing
+    /// else drives the data bus then, so it's what the CPU reads from unmapped
+    /// addresses (open bus). `read` gives bytes of the VIC's 16kB bank.
+    ///
+    /// The fetches follow the PAL timing of a raster line (cycles counted from 1):
+    /// 1-10 sprite 3-7 pointers (odd cycles), 11-15 DRAM refresh,
+    /// 16-55 graphics (or the idle byte at $3FFF outside the display),
+    /// sprite 0-2 pointers in the last 6 cycles. Sprite data is not fetched.
+    pub fn fetched_byte(&self, read: impl Fn(Addr) -> u8) -> u8 {
+        let line = self.raster_line();
+        let cycles_per_line = self.model.cycles_per_line();
+        let cycle = self.frame_cycle % cycles_per_line + 1;
+        let cr1 = self.registers[0x11];
+        let mem_ptr = self.registers[0x18] as Addr;
+        let screen = (mem_ptr & 0xf0) << 6;
+        let idle = if cr1 & 0x40 > 0 { 0x39ff } else { 0x3fff };
+
+        let sprites_0_2 = cycles_per_line - 5;
+        let addr = match cycle {
+            1..=10 if cycle % 2 == 1 => screen | 0x3f8 | (3 + cycle / 2) as Addr,
+            1..=10 => idle,
+            11..=15 => {
+                // refresh counter starts at $FF and decrements 5 times a line
+                let refresh = 0xff - (line as Cycles * 5 + cycle - 11) as u8;
+                0x3f00 | refresh as Addr
+            }
+            16..=55 => {
+                // display starts at the first bad line (with Y scroll)
+                let top = 0x30 + (cr1 & 0x07) as u16;
+                if cr1 & 0x10 == 0 || line < top || line >= top + 200 {
+                    idle
+                } else {
+                    let (row, rc) = ((line - top) / 8, (line - top) % 8);
+                    let vc = row * 40 + (cycle - 16) as u16;
+                    let addr = if cr1 & 0x20 > 0 {
+                        (mem_ptr & 0x08) << 10 | vc << 3 | rc
+                    } else {
+                        let code = read(screen | vc) as Addr;
+                        (mem_ptr & 0x0e) << 10 | code << 3 | rc
+                    };
+                    // ECM forces address lines 9 and 10 low
+                    if cr1 & 0x40 > 0 {
+                        addr & 0x39ff
+                    } else {
+                        addr
+                    }
+                }
+            }
+            _ if cycle >= sprites_0_2 && (cycle - sprites_0_2).is_multiple_of(2) => {
+                screen | 0x3f8 | ((cycle - sprites_0_2) / 2) as Addr
+            }
+            _ => idle,
+        };
+        read(addr)
     }
 
     pub fn print_screen(&self, mem: &C64Memory) {
@@ -42,7 +245,7 @@ impl VIC_II {
             let sc = mem.read_byte(i);
             print!(
                 "{}",
-                format!("{}", screen_code_to_ascii(&sc, self.char_set))
+                format!("{}", screen_code_to_ascii(&sc, self.read_byte(0x18)))
                     .on_truecolor(0x35, 0x28, 0x79)
             );
             n += 1;
@@ -56,3 +259,88 @@ impl VIC_II {
         println!("              ");
     }
 }
+
+/// Registers are mirrored every 64 bytes of $D000-$D3FF
+impl Addressable for VIC_II {
+    fn read_byte(&self, addr: Addr) -> u8 {
+        let reg = (addr & 0x3f) as usize;
+        match reg {
+            // bit 7 of CR1 is bit 8 of the raster line
+            0x11 => self.registers[reg] & 0x7f | ((self.raster_line() >> 1) as u8 & 0x80),
+            0x12 => self.raster_line() as u8,
+            // unused bits read as 1
+            0x16 => self.registers[reg] | 0xc0,
+            0x18 => self.registers[reg] | 0x01,
+            0x19 => self.registers[reg] | 0x70,
+            0x1a => self.registers[reg] | 0xf0,
+            0x20..=0x2e => self.registers[reg] | 0xf0,
+            REGISTERS.. => 0xff,
+            _ => self.registers[reg],
+        }
+    }
+
+    fn write_byte(&mut self, addr: Addr, value: u8) {
+        let reg = (addr & 0x3f) as usize;
+        match reg {
+            // interrupt flags are acknowledged by writing 1
+            0x19 => self.registers[reg] &= !value,
+            // read only
+            0x1e | 0x1f | REGISTERS.. => {}
+            _ => self.registers[reg] = value,
+        }
+    }
+
+    fn address_width(&self) -> u16 {
+        6
+    }
+}
+
+impl DeviceTrait for VIC_II {
+    fn name(&self) -> &str {
+        "VIC-II"
+    }
+
+    fn hard_reset(&mut self) {
+        *self = VIC_II::new(self.model);
+    }
+
+    /// VIC-II has no RESET pin: it keeps its registers and goes on with the frame
+    fn soft_reset(&mut self) {}
+
+    fn registers(&self) -> &'static [RegisterInfo] {
+        &VIC_REGISTERS
+    }
+
+    fn peek_register(&self, offset: Addr) -> Option<u8> {
+        Some(self.read_byte(offset))
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(&self.registers).u64(self.frame_cycle);
+        w.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = StateReader::new(state);
+        self.registers = r.array()?;
+        self.frame_cycle = r.u64()? % self.model.frame_cycles();
+        r.finish()
+    }
+
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        Some(self)
+    }
+}
+
+impl Clocked for VIC_II {
+    fn tick(&mut self, cycles: CPUCycles) {
+        self.frame_cycle = (self.frame_cycle + cycles) % self.model.frame_cycles();
+    }
+
+    /// Raster interrupts are not emulated yet, so the raster position
+    /// matters only when the registers are read
+    fn next_event(&self) -> Option<CPUCycles> {
+        None
+    }
+}
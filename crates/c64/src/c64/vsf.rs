@@ -0,0 +1,396 @@
+use super::{C64Memory, SID, VIC_II};
+use machine::{
+    emulator::{
+        abstractions::{Addressable, DeviceTrait, StateReader, StateWriter},
+        components::CIAState,
+        EmulatorError,
+    },
+    mos6502::{ProcessorStatus, Registers},
+    Cycles,
+};
+use std::num::Wrapping;
+
+const MAGIC: &[u8; 19] = b"VICE Snapshot File\x1a";
+const VERSION_MAGIC: &[u8; 13] = b"VICE Version\x1a";
+const NAME_LEN: usize = 16;
+const MODULE_HEADER_LEN: usize = NAME_LEN + 6;
+
+/// Version of the file format (VICE 3.x) and of VICE the module layouts follow
+const FILE_VERSION: (u8, u8) = (2, 0);
+const VICE_VERSION: [u8; 4] = [3, 7, 0, 0];
+
+/// Modules mapped onto the machine, with their versions (major, minor).
+/// Modules of other major versions are not imported.
+const MODULES: [(&str, u8, u8); 6] = [
+    ("MAINCPU", 1, 1),
+    ("C64MEM", 0, 0),
+    ("CIA1", 2, 2),
+    ("CIA2", 2, 2),
+    ("VIC-II", 1, 1),
+    ("SID", 1, 1),
+];
+
+/// Module of a VICE snapshot (state of a single chip or subsystem)
+#[derive(Debug, Clone, PartialEq)]
+pub struct VsfModule {
+    pub name: String,
+    pub major: u8,
+    pub minor: u8,
+    pub data: Vec<u8>,
+}
+
+impl VsfModule {
+    fn new(name: &str, data: Vec<u8>) -> Self {
+        let (_, major, minor) = MODULES.iter().find(|(n, ..)| *n == name).unwrap();
+        VsfModule {
+            name: name.to_string(),
+            major: *major,
+            minor: *minor,
+            data,
+        }
+    }
+
+    /// Fails with the reason if the module can't be imported
+    /// (unknown module or unsupported version)
+    pub fn check(&self) -> Result<(), String> {
+        match MODULES.iter().find(|(name, ..)| *name == self.name) {
+            None => Err(format!("{} (not supported)", self.name)),
+            Some((_, major, _)) if *major != self.major => Err(format!(
+                "{} {}.{} (unsupported version, expected {major}.x)",
+                self.name, self.major, self.minor
+            )),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Snapshot file of the VICE emulator (.vsf). The file format (little endian):
+///
+/// ```text
+/// 19 bytes    "VICE Snapshot File" + $1A
+/// u8, u8      format version (major, minor)
+/// 16 bytes    machine name (i.e. "C64", padded with zeros)
+/// 13 bytes    "VICE Version" + $1A, 4 version bytes and u32 revision (VICE 3.0+)
+/// and the modules:
+/// 16 bytes    module name (padded with zeros)
+/// u8, u8      module version (major, minor)
+/// u32         size of the module (with the 22 bytes of its header)
+/// ```
+///
+/// The layouts of the imported modules follow the VICE sources (`maincpu.c`,
+/// `c64memsnapshot.c`, `ciacore.c`, `vicii-snapshot.c` and `sid-snapshot.c`);
+/// the fields this emulator has no counterpart for are skipped on import
+/// and written as zeros on export (which covers only the fields described
+/// at the `read_*` functions below).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vsf {
+    pub machine: String,
+    pub modules: Vec<VsfModule>,
+}
+
+impl Vsf {
+    pub fn new(machine: &str) -> Self {
+        Vsf {
+            machine: machine.to_string(),
+            modules: Vec::new(),
+        }
+    }
+
+    pub fn is_vsf(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EmulatorError> {
+        if !Vsf::is_vsf(data) {
+            return Err(EmulatorError::InvalidState("not a VICE snapshot".to_string()));
+        }
+        let mut r = StateReader::new(&data[MAGIC.len()..]);
+        r.bytes(2)?; // the format version
+        let mut vsf = Vsf::new(&read_name(&mut r)?);
+        let rest = &data[MAGIC.len() + 2 + NAME_LEN..];
+        if rest.starts_with(VERSION_MAGIC) {
+            r.bytes(VERSION_MAGIC.len() + 8)?;
+        }
+        while !r.is_empty() {
+            let name = read_name(&mut r)?;
+            let (major, minor) = (r.u8()?, r.u8()?);
+            let size = r.u32()? as usize;
+            if size < MODULE_HEADER_LEN {
+                return Err(EmulatorError::InvalidState(format!("module {name} is corrupted")));
+            }
+            let data = r.bytes(size - MODULE_HEADER_LEN)?.to_vec();
+            vsf.modules.push(VsfModule {
+                name,
+                major,
+                minor,
+                data,
+            });
+        }
+        Ok(vsf)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(MAGIC).u8(FILE_VERSION.0).u8(FILE_VERSION.1);
+        write_name(&mut w, &self.machine);
+        w.bytes(VERSION_MAGIC).bytes(&VICE_VERSION).u32(0);
+        for module in self.modules.iter() {
+            write_name(&mut w, &module.name);
+            w.u8(module.major)
+                .u8(module.minor)
+                .u32((module.data.len() + MODULE_HEADER_LEN) as u32)
+                .bytes(&module.data);
+        }
+        w.finish()
+    }
+}
+
+fn read_name(r: &mut StateReader) -> Result<String, EmulatorError> {
+    let name = r.bytes(NAME_LEN)?;
+    let len = name.iter().position(|c| *c == 0).unwrap_or(NAME_LEN);
+    Ok(String::from_utf8_lossy(&name[..len]).to_string())
+}
+
+fn write_name(w: &mut StateWriter, name: &str) {
+    let mut bytes = [0; NAME_LEN];
+    let len = name.len().min(NAME_LEN);
+    bytes[..len].copy_from_slice(&name.as_bytes()[..len]);
+    w.bytes(&bytes);
+}
+
+/// MAINCPU: clock (u64), A, X, Y, SP, PC (u16), status; the interrupt
+/// state that follows is not imported
+pub(crate) fn read_cpu(m: &VsfModule) -> Result<(Registers, Cycles), EmulatorError> {
+    let mut r = StateReader::new(&m.data);
+    let cycles = r.u64()?;
+    let registers = Registers {
+        accumulator: Wrapping(r.u8()?),
+        x: Wrapping(r.u8()?),
+        y: Wrapping(r.u8()?),
+        stack: Wrapping(r.u8()?),
+        counter: r.u16()?,
+        status: ProcessorStatus::from(r.u8()?),
+    };
+    Ok((registers, cycles))
+}
+
+pub(crate) fn cpu_module(registers: &Registers, cycles: Cycles) -> VsfModule {
+    let mut w = StateWriter::new();
+    w.u64(cycles)
+        .u8(registers.accumulator.0)
+        .u8(registers.x.0)
+        .u8(registers.y.0)
+        .u8(registers.stack.0)
+        .u16(registers.counter)
+        .u8(u8::from(&registers.status));
+    VsfModule::new("MAINCPU", w.finish())
+}
+
+/// C64MEM: processor port data and direction, EXROM and GAME lines, 64kB of RAM;
+/// the port's capacitor emulation that follows is not imported.
+/// Returns the RAM with the processor port (kept at $00 and $01).
+pub(crate) fn read_memory(m: &VsfModule) -> Result<Vec<u8>, EmulatorError> {
+    let mut r = StateReader::new(&m.data);
+    let (data, dir) = (r.u8()?, r.u8()?);
+    let (exrom, game) = (r.u8()?, r.u8()?);
+    if exrom > 0 || game > 0 {
+        log::warn!("Cartridge lines in the VICE snapshot are ignored (EXROM: {exrom}, GAME: {game})");
+    }
+    let mut ram = r.bytes(0x10000)?.to_vec();
+    ram[0] = dir;
+    ram[1] = data;
+    Ok(ram)
+}
+
+pub(crate) fn memory_module(mem: &C64Memory) -> VsfModule {
+    let ram = mem.ram();
+    let mut w = StateWriter::new();
+    w.u8(ram[1]).u8(ram[0]).u8(0).u8(0).bytes(&ram);
+    VsfModule::new("C64MEM", w.finish())
+}
+
+/// CIA1 and CIA2: PRA, PRB, DDRA, DDRB, timer A and B counters (u16),
+/// TOD (tenths, seconds, minutes, hours), SDR, interrupt mask, CRA, CRB,
+/// timer A and B latches (u16), interrupt flags, timer outputs, serial bits
+/// and TOD alarm; the rest (TOD latch, delays of the CIA core) is not imported
+pub(crate) fn read_cia(m: &VsfModule, state: &mut CIAState) -> Result<(), EmulatorError> {
+    let mut r = StateReader::new(&m.data);
+    state.pra = r.u8()?;
+    state.prb = r.u8()?;
+    state.ddra = r.u8()?;
+    state.ddrb = r.u8()?;
+    let counters = (r.u16()?, r.u16()?);
+    let tod: [u8; 4] = r.array()?;
+    state.sdr = r.u8()?;
+    let mask = r.u8()?;
+    // (force load strobe is not a state)
+    state.write_cra(r.u8()? & !0x10);
+    state.write_crb(r.u8()? & !0x10);
+    state.timer_a.latch = r.u16()?;
+    state.timer_b.latch = r.u16()?;
+    state.timer_a.counter = counters.0;
+    state.timer_b.counter = counters.1;
+    state.icr_mask = mask & 0x1f;
+    state.read_icr();
+    state.set_interrupt(r.u8()? & 0x1f);
+    // writing hours stops the clock, and writing tenths starts it
+    for reg in (0..4).rev() {
+        state.tod.write(reg, tod[reg], false);
+    }
+    r.bytes(2)?;
+    let alarm: [u8; 4] = r.array()?;
+    for (reg, val) in alarm.iter().enumerate() {
+        state.tod.write(reg, *val, true);
+    }
+    Ok(())
+}
+
+pub(crate) fn cia_module(name: &str, state: &CIAState) -> VsfModule {
+    let mut w = StateWriter::new();
+    w.u8(state.pra)
+        .u8(state.prb)
+        .u8(state.ddra)
+        .u8(state.ddrb)
+        .u16(state.timer_a.counter)
+        .u16(state.timer_b.counter)
+        .bytes(&state.tod.time())
+        .u8(state.sdr)
+        .u8(state.icr_mask)
+        .u8(state.cra())
+        .u8(state.crb())
+        .u16(state.timer_a.latch)
+        .u16(state.timer_b.latch)
+        .u8(state.icr_data())
+        .u8(0)
+        .u8(0)
+        .bytes(&state.tod.alarm());
+    VsfModule::new(name, w.finish())
+}
+
+/// Offsets of the VIC-II module of x64: bad line and border flags, colour buffer
+/// (40 bytes) and colour RAM (1kB), idle state, light pen, matrix buffer (40 bytes),
+/// sprite DMA, bank (u32), raster cycle, raster line (u16) and 64 registers;
+/// the sprite state that follows is not imported. (The module of x64sc
+/// has another layout, so its snapshots are not imported.)
+const VIC_COLOUR_RAM: usize = 43;
+const VIC_BANK: usize = 1111;
+const VIC_RASTER: usize = 1115;
+
+/// State of VIC-II read from the module
+pub(crate) struct VicState {
+    colour_ram: Vec<u8>,
+    line: u16,
+    cycle: Cycles,
+    registers: Vec<u8>,
+}
+
+impl VicState {
+    pub(crate) fn apply(&self, vic: &mut VIC_II, mem: &mut C64Memory) {
+        mem.set_colour_ram(&self.colour_ram);
+        vic.set_raster_position(self.line, self.cycle);
+        for (reg, val) in self.registers.iter().enumerate() {
+            vic.set_raw_register(reg, *val);
+        }
+    }
+}
+
+pub(crate) fn read_vic(m: &VsfModule) -> Result<VicState, EmulatorError> {
+    let mut r = StateReader::new(&m.data);
+    r.bytes(VIC_COLOUR_RAM)?;
+    let colour_ram = r.bytes(0x400)?.to_vec();
+    r.bytes(VIC_RASTER - VIC_COLOUR_RAM - 0x400)?;
+    let cycle = r.u8()? as Cycles;
+    let line = r.u16()?;
+    let registers = r.bytes(0x40)?.to_vec();
+    Ok(VicState {
+        colour_ram,
+        line,
+        cycle,
+        registers,
+    })
+}
+
+pub(crate) fn vic_module(vic: &VIC_II, mem: &C64Memory) -> VsfModule {
+    let (line, cycle) = vic.raster_position();
+    let mut w = StateWriter::new();
+    w.bytes(&[0; VIC_COLOUR_RAM])
+        .bytes(&mem.colour_ram())
+        .bytes(&[0; VIC_BANK - VIC_COLOUR_RAM - 0x400])
+        .u32(mem.vic_bank() as u32)
+        .u8(cycle as u8)
+        .u16(line);
+    for reg in 0..0x40 {
+        w.u8(vic.raw_register(reg));
+    }
+    VsfModule::new("VIC-II", w.finish())
+}
+
+/// SID: sound enabled flag, and - if enabled - the engine and 32 registers;
+/// the engine's internal state is in another module (not imported).
+/// Returns the writable registers (None if the sound was disabled).
+pub(crate) fn read_sid(m: &VsfModule) -> Result<Option<Vec<u8>>, EmulatorError> {
+    let mut r = StateReader::new(&m.data);
+    if r.u8()? == 0 {
+        log::warn!("SID registers are missing in the VICE snapshot (sound was disabled)");
+        return Ok(None);
+    }
+    r.u8()?;
+    Ok(Some(r.bytes(0x20)?[..0x19].to_vec()))
+}
+
+pub(crate) fn set_sid_registers(sid: &mut SID, registers: &[u8]) {
+    for (reg, val) in registers.iter().enumerate() {
+        sid.write_byte(reg as u16, *val);
+    }
+}
+
+pub(crate) fn sid_module(sid: &SID) -> VsfModule {
+    let mut w = StateWriter::new();
+    w.u8(1).u8(0);
+    for reg in 0..0x20 {
+        w.u8(sid.peek_register(reg).unwrap_or(0));
+    }
+    VsfModule::new("SID", w.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_format() {
+        let mut vsf = Vsf::new("C64");
+        vsf.modules.push(VsfModule::new("SID", vec![1, 0, 2]));
+        vsf.modules.push(VsfModule {
+            name: "C64ROM".to_string(),
+            major: 0,
+            minor: 0,
+            data: vec![],
+        });
+        let data = vsf.to_bytes();
+        assert!(Vsf::is_vsf(&data));
+        assert_eq!(Vsf::from_bytes(&data).unwrap(), vsf);
+        assert!(Vsf::from_bytes(&data[..data.len() - 1]).is_err());
+
+        assert!(vsf.modules[0].check().is_ok());
+        assert_eq!(vsf.modules[1].check(), Err("C64ROM (not supported)".to_string()));
+    }
+
+    #[test]
+    fn test_cia_module() {
+        let mut state = CIAState::default();
+        state.reset();
+        state.ddra = 0xff;
+        state.timer_a.latch = 0x4025;
+        state.timer_a.counter = 0x1234;
+        state.write_cra(0x11);
+        state.icr_mask = 0x01;
+        state.set_interrupt(0x01);
+        let module = cia_module("CIA1", &state);
+
+        let mut loaded = CIAState::default();
+        read_cia(&module, &mut loaded).unwrap();
+        assert_eq!(cia_module("CIA1", &loaded), module);
+        assert_eq!(loaded.icr_data(), 0x81);
+    }
+}
@@ -1,15 +1,19 @@
-use crate::c64::C64;
-use crate::key_utils::ui_event_to_c64_key_codes;
+use crate::c64::{Vsf, C64};
 use crossbeam_channel::Receiver;
-use keyboard_types::{KeyState, KeyboardEvent};
+use keyboard_types::KeyboardEvent;
 use machine::{
-    client::*, debugger::DebuggerState, mos6502::Registers, Addr, Machine,
-    MachineError, MachineStatus, Memory,
+    client::*,
+    debugger::{Debugger, DebuggerState, RewindStatus},
+    mos6502::Registers,
+    Addr, Machine, MachineError, MachineStatus, Memory, ResetKind,
 };
 use std::sync::{Arc, Mutex};
 
 type Result<T> = std::result::Result<T, MachineError>;
 
+/// Number of executed instructions in the machine's state
+const HISTORY_LINES: usize = 100;
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct MachineState {
     pub status: MachineStatus,
@@ -20,6 +24,15 @@ pub struct MachineState {
     pub character_set: u8,
     pub debugger: DebuggerState,
     pub next_op: String,
+    /// Keys seen as pressed in the keyboard matrix
+    pub pressed_keys: Vec<u8>,
+    pub speed: SpeedStats,
+    pub warp: bool,
+    /// Colours of the machine's model (0xRRGGBB)
+    pub palette: [u32; 16],
+    /// Last executed instructions (disassembled) with their positions in the history
+    pub history: Vec<(u64, String)>,
+    pub rewind: RewindStatus,
 }
 
 pub struct C64Client {
@@ -33,6 +46,24 @@ impl C64Client {
         }
     }
 
+    /// Loads a snapshot of VICE (see `C64::import_vsf`).
+    /// Returns the modules that were skipped.
+    pub fn import_vsf(&mut self, data: &[u8]) -> Result<Vec<String>> {
+        let vsf = Vsf::from_bytes(data)?;
+        let (skipped, snapshot) = {
+            let mut c64 = self.base_client.lock();
+            let skipped = c64.import_vsf(&vsf)?;
+            (skipped, c64.save_snapshot().to_bytes())
+        };
+        // loaded as own snapshot, so the client resumes from it
+        self.base_client.load_snapshot(&snapshot)?;
+        Ok(skipped)
+    }
+
+    pub fn export_vsf(&mut self) -> Vec<u8> {
+        self.base_client.lock().export_vsf().to_bytes()
+    }
+
     pub fn start_sync(&mut self) -> Result<()> {
         self.base_client.start_sync()
     }
@@ -49,8 +80,20 @@ impl C64Client {
         self.base_client.lock().debugger_state = state;
     }
 
+    /// Types the text in with the keyboard (or by putting it into
+    /// KERNAL's keyboard buffer - see `TypeInMode`). New lines are
+    /// typed as RETURN and special keys can be given in curly brackets,
+    /// i.e. `{CLR}`
+    pub fn type_text(&mut self, text: &str) {
+        self.base_client.lock().type_text(text);
+    }
+
     pub fn step(&mut self) -> MachineState {
         self.handle_events();
+        let (speed, warp) = {
+            let throttle = self.base_client.throttle();
+            (throttle.stats(), throttle.is_warp())
+        };
         let c64 = self.base_client.lock();
         let registers = c64.cpu().registers.clone();
         let last_op = c64.disassemble(&c64.last_op, true, false);
@@ -61,6 +104,8 @@ impl C64Client {
         );
         let screen = c64.get_screen_memory();
         let character_set = c64.read_byte(0xd018); // https://www.c64-wiki.com/wiki/Character_set
+        let pressed_keys = c64.pressed_keys();
+        let history = c64.rewind().history(&*c64, HISTORY_LINES);
         MachineState {
             status: c64.get_status(),
             registers,
@@ -70,6 +115,12 @@ impl C64Client {
             character_set,
             debugger: c64.debugger_state.clone(),
             next_op,
+            pressed_keys,
+            speed,
+            warp,
+            palette: c64.model().palette(),
+            history,
+            rewind: c64.rewind().status(),
         }
     }
 
@@ -79,15 +130,42 @@ impl C64Client {
             for event in r.try_iter().collect::<Vec<ClientEvent>>().iter() {
                 match event {
                     EnableBreakpoint(b) => {
-                        self.base_client.lock().debugger_state.add_breakpoint(&b)
+                        self.base_client.lock().debugger_state.add_breakpoint(b)
                     }
                     DisableBreakpoint(b) => {
-                        self.base_client.lock().debugger_state.remove_breakpoint(&b)
+                        self.base_client.lock().debugger_state.remove_breakpoint(b)
+                    }
+                    EnableWatchpoint(w) => self.base_client.lock().debugger_state.add_watchpoint(w),
+                    DisableWatchpoint(w) => {
+                        self.base_client.lock().debugger_state.remove_watchpoint(w)
+                    }
+                    KeyPress(_) | MatrixKeys { .. } | Restore(_) | Joystick { .. }
+                    | Paddles { .. } | Mouse { .. } | TypeText(_) => {
+                        let mut c64 = self.base_client.lock();
+                        if c64.get_config().deterministic {
+                            // live input would make the run unrepeatable
+                            log::warn!("Input ignored in deterministic mode: {:?}", event);
+                        } else {
+                            c64.handle_input(event);
+                        }
                     }
-                    KeyPress(key_event) => self.send_key(key_event.clone()),
+                    SetSpeed(percent) => self.base_client.throttle().set_speed(*percent),
+                    SetWarp(warp) => self.base_client.throttle().set_warp(*warp),
                     SetObservedMemory(range) => {
                         self.base_client.lock().debugger_state.observed_mem = range.clone()
                     }
+                    StepBack | ReverseContinue | Seek(_) => {
+                        let mut c64 = self.base_client.lock();
+                        let result = match event {
+                            StepBack => c64.step_back(),
+                            ReverseContinue => c64.reverse_continue(),
+                            Seek(position) => c64.seek(*position),
+                            _ => unreachable!(),
+                        };
+                        if let Err(e) = result {
+                            log::error!("Going back in time failed: {e}");
+                        }
+                    }
                 };
             }
         }
@@ -98,24 +176,7 @@ impl InteractiveClient for C64Client {
     type Error = MachineError;
 
     fn send_key(&mut self, event: KeyboardEvent) {
-        log::debug!("Sending key {:?}", event);
-        if event.state == KeyState::Up {
-            log::error!("Can't send key-up events on this client.");
-            return ();
-        }
-
-        // key down
-        let mut c64 = self.base_client.lock();
-        c64.send_keys(&ui_event_to_c64_key_codes(&event), true);
-
-        // key up (simulated with timeout)
-        // let mut up_event = event.clone();
-        // up_event.state = KeyState::Up;
-        // let arc = self.base_client.mutex();
-        // thread::spawn(move || {
-        //     thread::sleep(Duration::from_millis(125));
-        //     lock::<C64>(&arc).send_keys(&ui_event_to_c64_key_codes(&up_event), false);
-        // });
+        self.base_client.lock().handle_input(&ClientEvent::KeyPress(event));
     }
 
     fn get_screen_memory(&self) -> Result<Vec<u8>> {
@@ -135,8 +196,8 @@ impl NonInteractiveClient for C64Client {
         self.base_client.start()
     }
 
-    fn reset(&mut self) -> Result<()> {
-        self.base_client.reset()
+    fn reset(&mut self, kind: ResetKind) -> Result<()> {
+        self.base_client.reset(kind)
     }
 
     fn stop(&mut self) -> Result<()> {
@@ -166,6 +227,18 @@ impl NonInteractiveClient for C64Client {
     fn set_receiver(&mut self, r: Receiver<ClientEvent>) {
         self.base_client.set_receiver(r);
     }
+
+    fn save_snapshot(&mut self) -> Result<Vec<u8>> {
+        self.base_client.save_snapshot()
+    }
+
+    /// Accepts also snapshots of VICE
+    fn load_snapshot(&mut self, data: &[u8]) -> Result<()> {
+        match Vsf::is_vsf(data) {
+            true => self.import_vsf(data).map(|_| ()),
+            false => self.base_client.load_snapshot(data),
+        }
+    }
 }
 
 impl Client for C64Client {}
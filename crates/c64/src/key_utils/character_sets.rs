@@ -27,8 +27,10 @@ lazy_static! {
             .collect();
 }
 
+/// `set` is the value of VIC's memory pointers register ($D018);
+/// its character base bits tell the upper case set from the lower case one
 pub fn screen_code_to_ascii(code: &u8, set: u8) -> char {
-    if set == 0x14 {
+    if set & 0x0e == 0x04 {
         CHAR_SET_1[*code as usize]
     } else {
         CHAR_SET_2[*code as usize]
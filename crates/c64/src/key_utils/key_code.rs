@@ -71,9 +71,9 @@ pub enum C64KeyCode {
     Stop = 0x3f,
 }
 
-impl Into<u8> for C64KeyCode {
-    fn into(self) -> u8 {
-        self as u8
+impl From<C64KeyCode> for u8 {
+    fn from(kc: C64KeyCode) -> u8 {
+        kc as u8
     }
 }
 
@@ -133,11 +133,18 @@ impl C64KeyCode {
         })
     }
 
+    /// Name of the key as used in keymaps: variant name without "Key_" prefix
+    pub fn name(&self) -> String {
+        let name = format!("{:?}", self);
+        name.strip_prefix("Key_").map_or(name.clone(), String::from)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..64u8).map(C64KeyCode::from).find(|kc| kc.name() == name)
+    }
+
     pub fn is_modifier(&self) -> bool {
-        match self {
-            C64KeyCode::RShift | C64KeyCode::LShift => true,
-            _ => false,
-        }
+        matches!(self, C64KeyCode::RShift | C64KeyCode::LShift)
     }
 }
 
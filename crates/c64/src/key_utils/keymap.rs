@@ -0,0 +1,233 @@
+use super::C64KeyCode;
+use anyhow::{anyhow, bail, Result};
+use keyboard_types::{Key, KeyState, KeyboardEvent};
+use std::{collections::HashMap, path::Path};
+
+const US_KEYMAP: &str = include_str!("../../keymaps/us.toml");
+const UK_KEYMAP: &str = include_str!("../../keymaps/uk.toml");
+const DE_KEYMAP: &str = include_str!("../../keymaps/de.toml");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeymapMode {
+    /// Host characters are mapped to C64 keys producing the same character
+    /// (i.e. `"` is SHIFT+2 on C64, regardless where it is on the host keyboard)
+    Symbolic,
+    /// Host keys are mapped to C64 keys at the same location
+    Positional,
+}
+
+impl KeymapMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "symbolic" => Some(KeymapMode::Symbolic),
+            "positional" => Some(KeymapMode::Positional),
+            _ => None,
+        }
+    }
+}
+
+/// Maps host keyboard events to C64 keys. Keymaps are defined in TOML files
+/// (see `keymaps` folder of the crate) with two tables: `symbolic` (indexed
+/// by characters or key names) and `positional` (indexed by key codes).
+/// A keymap can be based on a built-in one (`base = "us"`): it lists only
+/// the differences, and an empty mapping removes the inherited one.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub name: String,
+    pub mode: KeymapMode,
+    symbolic: HashMap<String, Vec<C64KeyCode>>,
+    positional: HashMap<String, Vec<C64KeyCode>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_toml(US_KEYMAP).expect("Invalid built-in keymap")
+    }
+}
+
+impl Keymap {
+    /// Finds built-in keymap (`us`, `uk` or `de`) or loads it from a file
+    pub fn load(name: &str) -> Result<Self> {
+        match builtin(name) {
+            Some(src) => Keymap::from_toml(src),
+            None => Keymap::from_file(name),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Keymap::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(src: &str) -> Result<Self> {
+        let table: toml::Table = src.parse()?;
+        let base = match table.get("base").and_then(|v| v.as_str()) {
+            Some(name) => builtin(name)
+                .ok_or_else(|| anyhow!("Unknown base keymap: {name} (expected us, uk or de)"))
+                .and_then(Keymap::from_toml)?,
+            None => Keymap {
+                name: "custom".to_string(),
+                mode: KeymapMode::Symbolic,
+                symbolic: HashMap::new(),
+                positional: HashMap::new(),
+            },
+        };
+        let mode = match table.get("mode").and_then(|v| v.as_str()) {
+            Some(mode) => KeymapMode::from_name(mode)
+                .ok_or_else(|| anyhow!("Unknown keymap mode: {mode}"))?,
+            None => base.mode,
+        };
+        Ok(Keymap {
+            name: table
+                .get("name")
+                .and_then(|v| v.as_str())
+                .map_or(base.name, |name| name.to_string()),
+            mode,
+            symbolic: merge_mapping(base.symbolic, parse_mapping(&table, "symbolic")?),
+            positional: merge_mapping(base.positional, parse_mapping(&table, "positional")?),
+        })
+    }
+
+    /// Returns C64 keys for the event: modifiers first, so on key-up
+    /// (where the order is reversed) they are released last.
+    pub fn map_event(&self, event: &KeyboardEvent) -> Vec<C64KeyCode> {
+        let (mapping, shift) = match (self.mode, &event.key) {
+            // shift is already applied to the character
+            (KeymapMode::Symbolic, Key::Character(ch)) => (self.symbolic.get(ch), false),
+            (KeymapMode::Symbolic, key) => (self.symbolic.get(&key.to_string()), true),
+            (KeymapMode::Positional, _) => (self.positional.get(&event.code.to_string()), true),
+        };
+        let Some(mapping) = mapping else {
+            log::warn!("Key {} ({}) is not mapped to C64", event.key, event.code);
+            return Vec::new();
+        };
+
+        let mut keys = Vec::with_capacity(4);
+        if shift && event.modifiers.shift() {
+            keys.push(C64KeyCode::LShift);
+        }
+        if event.modifiers.ctrl() {
+            keys.push(C64KeyCode::Ctrl);
+        }
+        if event.modifiers.alt() {
+            keys.push(C64KeyCode::Cmd); // Alt maps to Commodore key
+        }
+        for kc in mapping {
+            if !keys.iter().any(|k| *k as u8 == *kc as u8) {
+                keys.push(*kc);
+            }
+        }
+
+        if event.state == KeyState::Up {
+            keys.reverse()
+        }
+        keys
+    }
+}
+
+fn builtin(name: &str) -> Option<&'static str> {
+    match name {
+        "us" => Some(US_KEYMAP),
+        "uk" => Some(UK_KEYMAP),
+        "de" => Some(DE_KEYMAP),
+        _ => None,
+    }
+}
+
+/// Overrides the base mappings; empty ones are removed
+fn merge_mapping(
+    mut base: HashMap<String, Vec<C64KeyCode>>,
+    mapping: HashMap<String, Vec<C64KeyCode>>,
+) -> HashMap<String, Vec<C64KeyCode>> {
+    base.extend(mapping);
+    base.retain(|_, keys| !keys.is_empty());
+    base
+}
+
+fn parse_mapping(table: &toml::Table, name: &str) -> Result<HashMap<String, Vec<C64KeyCode>>> {
+    let Some(section) = table.get(name) else {
+        return Ok(HashMap::new());
+    };
+    let Some(section) = section.as_table() else {
+        bail!("Keymap section [{name}] must be a table");
+    };
+    section
+        .iter()
+        .map(|(key, val)| {
+            let names = val
+                .as_array()
+                .ok_or_else(|| anyhow!("Mapping of '{key}' must be an array of C64 keys"))?;
+            let codes = names
+                .iter()
+                .map(|n| {
+                    n.as_str()
+                        .and_then(C64KeyCode::from_name)
+                        .ok_or_else(|| anyhow!("Unknown C64 key {n} (mapping of '{key}')"))
+                })
+                .collect::<Result<Vec<C64KeyCode>>>()?;
+            Ok((key.clone(), codes))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use keyboard_types::{Code, Modifiers};
+
+    fn keymap_event(keymap: &Keymap, key: Key, code: Code, modifiers: Modifiers) -> Vec<u8> {
+        let event = KeyboardEvent {
+            key,
+            code,
+            modifiers,
+            ..Default::default()
+        };
+        keymap.map_event(&event).iter().map(|kc| *kc as u8).collect()
+    }
+
+    fn event(key: Key, code: Code, modifiers: Modifiers) -> Vec<u8> {
+        keymap_event(&Keymap::default(), key, code, modifiers)
+    }
+
+    #[test]
+    fn test_builtin_keymaps() {
+        assert!(Keymap::load("us").is_ok());
+        let uk = Keymap::load("uk").unwrap();
+        let de = Keymap::load("de").unwrap();
+        assert_eq!((uk.name.as_str(), de.name.as_str()), ("UK", "DE"));
+
+        let umlaut = || Key::Character("ü".to_string());
+        assert_eq!(keymap_event(&de, umlaut(), Code::BracketLeft, Modifiers::empty()), [0x2e]);
+        assert!(keymap_event(&uk, umlaut(), Code::BracketLeft, Modifiers::empty()).is_empty());
+    }
+
+    #[test]
+    fn test_inherited_keymap() {
+        let mut uk = Keymap::load("uk").unwrap();
+        assert_eq!(uk.mode, KeymapMode::Symbolic);
+        let pound = Key::Character("£".to_string());
+        assert_eq!(keymap_event(&uk, pound, Code::Digit3, Modifiers::SHIFT), [0x30]);
+
+        // the key next to Enter is at another place, End is not mapped at all
+        uk.mode = KeymapMode::Positional;
+        let us = Keymap {
+            mode: KeymapMode::Positional,
+            ..Keymap::default()
+        };
+        assert_eq!(keymap_event(&uk, Key::End, Code::End, Modifiers::empty()), []);
+        assert_eq!(keymap_event(&us, Key::End, Code::End, Modifiers::empty()), [0x35]);
+        assert_eq!(keymap_event(&uk, Key::Enter, Code::Backslash, Modifiers::empty()), [0x35]);
+        assert_eq!(keymap_event(&uk, Key::Enter, Code::KeyA, Modifiers::empty()), [0x0a]);
+
+        let custom = Keymap::from_toml("base = 'de'\nmode = 'positional'").unwrap();
+        assert_eq!((custom.name.as_str(), custom.mode), ("DE", KeymapMode::Positional));
+        assert!(Keymap::from_toml("base = 'fr'").is_err());
+    }
+
+    #[test]
+    fn test_symbolic_mapping() {
+        let quote = Key::Character("\"".to_string());
+        assert_eq!(event(quote, Code::Quote, Modifiers::SHIFT), [0x0f, 0x3b]);
+        assert_eq!(event(Key::Home, Code::Home, Modifiers::SHIFT), [0x0f, 0x33]);
+        assert_eq!(event(Key::ArrowUp, Code::ArrowUp, Modifiers::SHIFT), [0x0f, 0x07]);
+    }
+}
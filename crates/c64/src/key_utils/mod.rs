@@ -1,11 +1,12 @@
 mod character_sets;
 mod converters;
 mod key_code;
+mod keymap;
+mod petscii;
 mod scancodes;
-mod ui_events;
 
 pub use self::character_sets::*;
-pub use self::converters::*;
 pub use self::key_code::*;
+pub use self::keymap::*;
+pub use self::petscii::*;
 pub use self::scancodes::*;
-pub use self::ui_events::*;
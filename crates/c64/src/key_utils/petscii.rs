@@ -0,0 +1,121 @@
+use super::C64KeyCode::{self, *};
+
+/// Names of special keys/codes that can be used in typed text, i.e. `{CLR}`
+const CONTROL_CODES: [(&str, u8); 22] = [
+    ("STOP", 0x03),
+    ("RETURN", 0x0d),
+    ("DOWN", 0x11),
+    ("RVS ON", 0x12),
+    ("HOME", 0x13),
+    ("DEL", 0x14),
+    ("RIGHT", 0x1d),
+    ("POUND", 0x5c),
+    ("RUN", 0x83),
+    ("F1", 0x85),
+    ("F3", 0x86),
+    ("F5", 0x87),
+    ("F7", 0x88),
+    ("F2", 0x89),
+    ("F4", 0x8a),
+    ("F6", 0x8b),
+    ("F8", 0x8c),
+    ("UP", 0x91),
+    ("RVS OFF", 0x92),
+    ("CLR", 0x93),
+    ("INST", 0x94),
+    ("LEFT", 0x9d),
+];
+
+/// Converts text to PETSCII (upper case / graphics set).
+/// Letters are converted to upper case (unshifted on C64), so listings
+/// can be typed in any case. Special keys can be given in curly brackets,
+/// i.e. `{CLR}`, `{HOME}`, `{F1}`, `{RVS ON}`; new line is RETURN.
+pub fn text_to_petscii(text: &str) -> Vec<u8> {
+    let mut petscii = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            'a'..='z' => petscii.push(ch.to_ascii_uppercase() as u8),
+            ' '..='_' => petscii.push(ch as u8),
+            '\n' => petscii.push(0x0d),
+            '\r' => {}
+            '£' => petscii.push(0x5c),
+            '{' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                match control_code(&name) {
+                    Some(code) => petscii.push(code),
+                    None => log::warn!("Unknown key name: {{{name}}}"),
+                }
+            }
+            _ => log::warn!("Character '{ch}' can't be typed on C64"),
+        }
+    }
+    petscii
+}
+
+fn control_code(name: &str) -> Option<u8> {
+    let name = name.to_ascii_uppercase();
+    CONTROL_CODES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, code)| *code)
+}
+
+/// Keys to be pressed together to type given PETSCII character
+/// (modifier first), or None if the character can't be typed
+pub fn petscii_to_keys(code: u8) -> Option<Vec<C64KeyCode>> {
+    let (key, shift) = match code {
+        0x12 => return Some(vec![Ctrl, Key_9]),
+        0x92 => return Some(vec![Ctrl, Key_0]),
+        0x41..=0x5a => (C64KeyCode::from_char((code + 0x20) as char)?, false),
+        0xc1..=0xda => (C64KeyCode::from_char((code - 0x60) as char)?, true),
+        0x30..=0x39 => (C64KeyCode::from_char(code as char)?, false),
+        0x21..=0x29 => (C64KeyCode::from_char((code + 0x10) as char)?, true),
+        0x20 | 0xa0 => (Space, code > 0x7f),
+        0x0d | 0x8d => (Return, code > 0x7f),
+        0x14 | 0x94 => (Delete, code > 0x7f),
+        0x13 | 0x93 => (Home, code > 0x7f),
+        0x11 | 0x91 => (CursorUD, code > 0x7f),
+        0x1d | 0x9d => (CursorLR, code > 0x7f),
+        0x03 | 0x83 => (Stop, code > 0x7f),
+        0x85 | 0x89 => (F1, code == 0x89),
+        0x86 | 0x8a => (F3, code == 0x8a),
+        0x87 | 0x8b => (F5, code == 0x8b),
+        0x88 | 0x8c => (F7, code == 0x8c),
+        0x2b | 0xdb => (Plus, code > 0x7f),
+        0x2d | 0xdd => (Minus, code > 0x7f),
+        0x2e | 0x3e => (Dot, code == 0x3e),
+        0x2c | 0x3c => (Comma, code == 0x3c),
+        0x2f | 0x3f => (Slash, code == 0x3f),
+        0x3a | 0x5b => (Colon, code == 0x5b),
+        0x3b | 0x5d => (Semicolon, code == 0x5d),
+        0x40 | 0xba => (At, code > 0x7f),
+        0x2a | 0xc0 => (Asterix, code > 0x7f),
+        0x5c | 0xa9 => (Pound, code > 0x7f),
+        0x5e | 0xde | 0xff => (UpArrow, code > 0x7f),
+        0x3d => (Equal, false),
+        0x5f => (LeftArrow, false),
+        _ => return None,
+    };
+    Some(if shift { vec![LShift, key] } else { vec![key] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_to_keys() {
+        let petscii = text_to_petscii("load\"*\",8\n{clr}");
+        assert_eq!(petscii, b"LOAD\"*\",8\r\x93");
+        let keys: Vec<Vec<u8>> = petscii
+            .iter()
+            .map(|c| petscii_to_keys(*c).unwrap().iter().map(|k| *k as u8).collect())
+            .collect();
+        assert_eq!(keys[4], [0x0f, 0x3b]);
+        assert_eq!(keys[6], [0x0f, 0x3b]);
+        assert_eq!(keys[8], [0x1b]);
+        assert_eq!(keys[9], [0x01]);
+        assert_eq!(keys[10], [0x0f, 0x33]);
+    }
+}
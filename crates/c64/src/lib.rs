@@ -1,9 +1,13 @@
+#![allow(clippy::module_inception)]
+
 #[macro_use]
 extern crate lazy_static;
 
+pub mod audio;
 pub(crate) mod c64;
 pub(crate) mod client;
 pub mod key_utils;
+pub mod sid_player;
 
 pub use self::c64::*;
 pub use self::client::*;
@@ -1,23 +1,50 @@
+#![allow(clippy::module_inception)]
+
 #[macro_use]
 extern crate lazy_static;
 extern crate colored;
 
 use clap::Parser;
-use machine::cli::create_machine_from_cli_args;
 
+pub mod audio;
 pub mod c64;
 pub mod client;
 pub mod key_utils;
+pub mod sid_player;
 
-use crate::c64::C64;
+use crate::c64::{Vsf, C64};
 use crate::client::C64Client;
-use anyhow;
-use machine::{cli::Args, client::NonInteractiveClient};
+use crate::sid_player::{SIDPlayer, PSID};
+use machine::{
+    cli::{create_machine_from_profile, get_file_as_byte_vec, get_profile, Args},
+    client::NonInteractiveClient,
+    Machine,
+};
+use std::path::PathBuf;
+
+/// Default length of a tune rendered in player mode [s]
+const DEFAULT_SONG_LENGTH: u64 = 180;
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    let c64: C64 = create_machine_from_cli_args()?;
+    let profile = get_profile(Args::parse())?;
+    let args = &profile.config;
+    let c64: C64 = create_machine_from_profile(&profile)?;
+
+    if let Some(sid_file) = &args.sid {
+        return play_sid(c64, args, sid_file);
+    }
+
     let mut client = C64Client::new(c64);
+    if let Some(path) = &args.load_snapshot {
+        let data = get_file_as_byte_vec(path)?;
+        match Vsf::is_vsf(&data) {
+            true => client
+                .import_vsf(&data)?
+                .iter()
+                .for_each(|module| eprintln!("Module of the VICE snapshot skipped: {module}")),
+            false => client.load_snapshot(&data)?,
+        }
+    }
     client.start_sync()?;
 
     if args.show_status {
@@ -29,5 +56,35 @@ fn main() -> anyhow::Result<()> {
     }
 
     client.stop()?;
+    if let Some(path) = &args.save_snapshot_on_exit {
+        std::fs::write(path, client.save_snapshot()?)?;
+    }
+    if let Some(path) = &args.save_vsf_on_exit {
+        std::fs::write(path, client.export_vsf())?;
+    }
+    Ok(())
+}
+
+fn play_sid(mut c64: C64, args: &Args, sid_file: &PathBuf) -> anyhow::Result<()> {
+    if args.audio_out.is_none() {
+        return Err(anyhow::Error::msg("Player mode requires --audio-out"));
+    }
+
+    let tune = PSID::parse(&get_file_as_byte_vec(sid_file)?)?;
+    if args.sid_model.is_none() {
+        if let Some(model) = tune.sid_model {
+            c64.set_sid_model(model);
+        }
+    }
+    println!("{} - {} ({})", tune.author, tune.name, tune.released);
+
+    let song = args.song.unwrap_or(tune.start_song);
+    let mut player = SIDPlayer::new(c64, tune)?;
+    player.init(song)?;
+    player.render(args.seconds.unwrap_or(DEFAULT_SONG_LENGTH));
+
+    if args.show_status {
+        println!("{}", player.machine().cpu().registers);
+    }
     Ok(())
 }
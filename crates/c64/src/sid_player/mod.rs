@@ -0,0 +1,4 @@
+mod psid;
+mod sid_player;
+
+pub use {psid::*, sid_player::*};
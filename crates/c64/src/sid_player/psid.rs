@@ -0,0 +1,186 @@
+use crate::c64::SIDModel;
+use anyhow::{bail, Result};
+
+const MIN_HEADER_SIZE: usize = 0x76;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PSIDKind {
+    PSID,
+    RSID,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PSIDClock {
+    PAL,
+    NTSC,
+}
+
+/// Content of a .sid file (both PSID and RSID variants).
+/// See [SID file format](https://www.hvsc.c64.org/download/C64Music/DOCUMENTS/SID_file_format.txt)
+#[derive(Debug, Clone)]
+pub struct PSID {
+    pub kind: PSIDKind,
+    pub version: u16,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub songs: u16,
+    pub start_song: u16,
+    pub speed: u32,
+    pub name: String,
+    pub author: String,
+    pub released: String,
+    pub clock: Option<PSIDClock>,
+    pub sid_model: Option<SIDModel>,
+    pub start_page: u8,
+    pub page_length: u8,
+    pub data: Vec<u8>,
+}
+
+impl PSID {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < MIN_HEADER_SIZE {
+            bail!("File is too short to be a SID file");
+        }
+        let kind = match &bytes[0..4] {
+            b"PSID" => PSIDKind::PSID,
+            b"RSID" => PSIDKind::RSID,
+            _ => bail!("Not a SID file (expected PSID or RSID magic id)"),
+        };
+        let word = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let text = |i: usize| {
+            String::from_utf8_lossy(&bytes[i..i + 32])
+                .trim_end_matches('\0')
+                .to_string()
+        };
+
+        let version = word(0x04);
+        let data_offset = word(0x06) as usize;
+        if data_offset < MIN_HEADER_SIZE || data_offset + 2 > bytes.len() {
+            bail!("Invalid data offset: {data_offset:#06x}");
+        }
+
+        let (flags, start_page, page_length) = if version >= 2 && data_offset >= 0x7c {
+            (word(0x76), bytes[0x78], bytes[0x79])
+        } else {
+            (0, 0, 0)
+        };
+
+        // if load address is 0, the data starts with C64 program header (LE word)
+        let mut load_addr = word(0x08);
+        let mut data = &bytes[data_offset..];
+        if load_addr == 0 {
+            load_addr = u16::from_le_bytes([data[0], data[1]]);
+            data = &data[2..];
+        }
+        if load_addr as usize + data.len() > 0x10000 {
+            bail!("SID data doesn't fit into memory");
+        }
+
+        let init_addr = match word(0x0a) {
+            0 => load_addr,
+            addr => addr,
+        };
+
+        let psid = PSID {
+            kind,
+            version,
+            load_addr,
+            init_addr,
+            play_addr: word(0x0c),
+            songs: word(0x0e).max(1),
+            start_song: word(0x10).max(1),
+            speed: u32::from_be_bytes([bytes[0x12], bytes[0x13], bytes[0x14], bytes[0x15]]),
+            name: text(0x16),
+            author: text(0x36),
+            released: text(0x56),
+            clock: match (flags >> 2) & 0b11 {
+                0b01 => Some(PSIDClock::PAL),
+                0b10 => Some(PSIDClock::NTSC),
+                _ => None,
+            },
+            sid_model: match (flags >> 4) & 0b11 {
+                0b01 => Some(SIDModel::MOS6581),
+                0b10 => Some(SIDModel::MOS8580),
+                _ => None,
+            },
+            start_page,
+            page_length,
+            data: data.to_vec(),
+        };
+
+        if psid.kind == PSIDKind::RSID && (psid.play_addr != 0 || psid.speed != 0) {
+            bail!("Invalid RSID file: play address and speed must be 0");
+        }
+
+        Ok(psid)
+    }
+
+    /// Checks whether given song (1-based) is timed with CIA timer (rather than 50/60Hz VBI)
+    pub fn is_cia_speed(&self, song: u16) -> bool {
+        if self.kind == PSIDKind::RSID {
+            return true;
+        }
+        let bit = (song.max(1) - 1).min(31);
+        self.speed & (1 << bit) > 0
+    }
+
+    /// Address range occupied by the tune's data
+    pub fn data_range(&self) -> std::ops::Range<usize> {
+        let start = self.load_addr as usize;
+        start..(start + self.data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(magic: &[u8; 4], load: u16, play: u16, speed: u32) -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x7c];
+        bytes[0..4].copy_from_slice(magic);
+        bytes[0x04..0x06].copy_from_slice(&2u16.to_be_bytes());
+        bytes[0x06..0x08].copy_from_slice(&0x7cu16.to_be_bytes());
+        bytes[0x08..0x0a].copy_from_slice(&load.to_be_bytes());
+        bytes[0x0c..0x0e].copy_from_slice(&play.to_be_bytes());
+        bytes[0x0e..0x10].copy_from_slice(&3u16.to_be_bytes());
+        bytes[0x12..0x16].copy_from_slice(&speed.to_be_bytes());
+        bytes[0x16..0x1a].copy_from_slice(b"Test");
+        bytes[0x76..0x78].copy_from_slice(&0b100100u16.to_be_bytes()); // PAL, 8580
+        bytes
+    }
+
+    #[test]
+    fn test_parse_psid() {
+        let mut bytes = header(b"PSID", 0x1000, 0x1003, 0b10);
+        bytes.extend_from_slice(&[0x60, 0x60, 0x60, 0x60]);
+        let psid = PSID::parse(&bytes).unwrap();
+        assert_eq!(PSIDKind::PSID, psid.kind);
+        assert_eq!(0x1000, psid.load_addr);
+        assert_eq!(0x1000, psid.init_addr);
+        assert_eq!(0x1003, psid.play_addr);
+        assert_eq!(3, psid.songs);
+        assert_eq!("Test", psid.name);
+        assert_eq!(Some(PSIDClock::PAL), psid.clock);
+        assert_eq!(Some(SIDModel::MOS8580), psid.sid_model);
+        assert!(!psid.is_cia_speed(1));
+        assert!(psid.is_cia_speed(2));
+        assert_eq!(0x1000..0x1004, psid.data_range());
+    }
+
+    #[test]
+    fn test_load_address_from_data() {
+        let mut bytes = header(b"PSID", 0, 0x1003, 0);
+        bytes.extend_from_slice(&[0x00, 0x20, 0x60]);
+        let psid = PSID::parse(&bytes).unwrap();
+        assert_eq!(0x2000, psid.load_addr);
+        assert_eq!(vec![0x60], psid.data);
+    }
+
+    #[test]
+    fn test_invalid_rsid() {
+        let mut bytes = header(b"RSID", 0x1000, 0x1003, 0);
+        bytes.push(0x60);
+        assert!(PSID::parse(&bytes).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use super::{PSIDKind, PSID};
+use crate::c64::C64;
+use anyhow::{bail, Result};
+use machine::{
+    emulator::{abstractions::Accessor, components::CIA_6526},
+    Machine, MachineStatus, RegSetter,
+};
+
+/// How long init routine can run before giving up [s]
+const INIT_TIMEOUT: u64 = 5;
+
+const DEFAULT_DRIVER_ADDR: u16 = 0x0334;
+
+// Minimal replacements of KERNAL's interrupt handling, so tunes
+// that hook into $0314 vector (or jump to $EA31/$EA81) work without KERNAL
+const KERNAL_IRQ_ENTRY: u16 = 0xff48;
+const KERNAL_IRQ_HANDLER: u16 = 0xea31;
+const KERNAL_IRQ_EXIT: u16 = 0xea81;
+
+/// Plays PSID/RSID tunes on a bare C64 (without BASIC and KERNAL ROMs).
+/// It installs a small driver in RAM that calls the tune's play routine
+/// on every CIA1 timer A interrupt, running at the frame rate (VBI tunes)
+/// or at the rate set by the tune itself (CIA tunes).
+pub struct SIDPlayer {
+    c64: C64,
+    tune: PSID,
+    driver: u16,
+    song: u16,
+}
+
+impl SIDPlayer {
+    pub fn new(mut c64: C64, tune: PSID) -> Result<Self> {
+        let driver = Self::driver_addr(&tune)?;
+        c64.start();
+        c64.load(&tune.data, tune.load_addr);
+        let song = tune.start_song;
+        let mut player = SIDPlayer {
+            c64,
+            tune,
+            driver,
+            song,
+        };
+        player.install_driver();
+        Ok(player)
+    }
+
+    /// Finds a free page for the driver: either the one declared in the file
+    /// or - if not specified - the tape buffer or first page after the tune
+    fn driver_addr(tune: &PSID) -> Result<u16> {
+        let range = tune.data_range();
+        match tune.start_page {
+            0xff => bail!("The tune doesn't leave any free memory for the driver"),
+            0 => {
+                let fits = |addr: usize| range.end <= addr || range.start >= addr + 0x100;
+                if fits(DEFAULT_DRIVER_ADDR as usize) {
+                    return Ok(DEFAULT_DRIVER_ADDR);
+                }
+                (0x04..0xd0)
+                    .map(|page: usize| page << 8)
+                    .find(|addr| fits(*addr))
+                    .map(|addr| addr as u16)
+                    .ok_or(anyhow::Error::msg("No free memory for the driver"))
+            }
+            page => Ok((page as u16) << 8),
+        }
+    }
+
+    fn install_driver(&mut self) {
+        let base = self.driver;
+        let [base_lo, base_hi] = base.to_le_bytes();
+        let irq = base + 3;
+
+        // processor port - see "Environment" section of the SID file format
+        self.c64.write_byte(0x0000, 0x2f);
+        let port = match self.tune.init_addr {
+            0x0000..=0x9fff => 0x37,
+            0xa000..=0xcfff => 0x36,
+            0xe000..=0xffff => 0x35,
+            _ => 0x34,
+        };
+        self.c64.write_byte(0x0001, port);
+
+        if self.tune.play_addr != 0 {
+            let [play_lo, play_hi] = self.tune.play_addr.to_le_bytes();
+            #[rustfmt::skip]
+            let code = [
+                0x4c, base_lo, base_hi,   // trap: JMP trap
+                0x48, 0x8a, 0x48, 0x98,   // irq:  PHA, TXA, PHA, TYA
+                0x48, 0xad, 0x0d, 0xdc,   //       PHA, LDA $DC0D (ack CIA)
+                0x20, play_lo, play_hi,   //       JSR play
+                0x68, 0xa8, 0x68, 0xaa,   //       PLA, TAY, PLA, TAX
+                0x68, 0x40,               //       PLA, RTI
+            ];
+            self.c64.load(&code, base);
+            self.write_word(0xfffe, irq);
+            self.write_word(0xfffa, base + code.len() as u16 - 1);
+        } else {
+            // the tune installs its own interrupt handler
+            self.c64.load(&[0x4c, base_lo, base_hi], base);
+            self.c64
+                .load(&[0x48, 0x8a, 0x48, 0x98, 0x48, 0x6c, 0x14, 0x03], KERNAL_IRQ_ENTRY);
+            self.c64
+                .load(&[0xad, 0x0d, 0xdc, 0x4c, 0x81, 0xea], KERNAL_IRQ_HANDLER);
+            self.c64
+                .load(&[0x68, 0xa8, 0x68, 0xaa, 0x68, 0x40], KERNAL_IRQ_EXIT);
+            self.write_word(0x0314, KERNAL_IRQ_HANDLER);
+            self.write_word(0xfffe, KERNAL_IRQ_ENTRY);
+            self.write_word(0xfffa, KERNAL_IRQ_EXIT + 5);
+        }
+    }
+
+    fn write_word(&mut self, addr: u16, val: u16) {
+        self.c64.load(&val.to_le_bytes(), addr);
+    }
+
+    /// Calls init routine for given song (1-based)
+    pub fn init(&mut self, song: u16) -> Result<()> {
+        if song == 0 || song > self.tune.songs {
+            bail!("Song {song} doesn't exist (the tune has {} songs)", self.tune.songs);
+        }
+
+        self.song = song;
+        self.setup_timer();
+        let trap = self.driver;
+        let [hi, lo] = trap.wrapping_sub(1).to_be_bytes();
+        self.c64.cpu_mut().registers.status.interrupt_disable = true;
+        self.c64.push(hi);
+        self.c64.push(lo);
+        self.c64.set_A((song - 1) as u8);
+        self.c64.set_PC(self.tune.init_addr);
+
+        let end = self.c64.get_cycles() + INIT_TIMEOUT * self.c64.clock_frequency() as u64;
+        while self.c64.PC() != trap && self.c64.get_cycles() < end {
+            self.c64.next();
+        }
+
+        // RSID init routines are allowed to never return
+        if self.c64.PC() != trap && self.tune.kind == PSIDKind::PSID {
+            log::warn!("Init routine of song {song} didn't return");
+        }
+        Ok(())
+    }
+
+    /// Runs the machine for given number of (emulated) seconds
+    pub fn render(&mut self, seconds: u64) {
+        let end = self.c64.get_cycles() + seconds * self.c64.clock_frequency() as u64;
+        self.c64.cpu_mut().registers.status.interrupt_disable = false;
+        while self.c64.get_cycles() < end && self.c64.get_status() != MachineStatus::Stopped {
+            self.c64.next();
+        }
+        self.c64.stop();
+    }
+
+    /// Programs CIA1 timer A to call the play routine at the frame rate (VBI tunes)
+    /// or at KERNAL's default rate (CIA tunes that may reprogram the timer later)
+    fn setup_timer(&mut self) {
+        let latch = if self.tune.is_cia_speed(self.song) {
+            self.c64.model().jiffy_timer()
+        } else {
+            (self.c64.frame_cycles() - 1) as u16
+        };
+        let [lo, hi] = latch.to_le_bytes();
+        // the chip is accessed directly, so it must be synced with the clock
+        self.c64.sync_devices();
+        {
+            let mut cia = self.c64.cia1().lock();
+            CIA_6526::write_byte(&mut *cia, 0x04, lo);
+            CIA_6526::write_byte(&mut *cia, 0x05, hi);
+            CIA_6526::write_byte(&mut *cia, 0x0d, 0x81);
+            CIA_6526::write_byte(&mut *cia, 0x0e, 0x11);
+        }
+        self.c64.sync_devices();
+    }
+
+    pub fn machine(&self) -> &C64 {
+        &self.c64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{WavWriter, DEFAULT_SAMPLE_RATE};
+    use machine::MachineConfig;
+
+    const PLAYS: u16 = 0x0002;
+    const INIT_A: u16 = 0x0003;
+
+    /// Tune with 2 songs: the first one at the frame rate, the second one at the CIA rate
+    fn tune() -> PSID {
+        #[rustfmt::skip]
+        let data = vec![
+            0x85, 0x03,         // init: STA $03
+            0xa2, 0x00,         //       LDX #0
+            0x86, 0x02,         //       STX $02
+            0x60,               //       RTS
+            0xe6, 0x02,         // play: INC $02
+            0x60,               //       RTS
+        ];
+        PSID {
+            kind: PSIDKind::PSID,
+            version: 2,
+            load_addr: 0x1000,
+            init_addr: 0x1000,
+            play_addr: 0x1007,
+            songs: 2,
+            start_song: 1,
+            speed: 0b10,
+            name: "Test".to_string(),
+            author: String::new(),
+            released: String::new(),
+            clock: None,
+            sid_model: None,
+            start_page: 0,
+            page_length: 0,
+            data,
+        }
+    }
+
+    fn player(model: &str) -> SIDPlayer {
+        let mut config = MachineConfig::new();
+        config.model = Some(model.to_string());
+        SIDPlayer::new(C64::new(config), tune()).unwrap()
+    }
+
+    #[test]
+    fn test_init_and_play() {
+        // PAL frame: 19656 cycles, NTSC frame: 17095 cycles, CIA: 16422 cycles
+        for (model, song, plays) in [("pal", 1, 50), ("pal", 2, 60), ("ntsc", 1, 60)] {
+            let mut player = player(model);
+            player.init(song).unwrap();
+            assert_eq!(song as u8 - 1, player.c64.read_byte(INIT_A));
+            assert_eq!(0, player.c64.read_byte(PLAYS));
+
+            player.render(1);
+            let played = player.c64.read_byte(PLAYS) as i32;
+            assert!((played - plays).abs() <= 1, "{model} song {song}: {played} plays");
+        }
+
+        let mut player = player("pal");
+        assert!(player.init(0).is_err());
+        assert!(player.init(3).is_err());
+    }
+
+    #[test]
+    fn test_render_to_wav() {
+        let path = std::env::temp_dir().join(format!("sid_player_{}.wav", std::process::id()));
+        let mut c64 = C64::new(MachineConfig::new());
+        c64.set_audio_sink(Box::new(WavWriter::create(&path, DEFAULT_SAMPLE_RATE).unwrap()));
+        let mut player = SIDPlayer::new(c64, tune()).unwrap();
+        player.init(1).unwrap();
+        player.render(1);
+
+        // a sample is produced every clock / rate cycles since the power-on
+        let clock = player.machine().clock_frequency() as u64;
+        let samples = player.machine().get_cycles() * DEFAULT_SAMPLE_RATE as u64 / clock;
+        assert!(samples.abs_diff(DEFAULT_SAMPLE_RATE as u64) <= 1);
+
+        let wav = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(44 + 2 * samples as usize, wav.len());
+        let data_len = u32::from_le_bytes(wav[40..44].try_into().unwrap());
+        assert_eq!(2 * samples as u32, data_len);
+    }
+}
@@ -1,19 +1,43 @@
-use lazy_static;
+use crate::utils::{joystick::JoystickKeys, mouse::MouseMode};
+use std::time::Duration;
 
 lazy_static! {
     pub static ref CONFIG: Config<'static> = Config {
-        // machine screen colors
-        machine_screen_front: "#70a4b2",
-        machine_screen_back: "#352879",
+        // machine screen colors (indexes of the machine's palette)
+        machine_screen_front: 3,
+        machine_screen_back: 6,
 
-        memory_view_size: 200
+        memory_view_size: 200,
 
+        // most of the games use joystick in port 2
+        joystick_keys: JoystickKeys::Off,
+        joystick_port: 2,
+        joystick_release: Duration::from_millis(250),
+        mouse_mode: MouseMode::Off,
+
+        // quick-save slots are kept in <dir>/slot<n>.snap (in the working directory)
+        snapshot_dir: "snapshots",
+        snapshot_slots: 4,
+
+        // memory for the execution history (step back, time slider) [MB],
+        // unless it's set with --rewind
+        rewind_budget: 64,
     };
 }
 
 pub struct Config<'a> {
-    pub machine_screen_back: &'a str,
-    pub machine_screen_front: &'a str,
+    pub machine_screen_back: usize,
+    pub machine_screen_front: usize,
+
+    pub memory_view_size: u16,
+
+    pub joystick_keys: JoystickKeys,
+    pub joystick_port: u8,
+    pub joystick_release: Duration,
+    pub mouse_mode: MouseMode,
+
+    pub snapshot_dir: &'a str,
+    pub snapshot_slots: u8,
 
-    pub memory_view_size: u16
+    pub rewind_budget: usize,
 }
@@ -1,40 +1,94 @@
 use super::UIState;
+use crate::messaging::send_client_event;
+use c64::MachineState;
 use cursive::{
     event::{EventResult, Key},
-    theme::Style,
-    traits::{Nameable, With},
+    traits::{Nameable, Resizable, With},
     view::{scroll::Scroller, ScrollStrategy, Scrollable, View},
     views::*,
     Cursive,
 };
+use machine::{client::ClientEvent, debugger::RewindStatus};
 
-pub fn update_asm_view(s: &mut Cursive, last_op: &String, next_op: &String) {
-    let lines = if let Some(ud) = s.user_data::<UIState>() {
-        ud.asm_lines.pop();
-        if let Some(last) = ud.asm_lines.pop() {
-            ud.asm_lines.push(last.to_string().replacen(">", " ", 1));
-        }
-        ud.asm_lines.push(["> ",last_op].join(""));
-        ud.asm_lines.push(["  ",next_op].join(""));
-        if ud.asm_lines.len() > 100 {
-            ud.asm_lines.remove(0);
+/// Number of values of the history slider
+const SLIDER_STEPS: usize = 64;
+
+/// Executed instructions are taken from the execution history (if it's recorded);
+/// selecting one moves the machine back to the moment after it was executed
+pub fn update_asm_view(s: &mut Cursive, state: &MachineState) {
+    let lines: Vec<(String, Option<u64>)> = if let Some(ud) = s.user_data::<UIState>() {
+        ud.rewind = state.rewind;
+        if state.history.is_empty() {
+            ud.asm_lines.pop();
+            if let Some(last) = ud.asm_lines.pop() {
+                ud.asm_lines.push(last.to_string().replacen(">", " ", 1));
+            }
+            ud.asm_lines.push(["> ", &state.last_op].join(""));
+            ud.asm_lines.push(["  ", &state.next_op].join(""));
+            if ud.asm_lines.len() > 100 {
+                ud.asm_lines.remove(0);
+            }
+            ud.asm_lines.iter().map(|line| (line.clone(), None)).collect()
+        } else {
+            let last = state.history.len() - 1;
+            let mut lines: Vec<_> = state
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, (pos, op))| {
+                    let marker = if i == last { "> " } else { "  " };
+                    ([marker, op].join(""), Some(pos + 1))
+                })
+                .collect();
+            lines.push((["  ", &state.next_op].join(""), None));
+            lines
         }
-        ud.asm_lines.join("\n")
     } else {
         log::warn!("No user data found in update_asm_view");
-        String::new()
+        Vec::new()
     };
 
-    s.call_on_name("asm", move |view: &mut TextView| {
-        view.set_content(lines);
+    s.call_on_name("asm", move |view: &mut SelectView<Option<u64>>| {
+        let selected = lines.len().saturating_sub(2);
+        view.clear();
+        view.add_all(lines);
+        let _ = view.set_selection(selected);
+    });
+    s.call_on_name("rewind_slider", |view: &mut SliderView| {
+        let _ = view.set_value(slider_value(&state.rewind));
+    });
+    s.call_on_name("rewind_info", |view: &mut TextView| {
+        let rewind = &state.rewind;
+        view.set_content(format!(
+            " -{} of {} instructions",
+            rewind.end - rewind.position,
+            rewind.end - rewind.start
+        ));
     });
 }
 
-pub fn get_asm_view() -> impl View {
-    let style = Style::default();
+fn slider_value(rewind: &RewindStatus) -> usize {
+    match rewind.end - rewind.start {
+        0 => SLIDER_STEPS - 1,
+        len => ((rewind.position - rewind.start) * (SLIDER_STEPS as u64 - 1) / len) as usize,
+    }
+}
 
-    TextView::new("")
-        .style(style)
+fn seek_handler(s: &mut Cursive, value: usize) {
+    if let Some(rewind) = s.user_data::<UIState>().map(|ud| ud.rewind) {
+        let len = rewind.end - rewind.start;
+        let position = rewind.start + len * value as u64 / (SLIDER_STEPS as u64 - 1);
+        send_client_event(ClientEvent::Seek(position));
+    }
+}
+
+pub fn get_asm_view() -> impl View {
+    let asm = SelectView::<Option<u64>>::new()
+        .on_submit(|_s, position: &Option<u64>| {
+            if let Some(position) = position {
+                send_client_event(ClientEvent::Seek(*position));
+            }
+        })
         .with_name("asm")
         .scrollable()
         .scroll_strategy(ScrollStrategy::StickToBottom)
@@ -54,7 +108,22 @@ pub fn get_asm_view() -> impl View {
             Some(EventResult::Consumed(None))
         })
         .wrap_with(|v| PaddedView::lrtb(0, 0, 0, 0, v))
-        .wrap_with(|v| ResizedView::with_fixed_height(10, v))
+        .wrap_with(|v| ResizedView::with_fixed_height(10, v));
+
+    // time slider of the execution history (Enter goes to the chosen moment)
+    let slider = LinearLayout::horizontal()
+        .child(TextView::new("History "))
+        .child(
+            SliderView::horizontal(SLIDER_STEPS)
+                .value(SLIDER_STEPS - 1)
+                .on_enter(seek_handler)
+                .with_name("rewind_slider"),
+        )
+        .child(TextView::new("").with_name("rewind_info").full_width());
+
+    LinearLayout::vertical()
+        .child(asm)
+        .child(slider)
         .wrap_with(|v| {
             let mut hv = HideableView::new(v);
             hv.hide();
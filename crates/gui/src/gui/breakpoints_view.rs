@@ -3,13 +3,16 @@ use cursive::{
     views::{Checkbox, LinearLayout, Panel, ResizedView, TextView},
     Cursive, View, With,
 };
-use machine::{client::ClientEvent, debugger::Breakpoint};
+use machine::{
+    client::ClientEvent,
+    debugger::{Breakpoint, Watchpoint},
+};
 
 use crate::messaging::send_client_event;
 
 
 
-pub fn init_breakpoints_view(s: &mut Cursive, bps: &Vec<Breakpoint>) {
+pub fn init_breakpoints_view(s: &mut Cursive, bps: &[Breakpoint], wps: &[Watchpoint]) {
     let content: Vec<Breakpoint> = bps
         .iter()
         .filter(|b| {
@@ -21,6 +24,7 @@ pub fn init_breakpoints_view(s: &mut Cursive, bps: &Vec<Breakpoint>) {
         })
         .map(|b| b.clone())
         .collect();
+    let watchpoints = wps.to_vec();
 
     s.call_on_name("breakpoints", move |view: &mut LinearLayout| {
         if view.is_empty() {
@@ -28,11 +32,22 @@ pub fn init_breakpoints_view(s: &mut Cursive, bps: &Vec<Breakpoint>) {
                 if let Breakpoint::Address(addr) = bp {
                     view.add_child(get_checkbox(*addr));
                 }
-            })
+            });
+            watchpoints
+                .iter()
+                .for_each(|wp| view.add_child(get_watchpoint_checkbox(*wp)));
         }
     });
 }
 
+/// Enables the watchpoint and adds it to the list
+pub fn add_watchpoint(s: &mut Cursive, wp: Watchpoint) {
+    send_client_event(ClientEvent::EnableWatchpoint(wp));
+    s.call_on_name("breakpoints", |view: &mut LinearLayout| {
+        view.add_child(get_watchpoint_checkbox(wp))
+    });
+}
+
 fn get_checkbox(addr: u16) -> impl View {
     LinearLayout::horizontal()
         .child(Checkbox::new().checked().on_change(move |_c, value| {
@@ -46,6 +61,19 @@ fn get_checkbox(addr: u16) -> impl View {
         .child(TextView::new(&format!("{:04x}", addr)))
 }
 
+fn get_watchpoint_checkbox(wp: Watchpoint) -> impl View {
+    LinearLayout::horizontal()
+        .child(Checkbox::new().checked().on_change(move |_c, value| {
+            send_client_event(if value {
+                ClientEvent::EnableWatchpoint(wp)
+            } else {
+                ClientEvent::DisableWatchpoint(wp)
+            });
+        }))
+        .child(TextView::new(" "))
+        .child(TextView::new(wp.to_string()))
+}
+
 pub fn get_breakpoints_view() -> impl View {
     // TextView::new("teste test")
     LinearLayout::vertical()
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -10,17 +11,30 @@ use cursive::{
     Cursive, CursiveRunnable,
 };
 use cursive_hexview::HexView;
-use machine::MachineStatus;
-use machine::{client::NonInteractiveClient, utils::lock, MachineError};
+use keyboard_types::{KeyState, Modifiers};
+use machine::{MachineStatus, ResetKind};
+use machine::{
+    client::{ClientEvent, NonInteractiveClient},
+    utils::lock,
+    MachineError,
+};
 
-use crate::gui::{address_dialog, main_screen, UIState};
+use crate::config::CONFIG;
+use crate::gui::{address_dialog, main_screen, type_in_dialog, watchpoint_dialog, UIState};
+use crate::messaging::send_client_event;
+use crate::utils::{
+    joystick::JoystickKeys, keyboard::key_event_from_ckey, mouse::MouseMode,
+};
 
-use super::{update_asm_view, update_variables_view, CpuState, MachineScreen};
+use super::{
+    update_asm_view, update_variables_view, CpuState, MachineScreen, StatusLine, VirtualKeyboard,
+};
 
 static FIRST_DEBUG: AtomicBool = AtomicBool::new(true);
 
-type AsmIsEasierThanThis = ResizedView<PaddedView<OnEventView<ScrollView<NamedView<TextView>>>>>;
+type AsmIsEasierThanThis = LinearLayout;
 type OnceUponAMidnightDreary = PaddedView<LinearLayout>;
+type KeyboardPanel = NamedView<VirtualKeyboard>;
 
 pub(crate) fn update_ui(state: &MachineState, s: &mut Cursive) {
     let addr = s.user_data::<UIState>().map_or(0, |data| data.addr_from);
@@ -37,9 +51,25 @@ pub(crate) fn update_ui(state: &MachineState, s: &mut Cursive) {
 
     s.call_on_name("machine_screen", move |view: &mut MachineScreen| {
         view.set_state(screen, state.character_set);
+        view.set_palette(&state.palette);
+    });
+
+    s.call_on_name("status_line", |view: &mut Canvas<StatusLine>| {
+        let status_line = view.state_mut();
+        status_line.status = state.status;
+        status_line.speed = state.speed;
+        status_line.warp = state.warp;
+        status_line.watch = match state.status {
+            MachineStatus::Running => None,
+            _ => state.debugger.watch_hit.as_ref().map(|hit| hit.to_string()),
+        };
+    });
+
+    s.call_on_name("virtual_keyboard", |view: &mut VirtualKeyboard| {
+        view.set_pressed(&state.pressed_keys);
     });
 
-    update_asm_view(s, &state.last_op, &state.next_op);
+    update_asm_view(s, state);
     update_variables_view(s, &state.debugger.variables);
 
     if FIRST_DEBUG.load(Ordering::Relaxed) && state.status == MachineStatus::Debug {
@@ -63,10 +93,10 @@ pub(crate) fn init_ui(client: Arc<Mutex<C64Client>>) -> CursiveRunnable {
         }
     };
 
-    let reset_handler = {
+    let reset_handler = |kind: ResetKind| {
         let arc = client.clone();
         move |_s: &mut Cursive| {
-            lock(&arc).reset().unwrap_or_else(handle_error);
+            lock(&arc).reset(kind).unwrap_or_else(handle_error);
         }
     };
 
@@ -98,23 +128,91 @@ pub(crate) fn init_ui(client: Arc<Mutex<C64Client>>) -> CursiveRunnable {
         }
     };
 
+    let mut snapshot_menu = menu::Tree::new();
+    for slot in 1..=CONFIG.snapshot_slots {
+        snapshot_menu.add_leaf(
+            format!("Quick save: slot {slot}"),
+            create_quick_save_handler(client.clone(), slot),
+        );
+    }
+    snapshot_menu.add_delimiter();
+    for slot in 1..=CONFIG.snapshot_slots {
+        snapshot_menu.add_leaf(
+            format!("Quick load: slot {slot}"),
+            create_quick_load_handler(client.clone(), slot),
+        );
+    }
+
     let screen = main_screen();
 
     siv.menubar()
         .add_subtree(
             "Machine",
             menu::Tree::new()
-                .leaf("Restart", reset_handler)
+                .leaf("RESTORE [PgUp]", restore_handler)
+                .leaf("RUN/STOP + RESTORE [Shift+PgUp]", stop_restore_handler)
+                .leaf("Type in from file...", |s| s.add_layer(type_in_dialog()))
+                .delimiter()
+                .subtree(
+                    "Restart",
+                    menu::Tree::new()
+                        .leaf("Reset (keep memory)", reset_handler(ResetKind::Soft))
+                        .leaf("Power cycle", reset_handler(ResetKind::Hard)),
+                )
                 .leaf("Stop (and quit)", quit_handler.clone()),
         )
+        .add_subtree("Snapshot", snapshot_menu)
+        .add_subtree(
+            "Speed",
+            menu::Tree::new()
+                .leaf("Warp on/off", |s| {
+                    let warp = s
+                        .call_on_name("status_line", |view: &mut Canvas<StatusLine>| {
+                            view.state_mut().warp
+                        })
+                        .unwrap_or(false);
+                    send_client_event(ClientEvent::SetWarp(!warp));
+                })
+                .delimiter()
+                .leaf("25%", create_speed_handler(25))
+                .leaf("50%", create_speed_handler(50))
+                .leaf("100%", create_speed_handler(100))
+                .leaf("200%", create_speed_handler(200)),
+        )
         .add_subtree(
             "Debug",
             menu::Tree::new()
                 .leaf("Go to address [F6]", |s| s.add_layer(address_dialog()))
                 .leaf("Toggle debugging [F7]", debug_handler.clone())
                 .leaf("Next step [F8]", next_handler.clone())
+                .leaf("Step back [Shift+F8]", step_back_handler)
+                .leaf("Reverse continue [Shift+F7]", reverse_continue_handler)
+                .leaf("Add watchpoint...", |s| s.add_layer(watchpoint_dialog()))
                 .leaf("Skip interrupts", |_s| {}),
         )
+        .add_subtree(
+            "Controllers",
+            menu::Tree::new()
+                .leaf("Keys: off", create_joystick_keys_handler(JoystickKeys::Off))
+                .leaf(
+                    "Keys: numeric keypad",
+                    create_joystick_keys_handler(JoystickKeys::Numpad),
+                )
+                .leaf(
+                    "Keys: arrows + space",
+                    create_joystick_keys_handler(JoystickKeys::Cursor),
+                )
+                .delimiter()
+                .leaf("Mouse: off", create_mouse_mode_handler(MouseMode::Off))
+                .leaf("Mouse: 1351 mouse", create_mouse_mode_handler(MouseMode::Mouse1351))
+                .leaf("Mouse: paddles", create_mouse_mode_handler(MouseMode::Paddles))
+                .delimiter()
+                .leaf("Swap ports", |s| {
+                    s.call_on_name("machine_screen", |view: &mut MachineScreen| {
+                        view.swap_joystick_ports()
+                    });
+                }),
+        )
         .add_subtree(
             "View",
             menu::Tree::new()
@@ -127,7 +225,8 @@ pub(crate) fn init_ui(client: Arc<Mutex<C64Client>>) -> CursiveRunnable {
                 .leaf(
                     "Toggle variables/breakpoints view [F3]",
                     create_toggle_handler::<OnceUponAMidnightDreary>("variables_panel"),
-                ),
+                )
+                .leaf("Toggle C64 keyboard [F12]", keyboard_panel_handler),
         )
         .add_leaf("Quit", quit_handler.clone());
 
@@ -136,6 +235,10 @@ pub(crate) fn init_ui(client: Arc<Mutex<C64Client>>) -> CursiveRunnable {
     siv.add_global_callback(Key::F6, |s| s.add_layer(address_dialog()));
     siv.add_global_callback(Key::F7, debug_handler);
     siv.add_global_callback(Key::F8, next_handler);
+    siv.add_global_callback(Event::Shift(Key::F8), step_back_handler);
+    siv.add_global_callback(Event::Shift(Key::F7), reverse_continue_handler);
+    siv.add_global_callback(Key::PageUp, restore_handler);
+    siv.add_global_callback(Event::Shift(Key::PageUp), stop_restore_handler);
     siv.add_global_callback(Event::Char('`'), cursive::Cursive::toggle_debug_console);
     siv.add_global_callback(
         Key::F3,
@@ -145,6 +248,7 @@ pub(crate) fn init_ui(client: Arc<Mutex<C64Client>>) -> CursiveRunnable {
         Key::F2,
         create_toggle_handler::<OnceUponAMidnightDreary>("variables_panel"),
     );
+    siv.add_global_callback(Key::F12, keyboard_panel_handler);
 
     siv.add_layer(screen);
     siv.set_user_data(UIState::new());
@@ -174,6 +278,105 @@ fn create_toggle_handler<V: ViewWrapper>(name: &str) -> impl Fn(&mut Cursive) +
     }
 }
 
+// focuses the keyboard when shown, so it can be used with the arrow keys
+fn keyboard_panel_handler(s: &mut Cursive) {
+    create_toggle_handler::<KeyboardPanel>("keyboard_panel")(s);
+    let visible = s.call_on_name("keyboard_panel", |view: &mut HideableView<KeyboardPanel>| {
+        view.is_visible()
+    });
+    let name = match visible {
+        Some(true) => "virtual_keyboard",
+        _ => "machine_screen",
+    };
+    if let Ok(result) = s.focus_name(name) {
+        result.process(s);
+    }
+}
+
+fn step_back_handler(_s: &mut Cursive) {
+    send_client_event(ClientEvent::StepBack);
+}
+
+// goes back to the last breakpoint
+fn reverse_continue_handler(_s: &mut Cursive) {
+    send_client_event(ClientEvent::ReverseContinue);
+}
+
+fn restore_handler(_s: &mut Cursive) {
+    send_client_event(ClientEvent::Restore(KeyState::Down));
+}
+
+// RUN/STOP must be held while RESTORE is pressed
+fn stop_restore_handler(_s: &mut Cursive) {
+    send_client_event(ClientEvent::KeyPress(key_event_from_ckey(
+        Key::Esc,
+        Modifiers::empty(),
+    )));
+    send_client_event(ClientEvent::Restore(KeyState::Down));
+}
+
+fn create_joystick_keys_handler(keys: JoystickKeys) -> impl Fn(&mut Cursive) {
+    move |s| {
+        s.call_on_name("machine_screen", |view: &mut MachineScreen| {
+            view.set_joystick_keys(keys)
+        });
+    }
+}
+
+// choosing a speed turns warp off
+fn create_speed_handler(percent: u32) -> impl Fn(&mut Cursive) {
+    move |_s| {
+        send_client_event(ClientEvent::SetWarp(false));
+        send_client_event(ClientEvent::SetSpeed(percent));
+    }
+}
+
+fn create_mouse_mode_handler(mode: MouseMode) -> impl Fn(&mut Cursive) {
+    move |s| {
+        s.call_on_name("machine_screen", |view: &mut MachineScreen| {
+            view.set_mouse_mode(mode)
+        });
+    }
+}
+
+fn snapshot_path(slot: u8) -> PathBuf {
+    PathBuf::from(CONFIG.snapshot_dir).join(format!("slot{slot}.snap"))
+}
+
+fn create_quick_save_handler(client: Arc<Mutex<C64Client>>, slot: u8) -> impl Fn(&mut Cursive) {
+    move |s| {
+        let result = lock(&client)
+            .save_snapshot()
+            .map_err(anyhow::Error::from)
+            .and_then(|data| {
+                std::fs::create_dir_all(CONFIG.snapshot_dir)?;
+                std::fs::write(snapshot_path(slot), data)?;
+                Ok(())
+            });
+        match result {
+            Ok(()) => log::info!("Snapshot saved in slot {slot}"),
+            Err(err) => snapshot_error(s, &format!("Couldn't save slot {slot}: {err}")),
+        }
+    }
+}
+
+fn create_quick_load_handler(client: Arc<Mutex<C64Client>>, slot: u8) -> impl Fn(&mut Cursive) {
+    move |s| {
+        let result = std::fs::read(snapshot_path(slot))
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(lock(&client).load_snapshot(&data)?));
+        match result {
+            Ok(()) => log::info!("Snapshot loaded from slot {slot}"),
+            Err(err) => snapshot_error(s, &format!("Couldn't load slot {slot}: {err}")),
+        }
+    }
+}
+
+fn snapshot_error(s: &mut Cursive, msg: &str) {
+    log::error!("{msg}");
+    s.add_layer(Dialog::info(msg));
+}
+
 fn set_visible<V: ViewWrapper>(s: &mut Cursive, name: &str, visible: bool) {
     s.call_on_name(name, |view: &mut HideableView<V>| {
         view.set_visible(visible);
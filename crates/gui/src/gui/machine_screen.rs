@@ -1,12 +1,15 @@
 #![allow(non_camel_case_types)]
 
 use crate::{config::CONFIG, messaging::send_client_event};
-use crate::utils::color::color;
+use crate::utils::color::palette_color;
+use crate::utils::joystick::{JoystickEmulation, JoystickKeys};
 use crate::utils::keyboard::map_key_event;
-use c64::key_utils::screen_code_to_ascii;
+use crate::utils::mouse::{MouseEmulation, MouseMode};
+use c64::{key_utils::screen_code_to_ascii, MachineModel};
 use cursive::{
     event::{Event, EventResult, MouseEvent},
     theme::{ColorStyle},
+    view::CannotFocus,
     Printer, Vec2, View, direction::Direction,
 };
 use keyboard_types::Key;
@@ -15,30 +18,30 @@ use machine::client::ClientEvent;
 pub struct MachineScreen {
     state: Vec<u8>,
     screen_size: Vec2,
+    palette: [u32; 16],
     color: ColorStyle,
     reversed_color: ColorStyle,
     frame_color: ColorStyle,
     character_set: u8,
+    joystick: JoystickEmulation,
+    mouse: MouseEmulation,
 }
 
 impl MachineScreen {
     pub fn new() -> Self {
+        let palette = MachineModel::default().palette();
+        let (color, reversed_color) = color_styles(&palette);
         MachineScreen {
             state: [0u8; 40 * 25].to_vec(),
             screen_size: Vec2::new(44, 27),
-            reversed_color: ColorStyle::new(
-                color(CONFIG.machine_screen_back),
-                color(CONFIG.machine_screen_front),
-            ),
-            frame_color: ColorStyle::new(
-                color(CONFIG.machine_screen_back),
-                color(CONFIG.machine_screen_front),
-            ),
-            color: ColorStyle::new(
-                color(CONFIG.machine_screen_front),
-                color(CONFIG.machine_screen_back),
-            ),
+            palette,
+            reversed_color,
+            frame_color: reversed_color,
+            color,
             character_set: 14,
+            joystick: JoystickEmulation::new(CONFIG.joystick_keys, CONFIG.joystick_port),
+            // mouse goes to the port not used by the joystick
+            mouse: MouseEmulation::new(CONFIG.mouse_mode, 3 - CONFIG.joystick_port),
         }
     }
 }
@@ -51,6 +54,63 @@ impl MachineScreen {
         self.character_set = char_set;
     }
 
+    /// Sets colours of the machine's model (they differ between VIC II revisions)
+    pub fn set_palette(&mut self, palette: &[u32; 16]) {
+        if self.palette != *palette {
+            self.palette = *palette;
+            (self.color, self.reversed_color) = color_styles(palette);
+            self.frame_color = self.reversed_color;
+        }
+    }
+
+    pub fn set_joystick_keys(&mut self, keys: JoystickKeys) {
+        self.joystick.keys = keys;
+        self.reset_joystick();
+    }
+
+    /// Moves the emulated joystick to the other control port
+    /// (and the mouse or paddles - if used - to the joystick's one)
+    pub fn swap_joystick_ports(&mut self) {
+        self.reset_joystick();
+        self.mouse.reset();
+        self.mouse.port = self.joystick.port;
+        self.joystick.port = 3 - self.joystick.port;
+        log::info!("Joystick connected to port {}", self.joystick.port);
+    }
+
+    pub fn set_mouse_mode(&mut self, mode: MouseMode) {
+        let was_on = self.mouse.is_on();
+        self.mouse.mode = mode;
+        self.mouse.reset();
+        if was_on && !self.mouse.is_on() {
+            // plug the joystick back
+            send_client_event(ClientEvent::Joystick {
+                port: self.mouse.port,
+                state: 0,
+            });
+        }
+    }
+
+    /// Must be called periodically to release joystick switches
+    /// (there are no key-up events in the terminal)
+    pub fn release_joystick(&mut self) {
+        if let Some(state) = self.joystick.release_expired() {
+            self.send_joystick_state(state);
+        }
+    }
+
+    fn reset_joystick(&mut self) {
+        self.joystick.reset();
+        self.send_joystick_state(0);
+    }
+
+    fn send_joystick_state(&self, state: u8) {
+        send_client_event(ClientEvent::Joystick {
+            port: self.joystick.port,
+            state,
+        });
+    }
+
     // draw chars for charcode > 127 (reversed colors)
     fn draw_reversed(&self, printer: &Printer) {
         let cs = self.character_set;
@@ -101,18 +161,34 @@ impl View for MachineScreen {
         self.screen_size
     }
 
+    // accepting focus lets the parent layout focus the screen on mouse click
+    fn take_focus(&mut self, _source: Direction) -> Result<EventResult, CannotFocus> {
+        Ok(EventResult::Consumed(None))
+    }
+
     fn on_event(&mut self, event: Event) -> EventResult {
         match event {
             Event::Mouse {
-                offset: _,
-                position: _,
-                event: MouseEvent::Press(_btn),
+                offset,
+                position,
+                event,
             } => {
-                    // FIXME It doesn't work
-                    let _ = self.take_focus(Direction::none());
-                    EventResult::Consumed(None)
+                // the screen is drawn inside the frame
+                let pos = position.saturating_sub(offset + Vec2::new(2, 1));
+                if let Some(client_event) = self.mouse.on_event(pos, event) {
+                    send_client_event(client_event);
+                    return EventResult::Consumed(None);
+                }
+                match event {
+                    MouseEvent::Press(_) => EventResult::Consumed(None),
+                    _ => EventResult::Ignored,
+                }
             }
             _ => {
+                if let Some(state) = self.joystick.on_event(&event) {
+                    self.send_joystick_state(state);
+                    return EventResult::Consumed(None);
+                }
                 let event = map_key_event(event);
                 if event.key == Key::Unidentified {
                     EventResult::Ignored
@@ -128,3 +204,10 @@ impl View for MachineScreen {
 fn pos(i: usize) -> (usize, usize) {
     (i % 40, i / 40)
 }
+
+/// Regular and reversed colours of the screen
+fn color_styles(palette: &[u32; 16]) -> (ColorStyle, ColorStyle) {
+    let front = palette_color(palette[CONFIG.machine_screen_front]);
+    let back = palette_color(palette[CONFIG.machine_screen_back]);
+    (ColorStyle::new(front, back), ColorStyle::new(back, front))
+}
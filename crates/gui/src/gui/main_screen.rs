@@ -1,5 +1,6 @@
 use super::{
-    cpu_state, get_asm_view, get_breakpoints_view, get_variables_view, memory_view, MachineScreen,
+    cpu_state, get_asm_view, get_breakpoints_view, get_variables_view, memory_view, status_line,
+    MachineScreen, VirtualKeyboard,
 };
 use cursive::{
     view::Nameable,
@@ -18,8 +19,14 @@ pub fn main_screen() -> LinearLayout {
 
     let lines = LinearLayout::vertical()
         .child(mem_and_c64)
+        .child(
+            HideableView::new(NamedView::new("virtual_keyboard", VirtualKeyboard::new()))
+                .hidden()
+                .with_name("keyboard_panel"),
+        )
         .child(cpu_state(String::new()))
-        .child(get_asm_view());
+        .child(get_asm_view())
+        .child(status_line());
 
     LinearLayout::horizontal()
         .child(
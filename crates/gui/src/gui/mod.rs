@@ -6,13 +6,18 @@ mod handlers;
 mod machine_screen;
 mod main_screen;
 mod memory_view;
+mod status_line;
+mod type_in_dialog;
 mod ui_state;
 mod variables_view;
+mod virtual_keyboard;
+mod watchpoint_dialog;
 
 pub use {
     address_dialog::address_dialog, asm_view::*, breakpoints_view::*, cpu_state::*,
-    machine_screen::*, main_screen::main_screen, memory_view::*, ui_state::UIState,
-    variables_view::*,
+    machine_screen::*, main_screen::main_screen, memory_view::*, type_in_dialog::type_in_dialog,
+    status_line::*, ui_state::UIState, variables_view::*, virtual_keyboard::*,
+    watchpoint_dialog::watchpoint_dialog,
 };
 
 pub (crate) use handlers::*;
@@ -0,0 +1,33 @@
+use cursive::{theme::*, view::Nameable, views::*};
+use machine::{client::SpeedStats, MachineStatus};
+
+#[derive(Default)]
+pub struct StatusLine {
+    pub status: MachineStatus,
+    pub speed: SpeedStats,
+    pub warp: bool,
+    /// Last watchpoint hit, shown while the machine is paused
+    pub watch: Option<String>,
+}
+
+pub fn status_line() -> NamedView<Canvas<StatusLine>> {
+    Canvas::new(StatusLine::default())
+        .with_draw(|state: &StatusLine, printer| {
+            let color =
+                ColorStyle::new(Color::Dark(BaseColor::Black), Color::Light(BaseColor::White));
+            let warp = if state.warp { " | WARP" } else { "" };
+            let status = format!(
+                "{:?} | speed: {:.0}% | {:.1} fps{warp} ",
+                state.status, state.speed.speed, state.speed.fps
+            );
+
+            printer.with_color(color, |printer| {
+                printer.print((0, 0), &format!("{status: >89}"));
+                if let Some(watch) = &state.watch {
+                    printer.print((1, 0), &format!("watch: {watch}"));
+                }
+            })
+        })
+        .with_required_size(|_, _| (89, 1).into())
+        .with_name("status_line")
+}
@@ -0,0 +1,45 @@
+use crate::messaging::send_client_event;
+
+use cursive::{
+    event::Key,
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, OnEventView},
+    Cursive,
+};
+use machine::client::ClientEvent;
+
+/// Asks for a text file (i.e. BASIC listing) to be typed into the machine
+pub fn type_in_dialog() -> OnEventView<Dialog> {
+    OnEventView::new(
+        Dialog::new()
+            .title("Type in from file")
+            .padding_lrtb(1, 1, 1, 0)
+            .content(
+                EditView::new()
+                    .on_submit(on_submit)
+                    .with_name("type_in_dialog")
+                    .fixed_width(50),
+            )
+            .button("Ok", |s| {
+                let path = s
+                    .call_on_name("type_in_dialog", |view: &mut EditView| view.get_content())
+                    .unwrap();
+                on_submit(s, &path);
+            }),
+    )
+    .on_event(Key::Esc, |s| {
+        s.pop_layer();
+    })
+}
+
+fn on_submit(s: &mut Cursive, path: &str) {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            send_client_event(ClientEvent::TypeText(text));
+            s.pop_layer();
+        }
+        Err(err) => {
+            s.add_layer(Dialog::info(format!("Couldn't read '{path}': {err}")));
+        }
+    };
+}
@@ -1,6 +1,10 @@
+use machine::debugger::RewindStatus;
+
 pub struct UIState {
     pub addr_from: u16,
     pub asm_lines: Vec<String>,
+    /// Execution history as of the last update (for the time slider)
+    pub rewind: RewindStatus,
 }
 
 impl UIState {
@@ -8,6 +12,7 @@ impl UIState {
         UIState {
             addr_from: 0,
             asm_lines: Vec::with_capacity(100),
+            rewind: RewindStatus::default(),
         }
     }
 }
@@ -5,7 +5,7 @@ use cursive::{
 };
 use machine::debugger::Variable;
 
-pub fn update_variables_view(s: &mut Cursive, vars: &Vec<Variable>) {
+pub fn update_variables_view(s: &mut Cursive, vars: &[Variable]) {
     let content = vars
         .iter()
         .map(|v| v.to_string())
@@ -0,0 +1,316 @@
+use crate::messaging::send_client_event;
+use c64::key_utils::C64KeyCode::{self, *};
+use cursive::{
+    direction::Direction,
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::{BaseColor, Color, ColorStyle},
+    view::CannotFocus,
+    Printer, Vec2, View,
+};
+use keyboard_types::KeyState;
+use machine::client::ClientEvent;
+
+#[derive(Clone, Copy)]
+enum VKey {
+    Matrix(C64KeyCode),
+    /// RESTORE is not a part of the keyboard matrix
+    Restore,
+    /// SHIFT LOCK latches left shift
+    ShiftLock,
+}
+
+use VKey::*;
+
+/// Column of the function keys (placed to the right of the main block)
+const FN_KEYS_X: usize = 76;
+
+#[rustfmt::skip]
+const LAYOUT: [&[(&str, VKey)]; 5] = [
+    &[
+        ("←", Matrix(LeftArrow)), ("1", Matrix(Key_1)), ("2", Matrix(Key_2)), ("3", Matrix(Key_3)),
+        ("4", Matrix(Key_4)), ("5", Matrix(Key_5)), ("6", Matrix(Key_6)), ("7", Matrix(Key_7)),
+        ("8", Matrix(Key_8)), ("9", Matrix(Key_9)), ("0", Matrix(Key_0)), ("+", Matrix(Plus)),
+        ("-", Matrix(Minus)), ("£", Matrix(Pound)), ("HOME", Matrix(Home)), ("DEL", Matrix(Delete)),
+        ("F1", Matrix(F1)),
+    ],
+    &[
+        ("CTRL", Matrix(Ctrl)), ("Q", Matrix(Key_Q)), ("W", Matrix(Key_W)), ("E", Matrix(Key_E)),
+        ("R", Matrix(Key_R)), ("T", Matrix(Key_T)), ("Y", Matrix(Key_Y)), ("U", Matrix(Key_U)),
+        ("I", Matrix(Key_I)), ("O", Matrix(Key_O)), ("P", Matrix(Key_P)), ("@", Matrix(At)),
+        ("*", Matrix(Asterix)), ("↑", Matrix(UpArrow)), ("RESTORE", Restore),
+        ("F3", Matrix(F3)),
+    ],
+    &[
+        ("R/S", Matrix(Stop)), ("SHLK", ShiftLock), ("A", Matrix(Key_A)), ("S", Matrix(Key_S)),
+        ("D", Matrix(Key_D)), ("F", Matrix(Key_F)), ("G", Matrix(Key_G)), ("H", Matrix(Key_H)),
+        ("J", Matrix(Key_J)), ("K", Matrix(Key_K)), ("L", Matrix(Key_L)), (":", Matrix(Colon)),
+        (";", Matrix(Semicolon)), ("=", Matrix(Equal)), ("RETURN", Matrix(Return)),
+        ("F5", Matrix(F5)),
+    ],
+    &[
+        ("C=", Matrix(Cmd)), ("SHIFT", Matrix(LShift)), ("Z", Matrix(Key_Z)), ("X", Matrix(Key_X)),
+        ("C", Matrix(Key_C)), ("V", Matrix(Key_V)), ("B", Matrix(Key_B)), ("N", Matrix(Key_N)),
+        ("M", Matrix(Key_M)), (",", Matrix(Comma)), (".", Matrix(Dot)), ("/", Matrix(Slash)),
+        ("SHIFT", Matrix(RShift)), ("↓", Matrix(CursorUD)), ("→", Matrix(CursorLR)),
+        ("F7", Matrix(F7)),
+    ],
+    &[
+        ("                SPACE                ", Matrix(Space)),
+    ],
+];
+
+/// Modifiers latched on the keyboard: SHIFT, C= and CTRL are pressed
+/// together with the next key (and released with it), SHIFT LOCK holds
+/// left shift until it's pressed again
+#[derive(Default)]
+struct StickyModifiers {
+    sticky: Vec<u8>,
+    shift_lock: bool,
+}
+
+impl StickyModifiers {
+    /// Event to be sent to the machine when the key is pressed (None for the modifiers)
+    fn press(&mut self, key: VKey) -> Option<ClientEvent> {
+        match key {
+            Matrix(kc) if is_sticky(kc) => {
+                let code = kc as u8;
+                if self.sticky.contains(&code) {
+                    self.sticky.retain(|c| *c != code);
+                } else {
+                    self.sticky.push(code);
+                }
+                None
+            }
+            Matrix(kc) => {
+                let mut keys = Vec::with_capacity(4);
+                if self.shift_lock {
+                    keys.push(LShift as u8);
+                }
+                keys.extend(self.sticky.drain(..));
+                keys.push(kc as u8);
+                Some(ClientEvent::MatrixKeys {
+                    keys,
+                    state: KeyState::Down,
+                })
+            }
+            Restore => Some(ClientEvent::Restore(KeyState::Down)),
+            ShiftLock => {
+                self.shift_lock = !self.shift_lock;
+                None
+            }
+        }
+    }
+
+    fn is_latched(&self, key: VKey) -> bool {
+        match key {
+            Matrix(kc) => self.sticky.contains(&(kc as u8)),
+            ShiftLock => self.shift_lock,
+            Restore => false,
+        }
+    }
+}
+
+struct KeyCap {
+    label: &'static str,
+    key: VKey,
+    pos: Vec2,
+    width: usize,
+}
+
+/// On-screen C64 keyboard. Keys can be pressed with the mouse or selected
+/// with arrow keys and pressed with Enter/Space. The modifiers (SHIFT, C=, CTRL)
+/// are sticky: they're pressed together with the next key.
+/// Keys seen as pressed in the emulated keyboard matrix are highlighted.
+pub struct VirtualKeyboard {
+    keys: Vec<Vec<KeyCap>>,
+    /// Selected key (row, index)
+    selected: (usize, usize),
+    modifiers: StickyModifiers,
+    pressed: Vec<u8>,
+    size: Vec2,
+}
+
+impl VirtualKeyboard {
+    pub fn new() -> Self {
+        let keys: Vec<Vec<KeyCap>> = LAYOUT
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                // rows are shifted as on the real keyboard; space bar is centered
+                let mut x = [0, 2, 0, 0, 17][y];
+                row.iter()
+                    .map(|(label, key)| {
+                        let width = label.chars().count() + 2;
+                        if label.starts_with('F') && label.len() == 2 {
+                            x = FN_KEYS_X;
+                        }
+                        let cap = KeyCap {
+                            label,
+                            key: *key,
+                            pos: Vec2::new(x, y),
+                            width,
+                        };
+                        x += width + 1;
+                        cap
+                    })
+                    .collect()
+            })
+            .collect();
+
+        VirtualKeyboard {
+            keys,
+            selected: (0, 0),
+            modifiers: StickyModifiers::default(),
+            pressed: Vec::new(),
+            size: Vec2::new(FN_KEYS_X + 4, LAYOUT.len()),
+        }
+    }
+
+    /// Sets keys pressed in the emulated keyboard matrix
+    pub fn set_pressed(&mut self, pressed: &[u8]) {
+        if self.pressed != pressed {
+            self.pressed = pressed.to_vec();
+        }
+    }
+
+    fn press(&mut self, key: VKey) {
+        if let Some(event) = self.modifiers.press(key) {
+            send_client_event(event);
+        }
+    }
+
+    fn is_highlighted(&self, key: VKey) -> bool {
+        match key {
+            Matrix(kc) => self.pressed.contains(&(kc as u8)) || self.modifiers.is_latched(key),
+            _ => self.modifiers.is_latched(key),
+        }
+    }
+
+    fn key_at(&self, pos: Vec2) -> Option<(usize, usize)> {
+        let row = self.keys.get(pos.y)?;
+        row.iter()
+            .position(|cap| pos.x >= cap.pos.x && pos.x < cap.pos.x + cap.width)
+            .map(|i| (pos.y, i))
+    }
+
+    fn move_selection(&mut self, dx: isize, dy: isize) -> EventResult {
+        let (row, i) = self.selected;
+        if dy != 0 {
+            let Some(new_row) = row.checked_add_signed(dy).filter(|r| *r < self.keys.len()) else {
+                return EventResult::Ignored;
+            };
+            // the closest key in the other row
+            let x = self.keys[row][i].pos.x;
+            let new_i = (0..self.keys[new_row].len())
+                .min_by_key(|j| self.keys[new_row][*j].pos.x.abs_diff(x))
+                .unwrap_or(0);
+            self.selected = (new_row, new_i);
+        } else {
+            let Some(new_i) = i
+                .checked_add_signed(dx)
+                .filter(|i| *i < self.keys[row].len())
+            else {
+                return EventResult::Ignored;
+            };
+            self.selected = (row, new_i);
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+impl View for VirtualKeyboard {
+    fn draw(&self, printer: &Printer) {
+        let normal = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Blue));
+        let highlighted = ColorStyle::new(
+            Color::Dark(BaseColor::Black),
+            Color::Light(BaseColor::Yellow),
+        );
+        let selected = ColorStyle::new(Color::Light(BaseColor::White), Color::Dark(BaseColor::Red));
+
+        for (y, row) in self.keys.iter().enumerate() {
+            for (i, cap) in row.iter().enumerate() {
+                let color = if printer.focused && self.selected == (y, i) {
+                    selected
+                } else if self.is_highlighted(cap.key) {
+                    highlighted
+                } else {
+                    normal
+                };
+                printer.with_color(color, |p| p.print(cap.pos, &format!(" {} ", cap.label)));
+            }
+        }
+    }
+
+    fn required_size(&mut self, _constraint: Vec2) -> Vec2 {
+        self.size
+    }
+
+    fn take_focus(&mut self, _source: Direction) -> Result<EventResult, CannotFocus> {
+        Ok(EventResult::Consumed(None))
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Left) => self.move_selection(-1, 0),
+            Event::Key(Key::Right) => self.move_selection(1, 0),
+            Event::Key(Key::Up) => self.move_selection(0, -1),
+            Event::Key(Key::Down) => self.move_selection(0, 1),
+            Event::Key(Key::Enter) | Event::Char(' ') => {
+                let (row, i) = self.selected;
+                self.press(self.keys[row][i].key);
+                EventResult::Consumed(None)
+            }
+            Event::Mouse {
+                offset,
+                position,
+                event: MouseEvent::Press(MouseButton::Left),
+            } => {
+                let Some(selected) = position.checked_sub(offset).and_then(|p| self.key_at(p))
+                else {
+                    return EventResult::Ignored;
+                };
+                self.selected = selected;
+                self.press(self.keys[selected.0][selected.1].key);
+                EventResult::Consumed(None)
+            }
+            _ => EventResult::Ignored,
+        }
+    }
+}
+
+fn is_sticky(kc: C64KeyCode) -> bool {
+    matches!(kc, LShift | RShift | Cmd | Ctrl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix_keys(keys: &[C64KeyCode]) -> Option<ClientEvent> {
+        Some(ClientEvent::MatrixKeys {
+            keys: keys.iter().map(|kc| *kc as u8).collect(),
+            state: KeyState::Down,
+        })
+    }
+
+    #[test]
+    fn test_sticky_modifiers() {
+        let mut modifiers = StickyModifiers::default();
+        assert_eq!(modifiers.press(Matrix(LShift)), None);
+        assert_eq!(modifiers.press(Matrix(Cmd)), None);
+        assert!(modifiers.is_latched(Matrix(LShift)));
+        assert_eq!(modifiers.press(Matrix(Key_A)), matrix_keys(&[LShift, Cmd, Key_A]));
+
+        // the modifiers are released with the key
+        assert!(!modifiers.is_latched(Matrix(LShift)));
+        assert_eq!(modifiers.press(Matrix(Key_A)), matrix_keys(&[Key_A]));
+
+        // second press releases the modifier, SHIFT LOCK holds shift for all the keys
+        modifiers.press(Matrix(Ctrl));
+        modifiers.press(Matrix(Ctrl));
+        modifiers.press(ShiftLock);
+        assert_eq!(modifiers.press(Matrix(Key_B)), matrix_keys(&[LShift, Key_B]));
+        assert_eq!(modifiers.press(Matrix(Key_B)), matrix_keys(&[LShift, Key_B]));
+        assert!(modifiers.is_latched(ShiftLock));
+    }
+}
@@ -0,0 +1,65 @@
+use super::add_watchpoint;
+
+use cursive::{
+    event::Key,
+    view::{Nameable, Resizable},
+    views::{Dialog, EditView, LinearLayout, OnEventView, SelectView, TextView},
+    Cursive,
+};
+use machine::debugger::{TraceFilter, WatchKind, Watchpoint};
+
+/// Asks for a range of addresses (i.e. `d020` or `0400-07e7`) and the access to watch
+pub fn watchpoint_dialog() -> OnEventView<Dialog> {
+    OnEventView::new(
+        Dialog::new()
+            .title("Add watchpoint")
+            .padding_lrtb(1, 1, 1, 0)
+            .content(
+                LinearLayout::vertical()
+                    .child(TextView::new("Addresses"))
+                    .child(
+                        EditView::new()
+                            .on_submit(|s, _| on_submit(s))
+                            .max_content_width(9)
+                            .with_name("watchpoint_range")
+                            .fixed_width(20),
+                    )
+                    .child(TextView::new("Break on"))
+                    .child(
+                        SelectView::new()
+                            .popup()
+                            .item("write", WatchKind::Write)
+                            .item("read", WatchKind::Read)
+                            .item("change", WatchKind::Change)
+                            .with_name("watchpoint_kind"),
+                    ),
+            )
+            .button("Ok", on_submit),
+    )
+    .on_event(Key::Esc, |s| {
+        s.pop_layer();
+    })
+}
+
+fn on_submit(s: &mut Cursive) {
+    let range = s
+        .call_on_name("watchpoint_range", |view: &mut EditView| view.get_content())
+        .unwrap();
+    let kind = s
+        .call_on_name("watchpoint_kind", |view: &mut SelectView<WatchKind>| {
+            view.selection().map(|kind| *kind)
+        })
+        .flatten()
+        .unwrap_or(WatchKind::Write);
+    match TraceFilter::parse_range(&range) {
+        Some(range) => {
+            s.pop_layer();
+            add_watchpoint(s, Watchpoint::new(range, kind));
+        }
+        None => {
+            s.add_layer(Dialog::info(format!(
+                "'{range}' is not a valid address or range (i.e. 0400-07e7)"
+            )));
+        }
+    };
+}
@@ -11,14 +11,18 @@ pub mod utils;
 
 use crate::gui::*;
 use crate::messaging::*;
-use anyhow;
-use c64::{C64Client, MachineState, C64};
+use c64::{key_utils::KeymapMode, C64Client, MachineState, C64};
 use config::CONFIG;
 use cursive::views::Dialog;
 use log::LevelFilter;
 use machine::client::ClientEvent;
+use clap::Parser;
 use machine::{
-    cli::create_machine_from_cli_args, client::NonInteractiveClient, utils::lock, MachineError,
+    cli::{create_machine_from_profile, get_file_as_byte_vec, get_profile, Args, Profile},
+    client::NonInteractiveClient,
+    debugger::Debugger,
+    utils::lock,
+    Machine, MachineError,
 };
 use std::sync::{Arc, Mutex};
 use std::thread;
@@ -30,20 +34,35 @@ fn main() -> anyhow::Result<()> {
     colored::control::set_override(false);
     init_log();
 
-    let client = init_client()?;
+    let profile = get_profile(Args::parse())?;
+    let client = init_client(&profile)?;
     let mut siv = init_ui(client.clone());
+    if use_symbolic_keymap(&client) {
+        siv.add_layer(Dialog::info(
+            "Positional keymaps need physical keys, but the terminal reports \
+             characters only. The keymap is used in symbolic mode.",
+        ));
+    }
 
     let mut prev_state = MachineState::default();
     let mut runner = siv.runner();
 
     runner.refresh();
-    init_breakpoints_view(&mut runner, &lock(&client).get_debugger_state().breakpoints);
+    let debugger_state = lock(&client).get_debugger_state();
+    init_breakpoints_view(
+        &mut runner,
+        &debugger_state.breakpoints,
+        &debugger_state.watchpoints,
+    );
 
     loop {
         runner.step();
         if !runner.is_running() {
             break;
         }
+        runner.call_on_name("machine_screen", |view: &mut MachineScreen| {
+            view.release_joystick()
+        });
         if lock(&client).is_running() {
             let state = lock(&client).step();
             if state != prev_state {
@@ -56,12 +75,27 @@ fn main() -> anyhow::Result<()> {
     }
 
     lock(&client.clone()).stop()?;
+    if let Some(path) = &profile.config.save_snapshot_on_exit {
+        std::fs::write(path, lock(&client).save_snapshot()?)?;
+    }
     Ok(())
 }
 
-fn init_client() -> anyhow::Result<Arc<Mutex<C64Client>>> {
-    let mut c64_client = C64Client::new(create_machine_from_cli_args::<C64>()?);
+fn init_client(profile: &Profile) -> anyhow::Result<Arc<Mutex<C64Client>>> {
+    let mut c64: C64 = create_machine_from_profile(profile)?;
+    if c64.get_config().rewind_budget.is_none() {
+        c64.rewind_mut().set_budget(CONFIG.rewind_budget << 20);
+    }
+    if c64.get_config().disassemble && c64.get_config().trace.is_none() {
+        // the trace would be printed over the UI
+        log::warn!("--disassemble is ignored by the text client (use --trace <file>)");
+        c64.set_tracer(None);
+    }
+    let mut c64_client = C64Client::new(c64);
     connect_client(&mut c64_client);
+    if let Some(path) = &profile.config.load_snapshot {
+        c64_client.load_snapshot(&get_file_as_byte_vec(path)?)?;
+    }
     let client = Arc::new(Mutex::new(c64_client));
 
     lock(&client).start().unwrap_or_else(handle_error);
@@ -70,6 +104,21 @@ fn init_client() -> anyhow::Result<Arc<Mutex<C64Client>>> {
     Ok(client)
 }
 
+/// Switches positional keymap to symbolic mode (positions of the keys could
+/// only be guessed from the characters). Returns whether the mode was changed.
+fn use_symbolic_keymap(client: &Arc<Mutex<C64Client>>) -> bool {
+    let mutex = lock(client).mutex();
+    let mut c64 = lock(&mutex);
+    if c64.keymap().mode != KeymapMode::Positional {
+        return false;
+    }
+    log::warn!("Positional keymaps are not supported by the text client, using symbolic mode");
+    let mut keymap = c64.keymap().clone();
+    keymap.mode = KeymapMode::Symbolic;
+    c64.set_keymap(keymap);
+    true
+}
+
 fn init_log() {
     cursive::logger::init();
     match std::env::var("RUST_LOG")
@@ -1,5 +1,7 @@
 use cursive::theme::Color;
 
-pub fn color(rgb: &str) -> Color {
-    Color::parse(rgb).unwrap()
+/// Converts colour of machine's palette (0xRRGGBB)
+pub fn palette_color(rgb: u32) -> Color {
+    let [_, r, g, b] = rgb.to_be_bytes();
+    Color::Rgb(r, g, b)
 }
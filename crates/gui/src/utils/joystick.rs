@@ -0,0 +1,120 @@
+use crate::config::CONFIG;
+use c64::{JOY_DOWN, JOY_FIRE, JOY_LEFT, JOY_RIGHT, JOY_UP};
+use cursive::event::{Event, Key};
+use std::time::Instant;
+
+/// Host keys used to control the emulated joystick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoystickKeys {
+    /// All the keys go to the C64 keyboard
+    Off,
+    /// Digits as on the numeric keypad (with diagonals), 5 or 0 for fire
+    Numpad,
+    /// Arrow keys and space for fire
+    Cursor,
+}
+
+/// Emulates joystick with the keyboard. As terminals don't report key releases,
+/// a switch is released when its key hasn't been pressed (or auto-repeated)
+/// for `CONFIG.joystick_release`.
+pub struct JoystickEmulation {
+    pub keys: JoystickKeys,
+    pub port: u8,
+    state: u8,
+    pressed_at: [Option<Instant>; 5],
+}
+
+impl JoystickEmulation {
+    pub fn new(keys: JoystickKeys, port: u8) -> Self {
+        JoystickEmulation {
+            keys,
+            port,
+            state: 0,
+            pressed_at: [None; 5],
+        }
+    }
+
+    fn switches(&self, event: &Event) -> Option<u8> {
+        use JoystickKeys::*;
+        match (self.keys, event) {
+            (Numpad, Event::Char(c)) => match c {
+                '8' => Some(JOY_UP),
+                '2' => Some(JOY_DOWN),
+                '4' => Some(JOY_LEFT),
+                '6' => Some(JOY_RIGHT),
+                '7' => Some(JOY_UP | JOY_LEFT),
+                '9' => Some(JOY_UP | JOY_RIGHT),
+                '1' => Some(JOY_DOWN | JOY_LEFT),
+                '3' => Some(JOY_DOWN | JOY_RIGHT),
+                '5' | '0' => Some(JOY_FIRE),
+                _ => None,
+            },
+            (Cursor, Event::Key(Key::Up)) => Some(JOY_UP),
+            (Cursor, Event::Key(Key::Down)) => Some(JOY_DOWN),
+            (Cursor, Event::Key(Key::Left)) => Some(JOY_LEFT),
+            (Cursor, Event::Key(Key::Right)) => Some(JOY_RIGHT),
+            (Cursor, Event::Char(' ')) => Some(JOY_FIRE),
+            _ => None,
+        }
+    }
+
+    /// Presses the switches mapped to the event. Returns new joystick state,
+    /// or None if the event is not a joystick key.
+    pub fn on_event(&mut self, event: &Event) -> Option<u8> {
+        let switches = self.switches(event)?;
+        let now = Instant::now();
+        let opposite = |sw: u8| match sw {
+            JOY_UP => JOY_DOWN,
+            JOY_DOWN => JOY_UP,
+            JOY_LEFT => JOY_RIGHT,
+            JOY_RIGHT => JOY_LEFT,
+            _ => 0,
+        };
+        for bit in 0..5 {
+            let sw = 1 << bit;
+            if switches & sw > 0 {
+                self.pressed_at[bit] = Some(now);
+                self.release(opposite(sw));
+            }
+        }
+        self.state = self.current_state();
+        Some(self.state)
+    }
+
+    /// Releases switches whose keys timed out. Returns new state if it has changed.
+    pub fn release_expired(&mut self) -> Option<u8> {
+        for pressed in self.pressed_at.iter_mut() {
+            if pressed.is_some_and(|t| t.elapsed() > CONFIG.joystick_release) {
+                *pressed = None;
+            }
+        }
+        let state = self.current_state();
+        if state == self.state {
+            return None;
+        }
+        self.state = state;
+        Some(state)
+    }
+
+    /// Releases all the switches
+    pub fn reset(&mut self) {
+        self.pressed_at = [None; 5];
+        self.state = 0;
+    }
+
+    fn release(&mut self, switches: u8) {
+        for bit in 0..5 {
+            if switches & (1 << bit) > 0 {
+                self.pressed_at[bit] = None;
+            }
+        }
+    }
+
+    fn current_state(&self) -> u8 {
+        self.pressed_at
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.is_some())
+            .fold(0, |state, (bit, _)| state | (1 << bit))
+    }
+}
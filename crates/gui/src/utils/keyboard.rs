@@ -3,95 +3,124 @@ use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Modifiers};
 use std::collections::HashMap;
 use std::str::FromStr;
 
+// Terminal reports characters only, so the physical key is guessed assuming
+// US host keyboard. The guess is wrong for other layouts, that's why the text
+// client uses keymaps in symbolic mode only (see main.rs). Each item is a pair
+// of (unshifted, shifted) characters of a key.
 lazy_static! {
-    static ref C64_PUNCTATION_KEYS: Vec<char> = String::from("+-=:;,./*@ £").chars().collect();
-    static ref C64_SHIFTED_PUNCTATION_KEYS: HashMap<char, char> = HashMap::from([
-        ('!', '1'),
-        ('"', '2'),
-        ('#', '3'),
-        ('$', '4'),
-        ('%', '5'),
-        ('&', '6'),
-        ('\'', '7'),
-        ('(', '8'),
-        (')', '9'),
-        ('<', ','),
-        ('>', '.'),
-        ('?', '/'),
-        ('[', ':'),
-        (']', ';'),
-    ]);
-    static ref C64_SPECIAL_KEYS: HashMap<CKey, (Key, Code, bool)> = HashMap::from([
-        (CKey::Enter, (Key::Enter, Code::Enter, false)),
-        (CKey::Backspace, (Key::Backspace, Code::Backspace, false)),
-        (CKey::Home, (Key::Home, Code::Home, false)),
-        (CKey::Up, (Key::ArrowDown, Code::ArrowDown, true)),
-        (CKey::Down, (Key::ArrowDown, Code::ArrowDown, false)),
-        (CKey::Right, (Key::ArrowRight, Code::ArrowRight, false)),
-        (CKey::Left, (Key::ArrowRight, Code::ArrowRight, true)),
+    static ref US_PUNCTATION_KEYS: HashMap<char, (Code, bool)> = [
+        ('`', '~', Code::Backquote),
+        ('1', '!', Code::Digit1),
+        ('2', '@', Code::Digit2),
+        ('3', '#', Code::Digit3),
+        ('4', '$', Code::Digit4),
+        ('5', '%', Code::Digit5),
+        ('6', '^', Code::Digit6),
+        ('7', '&', Code::Digit7),
+        ('8', '*', Code::Digit8),
+        ('9', '(', Code::Digit9),
+        ('0', ')', Code::Digit0),
+        ('-', '_', Code::Minus),
+        ('=', '+', Code::Equal),
+        ('[', '{', Code::BracketLeft),
+        (']', '}', Code::BracketRight),
+        ('\\', '|', Code::Backslash),
+        (';', ':', Code::Semicolon),
+        ('\'', '"', Code::Quote),
+        (',', '<', Code::Comma),
+        ('.', '>', Code::Period),
+        ('/', '?', Code::Slash),
+    ]
+    .iter()
+    .flat_map(|(c, shifted, code)| [(*c, (*code, false)), (*shifted, (*code, true))])
+    .chain([(' ', (Code::Space, false))])
+    .collect();
+
+    static ref SPECIAL_KEYS: HashMap<CKey, (Key, Code)> = HashMap::from([
+        (CKey::Enter, (Key::Enter, Code::Enter)),
+        (CKey::Tab, (Key::Tab, Code::Tab)),
+        (CKey::Backspace, (Key::Backspace, Code::Backspace)),
+        (CKey::Esc, (Key::Escape, Code::Escape)),
+        (CKey::Ins, (Key::Insert, Code::Insert)),
+        (CKey::Del, (Key::Delete, Code::Delete)),
+        (CKey::Home, (Key::Home, Code::Home)),
+        (CKey::End, (Key::End, Code::End)),
+        (CKey::Up, (Key::ArrowUp, Code::ArrowUp)),
+        (CKey::Down, (Key::ArrowDown, Code::ArrowDown)),
+        (CKey::Right, (Key::ArrowRight, Code::ArrowRight)),
+        (CKey::Left, (Key::ArrowLeft, Code::ArrowLeft)),
+        (CKey::F1, (Key::F1, Code::F1)),
+        (CKey::F2, (Key::F2, Code::F2)),
+        (CKey::F3, (Key::F3, Code::F3)),
+        (CKey::F4, (Key::F4, Code::F4)),
+        (CKey::F5, (Key::F5, Code::F5)),
+        (CKey::F6, (Key::F6, Code::F6)),
+        (CKey::F7, (Key::F7, Code::F7)),
+        (CKey::F8, (Key::F8, Code::F8)),
     ]);
+
+    /// Keys used by the GUI itself (see handlers.rs); they are not sent to C64
+    /// (C64 function keys can be sent with Ctrl+F1-F8)
+    static ref GUI_KEYS: Vec<CKey> = vec![
+        CKey::F2, CKey::F3, CKey::F6, CKey::F7, CKey::F8, CKey::F9, CKey::F10, CKey::F12,
+        CKey::PageUp,
+    ];
 }
 
-pub fn char_to_code(c: char) -> Code {
-    let code = if c.is_ascii_alphabetic() {
-        Code::from_str(&["Key", &c.to_ascii_uppercase().to_string()].join(""))
-    } else if c.is_ascii_digit() {
-        Code::from_str(&["Digit", &c.to_string()].join(""))
+/// Physical key (on US keyboard) of given character, and whether it requires shift
+pub fn char_to_code(c: char) -> (Code, bool) {
+    if c.is_ascii_alphabetic() {
+        let code = Code::from_str(&["Key", &c.to_ascii_uppercase().to_string()].join(""));
+        (code.unwrap_or_default(), c.is_ascii_uppercase())
     } else {
-        log::warn!("Couldn't convert key '{c}' into Code");
-        Ok(Code::default())
-    };
-
-    code.unwrap_or_else(|e| {
-        log::error!("Error converting '{c}' into Code: {e}");
-        Code::default()
-    })
+        US_PUNCTATION_KEYS.get(&c).copied().unwrap_or_else(|| {
+            log::debug!("Couldn't convert key '{c}' into Code");
+            (Code::Unidentified, false)
+        })
+    }
 }
 
 pub fn key_event_from_char(c: char, modifiers: Modifiers) -> KeyboardEvent {
+    let (code, shift) = char_to_code(c);
     KeyboardEvent {
         state: KeyState::Down,
         key: Key::Character(c.to_string()),
-        code: char_to_code(c),
-        modifiers,
+        code,
+        modifiers: if shift {
+            modifiers.union(Modifiers::SHIFT)
+        } else {
+            modifiers
+        },
         ..Default::default()
     }
 }
 
 pub fn key_event_from_ckey(ckey: CKey, modifiers: Modifiers) -> KeyboardEvent {
-    C64_SPECIAL_KEYS
+    SPECIAL_KEYS
         .get(&ckey)
-        .map_or(KeyboardEvent::default(), |(key, code, shift)| {
-            KeyboardEvent {
-                key: key.clone(),
-                code: *code,
-                modifiers: modifiers.union(if *shift {
-                    Modifiers::SHIFT
-                } else {
-                    Modifiers::empty()
-                }),
-                ..Default::default()
-            }
+        .map_or(KeyboardEvent::default(), |(key, code)| KeyboardEvent {
+            key: key.clone(),
+            code: *code,
+            modifiers,
+            ..Default::default()
         })
 }
 
+/// Converts terminal event into keyboard event. The mapping to C64 keys
+/// is done by the emulator's keymap. Returns "unidentified" key for
+/// events that are not meant for C64.
 pub fn map_key_event(e: Event) -> KeyboardEvent {
     let no_key = KeyboardEvent::default();
     match e {
-        Event::Char(c) => {
-            if c.is_ascii_lowercase() || c.is_ascii_digit() || C64_PUNCTATION_KEYS.contains(&c) {
-                key_event_from_char(c, Modifiers::empty())
-            } else if c.is_ascii_uppercase() {
-                key_event_from_char(c.to_ascii_lowercase(), Modifiers::SHIFT)
-            } else if C64_SHIFTED_PUNCTATION_KEYS.contains_key(&c) {
-                key_event_from_char(C64_SHIFTED_PUNCTATION_KEYS[&c], Modifiers::SHIFT)
-            } else {
-                no_key
-            }
-        }
+        Event::Char('`') => no_key, // debug console
+        Event::Char(c) => key_event_from_char(c, Modifiers::empty()),
+        Event::CtrlChar(c) => key_event_from_char(c, Modifiers::CONTROL),
+        Event::AltChar(c) => key_event_from_char(c, Modifiers::ALT),
 
+        Event::Key(ckey) if GUI_KEYS.contains(&ckey) => no_key,
         Event::Key(ckey) => key_event_from_ckey(ckey, Modifiers::empty()),
         Event::Shift(ckey) => key_event_from_ckey(ckey, Modifiers::SHIFT),
+        Event::Ctrl(ckey) if is_function_key(ckey) => key_event_from_ckey(ckey, Modifiers::empty()),
         Event::Ctrl(ckey) => key_event_from_ckey(ckey, Modifiers::CONTROL),
         Event::Alt(ckey) => key_event_from_ckey(ckey, Modifiers::ALT),
         Event::CtrlShift(ckey) => key_event_from_ckey(ckey, Modifiers::SHIFT | Modifiers::CONTROL),
@@ -99,3 +128,10 @@ pub fn map_key_event(e: Event) -> KeyboardEvent {
         _ => no_key,
     }
 }
+
+fn is_function_key(ckey: CKey) -> bool {
+    matches!(
+        ckey,
+        CKey::F1 | CKey::F2 | CKey::F3 | CKey::F4 | CKey::F5 | CKey::F6 | CKey::F7 | CKey::F8
+    )
+}
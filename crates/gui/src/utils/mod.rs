@@ -1,2 +1,4 @@
 pub(crate) mod color;
+pub(crate) mod joystick;
 pub(crate) mod keyboard;
+pub(crate) mod mouse;
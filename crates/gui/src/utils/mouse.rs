@@ -0,0 +1,87 @@
+use cursive::{
+    event::{MouseButton, MouseEvent},
+    Vec2,
+};
+use machine::client::ClientEvent;
+
+/// Width and height of the C64 screen in characters
+const SCREEN_COLS: usize = 40;
+const SCREEN_ROWS: usize = 25;
+
+/// Size of a character in C64 pixels
+const CHAR_SIZE: i16 = 8;
+
+/// What the host mouse controls
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseMode {
+    Off,
+    /// 1351 mouse: movements are sent as deltas
+    Mouse1351,
+    /// Paddles: position on the screen sets the paddles
+    Paddles,
+}
+
+/// Translates terminal mouse events (positions in characters)
+/// into control port events. Note that most terminals report
+/// movement only while a button is held.
+pub struct MouseEmulation {
+    pub mode: MouseMode,
+    pub port: u8,
+    last_pos: Option<Vec2>,
+    buttons: u8,
+}
+
+impl MouseEmulation {
+    pub fn new(mode: MouseMode, port: u8) -> Self {
+        MouseEmulation {
+            mode,
+            port,
+            last_pos: None,
+            buttons: 0,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.mode != MouseMode::Off
+    }
+
+    /// Maps the event (with position relative to the C64 screen) to client event
+    pub fn on_event(&mut self, pos: Vec2, event: MouseEvent) -> Option<ClientEvent> {
+        match event {
+            MouseEvent::Press(btn) => self.buttons |= button_bit(btn),
+            MouseEvent::Release(btn) => self.buttons &= !button_bit(btn),
+            MouseEvent::Hold(_) => {}
+            _ => return None,
+        };
+        let pos = Vec2::new(pos.x.min(SCREEN_COLS - 1), pos.y.min(SCREEN_ROWS - 1));
+        let last_pos = self.last_pos.replace(pos).unwrap_or(pos);
+        match self.mode {
+            MouseMode::Off => None,
+            MouseMode::Mouse1351 => Some(ClientEvent::Mouse {
+                port: self.port,
+                dx: (pos.x as i16 - last_pos.x as i16) * CHAR_SIZE,
+                dy: (pos.y as i16 - last_pos.y as i16) * CHAR_SIZE,
+                buttons: self.buttons,
+            }),
+            MouseMode::Paddles => Some(ClientEvent::Paddles {
+                port: self.port,
+                x: (pos.x * 255 / (SCREEN_COLS - 1)) as u8,
+                y: (pos.y * 255 / (SCREEN_ROWS - 1)) as u8,
+                buttons: self.buttons,
+            }),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.last_pos = None;
+        self.buttons = 0;
+    }
+}
+
+fn button_bit(btn: MouseButton) -> u8 {
+    match btn {
+        MouseButton::Left => 0b01,
+        MouseButton::Right => 0b10,
+        _ => 0,
+    }
+}
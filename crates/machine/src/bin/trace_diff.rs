@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::Parser;
+use machine::debugger::{diff_traces, TraceReader, TraceRecord};
+use machine::mos6502::{OpsMap, MOS6502};
+use std::path::PathBuf;
+
+/// Compares two traces (written with `--trace`, or VICE's CPU history)
+/// and shows the first instruction they differ at
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    left: PathBuf,
+
+    right: PathBuf,
+
+    #[arg(long = "ignore-cycles")]
+    ignore_cycles: bool,
+}
+
+fn describe(record: &Option<TraceRecord>, operations: &OpsMap) -> String {
+    let record = match record {
+        Some(record) => record,
+        None => return String::from("(end of trace)"),
+    };
+    let mut text = record.to_text(operations);
+    if let (Some(addr), Some(value)) = (record.address, record.value) {
+        text.push_str(&format!("  [${addr:04X} = ${value:02X}]"));
+    }
+    for access in record.accesses.iter() {
+        let kind = if access.write { "write" } else { "read " };
+        text.push_str(&format!(
+            "\n      {kind} ${:04X} = ${:02X} ({})",
+            access.addr, access.value, access.device
+        ));
+    }
+    text
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let left = std::fs::read(&args.left)?;
+    let right = std::fs::read(&args.right)?;
+    let operations = MOS6502::new().operations;
+
+    let divergence = diff_traces(
+        TraceReader::new(&left)?,
+        TraceReader::new(&right)?,
+        !args.ignore_cycles,
+    )?;
+    match divergence {
+        None => println!("Traces are identical"),
+        Some(div) => {
+            println!("Traces differ at instruction {} ({})", div.index, div.fields.join(", "));
+            if let Some(record) = &div.last_match {
+                println!("last match:\n    {}", record.to_text(&operations));
+            }
+            println!("{}:\n    {}", args.left.display(), describe(&div.left, &operations));
+            println!("{}:\n    {}", args.right.display(), describe(&div.right, &operations));
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
@@ -54,22 +54,123 @@ pub struct Args {
     #[arg(long = "char-rom")]
     pub character_rom: Option<PathBuf>,
 
+    #[arg(long = "basic-rom")]
+    pub basic_rom: Option<PathBuf>,
+
+    #[arg(long = "kernal-rom")]
+    pub kernal_rom: Option<PathBuf>,
+
+    #[arg(long = "cart-lo-rom")]
+    pub cart_lo_rom: Option<PathBuf>,
+
+    #[arg(long = "cart-hi-rom")]
+    pub cart_hi_rom: Option<PathBuf>,
+
+    #[arg(long = "remove-chips")]
+    pub remove_chips: Option<String>,
+
+    #[arg(long = "missing-device")]
+    pub missing_device: Option<String>,
+
     #[arg(long = "profile")]
     pub profile: Option<PathBuf>,
+
+    #[arg(long = "audio-out")]
+    pub audio_out: Option<PathBuf>,
+
+    #[arg(long = "sid-model")]
+    pub sid_model: Option<String>,
+
+    #[arg(long = "sid")]
+    pub sid: Option<PathBuf>,
+
+    #[arg(long = "song")]
+    pub song: Option<u16>,
+
+    #[arg(long = "seconds")]
+    pub seconds: Option<u64>,
+
+    #[arg(long = "tod")]
+    pub tod: Option<String>,
+
+    #[arg(long = "key-release")]
+    pub key_release: Option<u64>,
+
+    #[arg(long = "keymap")]
+    pub keymap: Option<String>,
+
+    #[arg(long = "keymap-mode")]
+    pub keymap_mode: Option<String>,
+
+    #[arg(long = "type")]
+    pub type_text: Option<String>,
+
+    #[arg(long = "type-mode")]
+    pub type_mode: Option<String>,
+
+    #[arg(long = "speed")]
+    pub speed: Option<u32>,
+
+    #[arg(long = "warp")]
+    #[serde(default)]
+    pub warp: bool,
+
+    #[arg(long = "model")]
+    pub model: Option<String>,
+
+    #[arg(long = "deterministic")]
+    #[serde(default)]
+    pub deterministic: bool,
+
+    #[arg(long = "seed")]
+    pub seed: Option<u64>,
+
+    #[arg(long = "ram-pattern")]
+    pub ram_pattern: Option<String>,
+
+    #[arg(long = "input")]
+    pub input: Option<PathBuf>,
+
+    #[arg(long = "rewind")]
+    pub rewind: Option<usize>,
+
+    #[arg(long = "trace")]
+    pub trace: Option<PathBuf>,
+
+    #[arg(long = "trace-format")]
+    pub trace_format: Option<String>,
+
+    #[arg(long = "trace-range")]
+    pub trace_range: Option<String>,
+
+    #[arg(long = "trace-memory")]
+    pub trace_memory: Option<String>,
+
+    #[arg(long = "trace-bus")]
+    #[serde(default)]
+    pub trace_bus: bool,
+
+    #[arg(long = "load-snapshot")]
+    pub load_snapshot: Option<PathBuf>,
+
+    #[arg(long = "save-snapshot-on-exit")]
+    pub save_snapshot_on_exit: Option<PathBuf>,
+
+    #[arg(long = "save-vsf-on-exit")]
+    pub save_vsf_on_exit: Option<PathBuf>,
 }
 
 impl From<&Args> for MachineConfig {
     fn from(args: &Args) -> Self {
         MachineConfig {
-            ram_size: args.ram_size as usize,
+            ram_size: args.ram_size,
             rom_size: 1 << 16,
             max_time: args.max_time,
             max_cycles: args.max_cycles,
-            exit_on_addr: if let Some(str) = &args.stop_on_addr {
-                Some(u16::from_str_radix(&str, 16).unwrap())
-            } else {
-                None
-            },
+            exit_on_addr: args
+                .stop_on_addr
+                .as_ref()
+                .map(|str| u16::from_str_radix(str, 16).unwrap()),
             exit_on_brk: args.stop_on_brk,
             disassemble: args.disassemble,
             verbose: args.verbose,
@@ -77,6 +178,18 @@ impl From<&Args> for MachineConfig {
                 .start_addr
                 .clone()
                 .map(|addr| u16::from_str_radix(&addr, 16).unwrap()),
+            speed: args.speed.unwrap_or(100),
+            warp: args.warp,
+            model: args.model.clone(),
+            deterministic: args.deterministic,
+            seed: args.seed,
+            ram_pattern: args.ram_pattern.clone(),
+            rewind_budget: args.rewind,
+            trace: args.trace.clone(),
+            trace_format: args.trace_format.clone(),
+            trace_range: args.trace_range.clone(),
+            trace_memory: args.trace_memory.clone(),
+            trace_bus: args.trace_bus,
         }
     }
 }
@@ -119,7 +232,43 @@ impl Args {
             stop_on_brk: val_or(cli.stop_on_brk, file.stop_on_brk),
             verbose: val_or(cli.verbose, file.verbose),
             character_rom: cli.character_rom.clone().or(file.character_rom.clone()),
+            basic_rom: cli.basic_rom.clone().or(file.basic_rom.clone()),
+            kernal_rom: cli.kernal_rom.clone().or(file.kernal_rom.clone()),
+            cart_lo_rom: cli.cart_lo_rom.clone().or(file.cart_lo_rom.clone()),
+            cart_hi_rom: cli.cart_hi_rom.clone().or(file.cart_hi_rom.clone()),
+            remove_chips: cli.remove_chips.clone().or(file.remove_chips.clone()),
+            missing_device: cli.missing_device.clone().or(file.missing_device.clone()),
             profile: None,
+            audio_out: cli.audio_out.clone().or(file.audio_out.clone()),
+            sid_model: cli.sid_model.clone().or(file.sid_model.clone()),
+            sid: cli.sid.clone().or(file.sid.clone()),
+            song: cli.song.or(file.song),
+            seconds: cli.seconds.or(file.seconds),
+            tod: cli.tod.clone().or(file.tod.clone()),
+            key_release: cli.key_release.or(file.key_release),
+            keymap: cli.keymap.clone().or(file.keymap.clone()),
+            keymap_mode: cli.keymap_mode.clone().or(file.keymap_mode.clone()),
+            type_text: cli.type_text.clone().or(file.type_text.clone()),
+            type_mode: cli.type_mode.clone().or(file.type_mode.clone()),
+            speed: cli.speed.or(file.speed),
+            warp: val_or(cli.warp, file.warp),
+            model: cli.model.clone().or(file.model.clone()),
+            deterministic: val_or(cli.deterministic, file.deterministic),
+            seed: cli.seed.or(file.seed),
+            ram_pattern: cli.ram_pattern.clone().or(file.ram_pattern.clone()),
+            input: cli.input.clone().or(file.input.clone()),
+            rewind: cli.rewind.or(file.rewind),
+            trace: cli.trace.clone().or(file.trace.clone()),
+            trace_format: cli.trace_format.clone().or(file.trace_format.clone()),
+            trace_range: cli.trace_range.clone().or(file.trace_range.clone()),
+            trace_memory: cli.trace_memory.clone().or(file.trace_memory.clone()),
+            trace_bus: val_or(cli.trace_bus, file.trace_bus),
+            load_snapshot: cli.load_snapshot.clone().or(file.load_snapshot.clone()),
+            save_snapshot_on_exit: cli
+                .save_snapshot_on_exit
+                .clone()
+                .or(file.save_snapshot_on_exit.clone()),
+            save_vsf_on_exit: cli.save_vsf_on_exit.clone().or(file.save_vsf_on_exit.clone()),
         }
     }
 }
@@ -34,32 +34,42 @@ pub fn create_machine_from_cli_args<M>() -> Result<M>
 where
     M: FromProfile + Machine,
 {
-    let args = Args::parse();
-    let profile = get_profile(args)?;
+    let profile = get_profile(Args::parse())?;
+    create_machine_from_profile(&profile)
+}
 
-    let mut machine = M::from_profile(&profile);
+/// Creates the machine from the profile merged with command line arguments
+/// (see `get_profile`), so the caller can read the merged options too
+pub fn create_machine_from_profile<M>(profile: &Profile) -> Result<M>
+where
+    M: FromProfile + Machine,
+{
+    let mut machine = M::from_profile(profile);
 
-    if let Some(rom_file) = profile.config.rom {
-        let rom = get_file_as_byte_vec(&rom_file)?;
+    if let Some(rom_file) = &profile.config.rom {
+        let rom = get_file_as_byte_vec(rom_file)?;
         machine.memory_mut().init_rom(&rom[..]);
     }
 
-    if let Some(ram_file) = profile.config.ram {
-        let ram = get_file_as_byte_vec(&ram_file)?;
+    if let Some(ram_file) = &profile.config.ram {
+        let ram = get_file_as_byte_vec(ram_file)?;
         let addr = u16::from_str_radix(
-            &profile
+            profile
                 .config
                 .ram_file_addr
+                .as_ref()
                 .ok_or(anyhow::Error::msg("couldn't parse ram-file-addr"))?,
             16,
         )?;
         machine.memory_mut().write(addr, &ram[..]);
     }
 
-    if let Some(character_rom) = profile.config.character_rom {
-        let rom = get_file_as_byte_vec(&character_rom)?;
+    if let Some(character_rom) = &profile.config.character_rom {
+        let rom = get_file_as_byte_vec(character_rom)?;
         machine.memory_mut().init_rom_at_addr(0xd000, &rom[..]);
     }
 
+    machine.images_loaded(profile)?;
+
     Ok(machine)
 }
@@ -1,6 +1,6 @@
 use serde_derive::Deserialize;
 
-use crate::debugger::{Breakpoint, DebuggerState, Variable};
+use crate::debugger::{Breakpoint, DebuggerState, Variable, WatchKind, Watchpoint};
 use crate::machine::MachineConfig;
 
 use super::Args;
@@ -24,6 +24,7 @@ impl Profile {
 pub struct DebuggerConfig {
     variable: Option<Vec<Var>>,
     breakpoint: Option<Vec<Breakpoint>>,
+    watchpoint: Option<Vec<Watch>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -32,6 +33,14 @@ struct Var {
     pub address: u16,
 }
 
+/// Watchpoint of a single address, or of a range (when `end` is given)
+#[derive(Debug, Deserialize)]
+struct Watch {
+    pub address: u16,
+    pub end: Option<u16>,
+    pub on: WatchKind,
+}
+
 impl From<&Profile> for MachineConfig {
     fn from(profile: &Profile) -> Self {
         MachineConfig::from(&profile.config)
@@ -40,6 +49,12 @@ impl From<&Profile> for MachineConfig {
 
 pub trait FromProfile {
     fn from_profile(profile: &Profile) -> Self;
+
+    /// Called once ROM and RAM images of the profile are loaded
+    /// (i.e. to put ROMs into machine specific slots, or to remove some of them)
+    fn images_loaded(&mut self, _profile: &Profile) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 impl From<&Var> for Variable {
@@ -52,15 +67,24 @@ impl From<&Var> for Variable {
     }
 }
 
+impl From<&Watch> for Watchpoint {
+    fn from(watch: &Watch) -> Self {
+        Watchpoint::new(watch.address..=watch.end.unwrap_or(watch.address), watch.on)
+    }
+}
+
 impl From<&DebuggerConfig> for DebuggerState {
     fn from(profile: &DebuggerConfig) -> Self {
         let mut state = DebuggerState::default();
         if let Some(v) = &profile.variable {
-            state.variables = v.iter().map(|x| Variable::from(x)).collect();
+            state.variables = v.iter().map(Variable::from).collect();
         }
         if let Some(b) = &profile.breakpoint {
             state.breakpoints = b.to_vec();
         }
+        if let Some(w) = &profile.watchpoint {
+            state.watchpoints = w.iter().map(Watchpoint::from).collect();
+        }
         state
     }
 }
@@ -1,4 +1,4 @@
-use crate::machine::{Addr, MachineStatus};
+use crate::machine::{Addr, MachineStatus, ResetKind};
 use crate::mos6502::Registers;
 use crossbeam_channel::Receiver;
 use keyboard_types::KeyboardEvent;
@@ -10,7 +10,8 @@ pub trait NonInteractiveClient {
 
     fn start(&mut self) -> Result<(), Self::Error>;
     fn stop(&mut self) -> Result<(), Self::Error>;
-    fn reset(&mut self) -> Result<(), Self::Error>;
+    /// Power cycle (hard) or reset with the RESET line (soft)
+    fn reset(&mut self, kind: ResetKind) -> Result<(), Self::Error>;
     fn pause(&mut self) -> Result<(), Self::Error>;
     fn resume(&mut self) -> Result<(), Self::Error>;
 
@@ -38,6 +39,13 @@ pub trait NonInteractiveClient {
     }
 
     fn set_receiver(&mut self, r: Receiver<ClientEvent>);
+
+    /// Complete state of the machine in the snapshot format (see `Snapshot`)
+    fn save_snapshot(&mut self) -> Result<Vec<u8>, Self::Error>;
+
+    /// Restores the machine from a snapshot. It can be done before the start
+    /// (the machine starts then from the restored state) or while it runs.
+    fn load_snapshot(&mut self, data: &[u8]) -> Result<(), Self::Error>;
 }
 
 pub trait InteractiveClient {
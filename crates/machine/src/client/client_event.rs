@@ -1,13 +1,38 @@
 use std::ops::Range;
 
-use keyboard_types::KeyboardEvent;
+use keyboard_types::{KeyState, KeyboardEvent};
 
-use crate::debugger::Breakpoint;
+use crate::debugger::{Breakpoint, Watchpoint};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ClientEvent {
     EnableBreakpoint(Breakpoint),
     DisableBreakpoint(Breakpoint),
+    EnableWatchpoint(Watchpoint),
+    DisableWatchpoint(Watchpoint),
     KeyPress(KeyboardEvent),
-    SetObservedMemory(Range<u16>)
+    /// Keys of the machine's own keyboard (bypassing the host keymap),
+    /// i.e. matrix codes of C64 keys (column * 8 + row)
+    MatrixKeys { keys: Vec<u8>, state: KeyState },
+    /// Text to be typed in (see `C64Client::type_text`)
+    TypeText(String),
+    /// RESTORE key (it's not a part of the keyboard matrix)
+    Restore(KeyState),
+    /// State of joystick in given control port (1 or 2):
+    /// bits 0-4 are up, down, left, right and fire (1 means pressed)
+    Joystick { port: u8, state: u8 },
+    /// Positions (0-255) and fire buttons (bits 0-1) of paddles in given control port
+    Paddles { port: u8, x: u8, y: u8, buttons: u8 },
+    /// Movement (in C64 pixels) and buttons (bit 0 - left, bit 1 - right)
+    /// of 1351 mouse in given control port
+    Mouse { port: u8, dx: i16, dy: i16, buttons: u8 },
+    /// Speed in percent of the real machine
+    SetSpeed(u32),
+    SetWarp(bool),
+    SetObservedMemory(Range<u16>),
+    /// Reverse debugging (see `Rewind`): one instruction back, back to
+    /// the last breakpoint, or to given position of the execution history
+    StepBack,
+    ReverseContinue,
+    Seek(u64),
 }
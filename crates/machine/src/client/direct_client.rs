@@ -1,6 +1,6 @@
 use super::*;
 use crate::error::MachineError;
-use crate::machine::{Addr, Machine, MachineStatus, Memory};
+use crate::machine::{Addr, Machine, MachineStatus, Memory, ResetKind, Snapshot};
 use crate::mos6502::Registers;
 use crate::utils::lock;
 // use crate::error::MachineError;
@@ -17,30 +17,53 @@ type Result<T> = std::result::Result<T, MachineError>;
 /// connecting to some remote machine
 pub struct DirectClient<T: Machine + Send + 'static> {
     machine_mtx: Arc<Mutex<T>>,
+    throttle: Arc<Mutex<Throttle>>,
     handle: Option<thread::JoinHandle<()>>,
+    /// The machine was restored from a snapshot before the start
+    restored: bool,
     pub receiver: Option<Receiver<ClientEvent>>
 }
 
 impl<T: Machine + Send + 'static> DirectClient<T> {
     pub fn new(machine: T) -> Self {
+        let config = machine.get_config();
+        let throttle = Throttle::new(config.speed, config.warp);
         DirectClient {
             machine_mtx: Arc::new(Mutex::new(machine)),
+            throttle: Arc::new(Mutex::new(throttle)),
             handle: None,
+            restored: false,
             receiver: None
         }
     }
 
-    pub fn lock(&self) -> MutexGuard<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
         lock::<T>(&self.machine_mtx)
     }
 
+    /// Speed settings and statistics of the machine's thread
+    pub fn throttle(&self) -> MutexGuard<'_, Throttle> {
+        lock::<Throttle>(&self.throttle)
+    }
+
+    /// Wakes up the machine's thread (it sleeps while paused)
+    fn wake_up(&self) {
+        if let Some(handle) = &self.handle {
+            handle.thread().unpark();
+        }
+    }
+
     fn start_machine_in_thread(&mut self) {
-        self.lock().start();
+        // a restored machine continues from its snapshot
+        if !std::mem::take(&mut self.restored) {
+            self.lock().start();
+        }
         let arc = self.machine_mtx.clone();
+        let throttle = self.throttle.clone();
         let handle = thread::Builder::new()
             .name("MOS6502 Machine".to_string())
             .spawn(move || {
-                let mut runtime = Runtime::<T>::new(arc);
+                let mut runtime = Runtime::<T>::new(arc, throttle);
                 runtime.machine_loop();
             })
             .unwrap();
@@ -85,8 +108,8 @@ impl<T: Machine + Send + 'static> NonInteractiveClient for DirectClient<T> {
         Ok(())
     }
 
-    fn reset(&mut self) -> Result<()> {
-        self.lock().reset();
+    fn reset(&mut self, kind: ResetKind) -> Result<()> {
+        self.lock().reset(kind);
         Ok(())
     }
 
@@ -94,6 +117,7 @@ impl<T: Machine + Send + 'static> NonInteractiveClient for DirectClient<T> {
         if self.is_running() {
             self.lock().stop();
         }
+        self.wake_up();
         let result = self.join();
         self.handle = None;
         result
@@ -106,6 +130,7 @@ impl<T: Machine + Send + 'static> NonInteractiveClient for DirectClient<T> {
 
     fn resume(&mut self) -> Result<()> {
         self.lock().resume();
+        self.wake_up();
         Ok(())
     }
 
@@ -125,4 +150,25 @@ impl<T: Machine + Send + 'static> NonInteractiveClient for DirectClient<T> {
         self.receiver = Some(r);
     }
 
+    fn save_snapshot(&mut self) -> Result<Vec<u8>> {
+        Ok(self.lock().save_snapshot().to_bytes())
+    }
+
+    fn load_snapshot(&mut self, data: &[u8]) -> Result<()> {
+        let snapshot = Snapshot::from_bytes(data)?;
+        let mut machine = self.lock();
+        let status = machine.get_status();
+        machine.load_snapshot(&snapshot)?;
+        let running = status != MachineStatus::Stopped;
+        if !running || machine.get_status() == MachineStatus::Stopped {
+            // the status is up to the client: a stopped machine is started
+            // with `start`, and a running one isn't stopped by the snapshot
+            machine.set_status(status);
+        }
+        drop(machine);
+        self.restored = !running;
+        self.wake_up();
+        Ok(())
+    }
+
 }
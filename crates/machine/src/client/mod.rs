@@ -2,5 +2,6 @@ mod client;
 mod direct_client;
 mod runtime;
 mod client_event;
+mod throttle;
 
-pub use {client::*, direct_client::*, client_event::*};
+pub use {client::*, direct_client::*, client_event::*, throttle::*};
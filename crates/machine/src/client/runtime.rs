@@ -1,58 +1,121 @@
-use crate::machine::{Machine, MachineStatus::*};
+use super::Throttle;
+use crate::machine::{Cycles, Machine, MachineStatus::*};
 use crate::utils::lock;
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
 
-/// Interval between IRQs in [ms]
-/// The value is specific for PAL systems. On NTSC systems the value
-/// was 1/60s.
-/// See:
+/// Interval between IRQs generated for machines without interrupt sources.
+/// KERNAL programs its timer IRQ to 1/60s on both PAL and NTSC machines
+/// (only the timer values differ, as the clocks do). See:
 /// https://dustlayer.com/machine-coding-tutorials/2013/4/8/episode-2-3-did-i-interrupt-you
-/// (it's always 1/60 of a second ,regardless whether it's PAL or NTSC)
 const JIFFY: Duration = Duration::from_millis(1000/60);
 
 pub struct Runtime<M: Machine> {
     mutex: Arc<Mutex<M>>,
+    throttle: Arc<Mutex<Throttle>>,
     irq_time: Instant,
+    /// Jiffy (counted in cycles from power-on) of the last IRQ in deterministic mode
+    irq_jiffy: Option<Cycles>,
+    next_frame: Cycles,
 }
 
 impl<M: Machine> Runtime<M> {
-    pub fn new(mutex: Arc<Mutex<M>>) -> Self {
+    pub fn new(mutex: Arc<Mutex<M>>, throttle: Arc<Mutex<Throttle>>) -> Self {
         Runtime {
             mutex,
+            throttle,
             irq_time: Instant::now(),
+            irq_jiffy: None,
+            next_frame: 0,
         }
     }
 
-    fn irq_loop(&mut self) {
-        if self.irq_time.elapsed() > JIFFY {
+    fn irq_loop(&mut self, machine: &mut M, frequency: u32, deterministic: bool) {
+        if machine.has_interrupt_sources() {
+            return;
+        }
+        let cycles = machine.get_cycles();
+        if deterministic {
+            // the jiffy is measured in emulated cycles, so the IRQs depend
+            // only on the clock (also when it's restored from a snapshot)
+            let jiffy = cycles / (frequency as f64 * JIFFY.as_secs_f64()) as Cycles;
+            match self.irq_jiffy {
+                Some(last) if last == jiffy => return,
+                Some(_) => self.irq_jiffy = Some(jiffy),
+                None => {
+                    self.irq_jiffy = Some(jiffy);
+                    return;
+                }
+            }
+        } else if self.irq_time.elapsed() > JIFFY {
             self.irq_time = Instant::now();
-            let mut machine = lock::<M>(&self.mutex);
-            machine.irq();
+        } else {
+            return;
+        }
+        machine.irq();
+    }
+
+    /// Whether the frame is over (the cycles may go back, i.e. on reset)
+    fn is_frame_end(&self, cycles: Cycles, frame_cycles: Cycles) -> bool {
+        cycles >= self.next_frame || self.next_frame - cycles > frame_cycles
+    }
+
+    /// Sleeps at the end of each frame, so the machine runs at its real speed
+    fn throttle_loop(&mut self, cycles: Cycles, frequency: u32, frame_cycles: Cycles) {
+        self.next_frame = cycles + frame_cycles;
+        let wait = lock(&self.throttle).end_frame(cycles, frequency);
+        if !wait.is_zero() {
+            thread::sleep(wait);
         }
     }
 
     pub fn machine_loop(&mut self) {
         let mut status = Running;
+        let mut paused = false;
 
         lock::<M>(&self.mutex).set_status(Running);
+        let mutex = self.mutex.clone();
         while status != Stopped {
-            {
-                let mut machine = lock::<M>(&self.mutex);
+            let (cycles, frequency, frame_cycles) = {
+                let mut machine = lock::<M>(&mutex);
                 status = machine.get_status();
                 if status == Debug {
+                    paused = true;
+                    drop(machine);
+                    // the client unparks the thread on resume
+                    thread::park_timeout(JIFFY);
                     continue;
                 }
-                machine.next();
-                if let Some(addr) = machine.get_config().exit_on_addr {
-                    if machine.PC() == addr {
-                        machine.debug();
+                if paused {
+                    paused = false;
+                    self.next_frame = machine.get_cycles() + machine.frame_cycles();
+                    lock(&self.throttle).resync(machine.get_cycles());
+                }
+                let frequency = machine.clock_frequency();
+                let frame_cycles = machine.frame_cycles();
+                let deterministic = machine.get_config().deterministic;
+                // the instructions of a frame are executed under one lock
+                // (the client gets the machine between the frames)
+                loop {
+                    machine.next();
+                    if let Some(addr) = machine.get_config().exit_on_addr {
+                        if machine.PC() == addr {
+                            machine.debug();
+                        }
+                    }
+                    // status must be checked 2nd time after next() - in case of BRK
+                    status = machine.get_status();
+                    self.irq_loop(&mut *machine, frequency, deterministic);
+                    let cycles = machine.get_cycles();
+                    if status != Running || self.is_frame_end(cycles, frame_cycles) {
+                        break (cycles, frequency, frame_cycles);
                     }
                 }
-                // status must be checked 2nd time after next() - in case of BRK
-                status = machine.get_status();
+            };
+            if self.is_frame_end(cycles, frame_cycles) {
+                self.throttle_loop(cycles, frequency, frame_cycles);
             }
-            self.irq_loop();
         }
     }
 }
@@ -0,0 +1,197 @@
+use crate::machine::Cycles;
+use std::time::{Duration, Instant};
+
+/// When the emulation falls behind the host more than that (i.e. the host
+/// is too slow), the throttle stops trying to catch up
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Period of measuring the actual speed
+const STATS_PERIOD: Duration = Duration::from_millis(500);
+
+/// Speed measured by the throttle
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SpeedStats {
+    /// Percent of the real machine's speed
+    pub speed: f32,
+    /// Emulated frames per second (of host's time)
+    pub fps: f32,
+}
+
+/// Paces the emulation to the real machine's clock. The machine runs in
+/// frame-sized slices: after each frame the throttle tells how long to sleep,
+/// so the emulated time matches the host's one (scaled by the speed).
+/// In warp mode the machine runs as fast as possible.
+#[derive(Debug)]
+pub struct Throttle {
+    /// Speed in percent of the real machine
+    speed: u32,
+    warp: bool,
+    /// Reference point of pacing: host's time at given cycle
+    sync_time: Instant,
+    sync_cycles: Cycles,
+    /// Pacing starts over at the end of next frame
+    sync_pending: bool,
+    stats_time: Instant,
+    stats_cycles: Cycles,
+    stats_frames: u32,
+    stats: SpeedStats,
+}
+
+impl Throttle {
+    pub fn new(speed: u32, warp: bool) -> Self {
+        let now = Instant::now();
+        Throttle {
+            speed: speed.max(1),
+            warp,
+            sync_time: now,
+            sync_cycles: 0,
+            sync_pending: true,
+            stats_time: now,
+            stats_cycles: 0,
+            stats_frames: 0,
+            stats: SpeedStats::default(),
+        }
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, percent: u32) {
+        self.speed = percent.max(1);
+        self.sync_pending = true;
+    }
+
+    pub fn is_warp(&self) -> bool {
+        self.warp
+    }
+
+    pub fn set_warp(&mut self, warp: bool) {
+        self.warp = warp;
+    }
+
+    pub fn stats(&self) -> SpeedStats {
+        self.stats
+    }
+
+    /// Starts pacing from given cycle (i.e. after a pause)
+    pub fn resync(&mut self, cycles: Cycles) {
+        self.resync_at(Instant::now(), cycles);
+    }
+
+    fn resync_at(&mut self, now: Instant, cycles: Cycles) {
+        self.sync_time = now;
+        self.sync_cycles = cycles;
+        self.sync_pending = false;
+        self.stats_time = now;
+        self.stats_cycles = cycles;
+        self.stats_frames = 0;
+    }
+
+    /// Must be called at the end of every frame. Returns the time
+    /// the emulation is ahead of the real machine (running at `frequency`)
+    pub fn end_frame(&mut self, cycles: Cycles, frequency: u32) -> Duration {
+        self.end_frame_at(Instant::now(), cycles, frequency)
+    }
+
+    fn end_frame_at(&mut self, now: Instant, cycles: Cycles, frequency: u32) -> Duration {
+        self.update_stats(now, cycles, frequency);
+        if self.warp || self.sync_pending {
+            self.sync_pending = false;
+            self.sync_time = now;
+            self.sync_cycles = cycles;
+            return Duration::ZERO;
+        }
+
+        let cycles_per_sec = frequency as f64 * self.speed as f64 / 100.0;
+        let emulated = Duration::from_secs_f64(
+            cycles.saturating_sub(self.sync_cycles) as f64 / cycles_per_sec,
+        );
+        let elapsed = now - self.sync_time;
+        if elapsed > emulated + MAX_LAG {
+            self.sync_time = now;
+            self.sync_cycles = cycles;
+        }
+        emulated.saturating_sub(elapsed)
+    }
+
+    fn update_stats(&mut self, now: Instant, cycles: Cycles, frequency: u32) {
+        self.stats_frames += 1;
+        let elapsed = now - self.stats_time;
+        if elapsed < STATS_PERIOD {
+            return;
+        }
+        let secs = elapsed.as_secs_f32();
+        self.stats = SpeedStats {
+            speed: cycles.saturating_sub(self.stats_cycles) as f32 / secs / frequency as f32
+                * 100.0,
+            fps: self.stats_frames as f32 / secs,
+        };
+        self.stats_time = now;
+        self.stats_cycles = cycles;
+        self.stats_frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn assert_about(expected: Duration, actual: Duration) {
+        assert!(expected.abs_diff(actual) < Duration::from_micros(1), "{actual:?}");
+    }
+
+    #[test]
+    fn test_pacing() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(100, false);
+        throttle.resync_at(start, 0);
+        // a frame of 1MHz machine takes 20ms
+        assert_about(15 * MS, throttle.end_frame_at(start + 5 * MS, 20_000, 1_000_000));
+        assert_about(10 * MS, throttle.end_frame_at(start + 30 * MS, 40_000, 1_000_000));
+        // running late
+        assert_eq!(Duration::ZERO, throttle.end_frame_at(start + 70 * MS, 60_000, 1_000_000));
+
+        throttle.set_speed(200);
+        throttle.resync_at(start, 0);
+        assert_about(5 * MS, throttle.end_frame_at(start + 5 * MS, 20_000, 1_000_000));
+
+        throttle.set_warp(true);
+        assert_eq!(Duration::ZERO, throttle.end_frame_at(start, 40_000, 1_000_000));
+    }
+
+    #[test]
+    fn test_pacing_starts_over_when_too_far_behind() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(100, false);
+        throttle.resync_at(start, 0);
+        assert_eq!(Duration::ZERO, throttle.end_frame_at(start + 200 * MS, 20_000, 1_000_000));
+        // the lag is forgotten, so the next frame isn't run at full speed
+        assert_about(15 * MS, throttle.end_frame_at(start + 205 * MS, 40_000, 1_000_000));
+    }
+
+    #[test]
+    fn test_speed_change_starts_over() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(100, false);
+        throttle.resync_at(start, 0);
+        throttle.set_speed(50);
+        assert_eq!(Duration::ZERO, throttle.end_frame_at(start + 5 * MS, 20_000, 1_000_000));
+        assert_about(35 * MS, throttle.end_frame_at(start + 10 * MS, 40_000, 1_000_000));
+    }
+
+    #[test]
+    fn test_stats() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(100, false);
+        throttle.resync_at(start, 0);
+        for frame in 1..=25 {
+            throttle.end_frame_at(start + frame * 20 * MS, frame as Cycles * 10_000, 1_000_000);
+        }
+        let stats = throttle.stats();
+        assert!((stats.speed - 50.0).abs() < 0.01);
+        assert!((stats.fps - 50.0).abs() < 0.01);
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use crate::emulator::EmulatorError;
 use crate::machine::{Machine, MachineStatus};
 use crate::mos6502::Operation;
 
@@ -12,6 +13,9 @@ pub trait Debugger {
     fn debugger_state(&self) -> &DebuggerState;
     fn debugger_state_mut(&mut self) -> &mut DebuggerState;
     fn machine(&self) -> &Self::MachineImpl;
+    fn machine_mut(&mut self) -> &mut Self::MachineImpl;
+    fn rewind(&self) -> &Rewind;
+    fn rewind_mut(&mut self) -> &mut Rewind;
 
     fn should_pause(&self, op: &Operation) -> bool {
         let m = self.machine();
@@ -33,6 +37,38 @@ pub trait Debugger {
             .collect();
         self.debugger_state_mut().variables = vars;
     }
+
+    /// Moves the machine to the position of its history (see `Rewind`)
+    /// and pauses it there. Breakpoints and variables are kept.
+    fn seek(&mut self, position: u64) -> Result<(), EmulatorError> {
+        let state = self.debugger_state().clone();
+        // the history is taken out, so the replay doesn't record it again
+        let mut rewind = std::mem::replace(self.rewind_mut(), Rewind::for_replay());
+        let result = rewind.seek(self.machine_mut(), position);
+        *self.rewind_mut() = rewind;
+        *self.debugger_state_mut() = state;
+        self.machine_mut().set_status(MachineStatus::Debug);
+        self.update_debugger_state();
+        result
+    }
+
+    fn step_back(&mut self) -> Result<(), EmulatorError> {
+        let status = self.rewind().status();
+        match status.position > status.start {
+            true => self.seek(status.position - 1),
+            false => Err(EmulatorError::InvalidState("no history to step back".to_string())),
+        }
+    }
+
+    /// Goes back to the last position a breakpoint applies at
+    /// (or to the start of the history)
+    fn reverse_continue(&mut self) -> Result<(), EmulatorError> {
+        let position = self
+            .rewind()
+            .find_breakpoint(&self.debugger_state().breakpoints, self.machine().cpu())
+            .unwrap_or(self.rewind().status().start);
+        self.seek(position)
+    }
 }
 
 pub trait DebugMachine: Machine {
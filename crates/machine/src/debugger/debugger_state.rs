@@ -1,10 +1,19 @@
 use super::*;
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
 use crate::machine::Addr;
+use crate::mos6502::Mnemonic;
+use serde::{de::IntoDeserializer, Deserialize};
 use std::ops::Range;
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct DebuggerState {
     pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Last access that triggered a watchpoint
+    pub watch_hit: Option<WatchHit>,
     pub variables: Vec<Variable>,
     pub irq_on: bool,
     pub nmi_on: bool,
@@ -35,6 +44,91 @@ impl DebuggerState {
             self.breakpoints.push(*bp);
         }
     }
+
+    pub fn add_watchpoint(&mut self, wp: &Watchpoint) {
+        if !self.watchpoints.contains(wp) {
+            self.watchpoints.push(*wp);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, wp: &Watchpoint) {
+        self.watchpoints.retain(|w| w != wp);
+    }
+
+    /// The last watchpoint hit isn't saved
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u16(self.breakpoints.len() as u16);
+        for bp in self.breakpoints.iter() {
+            match *bp {
+                Breakpoint::Address(addr) => w.u8(0).u16(addr),
+                Breakpoint::Interrupt => w.u8(1),
+                Breakpoint::Instruction(mnemonic) => w.u8(2).str(&mnemonic.to_string()),
+                Breakpoint::BRK => w.u8(3),
+                Breakpoint::Opcode(opcode) => w.u8(4).u8(opcode),
+                Breakpoint::Byte((addr, val)) => w.u8(5).u16(addr).u8(val),
+            };
+        }
+        w.u16(self.watchpoints.len() as u16);
+        for wp in self.watchpoints.iter() {
+            w.u16(wp.start).u16(wp.end).u8(wp.kind as u8);
+        }
+        w.u16(self.variables.len() as u16);
+        for var in self.variables.iter() {
+            w.str(&var.name).u16(var.addr).u8(var.value);
+        }
+        w.bool(self.irq_on)
+            .bool(self.nmi_on)
+            .u16(self.observed_mem.start)
+            .u16(self.observed_mem.end);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.breakpoints.clear();
+        for _ in 0..r.u16()? {
+            let bp = match r.u8()? {
+                0 => Breakpoint::Address(r.u16()?),
+                1 => Breakpoint::Interrupt,
+                2 => Breakpoint::Instruction(parse_mnemonic(&r.str()?)?),
+                3 => Breakpoint::BRK,
+                4 => Breakpoint::Opcode(r.u8()?),
+                5 => Breakpoint::Byte((r.u16()?, r.u8()?)),
+                kind => {
+                    return Err(EmulatorError::InvalidState(format!("breakpoint kind {kind}")))
+                }
+            };
+            self.breakpoints.push(bp);
+        }
+        self.watchpoints.clear();
+        for _ in 0..r.u16()? {
+            let (start, end) = (r.u16()?, r.u16()?);
+            let kind = match r.u8()? {
+                0 => WatchKind::Read,
+                1 => WatchKind::Write,
+                2 => WatchKind::Change,
+                kind => {
+                    return Err(EmulatorError::InvalidState(format!("watchpoint kind {kind}")))
+                }
+            };
+            self.watchpoints.push(Watchpoint { start, end, kind });
+        }
+        self.variables.clear();
+        for _ in 0..r.u16()? {
+            self.variables.push(Variable {
+                name: r.str()?,
+                addr: r.u16()?,
+                value: r.u8()?,
+            });
+        }
+        self.irq_on = r.bool()?;
+        self.nmi_on = r.bool()?;
+        self.observed_mem = r.u16()?..r.u16()?;
+        Ok(())
+    }
+}
+
+fn parse_mnemonic(name: &str) -> Result<Mnemonic, EmulatorError> {
+    Mnemonic::deserialize(name.into_deserializer())
+        .map_err(|e: serde::de::value::Error| EmulatorError::InvalidState(e.to_string()))
 }
 
 #[cfg(test)]
@@ -54,6 +148,27 @@ mod tests {
         assert_eq!(2, state.breakpoints.len());
     }
 
+    #[test]
+    fn test_save_and_load() {
+        let mut state = DebuggerState::default();
+        state.add_breakpoint(&Breakpoint::Address(0xfce2));
+        state.add_breakpoint(&Breakpoint::Instruction(Mnemonic::JSR));
+        state.add_watchpoint(&Watchpoint::new(0xd020..=0xd021, WatchKind::Change));
+        state.upsert_variable(Variable {
+            name: "cursor".to_string(),
+            addr: 0xd3,
+            value: 4,
+        });
+        state.set_observed_mem(0x0400, 0x0500);
+        let mut w = StateWriter::new();
+        state.save(&mut w);
+        let data = w.finish();
+
+        let mut loaded = DebuggerState::default();
+        loaded.load(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(loaded, state);
+    }
+
     #[test]
     fn test_remove_breakpoint() {
         let mut state = DebuggerState::default();
@@ -1,9 +1,15 @@
 mod breakpoint;
 mod debugger;
 mod debugger_state;
+mod rewind;
+mod trace;
 mod variable;
+mod watchpoint;
 
 pub use breakpoint::*;
 pub use debugger::*;
 pub use debugger_state::*;
+pub use rewind::*;
+pub use trace::*;
 pub use variable::*;
+pub use watchpoint::*;
@@ -0,0 +1,406 @@
+use super::Breakpoint;
+use crate::client::ClientEvent;
+use crate::emulator::EmulatorError;
+use crate::machine::{Addr, Cycles, Machine, Snapshot};
+use crate::mos6502::{Mnemonic, Operand, Operation, Registers, MOS6502};
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::ops::RangeInclusive;
+
+/// Instruction of the history: the state before it was executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub cycles: Cycles,
+    pub registers: Registers,
+    pub opcode: u8,
+    /// Index of the first write of the instruction (counted from the start of recording)
+    first_write: u64,
+}
+
+/// Position in the history and the range of positions that can be reached
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RewindStatus {
+    pub start: u64,
+    pub position: u64,
+    pub end: u64,
+}
+
+/// History of the execution for reverse debugging. Positions are counted in
+/// instructions since the recording started: position `n` is the state before
+/// the n-th instruction was executed.
+///
+/// The history consists of periodic checkpoints (full snapshots of the machine)
+/// and a journal of every instruction (registers, opcode and memory writes,
+/// including the ones of an interrupt that followed it). Going back restores
+/// the last checkpoint before the position and re-executes the instructions up
+/// to it, so also the devices get their state. The input applied meanwhile
+/// (keys, controllers, typed text) is journaled with the clock it was applied at,
+/// and handed back to the machine to be applied again (see `Machine::replay_input`).
+/// A replay that doesn't match the journal is reported.
+///
+/// The oldest checkpoints (with their part of the journal) are dropped when
+/// the history exceeds the memory budget (the last one is always kept).
+/// Executing an instruction after going back drops the history after it.
+#[derive(Debug, Default)]
+pub struct Rewind {
+    /// Memory budget [bytes]; zero disables the recording
+    budget: usize,
+    checkpoint_cycles: Cycles,
+    /// Positions, clock and data of the snapshots
+    checkpoints: VecDeque<(u64, Cycles, Vec<u8>)>,
+    checkpoints_size: usize,
+    journal: VecDeque<JournalEntry>,
+    writes: VecDeque<(Addr, u8)>,
+    /// Input events with the clock they were applied at
+    inputs: VecDeque<(Cycles, ClientEvent)>,
+    /// Position of the first entry of the journal
+    first: u64,
+    /// Index of the first write
+    first_write: u64,
+    position: u64,
+    /// Stands in for the history while it's being replayed (see `Rewind::for_replay`)
+    replaying: bool,
+}
+
+impl Rewind {
+    /// Budget is given in bytes, and checkpoints are taken every `checkpoint_cycles`
+    pub fn new(budget: usize, checkpoint_cycles: Cycles) -> Self {
+        Rewind {
+            budget,
+            checkpoint_cycles,
+            ..Default::default()
+        }
+    }
+
+    /// Empty (disabled) history that tells the machine its execution is a replay,
+    /// so it's not recorded or traced again
+    pub fn for_replay() -> Self {
+        Rewind {
+            replaying: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replaying
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    /// The journal starts with the first checkpoint
+    pub fn is_recording(&self) -> bool {
+        self.is_enabled() && !self.checkpoints.is_empty()
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        if budget == 0 {
+            self.clear();
+        }
+    }
+
+    /// Drops the history (i.e. after a reset, as it wouldn't be replayed)
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.checkpoints_size = 0;
+        self.journal.clear();
+        self.writes.clear();
+        self.inputs.clear();
+        self.first = self.position;
+        self.first_write = 0;
+    }
+
+    /// Approximate memory used by the history [bytes]
+    pub fn memory_used(&self) -> usize {
+        self.checkpoints_size
+            + self.journal.len() * size_of::<JournalEntry>()
+            + self.writes.len() * size_of::<(Addr, u8)>()
+            + self.inputs.len() * size_of::<(Cycles, ClientEvent)>()
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn positions(&self) -> RangeInclusive<u64> {
+        self.first..=self.end()
+    }
+
+    pub fn status(&self) -> RewindStatus {
+        RewindStatus {
+            start: self.first,
+            position: self.position,
+            end: self.end(),
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.first + self.journal.len() as u64
+    }
+
+    /// Instruction executed at the position
+    pub fn entry(&self, position: u64) -> Option<&JournalEntry> {
+        position
+            .checked_sub(self.first)
+            .and_then(|idx| self.journal.get(idx as usize))
+    }
+
+    /// Memory writes (addresses and values) of the instruction executed at the position
+    pub fn writes(&self, position: u64) -> impl Iterator<Item = &(Addr, u8)> {
+        let range = match self.entry(position) {
+            Some(entry) => {
+                let end = self
+                    .entry(position + 1)
+                    .map_or(self.first_write + self.writes.len() as u64, |next| {
+                        next.first_write
+                    });
+                (entry.first_write - self.first_write) as usize..(end - self.first_write) as usize
+            }
+            None => 0..0,
+        };
+        self.writes.range(range)
+    }
+
+    /// Position of the last instruction before `before` that wrote to the address
+    pub fn last_write(&self, addr: Addr, before: u64) -> Option<u64> {
+        (self.first..before.min(self.end()))
+            .rev()
+            .find(|pos| self.writes(*pos).any(|(a, _)| *a == addr))
+    }
+
+    /// Starts the journal entry of an instruction (called before it's executed).
+    /// The history after the current position is dropped.
+    pub fn record_instruction(&mut self, registers: &Registers, opcode: u8, cycles: Cycles) {
+        if !self.is_recording() {
+            return;
+        }
+        if self.position < self.end() {
+            self.truncate(cycles);
+        }
+        self.journal.push_back(JournalEntry {
+            cycles,
+            registers: registers.clone(),
+            opcode,
+            first_write: self.first_write + self.writes.len() as u64,
+        });
+        self.position += 1;
+    }
+
+    pub fn record_write(&mut self, addr: Addr, val: u8) {
+        if self.is_recording() && !self.journal.is_empty() {
+            self.writes.push_back((addr, val));
+        }
+    }
+
+    /// Journals the input event applied by the machine at the cycle
+    pub fn record_input(&mut self, cycles: Cycles, event: &ClientEvent) {
+        if self.is_recording() {
+            self.inputs.push_back((cycles, event.clone()));
+        }
+    }
+
+    /// Whether the machine should take a checkpoint (at the end of an instruction)
+    pub fn is_checkpoint_due(&self, cycles: Cycles) -> bool {
+        self.is_enabled()
+            && match self.checkpoints.back() {
+                Some((_, at, _)) => cycles >= at + self.checkpoint_cycles,
+                None => true,
+            }
+    }
+
+    pub fn add_checkpoint(&mut self, cycles: Cycles, snapshot: &Snapshot) {
+        if self.checkpoints.is_empty() {
+            self.clear();
+        }
+        let data = snapshot.to_bytes();
+        self.checkpoints_size += data.len();
+        self.checkpoints.push_back((self.position, cycles, data));
+        while self.memory_used() > self.budget && self.checkpoints.len() > 1 {
+            self.drop_oldest();
+        }
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some((_, _, data)) = self.checkpoints.pop_front() {
+            self.checkpoints_size -= data.len();
+        }
+        let start = self.checkpoints.front().map_or(self.position, |(pos, ..)| *pos);
+        while self.first < start {
+            self.journal.pop_front();
+            self.first += 1;
+        }
+        let first_write = self
+            .journal
+            .front()
+            .map_or(self.first_write + self.writes.len() as u64, |e| e.first_write);
+        self.writes.drain(..(first_write - self.first_write) as usize);
+        self.first_write = first_write;
+        let start_cycles = self.checkpoints.front().map_or(Cycles::MAX, |(_, at, _)| *at);
+        while self.inputs.front().is_some_and(|(at, _)| *at <= start_cycles) {
+            self.inputs.pop_front();
+        }
+    }
+
+    /// Drops the history after the current position (at the cycle)
+    fn truncate(&mut self, cycles: Cycles) {
+        let len = (self.position - self.first) as usize;
+        if let Some(entry) = self.journal.get(len) {
+            self.writes.truncate((entry.first_write - self.first_write) as usize);
+        }
+        self.journal.truncate(len);
+        let position = self.position;
+        self.checkpoints.retain(|(pos, ..)| *pos <= position);
+        self.checkpoints_size = self.checkpoints.iter().map(|(.., data)| data.len()).sum();
+        while self.inputs.back().is_some_and(|(at, _)| *at > cycles) {
+            self.inputs.pop_back();
+        }
+    }
+
+    /// Moves the machine to the position: restores the last checkpoint before it and
+    /// re-executes the instructions. The machine must not record its history meanwhile.
+    pub fn seek<M: Machine>(&mut self, machine: &mut M, position: u64) -> Result<(), EmulatorError> {
+        if !self.positions().contains(&position) || self.checkpoints.is_empty() {
+            return Err(EmulatorError::InvalidState(format!(
+                "position {position} is not in the history ({}-{})",
+                self.first,
+                self.end()
+            )));
+        }
+        let (start, cycles, data) = self.checkpoints.iter().rev().find(|(pos, ..)| *pos <= position).unwrap();
+        machine.load_snapshot(&Snapshot::from_bytes(data)?)?;
+        // the input applied at the checkpoint is already in its snapshot
+        self.inputs
+            .iter()
+            .filter(|(at, _)| at > cycles)
+            .for_each(|(at, event)| machine.replay_input(*at, event.clone()));
+        for _ in *start..position {
+            machine.next();
+        }
+        self.position = position;
+
+        if let Some(entry) = self.entry(position) {
+            if entry.registers != machine.cpu().registers || entry.cycles != machine.get_cycles() {
+                log::warn!("Replay differs from the history at position {position}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the breakpoint applies at the position (as it would when the machine
+    /// stopped there: the position's instruction is the next one, the previous one
+    /// is the last executed)
+    pub fn breakpoint_applies(&self, bp: &Breakpoint, position: u64, cpu: &MOS6502) -> bool {
+        let next = self.entry(position).map(|e| (e.registers.counter, e.opcode));
+        let last_opcode = position
+            .checked_sub(1)
+            .and_then(|pos| self.entry(pos))
+            .map(|e| e.opcode);
+        let last_mnemonic = last_opcode
+            .and_then(|opcode| cpu.operations.get(&opcode))
+            .map(|def| def.mnemonic);
+        match *bp {
+            Breakpoint::Address(addr) => next.map(|(pc, _)| pc) == Some(addr),
+            Breakpoint::Interrupt => false,
+            Breakpoint::Instruction(m) => last_mnemonic == Some(m),
+            Breakpoint::BRK => last_mnemonic == Some(Mnemonic::BRK),
+            Breakpoint::Opcode(o) => last_opcode == Some(o),
+            Breakpoint::Byte((addr, val)) => next == Some((addr, val)),
+        }
+    }
+
+    /// Last position before the current one a breakpoint applies at
+    pub fn find_breakpoint(&self, breakpoints: &[Breakpoint], cpu: &MOS6502) -> Option<u64> {
+        (self.first..self.position)
+            .rev()
+            .find(|pos| breakpoints.iter().any(|bp| self.breakpoint_applies(bp, *pos, cpu)))
+    }
+
+    /// Instruction executed at the position, disassembled with the operand
+    /// as it's in memory now (so it may differ for self-modifying code)
+    pub fn disassemble<M: Machine>(&self, machine: &M, position: u64) -> Option<String> {
+        let entry = self.entry(position)?;
+        let def = machine.cpu().operations.get(&entry.opcode)?;
+        let addr = entry.registers.counter;
+        let bytes: Vec<u8> = (1..def.len() as u16)
+            .map(|i| machine.read_byte(addr.wrapping_add(i)))
+            .collect();
+        let operand = match bytes[..] {
+            [lo] => Some(Operand::Byte(lo)),
+            [lo, hi] => Some(Operand::Word(u16::from_le_bytes([lo, hi]))),
+            _ => None,
+        };
+        let val: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let op = Operation::new(def.clone(), operand, None);
+        Some(format!("{:04x}: {:02x} {:<5} | {}", addr, entry.opcode, val.join(" "), op))
+    }
+
+    /// Up to `count` last instructions before the current position (with their positions)
+    pub fn history<M: Machine>(&self, machine: &M, count: usize) -> Vec<(u64, String)> {
+        let from = self.position.saturating_sub(count as u64).max(self.first);
+        (from..self.position)
+            .filter_map(|pos| self.disassemble(machine, pos).map(|line| (pos, line)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(rewind: &mut Rewind, pc: u16, writes: &[(Addr, u8)]) {
+        let registers = Registers {
+            counter: pc,
+            ..Default::default()
+        };
+        rewind.record_instruction(&registers, 0xea, pc as Cycles);
+        writes.iter().for_each(|(addr, val)| rewind.record_write(*addr, *val));
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut rewind = Rewind::new(1 << 20, 100);
+        record(&mut rewind, 0, &[]);
+        assert!(rewind.journal.is_empty()); // no checkpoint yet
+
+        assert!(rewind.is_checkpoint_due(0));
+        rewind.add_checkpoint(0, &Snapshot::new("test"));
+        record(&mut rewind, 0x1000, &[(0xd020, 1)]);
+        record(&mut rewind, 0x1003, &[]);
+        record(&mut rewind, 0x1004, &[(0x0400, 2), (0xd020, 3)]);
+        rewind.record_input(0x1003, &ClientEvent::SetWarp(true));
+        rewind.record_input(0x1004, &ClientEvent::SetWarp(false));
+        assert_eq!(rewind.positions(), 0..=3);
+        assert_eq!(rewind.writes(2).collect::<Vec<_>>(), vec![&(0x0400, 2), &(0xd020, 3)]);
+        assert_eq!(rewind.last_write(0xd020, 2), Some(0));
+        assert_eq!(rewind.last_write(0xd020, 3), Some(2));
+
+        let breakpoints = [Breakpoint::Address(0x1003)];
+        assert_eq!(rewind.find_breakpoint(&breakpoints, &MOS6502::new()), Some(1));
+
+        // going back and executing another instruction drops the history after it
+        rewind.position = 1;
+        record(&mut rewind, 0x1003, &[(0x0401, 4)]);
+        assert_eq!(rewind.positions(), 0..=2);
+        assert_eq!(rewind.writes(1).collect::<Vec<_>>(), vec![&(0x0401, 4)]);
+        assert_eq!(rewind.inputs, [(0x1003, ClientEvent::SetWarp(true))]);
+        assert!(!rewind.is_checkpoint_due(99));
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut snapshot = Snapshot::new("test");
+        snapshot.add("memory", vec![0; 1000]);
+        let mut rewind = Rewind::new(2500, 10);
+        for i in 0..4 {
+            rewind.add_checkpoint(i * 10, &snapshot);
+            for _ in 0..5 {
+                record(&mut rewind, i as u16, &[(0, 0)]);
+            }
+        }
+        assert_eq!(rewind.checkpoints.len(), 2);
+        assert_eq!(rewind.positions(), 10..=20);
+        assert_eq!(rewind.writes(10).count(), 1);
+    }
+}
@@ -0,0 +1,604 @@
+use crate::emulator::{
+    abstractions::{Addr, BusAccess, StateReader, StateWriter},
+    EmulatorError,
+};
+use crate::machine::{Cycles, MachineConfig};
+use crate::mos6502::{AddressMode, Operand, Operation, OpsMap, ProcessorStatus, Registers, MOS6502};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::num::Wrapping;
+use std::ops::RangeInclusive;
+
+/// Header of a binary trace (magic and version of the format)
+const BINARY_HEADER: &[u8] = b"6502TRACE\x01";
+
+/// Prefix of instruction lines of the text format (VICE's main CPU)
+const TEXT_PREFIX: &str = ".C:";
+
+/// Executed instruction with its effects on the bus
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TraceRecord {
+    pub cycles: Cycles,
+    /// Registers before the instruction was executed (the counter points at it)
+    pub registers: Registers,
+    /// Opcode and operand
+    pub bytes: Vec<u8>,
+    /// Whether the instruction was fetched from ROM
+    pub rom: bool,
+    /// Effective address and the value read or written there (by the last access)
+    pub address: Option<Addr>,
+    pub value: Option<u8>,
+    /// Accesses of the instruction to the bus (without the fetch of the instruction)
+    pub accesses: Vec<BusAccess>,
+}
+
+impl TraceRecord {
+    pub fn new(
+        cycles: Cycles,
+        registers: Registers,
+        op: &Operation,
+        rom: bool,
+        accesses: Vec<BusAccess>,
+    ) -> Self {
+        let mut bytes = vec![op.def.opcode];
+        match op.operand {
+            Some(Operand::Byte(val)) => bytes.push(val),
+            Some(Operand::Word(val)) => bytes.extend_from_slice(&val.to_le_bytes()),
+            None => {}
+        }
+        let value = accesses
+            .iter()
+            .rev()
+            .find(|access| Some(access.addr) == op.address)
+            .map(|access| access.value);
+        TraceRecord {
+            cycles,
+            registers,
+            bytes,
+            rom,
+            address: op.address,
+            value,
+            accesses,
+        }
+    }
+
+    /// Line of VICE monitor's CPU history (`chis`), i.e.
+    /// `.C:e5cd  A5 C6       LDA $C6        - A:00 X:FF Y:0A SP:f3 ..-..IZ.   29358218`
+    pub fn to_text(&self, operations: &OpsMap) -> String {
+        let regs = &self.registers;
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{b:02X}")).collect();
+        format!(
+            "{TEXT_PREFIX}{:04x}  {:<10}  {:<15}- A:{:02X} X:{:02X} Y:{:02X} SP:{:02x} {} {:>10}",
+            regs.counter,
+            bytes.join(" "),
+            disassemble(&self.bytes, regs.counter, operations),
+            regs.accumulator,
+            regs.x,
+            regs.y,
+            regs.stack,
+            flags(&regs.status),
+            self.cycles
+        )
+    }
+
+    /// Parses a line of `to_text` (the effective address and bus accesses aren't there)
+    pub fn from_text(line: &str) -> Result<Self, EmulatorError> {
+        let err = || EmulatorError::InvalidState(format!("invalid trace line: {line}"));
+        let hex8 = |val: &str| u8::from_str_radix(val, 16).map_err(|_| err());
+        let (instruction, state) = line
+            .strip_prefix(TEXT_PREFIX)
+            .and_then(|line| line.split_once(" - "))
+            .ok_or_else(err)?;
+
+        let mut tokens = instruction.split_whitespace();
+        let counter = tokens
+            .next()
+            .and_then(|pc| u16::from_str_radix(pc, 16).ok())
+            .ok_or_else(err)?;
+        let bytes = tokens
+            .take_while(|token| token.len() == 2)
+            .map(hex8)
+            .take(3)
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        let tokens: Vec<&str> = state.split_whitespace().collect();
+        let [a, x, y, sp, status, cycles] = tokens[..] else {
+            return Err(err());
+        };
+        let reg = |token: &str, name: &str| token.strip_prefix(name).ok_or_else(err).and_then(hex8);
+        let status: Vec<bool> = status.chars().map(|c| c != '.' && c != '-').collect();
+        if status.len() != 8 {
+            return Err(err());
+        }
+        Ok(TraceRecord {
+            cycles: cycles.parse().map_err(|_| err())?,
+            registers: Registers {
+                counter,
+                stack: Wrapping(reg(sp, "SP:")?),
+                accumulator: Wrapping(reg(a, "A:")?),
+                x: Wrapping(reg(x, "X:")?),
+                y: Wrapping(reg(y, "Y:")?),
+                status: ProcessorStatus {
+                    negative: status[0],
+                    overflow: status[1],
+                    break_command: status[3],
+                    decimal_mode: status[4],
+                    interrupt_disable: status[5],
+                    zero: status[6],
+                    carry: status[7],
+                },
+            },
+            bytes,
+            ..Default::default()
+        })
+    }
+
+    pub fn save(&self, w: &mut StateWriter, bus: bool) {
+        w.u64(self.cycles);
+        self.registers.save(w);
+        w.u8(self.bytes.len() as u8)
+            .bytes(&self.bytes)
+            .bool(self.rom)
+            .bool(self.address.is_some())
+            .u16(self.address.unwrap_or_default())
+            .bool(self.value.is_some())
+            .u8(self.value.unwrap_or_default());
+        let accesses = if bus { &self.accesses[..] } else { &[] };
+        w.u16(accesses.len() as u16);
+        for access in accesses {
+            w.u16(access.addr).u8(access.value).bool(access.write).str(&access.device);
+        }
+    }
+
+    pub fn load(r: &mut StateReader) -> Result<Self, EmulatorError> {
+        let cycles = r.u64()?;
+        let mut registers = Registers::default();
+        registers.load(r)?;
+        let len = r.u8()?;
+        let bytes = r.bytes(len.into())?.to_vec();
+        let rom = r.bool()?;
+        let address = (r.bool()?, r.u16()?);
+        let value = (r.bool()?, r.u8()?);
+        let mut accesses = Vec::new();
+        for _ in 0..r.u16()? {
+            accesses.push(BusAccess {
+                addr: r.u16()?,
+                value: r.u8()?,
+                write: r.bool()?,
+                previous: None,
+                device: r.str()?,
+            });
+        }
+        Ok(TraceRecord {
+            cycles,
+            registers,
+            bytes,
+            rom,
+            address: address.0.then_some(address.1),
+            value: value.0.then_some(value.1),
+            accesses,
+        })
+    }
+
+    /// Names of the fields that differ from the other record. Cycles are compared
+    /// as offsets from the given starts (so traces started at different moments
+    /// can be compared); the effective address, value and bus accesses only
+    /// if both records have them.
+    pub fn differences(&self, other: &TraceRecord, starts: Option<(Cycles, Cycles)>) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if let Some((start, other_start)) = starts {
+            if self.cycles.wrapping_sub(start) != other.cycles.wrapping_sub(other_start) {
+                fields.push("cycles");
+            }
+        }
+        if self.registers.counter != other.registers.counter {
+            fields.push("PC");
+        }
+        if self.bytes != other.bytes {
+            fields.push("bytes");
+        }
+        let other_registers = Registers {
+            counter: self.registers.counter,
+            ..other.registers.clone()
+        };
+        if self.registers != other_registers {
+            fields.push("registers");
+        }
+        if self.address.is_some() && other.address.is_some() && self.address != other.address {
+            fields.push("address");
+        }
+        if self.value.is_some() && other.value.is_some() && self.value != other.value {
+            fields.push("value");
+        }
+        if !self.accesses.is_empty() && !other.accesses.is_empty() && self.accesses != other.accesses {
+            fields.push("bus");
+        }
+        fields
+    }
+}
+
+/// 6502 assembly as shown by VICE (i.e. `LDA ($FB),Y`, branches with their targets)
+pub fn disassemble(bytes: &[u8], pc: Addr, operations: &OpsMap) -> String {
+    let def = match bytes.first().and_then(|opcode| operations.get(opcode)) {
+        Some(def) => def,
+        None => return String::from("???"),
+    };
+    let m = def.mnemonic;
+    let byte = bytes.get(1).copied().unwrap_or_default();
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or_default()]);
+    match def.address_mode {
+        AddressMode::Implicit | AddressMode::Accumulator => m.to_string(),
+        AddressMode::Immediate => format!("{m} #${byte:02X}"),
+        AddressMode::Relative => {
+            let target = pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("{m} ${target:04X}")
+        }
+        AddressMode::ZeroPage => format!("{m} ${byte:02X}"),
+        AddressMode::ZeroPageX => format!("{m} ${byte:02X},X"),
+        AddressMode::ZeroPageY => format!("{m} ${byte:02X},Y"),
+        AddressMode::Absolute => format!("{m} ${word:04X}"),
+        AddressMode::AbsoluteX => format!("{m} ${word:04X},X"),
+        AddressMode::AbsoluteY => format!("{m} ${word:04X},Y"),
+        AddressMode::Indirect => format!("{m} (${word:04X})"),
+        AddressMode::IndirectX => format!("{m} (${byte:02X},X)"),
+        AddressMode::IndirectY => format!("{m} (${byte:02X}),Y"),
+    }
+}
+
+/// Flags as shown by VICE: `NV-BDIZC`, with dots for the cleared ones
+fn flags(status: &ProcessorStatus) -> String {
+    [
+        (status.negative, 'N'),
+        (status.overflow, 'V'),
+        (false, '-'),
+        (status.break_command, 'B'),
+        (status.decimal_mode, 'D'),
+        (status.interrupt_disable, 'I'),
+        (status.zero, 'Z'),
+        (status.carry, 'C'),
+    ]
+    .iter()
+    .map(|(set, c)| match *set || *c == '-' {
+        true => *c,
+        false => '.',
+    })
+    .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TraceFormat {
+    /// VICE monitor's CPU history; bus accesses follow the instruction in extra lines
+    #[default]
+    Text,
+    /// Records as written by `TraceRecord::save`, after a header
+    Binary,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of memory the traced instructions are fetched from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceMemory {
+    Rom,
+    Ram,
+}
+
+impl TraceMemory {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rom" => Some(TraceMemory::Rom),
+            "ram" => Some(TraceMemory::Ram),
+            _ => None,
+        }
+    }
+}
+
+/// Selects the instructions that are traced (all of them by default)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TraceFilter {
+    /// Addresses of the instructions
+    pub range: Option<RangeInclusive<Addr>>,
+    pub memory: Option<TraceMemory>,
+}
+
+impl TraceFilter {
+    fn from_config(config: &MachineConfig) -> Self {
+        let range = config.trace_range.as_ref().and_then(|range| {
+            let res = TraceFilter::parse_range(range);
+            if res.is_none() {
+                log::error!("Invalid trace range: {range} (expected i.e. c000-cfff)");
+            }
+            res
+        });
+        let memory = config.trace_memory.as_ref().and_then(|name| {
+            let res = TraceMemory::from_name(name);
+            if res.is_none() {
+                log::error!("Unknown memory of the trace: {name} (expected rom or ram)");
+            }
+            res
+        });
+        TraceFilter { range, memory }
+    }
+
+    /// Range of hex addresses (`c000-cfff`) or a single address
+    pub fn parse_range(range: &str) -> Option<RangeInclusive<Addr>> {
+        let (from, to) = range.split_once('-').unwrap_or((range, range));
+        let from = u16::from_str_radix(from.trim(), 16).ok()?;
+        let to = u16::from_str_radix(to.trim(), 16).ok()?;
+        (from <= to).then_some(from..=to)
+    }
+
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        let in_range = match &self.range {
+            Some(range) => range.contains(&record.registers.counter),
+            None => true,
+        };
+        let in_memory = match self.memory {
+            Some(TraceMemory::Rom) => record.rom,
+            Some(TraceMemory::Ram) => !record.rom,
+            None => true,
+        };
+        in_range && in_memory
+    }
+}
+
+/// Writes the executed instructions (see `TraceRecord`) to a file or stdout.
+/// Writing stops at the first error.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write + Send>>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    /// Whether the bus accesses are written
+    bus: bool,
+    operations: OpsMap,
+    failed: bool,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, format: TraceFormat, filter: TraceFilter, bus: bool) -> Self {
+        let mut tracer = Tracer {
+            out: BufWriter::new(out),
+            format,
+            filter,
+            bus,
+            operations: MOS6502::new().operations,
+            failed: false,
+        };
+        if format == TraceFormat::Binary {
+            tracer.write(BINARY_HEADER);
+        }
+        tracer
+    }
+
+    /// Trace to the file of the config (or to stdout with `disassemble`)
+    pub fn from_config(config: &MachineConfig) -> Option<Self> {
+        let format = config.trace_format.as_ref().map_or(TraceFormat::default(), |name| {
+            TraceFormat::from_name(name).unwrap_or_else(|| {
+                log::error!("Unknown trace format: {name} (expected text or binary)");
+                TraceFormat::default()
+            })
+        });
+        let out: Box<dyn Write + Send> = match &config.trace {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(file),
+                Err(err) => {
+                    log::error!("Can't create trace file {}: {err}", path.display());
+                    return None;
+                }
+            },
+            None if config.disassemble => Box::new(io::stdout()),
+            None => return None,
+        };
+        Some(Tracer::new(out, format, TraceFilter::from_config(config), config.trace_bus))
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        if self.failed || !self.filter.matches(record) {
+            return;
+        }
+        match self.format {
+            TraceFormat::Text => {
+                let mut text = record.to_text(&self.operations);
+                text.push('\n');
+                if self.bus {
+                    for access in record.accesses.iter() {
+                        text.push_str(&format!(
+                            "{:>11}{} ${:04X} = ${:02X} ({})\n",
+                            "",
+                            if access.write { "write" } else { "read " },
+                            access.addr,
+                            access.value,
+                            access.device
+                        ));
+                    }
+                }
+                self.write(text.as_bytes());
+            }
+            TraceFormat::Binary => {
+                let mut w = StateWriter::new();
+                record.save(&mut w, self.bus);
+                self.write(&w.finish());
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if !self.failed {
+            if let Err(err) = self.out.flush() {
+                log::error!("Can't write the trace: {err}");
+                self.failed = true;
+            }
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        if let Err(err) = self.out.write_all(data) {
+            log::error!("Can't write the trace: {err}");
+            self.failed = true;
+        }
+    }
+}
+
+/// Reads records of a trace of any format (lines of the text format
+/// that aren't instructions, i.e. bus accesses, are skipped)
+pub enum TraceReader<'a> {
+    Text(std::str::Lines<'a>),
+    Binary(StateReader<'a>),
+}
+
+impl<'a> TraceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, EmulatorError> {
+        match data.strip_prefix(BINARY_HEADER) {
+            Some(records) => Ok(TraceReader::Binary(StateReader::new(records))),
+            None => std::str::from_utf8(data)
+                .map(|text| TraceReader::Text(text.lines()))
+                .map_err(|_| EmulatorError::InvalidState("trace of unknown format".to_string())),
+        }
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Result<TraceRecord, EmulatorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            TraceReader::Text(lines) => lines
+                .find(|line| line.starts_with(TEXT_PREFIX))
+                .map(TraceRecord::from_text),
+            TraceReader::Binary(r) => match r.is_empty() {
+                true => None,
+                false => Some(TraceRecord::load(r)),
+            },
+        }
+    }
+}
+
+/// First record two traces differ at
+#[derive(Debug)]
+pub struct TraceDivergence {
+    /// Index of the record (counted from zero)
+    pub index: u64,
+    /// Last record the traces agree on
+    pub last_match: Option<TraceRecord>,
+    /// Records of both traces (`None` if the trace has ended)
+    pub left: Option<TraceRecord>,
+    pub right: Option<TraceRecord>,
+    /// Names of the differing fields (see `TraceRecord::differences`)
+    pub fields: Vec<&'static str>,
+}
+
+/// Compares the traces record by record; cycles are compared as offsets
+/// from the first records (or not at all, without `compare_cycles`)
+pub fn diff_traces<L, R>(left: L, right: R, compare_cycles: bool) -> Result<Option<TraceDivergence>, EmulatorError>
+where
+    L: IntoIterator<Item = Result<TraceRecord, EmulatorError>>,
+    R: IntoIterator<Item = Result<TraceRecord, EmulatorError>>,
+{
+    let (mut left, mut right) = (left.into_iter(), right.into_iter());
+    let mut starts = None;
+    let mut last_match = None;
+    let mut index = 0;
+    loop {
+        let (l, r) = (left.next().transpose()?, right.next().transpose()?);
+        let fields = match (&l, &r) {
+            (None, None) => return Ok(None),
+            (Some(l), Some(r)) => {
+                if compare_cycles && starts.is_none() {
+                    starts = Some((l.cycles, r.cycles));
+                }
+                l.differences(r, starts)
+            }
+            _ => vec!["end of trace"],
+        };
+        if !fields.is_empty() {
+            return Ok(Some(TraceDivergence {
+                index,
+                last_match,
+                left: l,
+                right: r,
+                fields,
+            }));
+        }
+        last_match = l;
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(cycles: Cycles, counter: u16, bytes: &[u8]) -> TraceRecord {
+        TraceRecord {
+            cycles,
+            registers: Registers {
+                counter,
+                ..Registers::new()
+            },
+            bytes: bytes.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_text_format() {
+        let ops = MOS6502::new().operations;
+        let mut rec = record(29358218, 0xe5cd, &[0xb1, 0xfb]);
+        rec.registers.x = Wrapping(0xff);
+        rec.registers.stack = Wrapping(0xf3);
+        rec.registers.status = ProcessorStatus::from(0b0010_0110);
+        let line = rec.to_text(&ops);
+        assert_eq!(
+            ".C:e5cd  B1 FB       LDA ($FB),Y    - A:00 X:FF Y:00 SP:f3 ..-..IZ.   29358218",
+            line
+        );
+        assert_eq!(rec, TraceRecord::from_text(&line).unwrap());
+        assert_eq!("BNE $E5C9", disassemble(&[0xd0, 0xfa], 0xe5cd, &ops));
+        assert!(TraceRecord::from_text(".C:e5cd  B1 FB").is_err());
+    }
+
+    #[test]
+    fn test_binary_format_and_diff() {
+        let mut rec = record(100, 0xc000, &[0x8d, 0x20, 0xd0]);
+        rec.address = Some(0xd020);
+        rec.value = Some(1);
+        rec.accesses.push(BusAccess {
+            addr: 0xd020,
+            value: 1,
+            write: true,
+            previous: None,
+            device: "VIC-II".into(),
+        });
+        let left = [rec.clone(), record(104, 0xc003, &[0xea])];
+        let mut data = BINARY_HEADER.to_vec();
+        for rec in left.iter() {
+            let mut w = StateWriter::new();
+            rec.save(&mut w, true);
+            data.extend(w.finish());
+        }
+        let records: Vec<_> = TraceReader::new(&data).unwrap().map(Result::unwrap).collect();
+        assert_eq!(&left[..], &records[..]);
+
+        // the same program started later, with a different value written
+        let mut right = [record(1100, 0xc000, &[0x8d, 0x20, 0xd0]), record(1104, 0xc003, &[0xea])];
+        assert!(diff_traces(left.clone().map(Ok), right.clone().map(Ok), true).unwrap().is_none());
+        right[1].cycles += 1;
+        right[0].value = Some(2);
+        let div = diff_traces(left.clone().map(Ok), right.clone().map(Ok), true).unwrap().unwrap();
+        assert_eq!((0, vec!["value"]), (div.index, div.fields));
+        right[0].value = None;
+        let div = diff_traces(left.clone().map(Ok), right.map(Ok), true).unwrap().unwrap();
+        assert_eq!((1, vec!["cycles"]), (div.index, div.fields));
+        assert_eq!(Some(rec), div.last_match);
+        let div = diff_traces(left.clone().map(Ok), left[..1].iter().cloned().map(Ok), false);
+        assert_eq!(vec!["end of trace"], div.unwrap().unwrap().fields);
+    }
+}
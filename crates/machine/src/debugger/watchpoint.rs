@@ -0,0 +1,142 @@
+use crate::emulator::abstractions::BusAccess;
+use crate::machine::Addr;
+use serde_derive::Deserialize;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Access that triggers a watchpoint
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Write of a different value than the one at the address (every write
+    /// to I/O, as the registers can't be read without side effects)
+    Change,
+}
+
+impl WatchKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "read" => Some(WatchKind::Read),
+            "write" => Some(WatchKind::Write),
+            "change" => Some(WatchKind::Change),
+            _ => None,
+        }
+    }
+}
+
+/// Pauses the machine after an instruction accessed the range of addresses
+/// (the fetch of the instruction itself doesn't count)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub start: Addr,
+    pub end: Addr,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<Addr>, kind: WatchKind) -> Self {
+        Watchpoint {
+            start: *range.start(),
+            end: *range.end(),
+            kind,
+        }
+    }
+
+    pub fn applies(&self, access: &BusAccess) -> bool {
+        if access.addr < self.start || access.addr > self.end {
+            return false;
+        }
+        match self.kind {
+            WatchKind::Read => !access.write,
+            WatchKind::Write => access.write,
+            WatchKind::Change => access.write && access.previous != Some(access.value),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = format!("{:?}", self.kind).to_lowercase();
+        match self.start == self.end {
+            true => write!(f, "{kind} {:04x}", self.start),
+            false => write!(f, "{kind} {:04x}-{:04x}", self.start, self.end),
+        }
+    }
+}
+
+/// Access that triggered a watchpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    /// Address of the accessing instruction
+    pub pc: Addr,
+    pub addr: Addr,
+    pub write: bool,
+    /// Value before the access (if it's known)
+    pub old: Option<u8>,
+    pub new: u8,
+}
+
+impl WatchHit {
+    /// First access of the instruction at `pc` that triggers any of the watchpoints
+    pub fn find(watchpoints: &[Watchpoint], pc: Addr, accesses: &[BusAccess]) -> Option<Self> {
+        accesses.iter().find_map(|access| {
+            let wp = watchpoints.iter().find(|wp| wp.applies(access))?;
+            Some(WatchHit {
+                watchpoint: *wp,
+                pc,
+                addr: access.addr,
+                write: access.write,
+                old: if access.write { access.previous } else { Some(access.value) },
+                new: access.value,
+            })
+        })
+    }
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = if self.write { "write" } else { "read" };
+        let old = self.old.map_or(String::from("??"), |val| format!("{val:02x}"));
+        write!(
+            f,
+            "{kind} ${:04x} by ${:04x}: ${old} -> ${:02x}",
+            self.addr, self.pc, self.new
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(addr: Addr, value: u8, write: bool, previous: Option<u8>) -> BusAccess {
+        BusAccess {
+            addr,
+            value,
+            write,
+            previous,
+            device: String::from("RAM"),
+        }
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let wps = [
+            Watchpoint::new(0x0400..=0x07e7, WatchKind::Change),
+            Watchpoint::new(0xd020..=0xd020, WatchKind::Read),
+        ];
+        let same = access(0x0400, 32, true, Some(32));
+        assert_eq!(None, WatchHit::find(&wps, 0xc000, std::slice::from_ref(&same)));
+
+        let accesses = [same, access(0xd020, 14, false, None), access(0x0401, 1, true, Some(32))];
+        let hit = WatchHit::find(&wps, 0xc000, &accesses).unwrap();
+        assert_eq!((wps[1], 0xd020, Some(14), 14), (hit.watchpoint, hit.addr, hit.old, hit.new));
+
+        let hit = WatchHit::find(&wps[..1], 0xc000, &accesses).unwrap();
+        assert_eq!("write $0401 by $c000: $20 -> $01", hit.to_string());
+        assert_eq!("change 0400-07e7", wps[0].to_string());
+    }
+}
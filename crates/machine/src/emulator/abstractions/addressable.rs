@@ -1,5 +1,8 @@
 use super::device::*;
+use super::StateReader;
+use crate::emulator::EmulatorError;
 use core::num::Wrapping;
+use std::sync::{Arc, Mutex};
 
 pub type Addr = u16;
 
@@ -13,6 +16,25 @@ pub trait Addressable {
     }
 }
 
+/// Tells the value left on the data bus, seen when no device responds to a read
+/// (on C64 it's the byte fetched by VIC-II in the first phase of the cycle)
+pub trait OpenBus {
+    fn open_bus(&self) -> u8;
+}
+
+pub type OpenBusSource = Arc<Mutex<dyn OpenBus + Send>>;
+
+/// Single access of the CPU to the bus, with the name of the device that served it
+#[derive(Debug, Clone, PartialEq)]
+pub struct BusAccess {
+    pub addr: Addr,
+    pub value: u8,
+    pub write: bool,
+    /// Value at the address before a write, if it can be seen without side effects
+    pub previous: Option<u8>,
+    pub device: String,
+}
+
 /// BankSwitch should NEVER expose any of Addressables, it switches between
 pub trait AddressResolver: Addressable {
     fn fragment(&self, from: Addr, to: Addr) -> Vec<u8> {
@@ -75,6 +97,10 @@ impl ArrayMemory {
     pub fn len(&self) -> usize {
         self.cells.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
 }
 
 impl Addressable for ArrayMemory {
@@ -91,4 +117,25 @@ impl Addressable for ArrayMemory {
     }
 }
 
-impl DeviceTrait for ArrayMemory {}
+impl DeviceTrait for ArrayMemory {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn hard_reset(&mut self) {
+        self.cells.fill(0);
+    }
+
+    /// Memory keeps its content on reset
+    fn soft_reset(&mut self) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        self.cells.to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = StateReader::new(state);
+        self.cells.copy_from_slice(r.bytes(self.cells.len())?);
+        r.finish()
+    }
+}
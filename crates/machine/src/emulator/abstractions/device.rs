@@ -1,14 +1,85 @@
 use std::sync::{Arc, Mutex, MutexGuard};
+use super::{Addr, Clocked};
+use crate::emulator::EmulatorError;
 use crate::utils::lock;
 
-pub trait DeviceTrait {}
+/// Bit field of a register, for debugger introspection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterField {
+    pub name: &'static str,
+    pub mask: u8,
+    pub description: &'static str,
+}
+
+/// Register at given offset of device's address space, for debugger introspection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterInfo {
+    pub offset: Addr,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub fields: &'static [RegisterField],
+}
+
+/// Lifecycle of an emulated device. All the methods have defaults,
+/// so simple devices (i.e. in tests) can stay markers.
+pub trait DeviceTrait {
+    /// Name of the device (i.e. the chip), as shown by the debugger
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Brings the device into its power-on state
+    fn hard_reset(&mut self) {}
+
+    /// Reaction to the RESET line. Most of the chips reset their registers
+    /// exactly as on power-on, but memories keep their content.
+    fn soft_reset(&mut self) {
+        self.hard_reset();
+    }
+
+    /// Map of the registers (empty if the device has none)
+    fn registers(&self) -> &'static [RegisterInfo] {
+        &[]
+    }
+
+    /// Value of the register without side effects of reading it
+    /// (i.e. clearing interrupt flags); None if it can't be told
+    fn peek_register(&self, _offset: Addr) -> Option<u8> {
+        None
+    }
+
+    /// Internal state of the device (see `StateWriter`)
+    fn save_state(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restores state returned by `save_state`
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), EmulatorError> {
+        Ok(())
+    }
+
+    /// The device as driven by the master clock (see `Scheduler`);
+    /// None if it changes only when accessed
+    fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+        None
+    }
+}
+
+/// Device shared by the machine and the chips wired to it
+pub type SharedDevice = Arc<Mutex<dyn DeviceTrait + Send>>;
+
+/// Whether both point to the same device (compared by address only,
+/// as the vtables of the same type may differ between codegen units)
+pub fn is_same_device(a: &SharedDevice, b: &SharedDevice) -> bool {
+    std::ptr::addr_eq(Arc::as_ptr(a), Arc::as_ptr(b))
+}
 
 #[derive(Clone)]
 pub struct Device<T: DeviceTrait>(Arc<Mutex<T>>);
 
 pub trait Accessor<T: DeviceTrait> {
     fn mutex(&self) -> Arc<Mutex<T>>;
-    fn lock(&self) -> MutexGuard<T>;
+    fn lock(&self) -> MutexGuard<'_, T>;
 }
 
 impl<T: DeviceTrait> Accessor<T> for Device<T> {
@@ -16,7 +87,7 @@ impl<T: DeviceTrait> Accessor<T> for Device<T> {
         self.0.clone()
     }
 
-    fn lock(&self) -> MutexGuard<T> {
+    fn lock(&self) -> MutexGuard<'_, T> {
         lock(&self.0)
     }
 }
@@ -41,13 +112,44 @@ mod tests {
         let _his_device: Device<A> = d.into();
     }
 
+    #[test]
+    fn test_lifecycle() {
+        struct Latch(u8);
+        impl DeviceTrait for Latch {
+            fn name(&self) -> &str {
+                "latch"
+            }
+
+            fn hard_reset(&mut self) {
+                self.0 = 0;
+            }
+
+            fn save_state(&self) -> Vec<u8> {
+                vec![self.0]
+            }
+
+            fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+                let mut r = crate::emulator::abstractions::StateReader::new(state);
+                self.0 = r.u8()?;
+                r.finish()
+            }
+        }
+
+        let mut devices: Vec<Box<dyn DeviceTrait>> = vec![Box::new(Latch(5))];
+        let state = devices[0].save_state();
+        devices.iter_mut().for_each(|d| d.soft_reset());
+        assert_eq!(devices[0].save_state(), vec![0]);
+        devices[0].load_state(&state).unwrap();
+        assert_eq!(devices[0].save_state(), vec![5]);
+        assert!(devices[0].load_state(&[1, 2]).is_err());
+        assert_eq!(devices[0].name(), "latch");
+    }
+
     #[test]
     fn nested_devices() {
         #[derive(Clone, Debug)]
         struct Ram(u16);
-        struct Rom {}
         impl DeviceTrait for Ram {}
-        impl DeviceTrait for Rom {}
 
         struct Gpu {
             ram: Device<Ram>,
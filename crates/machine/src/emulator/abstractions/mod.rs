@@ -1,7 +1,11 @@
 mod addressable;
-mod machine;
 mod device;
+mod machine;
+mod scheduler;
+mod state;
 
 pub use addressable::*;
-pub use machine::*;
 pub use device::*;
+pub use machine::*;
+pub use scheduler::*;
+pub use state::*;
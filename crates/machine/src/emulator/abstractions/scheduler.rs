@@ -0,0 +1,243 @@
+use super::{is_same_device, CPUCycles, SharedDevice};
+use crate::utils::lock;
+use std::collections::VecDeque;
+
+/// Device driven by the master clock (i.e. CIA timers, SID oscillators).
+/// A device with its own processor (i.e. a disk drive) would execute
+/// its instructions in `tick`, so it advances in lockstep with the machine.
+pub trait Clocked {
+    /// Advances the device by given number of cycles (cycle by cycle)
+    fn tick(&mut self, cycles: CPUCycles);
+
+    /// Number of cycles after which the device may change its outputs on its own
+    /// (i.e. raise an interrupt). The scheduler ticks the device then at latest.
+    /// None means the device changes only when accessed; the default
+    /// requests ticking on every step of the clock.
+    fn next_event(&self) -> Option<CPUCycles> {
+        Some(1)
+    }
+}
+
+struct Registration {
+    device: SharedDevice,
+    synced_at: CPUCycles,
+    due_at: CPUCycles,
+}
+
+/// Owns the master clock of a machine. Clocked devices are not ticked after
+/// every instruction, but catch up with the clock (all of them at once, so they
+/// advance in lockstep) when one of them is due, or when the machine syncs them,
+/// i.e. before they're accessed. Hence devices are locked only when needed.
+/// Besides that, the scheduler keeps timed events of the machine (type `E`).
+pub struct Scheduler<E> {
+    clock: CPUCycles,
+    devices: Vec<Registration>,
+    /// The earliest `due_at` of the devices
+    due_at: CPUCycles,
+    /// Events sorted by their cycle
+    events: VecDeque<(CPUCycles, E)>,
+}
+
+impl<E> Default for Scheduler<E> {
+    fn default() -> Self {
+        Scheduler {
+            clock: 0,
+            devices: Vec::new(),
+            due_at: CPUCycles::MAX,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl<E> Scheduler<E> {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    /// Current value of the master clock
+    pub fn clock(&self) -> CPUCycles {
+        self.clock
+    }
+
+    /// Registers the device, if it's clocked (see `DeviceTrait::clocked`)
+    pub fn register(&mut self, device: SharedDevice) {
+        let Some(next_event) = lock(&device).clocked().map(|dev| dev.next_event()) else {
+            return;
+        };
+        let due_at = due_time(self.clock, next_event);
+        self.devices.push(Registration {
+            device,
+            synced_at: self.clock,
+            due_at,
+        });
+        self.due_at = self.due_at.min(due_at);
+    }
+
+    /// Stops clocking the device (i.e. when it's taken out of the machine)
+    pub fn unregister(&mut self, device: &SharedDevice) {
+        self.devices.retain(|reg| !is_same_device(&reg.device, device));
+        self.update_due_at();
+    }
+
+    /// Advances the master clock. It doesn't tick the devices.
+    pub fn advance(&mut self, cycles: CPUCycles) {
+        self.clock += cycles;
+    }
+
+    /// Whether any of the devices must be synced (see `Clocked::next_event`)
+    pub fn is_due(&self) -> bool {
+        self.clock >= self.due_at
+    }
+
+    /// Brings all the devices up to the master clock
+    pub fn sync(&mut self) {
+        for id in 0..self.devices.len() {
+            self.sync_device(id);
+        }
+        self.update_due_at();
+    }
+
+    fn sync_device(&mut self, index: usize) {
+        let clock = self.clock;
+        let reg = &mut self.devices[index];
+        let mut guard = lock(&reg.device);
+        let Some(device) = guard.clocked() else {
+            return;
+        };
+        if clock > reg.synced_at {
+            device.tick(clock - reg.synced_at);
+        }
+        reg.synced_at = clock;
+        reg.due_at = due_time(clock, device.next_event());
+    }
+
+    fn update_due_at(&mut self) {
+        self.due_at = self.devices.iter().map(|reg| reg.due_at).min().unwrap_or(CPUCycles::MAX);
+    }
+
+    /// Schedules the event at given cycle (after events scheduled at the same cycle)
+    pub fn schedule(&mut self, at: CPUCycles, event: E) {
+        let pos = self.events.partition_point(|(cycle, _)| *cycle <= at);
+        self.events.insert(pos, (at, event));
+    }
+
+    /// Schedules the event given number of cycles from now
+    pub fn schedule_in(&mut self, cycles: CPUCycles, event: E) {
+        self.schedule(self.clock + cycles, event);
+    }
+
+    /// Moves the master clock to given cycle (i.e. when a snapshot is loaded).
+    /// The devices are considered in sync with it, so they're not ticked
+    /// for the difference; the events are kept (see `retain_events`).
+    pub fn set_clock(&mut self, clock: CPUCycles) {
+        self.clock = clock;
+        for reg in self.devices.iter_mut() {
+            reg.synced_at = clock;
+            let next_event = lock(&reg.device).clocked().and_then(|dev| dev.next_event());
+            reg.due_at = due_time(clock, next_event);
+        }
+        self.update_due_at();
+    }
+
+    /// Keeps only the events for which the predicate is true
+    pub fn retain_events(&mut self, mut f: impl FnMut(CPUCycles, &E) -> bool) {
+        self.events.retain(|(at, event)| f(*at, event));
+    }
+
+    /// Takes the next due event
+    pub fn next_event(&mut self) -> Option<E> {
+        match self.events.front() {
+            Some((at, _)) if self.clock >= *at => self.events.pop_front().map(|(_, event)| event),
+            _ => None,
+        }
+    }
+}
+
+fn due_time(clock: CPUCycles, next_event: Option<CPUCycles>) -> CPUCycles {
+    next_event.map_or(CPUCycles::MAX, |cycles| clock + cycles.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::abstractions::DeviceTrait;
+    use std::sync::{Arc, Mutex};
+
+    struct Counter {
+        ticks: CPUCycles,
+        period: CPUCycles,
+    }
+
+    impl Clocked for Counter {
+        fn tick(&mut self, cycles: CPUCycles) {
+            self.ticks += cycles;
+        }
+
+        fn next_event(&self) -> Option<CPUCycles> {
+            Some(self.period - self.ticks % self.period)
+        }
+    }
+
+    impl DeviceTrait for Counter {
+        fn clocked(&mut self) -> Option<&mut dyn Clocked> {
+            Some(self)
+        }
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let counter = Arc::new(Mutex::new(Counter { ticks: 0, period: 10 }));
+        let mut scheduler = Scheduler::<&str>::new();
+        scheduler.register(counter.clone());
+        scheduler.schedule(9, "b");
+        scheduler.schedule(5, "a");
+
+        // the device isn't ticked until it's due
+        scheduler.advance(4);
+        assert!(!scheduler.is_due());
+        assert_eq!(scheduler.next_event(), None);
+
+        scheduler.advance(4);
+        assert_eq!(scheduler.next_event(), Some("a"));
+        assert_eq!(scheduler.next_event(), None);
+        scheduler.advance(3);
+        assert!(scheduler.is_due());
+        assert_eq!(counter.lock().unwrap().ticks, 0);
+        scheduler.sync();
+        assert_eq!(counter.lock().unwrap().ticks, 11);
+        assert!(!scheduler.is_due());
+        assert_eq!(scheduler.next_event(), Some("b"));
+    }
+
+    #[test]
+    fn test_set_clock() {
+        let counter = Arc::new(Mutex::new(Counter { ticks: 0, period: 10 }));
+        let mut scheduler = Scheduler::<&str>::new();
+        scheduler.register(counter.clone());
+        scheduler.schedule(5, "a");
+        scheduler.schedule(1005, "b");
+
+        scheduler.set_clock(1000);
+        scheduler.retain_events(|at, _| at >= 1000);
+        assert!(!scheduler.is_due());
+        assert_eq!(scheduler.next_event(), None);
+        scheduler.advance(10);
+        scheduler.sync();
+        assert_eq!(counter.lock().unwrap().ticks, 10);
+        assert_eq!(scheduler.next_event(), Some("b"));
+    }
+
+    #[test]
+    fn test_unregister() {
+        let counter = Arc::new(Mutex::new(Counter { ticks: 0, period: 10 }));
+        let device: SharedDevice = counter.clone();
+        let mut scheduler = Scheduler::<&str>::new();
+        scheduler.register(device.clone());
+        scheduler.advance(20);
+        assert!(scheduler.is_due());
+        scheduler.unregister(&device);
+        assert!(!scheduler.is_due());
+        scheduler.sync();
+        assert_eq!(counter.lock().unwrap().ticks, 0);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::emulator::EmulatorError;
+
+/// Writes internal state of a device as a stream of bytes
+/// (little endian, no padding). See `DeviceTrait::save_state`.
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter::default()
+    }
+
+    pub fn u8(&mut self, val: u8) -> &mut Self {
+        self.data.push(val);
+        self
+    }
+
+    pub fn u16(&mut self, val: u16) -> &mut Self {
+        self.bytes(&val.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, val: u32) -> &mut Self {
+        self.bytes(&val.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, val: u64) -> &mut Self {
+        self.bytes(&val.to_le_bytes())
+    }
+
+    pub fn f32(&mut self, val: f32) -> &mut Self {
+        self.u32(val.to_bits())
+    }
+
+    pub fn bool(&mut self, val: bool) -> &mut Self {
+        self.u8(val.into())
+    }
+
+    /// UTF-8 string, preceded by its length (u16)
+    pub fn str(&mut self, val: &str) -> &mut Self {
+        self.u16(val.len() as u16).bytes(val.as_bytes())
+    }
+
+    pub fn bytes(&mut self, val: &[u8]) -> &mut Self {
+        self.data.extend_from_slice(val);
+        self
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reads state written with `StateWriter` (in the same order)
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, EmulatorError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, EmulatorError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, EmulatorError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, EmulatorError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f32(&mut self) -> Result<f32, EmulatorError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, EmulatorError> {
+        Ok(self.u8()? > 0)
+    }
+
+    pub fn str(&mut self) -> Result<String, EmulatorError> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec())
+            .map_err(|e| EmulatorError::InvalidState(e.to_string()))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], EmulatorError> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            return Err(EmulatorError::InvalidState(format!(
+                "expected {} bytes at offset {}, {} left",
+                len,
+                self.pos,
+                self.data.len() - self.pos
+            )));
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], EmulatorError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    /// Fails if not all the data was read (i.e. state of other version of the device)
+    pub fn finish(self) -> Result<(), EmulatorError> {
+        match self.data.len() - self.pos {
+            0 => Ok(()),
+            left => Err(EmulatorError::InvalidState(format!("{left} bytes left unread"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut w = StateWriter::new();
+        w.u8(1).u16(0x0203).bool(true).u64(42).bytes(&[7, 8]);
+        let data = w.finish();
+        assert_eq!(data.len(), 14);
+
+        let mut r = StateReader::new(&data);
+        assert_eq!(r.u8().unwrap(), 1);
+        assert_eq!(r.u16().unwrap(), 0x0203);
+        assert!(r.bool().unwrap());
+        assert_eq!(r.u64().unwrap(), 42);
+        assert!(r.u32().is_err());
+        assert_eq!(r.bytes(2).unwrap(), &[7, 8]);
+        assert!(r.finish().is_ok());
+
+        let mut w = StateWriter::new();
+        w.str("C64").f32(0.5);
+        let data = w.finish();
+        let mut r = StateReader::new(&data);
+        assert_eq!(r.str().unwrap(), "C64");
+        assert_eq!(r.f32().unwrap(), 0.5);
+        assert!(r.finish().is_ok());
+    }
+}
@@ -1,9 +1,314 @@
+use super::{CIATimer, CIATod};
 use crate::{
-    emulator::abstractions::{Addr, Addressable},
+    emulator::{
+        abstractions::{
+            Addr, Addressable, RegisterField, RegisterInfo, StateReader, StateWriter,
+        },
+        EmulatorError,
+    },
     utils::if_else,
 };
-use bcd_numbers::BCD;
-use chrono::Timelike;
+use std::cell::Cell;
+
+/// Interrupt sources (bits of ICR register)
+pub const ICR_TIMER_A: u8 = 0b0000_0001;
+pub const ICR_TIMER_B: u8 = 0b0000_0010;
+pub const ICR_ALARM: u8 = 0b0000_0100;
+pub const ICR_SERIAL: u8 = 0b0000_1000;
+pub const ICR_FLAG: u8 = 0b0001_0000;
+pub const ICR_IR: u8 = 0b1000_0000;
+
+/// Number of timer A underflows needed to shift a byte out
+/// (the CNT line toggles on each underflow, and a bit is shifted every second one)
+const SERIAL_BYTE_UNDERFLOWS: u8 = 16;
+
+/// Internal state of the chip, common for all the CIA implementations
+#[derive(Debug, Clone, Default)]
+pub struct CIAState {
+    pub pra: u8,
+    pub prb: u8,
+    pub ddra: u8,
+    pub ddrb: u8,
+    pub timer_a: CIATimer,
+    pub timer_b: CIATimer,
+    pub tod: CIATod,
+    pub sdr: u8,
+    pub icr_mask: u8,
+    /// Interrupt flags are cleared on read, therefore interior mutability
+    icr_data: Cell<u8>,
+    cra_mode: u8,
+    crb_mode: u8,
+    serial_count: u8,
+    serial_pending: bool,
+    tod_period: u32,
+    tod_cycles: u32,
+}
+
+impl CIAState {
+    pub fn icr_data(&self) -> u8 {
+        self.icr_data.get()
+    }
+
+    /// Sets the interrupt flag(s) and - if not masked - the IR bit
+    pub fn set_interrupt(&mut self, flags: u8) {
+        let mut data = self.icr_data.get() | flags;
+        if data & self.icr_mask & 0x1f > 0 {
+            data |= ICR_IR;
+        }
+        self.icr_data.set(data);
+    }
+
+    /// Reads ICR and acknowledges all the interrupts
+    pub fn read_icr(&self) -> u8 {
+        self.icr_data.replace(0)
+    }
+
+    pub fn write_icr_mask(&mut self, val: u8) {
+        if val & 0x80 > 0 {
+            self.icr_mask |= val & 0x1f;
+        } else {
+            self.icr_mask &= !val & 0x1f;
+        }
+        self.set_interrupt(0);
+    }
+
+    fn write_sdr(&mut self, val: u8) {
+        self.sdr = val;
+        if self.is_serial_output() {
+            if self.serial_count == 0 {
+                self.serial_count = SERIAL_BYTE_UNDERFLOWS;
+            } else {
+                self.serial_pending = true;
+            }
+        }
+    }
+
+    fn shift_out(&mut self) {
+        if self.serial_count == 0 {
+            return;
+        }
+        self.serial_count -= 1;
+        if self.serial_count == 0 {
+            self.set_interrupt(ICR_SERIAL);
+            if self.serial_pending {
+                self.serial_pending = false;
+                self.serial_count = SERIAL_BYTE_UNDERFLOWS;
+            }
+        }
+    }
+
+    /// Overrides PB6 and PB7 with timer outputs (if enabled)
+    pub fn with_timer_outputs(&self, mut val: u8) -> u8 {
+        if let Some(out) = self.timer_a.port_output() {
+            val = if_else(out, val | 0x40, val & !0x40);
+        }
+        if let Some(out) = self.timer_b.port_output() {
+            val = if_else(out, val | 0x80, val & !0x80);
+        }
+        val
+    }
+
+    fn is_serial_output(&self) -> bool {
+        self.cra_mode & 0x40 > 0
+    }
+
+    pub fn cra(&self) -> u8 {
+        self.timer_a.control() | self.cra_mode
+    }
+
+    pub fn crb(&self) -> u8 {
+        self.timer_b.control() | self.crb_mode
+    }
+
+    pub fn write_cra(&mut self, val: u8) {
+        self.timer_a.set_control(val & 0x1f);
+        self.cra_mode = val & 0xe0;
+        if !self.is_serial_output() {
+            self.serial_count = 0;
+            self.serial_pending = false;
+        }
+    }
+
+    pub fn write_crb(&mut self, val: u8) {
+        self.timer_b.set_control(val & 0x1f);
+        self.crb_mode = val & 0xe0;
+    }
+
+    /// Connects TOD pin to the power line: `cycles` is the number of
+    /// clock cycles between its pulses (i.e. clock frequency / 50Hz).
+    /// Zero means the pin is not connected and the TOD doesn't run.
+    pub fn set_tod_period(&mut self, cycles: u32) {
+        self.tod_period = cycles;
+        self.tod_cycles = 0;
+    }
+
+    /// State after RES pin was pulled low: all the registers are cleared,
+    /// except the timer latches (and counters) that are set to ones.
+    /// The TOD pin stays connected.
+    pub fn reset(&mut self) {
+        *self = CIAState {
+            tod_period: self.tod_period,
+            ..CIAState::default()
+        };
+        for timer in [&mut self.timer_a, &mut self.timer_b] {
+            timer.latch = 0xffff;
+            timer.counter = 0xffff;
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u8(self.pra).u8(self.prb).u8(self.ddra).u8(self.ddrb);
+        self.timer_a.save(w);
+        self.timer_b.save(w);
+        self.tod.save(w);
+        w.u8(self.sdr)
+            .u8(self.icr_mask)
+            .u8(self.icr_data.get())
+            .u8(self.cra_mode)
+            .u8(self.crb_mode)
+            .u8(self.serial_count)
+            .bool(self.serial_pending)
+            .u32(self.tod_period)
+            .u32(self.tod_cycles);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.pra = r.u8()?;
+        self.prb = r.u8()?;
+        self.ddra = r.u8()?;
+        self.ddrb = r.u8()?;
+        self.timer_a.load(r)?;
+        self.timer_b.load(r)?;
+        self.tod.load(r)?;
+        self.sdr = r.u8()?;
+        self.icr_mask = r.u8()?;
+        self.icr_data.set(r.u8()?);
+        self.cra_mode = r.u8()?;
+        self.crb_mode = r.u8()?;
+        self.serial_count = r.u8()?;
+        self.serial_pending = r.bool()?;
+        self.tod_period = r.u32()?;
+        self.tod_cycles = r.u32()?;
+        Ok(())
+    }
+
+    /// Number of cycles until the chip can raise an interrupt on its own
+    /// (underflow of a timer counting cycles, or TOD tick), None if it can't
+    pub fn cycles_until_event(&self) -> Option<u64> {
+        let timer_a = self.timer_a.is_running() && self.cra_mode & 0x20 == 0;
+        // timer B counting underflows of timer A can't underflow earlier than A
+        let timer_b = self.timer_b.is_running() && (self.crb_mode >> 5) & 0b11 == 0;
+        [
+            timer_a.then(|| self.timer_a.counter as u64 + 1),
+            timer_b.then(|| self.timer_b.counter as u64 + 1),
+            (self.tod_period > 0).then(|| self.tod_period.saturating_sub(self.tod_cycles) as u64),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// One clock cycle (Phi2) of both timers and the TOD
+    pub fn tick(&mut self) {
+        // timer A counting CNT pulses is not supported (no CNT source)
+        let a_underflow = self.cra_mode & 0x20 == 0 && self.timer_a.count();
+        if a_underflow {
+            self.set_interrupt(ICR_TIMER_A);
+            if self.is_serial_output() {
+                self.shift_out();
+            }
+        }
+        let count_b = match (self.crb_mode >> 5) & 0b11 {
+            0b00 => true,
+            0b01 => false,
+            // CNT is pulled up, so both modes count timer A underflows
+            _ => a_underflow,
+        };
+        if count_b && self.timer_b.count() {
+            self.set_interrupt(ICR_TIMER_B);
+        }
+        if self.tod_period > 0 {
+            self.tod_cycles += 1;
+            if self.tod_cycles >= self.tod_period {
+                self.tod_cycles = 0;
+                if self.tod.tick(self.cra_mode & 0x80 > 0) {
+                    self.set_interrupt(ICR_ALARM);
+                }
+            }
+        }
+    }
+}
+
+const TIMER_CONTROL_FIELDS: [RegisterField; 5] = [
+    RegisterField { name: "START", mask: 0x01, description: "Timer running" },
+    RegisterField { name: "PBON", mask: 0x02, description: "Timer output on port B" },
+    RegisterField { name: "OUTMODE", mask: 0x04, description: "Output toggles (or pulses)" },
+    RegisterField { name: "RUNMODE", mask: 0x08, description: "One-shot (or continuous)" },
+    RegisterField { name: "LOAD", mask: 0x10, description: "Force load (strobe)" },
+];
+
+const fn register(offset: Addr, name: &'static str, description: &'static str) -> RegisterInfo {
+    RegisterInfo { offset, name, description, fields: &[] }
+}
+
+/// Registers of CIA 6526 (see `DeviceTrait::registers`)
+pub static CIA_REGISTERS: [RegisterInfo; 16] = [
+    register(0x00, "PRA", "Data port A"),
+    register(0x01, "PRB", "Data port B (PB6/PB7: timer outputs)"),
+    register(0x02, "DDRA", "Data direction of port A (1 - output)"),
+    register(0x03, "DDRB", "Data direction of port B (1 - output)"),
+    register(0x04, "TA LO", "Timer A counter (read) / latch (write), low byte"),
+    register(0x05, "TA HI", "Timer A counter (read) / latch (write), high byte"),
+    register(0x06, "TB LO", "Timer B counter (read) / latch (write), low byte"),
+    register(0x07, "TB HI", "Timer B counter (read) / latch (write), high byte"),
+    register(0x08, "TOD 10THS", "Time of day: tenths of second (BCD)"),
+    register(0x09, "TOD SEC", "Time of day: seconds (BCD)"),
+    register(0x0a, "TOD MIN", "Time of day: minutes (BCD)"),
+    register(0x0b, "TOD HR", "Time of day: hours (BCD, bit 7: PM)"),
+    register(0x0c, "SDR", "Serial data register"),
+    RegisterInfo {
+        offset: 0x0d,
+        name: "ICR",
+        description: "Interrupt data (read, clears) / mask (write, bit 7: set or clear)",
+        fields: &[
+            RegisterField { name: "TA", mask: ICR_TIMER_A, description: "Timer A underflow" },
+            RegisterField { name: "TB", mask: ICR_TIMER_B, description: "Timer B underflow" },
+            RegisterField { name: "ALRM", mask: ICR_ALARM, description: "TOD alarm" },
+            RegisterField { name: "SP", mask: ICR_SERIAL, description: "Serial byte done" },
+            RegisterField { name: "FLG", mask: ICR_FLAG, description: "FLAG pin" },
+            RegisterField { name: "IR", mask: ICR_IR, description: "Interrupt requested" },
+        ],
+    },
+    RegisterInfo {
+        offset: 0x0e,
+        name: "CRA",
+        description: "Control register A",
+        fields: &[
+            TIMER_CONTROL_FIELDS[0],
+            TIMER_CONTROL_FIELDS[1],
+            TIMER_CONTROL_FIELDS[2],
+            TIMER_CONTROL_FIELDS[3],
+            TIMER_CONTROL_FIELDS[4],
+            RegisterField { name: "INMODE", mask: 0x20, description: "Count CNT pulses (or cycles)" },
+            RegisterField { name: "SPMODE", mask: 0x40, description: "Serial port output (or input)" },
+            RegisterField { name: "TODIN", mask: 0x80, description: "TOD pin at 50Hz (or 60Hz)" },
+        ],
+    },
+    RegisterInfo {
+        offset: 0x0f,
+        name: "CRB",
+        description: "Control register B",
+        fields: &[
+            TIMER_CONTROL_FIELDS[0],
+            TIMER_CONTROL_FIELDS[1],
+            TIMER_CONTROL_FIELDS[2],
+            TIMER_CONTROL_FIELDS[3],
+            TIMER_CONTROL_FIELDS[4],
+            RegisterField { name: "INMODE", mask: 0x60, description: "Count cycles, CNT or timer A underflows" },
+            RegisterField { name: "ALARM", mask: 0x80, description: "TOD writes set the alarm (or time)" },
+        ],
+    },
+];
 
 /// To find out more about CIA6526, read here
 /// [CIA #1 in Mapping C64](http://www.unusedino.de/ec64/technical/project64/mapping_c64.html)
@@ -12,64 +317,87 @@ use chrono::Timelike;
 /// [Data sheet](http://archive.6502.org/datasheets/mos_6526_cia_recreated.pdf)
 ///
 /// Current implementation status
-/// $00 (Port A) - Done for CIA 1 (Keyboard)
-/// $01 (Port B) - Done for CIA 1 (Keyboard)
-/// $02 (Port A flags) - Hardcoded to $FF
-/// $03 (Port B flags) - Hardcoded to 0
-/// $04-$07 (Timer and and B) - TO DO
-/// $08-$0B (RTC) - Read only
-/// $0C (Serial shift register) - TO DO
-/// $0D (Interrupt control and status) - TO DO
-/// $0E-$0F (Timer control) - TO DO
+/// $00-$01 (Port A and B) - Done (peripherals provided by implementations)
+/// $02-$03 (Data direction) - Done
+/// $04-$07 (Timer A and B) - Done (one-shot, continuous, cascading, PB6/PB7 output)
+/// $08-$0B (RTC) - Done (driven by the TOD pin, see `set_tod_period`)
+/// $0C (Serial shift register) - Done (output mode, input via `serial_input`)
+/// $0D (Interrupt control and status) - Done
+/// $0E-$0F (Timer control) - Done (except counting CNT pulses)
 #[allow(non_camel_case_types)]
 pub trait CIA_6526: Addressable {
-    fn mem(&self) -> &[u8];
-    fn mem_mut(&mut self) -> &mut [u8];
+    fn state(&self) -> &CIAState;
+    fn state_mut(&mut self) -> &mut CIAState;
+
+    /// Called after port or data direction register (0-3) was written,
+    /// so implementations can update connected peripherals
+    fn port_written(&mut self, _reg: Addr) {}
+
+    /// Value of port A pins. Inputs are pulled up by default.
+    fn read_port_a(&self) -> u8 {
+        let s = self.state();
+        s.pra | !s.ddra
+    }
+
+    /// Value of port B pins, including timer outputs on PB6 and PB7
+    fn read_port_b(&self) -> u8 {
+        let s = self.state();
+        s.with_timer_outputs(s.prb | !s.ddrb)
+    }
 
     fn read_byte(&self, addr: Addr) -> u8 {
-        if (0x8..=0xb).contains(&addr) {
-            let now = chrono::Local::now().time();
-            match addr {
-                0x08 => {
-                    BCD::<1>::new(((now.nanosecond() / 100_000_000) % 10).into()).get_number() as u8
-                }
-                0x09 => BCD::<1>::new(now.second().into()).get_number() as u8,
-                0x0a => BCD::<1>::new(now.minute().into()).get_number() as u8,
-                0x0b => {
-                    let (pm, hour) = now.hour12();
-                    let pm_flag = if_else(pm, 128, 0);
-                    let bcd = (BCD::<2>::new(hour.into()).get_number()) as u8;
-                    bcd | pm_flag
-                }
-                _ => 0,
-            }
-        }
-        else if (0x04..=0x07).contains(&addr) {
-            match addr {
-                0x04 => self.timer_a().to_le_bytes()[0],
-                0x05 => self.timer_a().to_le_bytes()[1],
-                0x06 => self.timer_b().to_le_bytes()[0],
-                0x07 => self.timer_b().to_le_bytes()[1],
-                _ => panic!("Shouldn't happen")
-            }
-        }
-        else {
-            self.mem()[addr as usize]
+        let s = self.state();
+        match addr & 0x0f {
+            0x00 => self.read_port_a(),
+            0x01 => self.read_port_b(),
+            0x02 => s.ddra,
+            0x03 => s.ddrb,
+            0x04 => s.timer_a.counter.to_le_bytes()[0],
+            0x05 => s.timer_a.counter.to_le_bytes()[1],
+            0x06 => s.timer_b.counter.to_le_bytes()[0],
+            0x07 => s.timer_b.counter.to_le_bytes()[1],
+            reg @ 0x08..=0x0b => s.tod.read(reg as usize - 8),
+            0x0c => s.sdr,
+            0x0d => s.read_icr(),
+            0x0e => s.cra(),
+            0x0f => s.crb(),
+            _ => panic!("Shouldn't happen"),
         }
     }
 
     fn write_byte(&mut self, addr: Addr, val: u8) {
-        if (0x04..=0x07).contains(&addr) {
-            log::info!("Setting timer at {:04x} for {val}", addr);
+        let s = self.state_mut();
+        match addr & 0x0f {
+            0x00 => s.pra = val,
+            0x01 => s.prb = val,
+            0x02 => s.ddra = val,
+            0x03 => s.ddrb = val,
+            0x04 => s.timer_a.set_latch_lo(val),
+            0x05 => s.timer_a.set_latch_hi(val),
+            0x06 => s.timer_b.set_latch_lo(val),
+            0x07 => s.timer_b.set_latch_hi(val),
+            reg @ 0x08..=0x0b => {
+                let alarm = s.crb() & 0x80 > 0;
+                s.tod.write(reg as usize - 8, val, alarm)
+            }
+            0x0c => s.write_sdr(val),
+            0x0d => s.write_icr_mask(val),
+            0x0e => s.write_cra(val),
+            0x0f => s.write_crb(val),
+            _ => panic!("Shouldn't happen"),
+        }
+        if addr & 0x0f < 4 {
+            self.port_written(addr & 0x0f);
         }
-        self.mem_mut()[addr as usize] = val;
     }
 
     fn address_width(&self) -> u16 {
         4
     }
 
-    fn tick(&mut self);
+    fn tick(&mut self) {
+        self.state_mut().tick();
+    }
 
     fn tick_times(&mut self, times: u8) {
         for _ in 0..times {
@@ -77,6 +405,209 @@ pub trait CIA_6526: Addressable {
         }
     }
 
-    fn timer_a(&self) -> u16;
-    fn timer_b(&self) -> u16;
+    /// Value of the register as read by the CPU, but without side effects
+    /// (ICR is not acknowledged, TOD is not latched)
+    fn peek_byte(&self, addr: Addr) -> u8 {
+        let s = self.state();
+        match addr & 0x0f {
+            0x0d => s.icr_data(),
+            reg @ 0x08..=0x0b => s.tod.time()[reg as usize - 8],
+            _ => CIA_6526::read_byte(self, addr),
+        }
+    }
+
+    /// State of the interrupt line (IRQ for CIA1, NMI for CIA2)
+    fn interrupt(&self) -> bool {
+        self.state().icr_data() & ICR_IR > 0
+    }
+
+    /// Byte received by the serial port (in input mode)
+    fn serial_input(&mut self, val: u8) {
+        let s = self.state_mut();
+        s.sdr = val;
+        s.set_interrupt(ICR_SERIAL);
+    }
+
+    fn timer_a(&self) -> u16 {
+        self.state().timer_a.counter
+    }
+
+    fn timer_b(&self) -> u16 {
+        self.state().timer_b.counter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::components::{TIMER_OUT_TOGGLE, TIMER_PB_ON, TIMER_START};
+
+    /// CIA with nothing connected to its ports
+    #[derive(Default)]
+    struct TestCIA(CIAState);
+
+    impl Addressable for TestCIA {
+        fn read_byte(&self, addr: Addr) -> u8 {
+            CIA_6526::read_byte(self, addr)
+        }
+
+        fn write_byte(&mut self, addr: Addr, value: u8) {
+            CIA_6526::write_byte(self, addr, value);
+        }
+
+        fn address_width(&self) -> u16 {
+            4
+        }
+    }
+
+    impl CIA_6526 for TestCIA {
+        fn state(&self) -> &CIAState {
+            &self.0
+        }
+
+        fn state_mut(&mut self) -> &mut CIAState {
+            &mut self.0
+        }
+    }
+
+    impl TestCIA {
+        fn read(&self, reg: Addr) -> u8 {
+            CIA_6526::read_byte(self, reg)
+        }
+
+        fn write(&mut self, reg: Addr, val: u8) {
+            CIA_6526::write_byte(self, reg, val);
+        }
+    }
+
+    /// CIA with the timer latches set (the counters are loaded, as the timers are stopped)
+    fn cia_with_latches(timer_a: u16, timer_b: u16) -> TestCIA {
+        let mut cia = TestCIA::default();
+        let latches = [timer_a.to_le_bytes(), timer_b.to_le_bytes()].concat();
+        for (reg, val) in (0x04..).zip(latches) {
+            cia.write(reg, val);
+        }
+        cia
+    }
+
+    fn tick(cia: &mut TestCIA, times: u8) {
+        CIA_6526::tick_times(cia, times);
+    }
+
+    #[test]
+    fn test_icr_acknowledged_on_read() {
+        let mut cia = cia_with_latches(1, 0xffff);
+        cia.write(0x0e, TIMER_START);
+        tick(&mut cia, 2);
+        // the flag is set, but masked sources don't request an interrupt
+        assert_eq!(cia.state().icr_data(), ICR_TIMER_A);
+        assert!(!cia.interrupt());
+
+        // reading through the shared reference clears the flags
+        let cia_ref: &TestCIA = &cia;
+        assert_eq!(cia_ref.read(0x0d), ICR_TIMER_A);
+        assert_eq!(cia_ref.read(0x0d), 0);
+        tick(&mut cia, 1);
+        assert_eq!(cia.peek_byte(0x0d), 0);
+        // peeking (i.e. by the debugger) doesn't acknowledge them
+        tick(&mut cia, 1);
+        assert_eq!(cia.peek_byte(0x0d), ICR_TIMER_A);
+        assert_eq!(cia.peek_byte(0x0d), ICR_TIMER_A);
+    }
+
+    #[test]
+    fn test_icr_mask_set_and_clear() {
+        let mut cia = cia_with_latches(1, 0xffff);
+        // bit 7 set: the given sources are enabled, the others are kept
+        cia.write(0x0d, ICR_IR | ICR_TIMER_A);
+        cia.write(0x0d, ICR_IR | ICR_ALARM);
+        assert_eq!(cia.state().icr_mask, ICR_TIMER_A | ICR_ALARM);
+
+        cia.write(0x0e, TIMER_START);
+        tick(&mut cia, 2);
+        assert!(cia.interrupt());
+        assert_eq!(cia.read(0x0d), ICR_IR | ICR_TIMER_A);
+        assert!(!cia.interrupt());
+
+        // bit 7 clear: the given sources are disabled
+        cia.write(0x0d, ICR_TIMER_A);
+        assert_eq!(cia.state().icr_mask, ICR_ALARM);
+        tick(&mut cia, 2);
+        assert_eq!(cia.peek_byte(0x0d), ICR_TIMER_A);
+        assert!(!cia.interrupt());
+
+        // enabling a source with its flag already set requests the interrupt at once
+        cia.write(0x0d, ICR_IR | ICR_TIMER_A);
+        assert!(cia.interrupt());
+        assert_eq!(cia.read(0x0d), ICR_IR | ICR_TIMER_A);
+    }
+
+    #[test]
+    fn test_timer_b_counts_timer_a_underflows() {
+        // timer A underflows every 2 cycles, timer B every 3 underflows of A
+        let mut cia = cia_with_latches(1, 2);
+        cia.write(0x0f, TIMER_START | 0x40);
+        cia.write(0x0e, TIMER_START);
+        tick(&mut cia, 2);
+        assert_eq!(cia.timer_b(), 1);
+        tick(&mut cia, 3);
+        assert_eq!(cia.timer_b(), 0);
+        assert_eq!(cia.peek_byte(0x0d) & ICR_TIMER_B, 0);
+        tick(&mut cia, 1);
+        assert_eq!(cia.peek_byte(0x0d), ICR_TIMER_A | ICR_TIMER_B);
+        assert_eq!(cia.timer_b(), 2);
+
+        // the same when CNT is taken into account (it's pulled up)
+        cia.write(0x0f, TIMER_START | 0x60);
+        tick(&mut cia, 5);
+        assert_eq!(cia.timer_b(), 0);
+        // counting CNT pulses only: B stops, as there are none
+        cia.write(0x0f, TIMER_START | 0x20);
+        tick(&mut cia, 10);
+        assert_eq!(cia.timer_b(), 0);
+    }
+
+    #[test]
+    fn test_timer_outputs_on_port_b() {
+        // timer A toggles PB6, timer B pulses PB7 for one cycle on underflow
+        let mut cia = cia_with_latches(1, 1);
+        cia.write(0x0e, TIMER_START | TIMER_PB_ON | TIMER_OUT_TOGGLE);
+        cia.write(0x0f, TIMER_START | TIMER_PB_ON);
+        let port_b: Vec<u8> = (0..5)
+            .map(|_| {
+                tick(&mut cia, 1);
+                cia.read(0x01) & 0xc0
+            })
+            .collect();
+        assert_eq!(port_b, [0x40, 0x80, 0x00, 0xc0, 0x40]);
+
+        // the outputs override the port register, until they're disabled
+        cia.write(0x03, 0xff);
+        cia.write(0x01, 0x80);
+        assert_eq!(cia.read(0x01) & 0xc0, 0x40);
+        cia.write(0x0e, TIMER_START);
+        cia.write(0x0f, TIMER_START);
+        assert_eq!(cia.read(0x01) & 0xc0, 0x80);
+    }
+
+    #[test]
+    fn test_serial_interrupt_after_8_bits() {
+        // timer A underflows every cycle; a bit is shifted out on every second one
+        let mut cia = cia_with_latches(0, 0xffff);
+        cia.write(0x0e, TIMER_START | 0x40);
+        cia.write(0x0c, 0x55);
+        // the next byte waits until the first one is shifted out
+        cia.write(0x0c, 0xaa);
+        tick(&mut cia, 15);
+        assert_eq!(cia.read(0x0d) & ICR_SERIAL, 0);
+        tick(&mut cia, 1);
+        assert_eq!(cia.read(0x0d) & ICR_SERIAL, ICR_SERIAL);
+        tick(&mut cia, 15);
+        assert_eq!(cia.read(0x0d) & ICR_SERIAL, 0);
+        tick(&mut cia, 1);
+        assert_eq!(cia.read(0x0d) & ICR_SERIAL, ICR_SERIAL);
+        // nothing more to send
+        tick(&mut cia, 32);
+        assert_eq!(cia.read(0x0d) & ICR_SERIAL, 0);
+    }
 }
@@ -0,0 +1,131 @@
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+/// Control register bits shared by both timers (CRA/CRB)
+pub const TIMER_START: u8 = 0b0000_0001;
+pub const TIMER_PB_ON: u8 = 0b0000_0010;
+pub const TIMER_OUT_TOGGLE: u8 = 0b0000_0100;
+pub const TIMER_ONE_SHOT: u8 = 0b0000_1000;
+pub const TIMER_FORCE_LOAD: u8 = 0b0001_0000;
+
+/// 16-bit interval timer of CIA 6526.
+/// The counter decrements on every count signal and - when it passes zero -
+/// reloads from the latch and reports an underflow. Hence the period
+/// of continuous mode is `latch + 1` counts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CIATimer {
+    pub counter: u16,
+    pub latch: u16,
+    control: u8,
+    toggle: bool,
+    pulse: bool,
+}
+
+impl CIATimer {
+    pub fn is_running(&self) -> bool {
+        self.control & TIMER_START > 0
+    }
+
+    pub fn is_one_shot(&self) -> bool {
+        self.control & TIMER_ONE_SHOT > 0
+    }
+
+    /// Value of the control register (force load strobe is never stored)
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    pub fn set_control(&mut self, val: u8) {
+        if val & TIMER_START > 0 && !self.is_running() {
+            // toggle output always goes high when timer is started
+            self.toggle = true;
+        }
+        if val & TIMER_FORCE_LOAD > 0 {
+            self.counter = self.latch;
+        }
+        self.control = val & !TIMER_FORCE_LOAD;
+    }
+
+    pub fn set_latch_lo(&mut self, val: u8) {
+        self.latch = (self.latch & 0xff00) | val as u16;
+    }
+
+    /// Writing high byte of a stopped timer loads the counter as well
+    pub fn set_latch_hi(&mut self, val: u8) {
+        self.latch = (self.latch & 0x00ff) | ((val as u16) << 8);
+        if !self.is_running() {
+            self.counter = self.latch;
+        }
+    }
+
+    /// Counts once and returns true on underflow
+    pub fn count(&mut self) -> bool {
+        self.pulse = false;
+        if !self.is_running() {
+            return false;
+        }
+        if self.counter > 0 {
+            self.counter -= 1;
+            return false;
+        }
+        self.counter = self.latch;
+        if self.is_one_shot() {
+            self.control &= !TIMER_START;
+        }
+        self.toggle = !self.toggle;
+        self.pulse = true;
+        true
+    }
+
+    /// Timer output routed to port B (PB6 for timer A, PB7 for timer B),
+    /// or None when the output is disabled
+    pub fn port_output(&self) -> Option<bool> {
+        if self.control & TIMER_PB_ON == 0 {
+            None
+        } else if self.control & TIMER_OUT_TOGGLE > 0 {
+            Some(self.toggle)
+        } else {
+            Some(self.pulse)
+        }
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u16(self.counter)
+            .u16(self.latch)
+            .u8(self.control)
+            .bool(self.toggle)
+            .bool(self.pulse);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.counter = r.u16()?;
+        self.latch = r.u16()?;
+        self.control = r.u8()?;
+        self.toggle = r.bool()?;
+        self.pulse = r.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continuous_and_one_shot() {
+        let mut timer = CIATimer::default();
+        timer.set_latch_lo(2);
+        timer.set_latch_hi(0);
+        timer.set_control(TIMER_START);
+        let underflows: Vec<bool> = (0..6).map(|_| timer.count()).collect();
+        assert_eq!(underflows, [false, false, true, false, false, true]);
+
+        timer.set_control(TIMER_START | TIMER_ONE_SHOT | TIMER_FORCE_LOAD);
+        assert_eq!(timer.counter, 2);
+        let underflows: Vec<bool> = (0..6).map(|_| timer.count()).collect();
+        assert_eq!(underflows, [false, false, true, false, false, false]);
+        assert!(!timer.is_running());
+    }
+}
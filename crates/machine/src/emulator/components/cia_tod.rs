@@ -0,0 +1,198 @@
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+use std::cell::Cell;
+
+/// Time of day clock of CIA 6526.
+/// Registers (tenths, seconds, minutes, hours) are kept in BCD; the hours
+/// register is in 12h format with bit 7 as PM flag. The clock is driven
+/// by the power-line frequency (50Hz or 60Hz TOD pin), so it's fully
+/// deterministic and independent of the host's time.
+#[derive(Debug, Clone)]
+pub struct CIATod {
+    time: [u8; 4],
+    alarm: [u8; 4],
+    /// Registers frozen by reading hours, until tenths are read
+    latch: Cell<Option<[u8; 4]>>,
+    running: bool,
+    ticks: u8,
+}
+
+impl Default for CIATod {
+    fn default() -> Self {
+        CIATod {
+            time: [0, 0, 0, 0x01],
+            alarm: [0; 4],
+            latch: Cell::new(None),
+            running: true,
+            ticks: 0,
+        }
+    }
+}
+
+impl CIATod {
+    /// Reads register (0 - tenths, 1 - seconds, 2 - minutes, 3 - hours)
+    pub fn read(&self, reg: usize) -> u8 {
+        let regs = self.latch.get().unwrap_or(self.time);
+        match reg {
+            0 => self.latch.set(None),
+            3 => self.latch.set(Some(regs)),
+            _ => {}
+        };
+        regs[reg]
+    }
+
+    /// Sets either the time or the alarm (when `alarm` flag is on).
+    /// Writing hours stops the clock, writing tenths starts it again.
+    pub fn write(&mut self, reg: usize, val: u8, alarm: bool) {
+        let val = val & [0x0f, 0x7f, 0x7f, 0x9f][reg];
+        if alarm {
+            self.alarm[reg] = val;
+            return;
+        }
+        self.time[reg] = val;
+        match reg {
+            0 => {
+                self.running = true;
+                self.ticks = 0;
+            }
+            3 => self.running = false,
+            _ => {}
+        }
+    }
+
+    /// Time without latching (i.e. for debugging)
+    pub fn time(&self) -> [u8; 4] {
+        self.time
+    }
+
+    pub fn alarm(&self) -> [u8; 4] {
+        self.alarm
+    }
+
+    /// Sets time from 24h clock values
+    pub fn set_time(&mut self, hours: u8, minutes: u8, seconds: u8, tenths: u8) {
+        let pm = hours >= 12;
+        let hours = match hours % 12 {
+            0 => 12,
+            h => h,
+        };
+        self.time = [
+            tenths % 10,
+            to_bcd(seconds % 60),
+            to_bcd(minutes % 60),
+            to_bcd(hours) | if pm { 0x80 } else { 0 },
+        ];
+    }
+
+    /// Single pulse of the TOD pin. `hz50` is CRA bit 7 telling whether
+    /// the pin is driven at 50Hz (or 60Hz). Returns true when the alarm fires.
+    pub fn tick(&mut self, hz50: bool) -> bool {
+        if !self.running {
+            return false;
+        }
+        self.ticks += 1;
+        if self.ticks < if hz50 { 5 } else { 6 } {
+            return false;
+        }
+        self.ticks = 0;
+        self.advance();
+        self.time == self.alarm
+    }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        let latch = self.latch.get();
+        w.bytes(&self.time)
+            .bytes(&self.alarm)
+            .bool(latch.is_some())
+            .bytes(&latch.unwrap_or_default())
+            .bool(self.running)
+            .u8(self.ticks);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.time = r.array()?;
+        self.alarm = r.array()?;
+        let latched = r.bool()?;
+        let latch = r.array()?;
+        self.latch.set(latched.then_some(latch));
+        self.running = r.bool()?;
+        self.ticks = r.u8()?;
+        Ok(())
+    }
+
+    fn advance(&mut self) {
+        let [tenths, seconds, minutes, hours] = &mut self.time;
+        *tenths = (*tenths + 1) % 10;
+        if *tenths > 0 {
+            return;
+        }
+        *seconds = inc_bcd(*seconds, 0x60);
+        if *seconds > 0 {
+            return;
+        }
+        *minutes = inc_bcd(*minutes, 0x60);
+        if *minutes > 0 {
+            return;
+        }
+        let pm = *hours & 0x80;
+        *hours = match *hours & 0x1f {
+            0x11 => 0x12 | (pm ^ 0x80),
+            0x12 => 0x01 | pm,
+            h => inc_bcd(h, 0x13) | pm,
+        };
+    }
+}
+
+fn to_bcd(val: u8) -> u8 {
+    ((val / 10) << 4) | (val % 10)
+}
+
+/// Increments BCD value, wrapping to zero at `limit` (also in BCD)
+fn inc_bcd(val: u8, limit: u8) -> u8 {
+    let val = if val & 0x0f >= 9 {
+        (val & 0xf0) + 0x10
+    } else {
+        val + 1
+    };
+    if val >= limit {
+        0
+    } else {
+        val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollover_and_latch() {
+        let mut tod = CIATod::default();
+        tod.set_time(11, 59, 59, 9);
+        assert!((0..5).all(|_| !tod.tick(true)));
+        assert_eq!(tod.time(), [0, 0, 0, 0x92]);
+
+        assert_eq!(tod.read(3), 0x92);
+        (0..50).for_each(|_| {
+            tod.tick(true);
+        });
+        assert_eq!(tod.read(2), 0);
+        assert_eq!(tod.read(1), 0);
+        assert_eq!(tod.read(0), 0);
+        assert_eq!(tod.read(1), 1);
+    }
+
+    #[test]
+    fn test_alarm_and_stop() {
+        let mut tod = CIATod::default();
+        tod.write(0, 1, true);
+        tod.write(3, 0x01, true);
+        tod.write(3, 0x01, false);
+        assert!(!(0..10).any(|_| tod.tick(false)));
+        tod.write(0, 0, false);
+        assert!(!(0..5).any(|_| tod.tick(false)));
+        assert!(tod.tick(false));
+    }
+}
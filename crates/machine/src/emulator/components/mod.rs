@@ -1,6 +1,10 @@
 #[allow(non_snake_case)]
 mod pla_82S100;
 mod cia_6526;
+mod cia_timer;
+mod cia_tod;
 
 pub use pla_82S100::*;
 pub use cia_6526::*;
+pub use cia_timer::*;
+pub use cia_tod::*;
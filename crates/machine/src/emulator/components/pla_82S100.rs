@@ -1,9 +1,16 @@
 use crate::utils::lock;
 use lazy_static;
+use std::cell::{Cell as BusCell, RefCell};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::{
-    emulator::abstractions::{Addr, AddressResolver, Addressable},
+    emulator::{
+        abstractions::{
+            Addr, AddressResolver, Addressable, BusAccess, DeviceTrait, OpenBusSource, RegisterField,
+            RegisterInfo, StateReader,
+        },
+        EmulatorError,
+    },
     utils::if_else,
 };
 
@@ -112,16 +119,17 @@ const CHARGEN: usize = 5;
 const KERNAL: usize = 6;
 const INVALID: usize = 7;
 
-/// It's a generic implementation (hence an array of Addressables),
-/// but in C64 the structure is as follows
-/// 0 - RAM
-/// 1 - Cartirdge ROM (lo)
-/// 2 - BASIC ROM
-/// 3 - Cartridge ROM (hi)
-/// 4 - I/O
-/// 5 - CHAR ROM
-/// 6 - KERNAL
-/// 7 - invalid
+/// Names of the devices (by their ids), as seen in the bus log
+const DEVICE_NAMES: [&str; 8] = [
+    "RAM",
+    "cartridge ROM (lo)",
+    "BASIC ROM",
+    "cartridge ROM (hi)",
+    "I/O",
+    "character ROM",
+    "KERNAL ROM",
+    "open bus",
+];
 
 // TODO
 // Corrections are still required  for addresses 0x0000 and 0x0001
@@ -156,14 +164,55 @@ const INVALID: usize = 7;
 // that may be handy at the early stage of emu development
 // Fallback to 0 woudld let to spot problems with missing devices.
 // I somehow sense (can't prove) the latter is closer to reality.
+// As both have their uses, it's configurable: see `MissingDevicePolicy`
+// (the third option is the open bus, as the data lines are left floating).
 
 type Cell = Arc<Mutex<dyn Addressable + Send>>;
 type OptCell = Option<Cell>;
 
+/// What is read from a region mapped to a missing device (see CONSIDERATIONS above)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MissingDevicePolicy {
+    /// RAM underneath (handy when not all the devices are emulated)
+    #[default]
+    Ram,
+    /// Always 0 (makes problems with missing devices easy to spot)
+    Zero,
+    /// Value left on the data bus (see `OpenBus`)
+    OpenBus,
+}
+
+impl MissingDevicePolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ram" => Some(MissingDevicePolicy::Ram),
+            "zero" => Some(MissingDevicePolicy::Zero),
+            "open-bus" => Some(MissingDevicePolicy::OpenBus),
+            _ => None,
+        }
+    }
+}
+
+/// It's a generic implementation (hence an array of Addressables),
+/// but in C64 the structure is as follows
+/// 0 - RAM
+/// 1 - Cartirdge ROM (lo)
+/// 2 - BASIC ROM
+/// 3 - Cartridge ROM (hi)
+/// 4 - I/O
+/// 5 - CHAR ROM
+/// 6 - KERNAL
+/// 7 - nothing (unmapped regions of Ultimax mode, reads see the open bus)
 #[allow(non_camel_case_types)]
 #[derive(Default)]
 pub struct PLA_82S100 {
     devices: [OptCell; 7],
+    missing_device: MissingDevicePolicy,
+    open_bus: Option<OpenBusSource>,
+    /// Last value on the data bus (seen as open bus, if there is no other source)
+    bus: BusCell<u8>,
+    /// Accesses since `start_bus_log` (`None` when they aren't recorded)
+    bus_log: RefCell<Option<Vec<BusAccess>>>,
 }
 
 impl Addressable for PLA_82S100 {
@@ -171,39 +220,48 @@ impl Addressable for PLA_82S100 {
         let (byte0, byte1, ram) = self.get_state();
         let id = self.get_device_id(addr, byte0, byte1);
 
-        if id == INVALID {
-            // TODO check what to do in such this case
-            return 0;
-        }
-
-        let real_id = if_else(self.has_device(id), id, 0);
-
         // optimization - if read is from RAM no further checks are required
         // so we can skip further mutex locking
-        if real_id == RAM {
-            return ram.read_byte(addr);
-        }
-
-        drop(ram);
-
-        let opt_dev = &self.devices[real_id as usize];
-        if let Some(dev) = opt_dev {
-            let real_addr = self.internal_addr(&dev, addr, real_id);
-            dev.lock().unwrap().read_byte(real_addr)
+        let (value, served_by) = if id == RAM {
+            (ram.read_byte(addr), RAM)
+        } else if self.is_unmapped(id) {
+            drop(ram);
+            (self.open_bus(), INVALID)
+        } else if !self.has_device(id) {
+            match self.missing_device {
+                MissingDevicePolicy::Ram => (ram.read_byte(addr), RAM),
+                MissingDevicePolicy::Zero => (0, id),
+                MissingDevicePolicy::OpenBus => {
+                    drop(ram);
+                    (self.open_bus(), INVALID)
+                }
+            }
         } else {
-            0
-        }
+            drop(ram);
+            let dev = self.devices[id].as_ref().unwrap();
+            let real_addr = self.internal_addr(dev, addr, id);
+            (dev.lock().unwrap().read_byte(real_addr), id)
+        };
+        self.log_access(addr, value, false, served_by, || None);
+        self.bus.set(value);
+        value
     }
 
     fn write_byte(&mut self, addr: Addr, value: u8) {
+        self.bus.set(value);
         let (byte0, byte1, mut ram) = self.get_state();
         let id = self.get_device_id(addr, byte0, byte1);
-        if id == INVALID || !self.can_write(addr, byte0) {
+        if id == INVALID {
             // TODO check what to do in case of INVALID
-            return ();
+            self.log_access(addr, value, true, INVALID, || None);
+            return;
         }
 
         let real_id = if_else(id == IO, IO, RAM); // if not i/o, write to ram
+        // the previous value is known only in RAM (I/O registers can't be read without side effects)
+        self.log_access(addr, value, true, real_id, || {
+            (real_id == RAM).then(|| ram.read_byte(addr))
+        });
 
         // optimization - if write is to RAM, no further checks are required
         // so we can avoid additional mutex locking
@@ -215,10 +273,10 @@ impl Addressable for PLA_82S100 {
 
         if self.has_device(real_id) {
             let internal_addr = {
-                let dev = self.devices[real_id as usize].as_ref().unwrap();
-                self.internal_addr(&dev, addr, real_id)
+                let dev = self.devices[real_id].as_ref().unwrap();
+                self.internal_addr(dev, addr, real_id)
             };
-            let dev_mut = self.devices[real_id as usize].as_mut().unwrap();
+            let dev_mut = self.devices[real_id].as_mut().unwrap();
             dev_mut.lock().unwrap().write_byte(internal_addr, value);
         }
     }
@@ -230,8 +288,67 @@ impl Addressable for PLA_82S100 {
 
 impl AddressResolver for PLA_82S100 {}
 
+/// The PLA has no registers on its own, but it decodes the memory map
+/// from the processor port (kept at $00/$01 of RAM)
+static PROCESSOR_PORT: [RegisterInfo; 2] = [
+    RegisterInfo {
+        offset: 0x00,
+        name: "DDR",
+        description: "Data direction of the processor port (1 - output)",
+        fields: &[],
+    },
+    RegisterInfo {
+        offset: 0x01,
+        name: "PORT",
+        description: "Processor port",
+        fields: &[
+            RegisterField { name: "LORAM", mask: 0x01, description: "BASIC ROM at $A000 (or RAM)" },
+            RegisterField { name: "HIRAM", mask: 0x02, description: "KERNAL ROM at $E000 (or RAM)" },
+            RegisterField { name: "CHAREN", mask: 0x04, description: "I/O at $D000 (or character ROM)" },
+            RegisterField { name: "CASS WRT", mask: 0x08, description: "Cassette data output" },
+            RegisterField { name: "CASS SENSE", mask: 0x10, description: "Cassette button pressed (0)" },
+            RegisterField { name: "CASS MOTOR", mask: 0x20, description: "Cassette motor off" },
+        ],
+    },
+];
+
+impl DeviceTrait for PLA_82S100 {
+    fn name(&self) -> &str {
+        "PLA 82S100"
+    }
+
+    fn registers(&self) -> &'static [RegisterInfo] {
+        &PROCESSOR_PORT
+    }
+
+    fn peek_register(&self, offset: Addr) -> Option<u8> {
+        let ram = self.devices[RAM].as_ref()?;
+        (offset < 2).then(|| lock(ram).read_byte(offset))
+    }
+
+    /// 6510 resets the data direction register, so all the port lines
+    /// are inputs (pulled up: BASIC, KERNAL and I/O are visible)
+    fn hard_reset(&mut self) {
+        if let Some(ram) = &self.devices[RAM] {
+            lock(ram).write_byte(0, 0);
+        }
+    }
+
+    /// The processor port is kept in RAM (at $00 and $01),
+    /// so only the last value on the data bus is saved here
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.bus.get()]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmulatorError> {
+        let mut r = StateReader::new(state);
+        self.bus.set(r.u8()?);
+        r.finish()
+    }
+}
+
 impl PLA_82S100 {
-    fn get_state(&self) -> (u8, u8, MutexGuard<dyn Addressable + Send + 'static>) {
+    fn get_state(&self) -> (u8, u8, MutexGuard<'_, dyn Addressable + Send + 'static>) {
         if !self.has_device(RAM) {
             panic!("RAM is mandatory");
         }
@@ -241,20 +358,24 @@ impl PLA_82S100 {
         (byte0, byte1, ram)
     }
 
-    fn get_device_id(&self, addr: Addr, _byte0: u8, byte1: u8) -> usize {
-        // pin 8 and 9 are set low (false) when cartridge is present and high (true) when not
-        // regular cartridge: pin 8
-        // exrom: pin 8 and 9
-        let pin8 = !self.has_device(CARTRIDGE_LO);
-        let pin9 = !self.has_device(CARTRIDGE_HI);
+    fn get_device_id(&self, addr: Addr, byte0: u8, byte1: u8) -> usize {
+        // pin 8 (GAME) and 9 (EXROM) are pulled low by a cartridge, high (true) when there is none.
+        // The lines are derived from the cartridge's ROMs:
+        // 8kB cartridge (ROML): EXROM
+        // 16kB cartridge (ROML and ROMH): GAME and EXROM
+        // Ultimax (ROMH only, seen at $E000): GAME
+        let pin8 = !self.has_device(CARTRIDGE_HI);
+        let pin9 = !self.has_device(CARTRIDGE_LO);
 
         // flag is a combination of 3 youngest bits from processor port 0x01
         // and values from pin8 and 9, that act here as bit 4 and 5
         // that gives 32 combinations (although some of them are redundant, so
-        // effectively there is 14)
-        let flag = (byte1 & 0b111) | (u8::from(pin8) << 3) | (u8::from(pin9) << 4);
+        // effectively there is 14).
+        // Port lines set as inputs (0 in DDR at 0x00) are pulled up.
+        let port = byte1 | !byte0;
+        let flag = (port & 0b111) | (u8::from(pin8) << 3) | (u8::from(pin9) << 4);
         let bank = &BANKS[flag as usize];
-        let dev_id = match addr {
+        (match addr {
             0x0000..=0x0fff => bank[0],
             0x1000..=0x7fff => bank[1],
             0x8000..=0x9fff => bank[2],
@@ -262,15 +383,80 @@ impl PLA_82S100 {
             0xc000..=0xcfff => bank[4],
             0xd000..=0xdfff => bank[5],
             0xe000..=0xffff => bank[6],
-        } as usize;
+        }) as usize
+    }
 
-        dev_id
+    fn log_access(
+        &self,
+        addr: Addr,
+        value: u8,
+        write: bool,
+        dev_id: usize,
+        previous: impl FnOnce() -> Option<u8>,
+    ) {
+        if let Some(log) = self.bus_log.borrow_mut().as_mut() {
+            let device = match dev_id == INVALID || self.has_device(dev_id) {
+                true => DEVICE_NAMES[dev_id],
+                false => "none",
+            };
+            log.push(BusAccess {
+                addr,
+                value,
+                write,
+                previous: previous(),
+                device: device.to_string(),
+            });
+        }
+    }
+
+    /// Starts recording the accesses to the bus (see `take_bus_log`)
+    pub fn start_bus_log(&mut self) {
+        *self.bus_log.get_mut() = Some(Vec::new());
+    }
+
+    /// Accesses since `start_bus_log` (the recording is stopped)
+    pub fn take_bus_log(&mut self) -> Vec<BusAccess> {
+        self.bus_log.get_mut().take().unwrap_or_default()
+    }
+
+    /// Whether the address is served by a ROM (as mapped at the moment)
+    pub fn is_rom(&self, addr: Addr) -> bool {
+        let (byte0, byte1, _ram) = self.get_state();
+        let id = self.get_device_id(addr, byte0, byte1);
+        id != RAM && id != IO && !self.is_unmapped(id) && self.has_device(id)
     }
 
     fn has_device(&self, dev_id: usize) -> bool {
         self.devices[dev_id].is_some()
     }
 
+    /// Regions with nothing mapped (in Ultimax mode), where reads see the open bus
+    fn is_unmapped(&self, dev_id: usize) -> bool {
+        dev_id == INVALID
+            || ((dev_id == CARTRIDGE_LO || dev_id == CARTRIDGE_HI) && !self.has_device(dev_id))
+    }
+
+    fn open_bus(&self) -> u8 {
+        match &self.open_bus {
+            Some(src) => lock(src).open_bus(),
+            None => self.bus.get(),
+        }
+    }
+
+    /// Whether reading the address gives the value left on the data bus
+    /// (so the source of the open bus needs to be up to date)
+    pub fn reads_open_bus(&self, addr: Addr) -> bool {
+        let ultimax = self.has_device(CARTRIDGE_HI) && !self.has_device(CARTRIDGE_LO);
+        let missing_roms = [BASIC, CHARGEN, KERNAL].iter().any(|id| !self.has_device(*id));
+        if !(ultimax || missing_roms && self.missing_device == MissingDevicePolicy::OpenBus) {
+            return false;
+        }
+        let (byte0, byte1, _ram) = self.get_state();
+        let id = self.get_device_id(addr, byte0, byte1);
+        self.is_unmapped(id)
+            || (!self.has_device(id) && self.missing_device == MissingDevicePolicy::OpenBus)
+    }
+
     /// Converts provided address into "local address" (think of array cell id)
     /// of a specific addressable device.
     /// I.e. BASIC ROM has only 8kB, but it operates in the address space starting
@@ -281,6 +467,10 @@ impl PLA_82S100 {
         //     return addr - 0xdc00;
         // }
         // a & (dev.address_width() - 0)
+        if id == CARTRIDGE_HI {
+            // the same 8kB are seen at $A000 or (in Ultimax mode) at $E000
+            return addr & 0x1fff;
+        }
         addr - START_ADDR[id]
     }
 
@@ -297,17 +487,6 @@ impl PLA_82S100 {
     // At leasr if I got it right based on
     // 1. https://www.c64-wiki.com/wiki/Zeropage
     // 2. https://www.pagetable.com/c64ref/c64mem/
-    fn can_write(&self, addr: Addr, byte0: u8) -> bool {
-        if ((byte0 & 0b001) == 0 && (0xa000..=0xbfff).contains(&addr))
-            || ((byte0 & 0b010) == 0 && (0xe000..=0xffff).contains(&addr))
-            || ((byte0 & 0b100) == 0 && (0xd000..=0xdfff).contains(&addr))
-        {
-            false
-        } else {
-            true
-        }
-    }
-
     #[cfg(test)]
     pub(crate) fn set_mode(&mut self, mode: u8) {
         // self.write_byte(1, mode);
@@ -319,6 +498,21 @@ impl PLA_82S100 {
         self
     }
 
+    pub(crate) fn unlink_dev(&mut self, id: usize) -> &mut Self {
+        self.devices[id] = None;
+        self
+    }
+
+    pub fn set_missing_device_policy(&mut self, policy: MissingDevicePolicy) {
+        self.missing_device = policy;
+    }
+
+    /// Source of the value read from unmapped regions and missing devices
+    pub fn link_open_bus(&mut self, src: OpenBusSource) -> &mut Self {
+        self.open_bus = Some(src);
+        self
+    }
+
     pub fn link_ram(&mut self, dev: Cell) -> &mut Self {
         self.link_dev(RAM, dev)
     }
@@ -334,15 +528,34 @@ impl PLA_82S100 {
     pub fn link_cartridge(&mut self, dev: Cell) -> &mut Self {
         self.link_dev(CARTRIDGE_LO, dev)
     }
+    pub fn link_cartridge_hi(&mut self, dev: Cell) -> &mut Self {
+        self.link_dev(CARTRIDGE_HI, dev)
+    }
     pub fn link_io(&mut self, dev: Cell) -> &mut Self {
         self.link_dev(IO, dev)
     }
+
+    pub fn unlink_basic(&mut self) -> &mut Self {
+        self.unlink_dev(BASIC)
+    }
+    pub fn unlink_kernal(&mut self) -> &mut Self {
+        self.unlink_dev(KERNAL)
+    }
+    pub fn unlink_chargen(&mut self) -> &mut Self {
+        self.unlink_dev(CHARGEN)
+    }
+    pub fn unlink_cartridge(&mut self) -> &mut Self {
+        self.unlink_dev(CARTRIDGE_LO)
+    }
+    pub fn unlink_cartridge_hi(&mut self) -> &mut Self {
+        self.unlink_dev(CARTRIDGE_HI)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::emulator::abstractions::Addressable;
+    use crate::emulator::abstractions::{Addressable, OpenBus};
 
     struct Mem {
         width: u16,
@@ -419,4 +632,87 @@ mod tests {
         pla.set_mode(0);
         assert_eq!(66, pla.read_byte(0xa000));
     }
+
+    #[test]
+    fn test_port_inputs() {
+        let mut basic = Mem::new(16);
+        basic.data[0] = 0x94;
+        let mut pla = PLA_82S100::default();
+        pla.link_dev(RAM, Arc::new(Mutex::new(create_ram())));
+        pla.link_basic(Arc::new(Mutex::new(basic)));
+        pla.set_mode(0);
+        assert_eq!(0, pla.read_byte(0xa000));
+
+        // after reset all the lines are inputs, pulled up
+        pla.hard_reset();
+        assert_eq!(0x94, pla.read_byte(0xa000));
+        pla.write_byte(0xa000, 42); // writes always reach RAM
+        pla.write_byte(0x0000, 0x2f);
+        assert_eq!(42, pla.read_byte(0xa000));
+    }
+
+    #[test]
+    fn test_missing_device() {
+        let mut pla = PLA_82S100::default();
+        pla.link_dev(RAM, Arc::new(Mutex::new(create_ram())));
+        pla.write_byte(0xe000, 42); // KERNAL is missing
+
+        assert_eq!(42, pla.read_byte(0xe000));
+        pla.set_missing_device_policy(MissingDevicePolicy::Zero);
+        assert_eq!(0, pla.read_byte(0xe000));
+        pla.set_missing_device_policy(MissingDevicePolicy::OpenBus);
+        pla.write_byte(0x1000, 7);
+        assert_eq!(7, pla.read_byte(0xe000));
+
+        let mut kernal = Mem::new(16);
+        kernal.data[0] = 13;
+        pla.link_kernal(Arc::new(Mutex::new(kernal)));
+        assert_eq!(13, pla.read_byte(0xe000));
+        pla.unlink_kernal();
+        assert_eq!(13, pla.read_byte(0xe000));
+    }
+
+    #[test]
+    fn test_bus_log() {
+        let mut pla = PLA_82S100::default();
+        pla.link_dev(RAM, Arc::new(Mutex::new(create_ram())));
+        pla.link_kernal(Arc::new(Mutex::new(Mem::new(16))));
+        pla.read_byte(0x1000);
+        assert!(pla.take_bus_log().is_empty());
+
+        pla.start_bus_log();
+        pla.write_byte(0xe000, 42);
+        pla.read_byte(0xe000);
+        let log = pla.take_bus_log();
+        assert_eq!(2, log.len());
+        assert!(log[0].write);
+        assert_eq!((0xe000, 42, "RAM"), (log[0].addr, log[0].value, log[0].device.as_str()));
+        assert_eq!(Some(0), log[0].previous);
+        assert_eq!((0, "KERNAL ROM"), (log[1].value, log[1].device.as_str()));
+        assert!(pla.is_rom(0xe000));
+        assert!(!pla.is_rom(0x1000));
+    }
+
+    #[test]
+    fn test_ultimax() {
+        struct Bus;
+        impl OpenBus for Bus {
+            fn open_bus(&self) -> u8 {
+                0xbd
+            }
+        }
+
+        let mut romh = Mem::new(16);
+        romh.data[0x1ffc] = 0x34;
+        let mut pla = PLA_82S100::default();
+        pla.link_dev(RAM, Arc::new(Mutex::new(create_ram())));
+        pla.link_cartridge_hi(Arc::new(Mutex::new(romh)));
+        pla.link_open_bus(Arc::new(Mutex::new(Bus)));
+
+        assert_eq!(0x34, pla.read_byte(0xfffc)); // ROMH at $E000
+        assert_eq!(0xbd, pla.read_byte(0x8000)); // no ROML
+        assert_eq!(0xbd, pla.read_byte(0x4000)); // unmapped
+        assert!(pla.reads_open_bus(0x4000));
+        assert!(!pla.reads_open_bus(0x0800));
+    }
 }
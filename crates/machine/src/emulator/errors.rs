@@ -9,7 +9,10 @@ pub enum EmulatorError {
 
 
     #[error("Memory device can't write to address {0}")]
-    AddressNotWriteable(Addr)
+    AddressNotWriteable(Addr),
+
+    #[error("Invalid device state: {0}")]
+    InvalidState(String),
 
 }
 
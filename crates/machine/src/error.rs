@@ -1,9 +1,13 @@
+use crate::emulator::EmulatorError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum MachineError {
     #[error("Machine client failed: {0}")]
     Client(String),
+
+    #[error("Snapshot failed: {0}")]
+    Snapshot(#[from] EmulatorError),
 }
 
 // impl std::fmt::Display for MachineError {
@@ -1,3 +1,5 @@
+#![allow(clippy::module_inception)]
+
 #[macro_use]
 extern crate lazy_static;
 extern crate colored;
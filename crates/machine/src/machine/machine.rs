@@ -1,5 +1,10 @@
 #![allow(non_snake_case)]
-use super::{Addr, MachineConfig, Memory};
+use super::{Addr, MachineConfig, Memory, Snapshot};
+use crate::client::ClientEvent;
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
 use crate::mos6502::{
     AddressMode, Mnemonic, Operand, Operation, OperationDef, ProcessorStatus, MOS6502,
 };
@@ -15,6 +20,32 @@ pub enum MachineStatus {
     Debug,
 }
 
+impl MachineStatus {
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u8(*self as u8);
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        *self = match r.u8()? {
+            0 => MachineStatus::Stopped,
+            1 => MachineStatus::Running,
+            2 => MachineStatus::Debug,
+            val => return Err(EmulatorError::InvalidState(format!("machine status {val}"))),
+        };
+        Ok(())
+    }
+}
+
+/// Kind of reset: power cycle or the RESET line
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum ResetKind {
+    /// Power cycle: memory and all the chips get their power-on state
+    #[default]
+    Hard,
+    /// RESET line pulled low: the chips are reset, but memory keeps its content
+    Soft,
+}
+
 pub trait RegSetter<T> {
     fn set_A(&mut self, val: T);
     fn set_X(&mut self, val: T);
@@ -35,6 +66,29 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
     fn get_cycles(&self) -> Cycles;
     fn advance_cycles(&mut self, cycles: u8);
 
+    /// Complete state of the machine (see `Snapshot`).
+    /// It's mutable, as the devices may need to catch up with the clock first.
+    fn save_snapshot(&mut self) -> Snapshot;
+
+    /// Restores the state saved with `save_snapshot`
+    /// (if it fails, the state may be restored partially)
+    fn load_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), EmulatorError>;
+
+    /// Applies again the input event the machine applied at given cycle,
+    /// when its execution history is replayed (see `Rewind::seek`)
+    fn replay_input(&mut self, _at: Cycles, _event: ClientEvent) {}
+
+    /// Frequency of the machine's clock [Hz] (used to run it at real speed)
+    fn clock_frequency(&self) -> u32 {
+        1_000_000
+    }
+
+    /// Number of cycles of a single video frame (the machine is
+    /// synchronized with the host after each frame)
+    fn frame_cycles(&self) -> Cycles {
+        20_000
+    }
+
     // registry shortcuts
     fn A(&self) -> Wrapping<u8> {
         self.cpu().registers.accumulator
@@ -93,10 +147,6 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
     /// but it doesn't cycle the machine! Either self.next() must be called
     /// or (better), a client should be used instead
     fn start(&mut self) {
-        // see https://www.pagetable.com/c64ref/c64mem/
-        self.write_byte(0x0000, 0x2f);
-        self.write_byte(0x0001, 0x37);
-
         // By default, after start, the PC is set to address from RST vector ($fffc)
         // http://wilsonminesco.com/6502primer/MemMapReqs.html
         let start_addr = self
@@ -111,6 +161,13 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
         self.set_status(MachineStatus::Stopped);
     }
 
+    /// Whether the machine currently raises interrupts on its own
+    /// (i.e. with enabled timer interrupts). If not, the runtime
+    /// emulates them by triggering IRQ every jiffy.
+    fn has_interrupt_sources(&self) -> bool {
+        false
+    }
+
     fn debug(&mut self) {
         self.set_status(MachineStatus::Debug);
     }
@@ -119,13 +176,16 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
         self.set_status(MachineStatus::Running);
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, kind: ResetKind) {
         if self.get_status() != MachineStatus::Stopped {
             self.stop();
         }
-        for i in 0..self.memory().size() {
-            self.write_byte(i as u16, 0);
+        if kind == ResetKind::Hard {
+            for i in 0..self.memory().size() {
+                self.write_byte(i as u16, 0);
+            }
         }
+        self.cpu_mut().reset(kind);
         self.start();
     }
 
@@ -151,14 +211,12 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
         let operand = { self.decode_operand(&def) };
         let address = operand
             .as_ref()
-            .map_or(None, |o| self.decode_address(&def, &o));
+            .and_then(|o| self.decode_address(&def, o));
         Operation::new(def.clone(), operand, address)
     }
 
     fn pre_next(&mut self, op: &Operation) {
-        if self.get_config().disassemble {
-            println!("{}", self.disassemble(op, self.get_config().verbose, false));
-        }
+        self.trace(op);
 
         if self.get_config().exit_on_brk && matches!(op.def.mnemonic, Mnemonic::BRK) {
             self.stop();
@@ -172,6 +230,14 @@ pub trait Machine: RegSetter<u8> + RegSetter<Wrapping<u8>> {
 
     fn post_next(&mut self, _op: &Operation) {}
 
+    /// Called before the operation is executed; by default the operation
+    /// is printed with `--disassemble`
+    fn trace(&mut self, op: &Operation) {
+        if self.get_config().disassemble {
+            println!("{}", self.disassemble(op, self.get_config().verbose, false));
+        }
+    }
+
     fn disassemble(&self, op: &Operation, verbose: bool, next_op: bool) -> String {
         use std::fmt::Write;
         let mut out = String::new();
@@ -1,4 +1,5 @@
 use super::Cycles;
+use std::path::PathBuf;
 
 pub struct MachineConfig {
     pub ram_size: usize,
@@ -10,6 +11,36 @@ pub struct MachineConfig {
     pub disassemble: bool,
     pub verbose: bool,
     pub start_addr: Option<u16>,
+    /// Speed in percent of the real machine
+    pub speed: u32,
+    /// Runs the machine as fast as possible
+    pub warp: bool,
+    /// Name of the machine's model (i.e. video standard); interpreted by the machine
+    pub model: Option<String>,
+    /// All timing is derived from emulated cycles (no host's time or live input)
+    pub deterministic: bool,
+    /// Seed of the power-on state
+    pub seed: Option<u64>,
+    /// Content of RAM after power-on; interpreted by the machine
+    pub ram_pattern: Option<String>,
+    /// Memory budget of the execution history for reverse debugging [MB] (0 disables it)
+    pub rewind_budget: Option<usize>,
+    /// File the executed instructions are traced to (see `Tracer`)
+    pub trace: Option<PathBuf>,
+    /// Format of the trace: text or binary
+    pub trace_format: Option<String>,
+    /// Addresses of the traced instructions (i.e. c000-cfff)
+    pub trace_range: Option<String>,
+    /// Memory of the traced instructions: rom or ram
+    pub trace_memory: Option<String>,
+    /// Whether every bus access is traced
+    pub trace_bus: bool,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MachineConfig {
@@ -24,6 +55,18 @@ impl MachineConfig {
             disassemble: false,
             verbose: false,
             start_addr: None,
+            speed: 100,
+            warp: false,
+            model: None,
+            deterministic: false,
+            seed: None,
+            ram_pattern: None,
+            rewind_budget: None,
+            trace: None,
+            trace_format: None,
+            trace_range: None,
+            trace_memory: None,
+            trace_bus: false,
         }
     }
 }
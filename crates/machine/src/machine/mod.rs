@@ -3,15 +3,17 @@ mod machine_config;
 mod memory;
 mod mos6502_machine;
 mod mos6502_memory;
+mod snapshot;
 
 #[macro_use]
 mod macros;
 
 pub use {
     impl_reg_setter,
-    machine::{Machine, MachineStatus, RegSetter,Cycles},
+    machine::{Machine, MachineStatus, RegSetter, ResetKind, Cycles},
     machine_config::{FromConfig, MachineConfig},
     memory::{Addr, Memory},
     mos6502_machine::MOS6502Machine,
     mos6502_memory::MOS6502Memory,
+    snapshot::{Snapshot, SNAPSHOT_VERSION},
 };
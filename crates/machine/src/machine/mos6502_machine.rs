@@ -1,9 +1,13 @@
 use super::{
     impl_reg_setter, FromConfig, MOS6502Memory, Machine, MachineConfig, MachineStatus,
-    RegSetter, Cycles
+    RegSetter, Cycles, Memory, Snapshot, Addr
 };
 use crate::cli::{Profile, FromProfile};
 use crate::debugger::DebugMachine;
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
 use crate::mos6502::{execute_operation, Operation, MOS6502};
 use std::num::Wrapping;
 
@@ -17,7 +21,7 @@ pub struct MOS6502Machine {
 
 impl MOS6502Machine {
     pub fn new(config: MachineConfig) -> Self {
-        let size = config.ram_size.clone();
+        let size = config.ram_size;
         MOS6502Machine {
             config,
             mos6502: MOS6502::new(),
@@ -62,7 +66,7 @@ impl Machine for MOS6502Machine {
     }
 
     fn execute_operation(&mut self, op: &Operation) -> u8 {
-        execute_operation(&op, self)
+        execute_operation(op, self)
     }
 
     fn get_cycles(&self) -> Cycles {
@@ -72,14 +76,53 @@ impl Machine for MOS6502Machine {
     fn advance_cycles(&mut self, cycles: u8) {
         self.cycles = self.cycles.wrapping_add(cycles.into());
     }
+
+    fn save_snapshot(&mut self) -> Snapshot {
+        let mut snapshot = Snapshot::new("MOS6502");
+        let mut w = StateWriter::new();
+        w.u64(self.cycles);
+        self.status.save(&mut w);
+        snapshot.add("machine", w.finish());
+
+        let mut w = StateWriter::new();
+        self.mos6502.registers.save(&mut w);
+        snapshot.add("CPU", w.finish());
+
+        let ram = (0..self.mem.size()).map(|addr| self.mem.read_byte(addr as Addr));
+        snapshot.add("memory", ram.collect());
+        snapshot
+    }
+
+    fn load_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), EmulatorError> {
+        snapshot.check_machine("MOS6502")?;
+        let ram = snapshot.section("memory")?;
+        if ram.len() != self.mem.size() {
+            return Err(EmulatorError::InvalidState(format!(
+                "snapshot of {} bytes of memory (expected {})",
+                ram.len(),
+                self.mem.size()
+            )));
+        }
+
+        let mut r = StateReader::new(snapshot.section("machine")?);
+        self.cycles = r.u64()?;
+        self.status.load(&mut r)?;
+        r.finish()?;
+
+        let mut r = StateReader::new(snapshot.section("CPU")?);
+        self.mos6502.registers.load(&mut r)?;
+        r.finish()?;
+
+        self.mem.write(0, ram);
+        Ok(())
+    }
 }
 
 impl DebugMachine for MOS6502Machine {}
 
 impl FromConfig for MOS6502Machine {
     fn from_config(config: MachineConfig) -> Self {
-        let machine = MOS6502Machine::new(config);
-        machine
+        MOS6502Machine::new(config)
     }
 }
 
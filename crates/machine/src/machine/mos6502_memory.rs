@@ -17,13 +17,12 @@ impl MOS6502Memory {
 impl Memory for MOS6502Memory {
     // TODO: Must check whether the three corresponding its at addr 0x00 are 1
     // check https://www.c64-wiki.com/wiki/Bank_Switching for details
-    #[allow(unused_comparisons)]
     fn mem(&self, addr: Addr) -> &[u8] {
         let flag = self.ram[1] & 0b00000111;
-        if flag & 1 > 0 && addr >= 0xa000 && addr <= 0xbfff {
+        if flag & 1 > 0 && (0xa000..=0xbfff).contains(&addr) {
             return &self.rom;
         };
-        if flag & 2 > 0 && addr >= 0xe000 && addr <= 0xffff {
+        if flag & 2 > 0 && addr >= 0xe000 {
             return &self.rom;
         };
         &self.ram
@@ -39,11 +38,8 @@ impl Memory for MOS6502Memory {
     }
 
     fn init_rom_at_addr(&mut self, addr: Addr, data: &[u8]) {
-        let mut idx = addr as usize;
-        for byte in data.iter() {
-            self.rom[idx] = *byte;
-            idx += 1;
-        }
+        let start = addr as usize;
+        self.rom[start..start + data.len()].copy_from_slice(data);
     }
 
     fn size(&self) -> usize {
@@ -0,0 +1,124 @@
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
+
+const MAGIC: &[u8; 8] = b"C64RUST\x1a";
+
+/// Version of the snapshot format. Snapshots of other versions are rejected.
+pub const SNAPSHOT_VERSION: u16 = 3;
+
+/// Complete state of a machine, as a list of named sections
+/// (i.e. CPU, memory and every device - see `DeviceTrait::save_state`).
+/// The binary format (little endian):
+///
+/// ```text
+/// 8 bytes     "C64RUST" + $1A
+/// u16         format version
+/// u16 + n     name of the machine
+/// u16         number of sections, and for every section:
+/// u16 + n     name
+/// u32 + n     data
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    machine: String,
+    sections: Vec<(String, Vec<u8>)>,
+}
+
+impl Snapshot {
+    pub fn new(machine: &str) -> Self {
+        Snapshot {
+            machine: machine.to_string(),
+            sections: Vec::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &str {
+        &self.machine
+    }
+
+    /// Adds the section (or replaces a section with the same name)
+    pub fn add(&mut self, name: &str, data: Vec<u8>) {
+        match self.sections.iter_mut().find(|(n, _)| n == name) {
+            Some(section) => section.1 = data,
+            None => self.sections.push((name.to_string(), data)),
+        }
+    }
+
+    pub fn section(&self, name: &str) -> Result<&[u8], EmulatorError> {
+        self.sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, data)| &data[..])
+            .ok_or_else(|| EmulatorError::InvalidState(format!("no {name} in the snapshot")))
+    }
+
+    /// Fails if the snapshot was taken of another machine
+    pub fn check_machine(&self, machine: &str) -> Result<(), EmulatorError> {
+        match self.machine == machine {
+            true => Ok(()),
+            false => Err(EmulatorError::InvalidState(format!(
+                "snapshot of {} can't be loaded into {machine}",
+                self.machine
+            ))),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(MAGIC)
+            .u16(SNAPSHOT_VERSION)
+            .str(&self.machine)
+            .u16(self.sections.len() as u16);
+        for (name, data) in self.sections.iter() {
+            w.str(name).u32(data.len() as u32).bytes(data);
+        }
+        w.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EmulatorError> {
+        let mut r = StateReader::new(data);
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(EmulatorError::InvalidState("not a snapshot".to_string()));
+        }
+        let version = r.u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(EmulatorError::InvalidState(format!(
+                "unsupported snapshot version {version} (expected {SNAPSHOT_VERSION})"
+            )));
+        }
+        let mut snapshot = Snapshot::new(&r.str()?);
+        for _ in 0..r.u16()? {
+            let name = r.str()?;
+            let len = r.u32()? as usize;
+            snapshot.add(&name, r.bytes(len)?.to_vec());
+        }
+        r.finish()?;
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_format() {
+        let mut snapshot = Snapshot::new("C64");
+        snapshot.add("CPU", vec![1, 2, 3]);
+        snapshot.add("SID", vec![]);
+        let data = snapshot.to_bytes();
+
+        let loaded = Snapshot::from_bytes(&data).unwrap();
+        assert_eq!(loaded, snapshot);
+        assert_eq!(loaded.section("CPU").unwrap(), &[1, 2, 3]);
+        assert!(loaded.section("VIC-II").is_err());
+        assert!(loaded.check_machine("MOS6502").is_err());
+
+        let mut other_version = data.clone();
+        other_version[8] = 0xff;
+        assert!(Snapshot::from_bytes(&other_version).is_err());
+        assert!(Snapshot::from_bytes(&data[..data.len() - 1]).is_err());
+    }
+}
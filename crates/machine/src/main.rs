@@ -1,3 +1,5 @@
+#![allow(clippy::module_inception)]
+
 #[macro_use]
 extern crate lazy_static;
 extern crate colored;
@@ -11,12 +13,11 @@ pub mod mos6502;
 pub mod utils;
 pub mod emulator;
 
-use crate::cli::{create_machine_from_cli_args, Args};
+use crate::cli::{create_machine_from_cli_args, get_file_as_byte_vec, Args};
 use crate::client::{DirectClient, NonInteractiveClient};
 use crate::machine::MOS6502Machine;
 use anyhow::Result;
 use clap::Parser;
-use env_logger;
 
 fn main() -> Result<()> {
     env_logger::init();
@@ -25,6 +26,9 @@ fn main() -> Result<()> {
 
     let machine: MOS6502Machine = create_machine_from_cli_args()?;
     let mut client = DirectClient::new(machine);
+    if let Some(path) = &args.load_snapshot {
+        client.load_snapshot(&get_file_as_byte_vec(path)?)?;
+    }
 
     client.start_sync()?;
 
@@ -33,5 +37,8 @@ fn main() -> Result<()> {
     }
 
     client.stop()?;
+    if let Some(path) = &args.save_snapshot_on_exit {
+        std::fs::write(path, client.save_snapshot()?)?;
+    }
     Ok(())
 }
@@ -22,6 +22,6 @@ pub enum AddressMode {
 
 impl fmt::Display for AddressMode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self)
+        write!(f, "{:?}", self)
     }
 }
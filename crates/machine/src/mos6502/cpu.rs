@@ -1,10 +1,17 @@
 use super::{define_operations, OpsMap, Registers};
+use crate::machine::ResetKind;
 
 pub struct MOS6502 {
     pub registers: Registers,
     pub operations: OpsMap,
 }
 
+impl Default for MOS6502 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MOS6502 {
     pub fn new() -> Self {
         let mut operations = OpsMap::new();
@@ -15,4 +22,17 @@ impl MOS6502 {
             operations,
         }
     }
+
+    /// Registers after reset (the program counter is loaded by the machine
+    /// from the RESET vector). The reset sequence is a fake interrupt:
+    /// the stack pointer is decremented by 3 (without writing) and interrupts
+    /// get disabled. Other registers keep their values unless it's a power cycle.
+    pub fn reset(&mut self, kind: ResetKind) {
+        if kind == ResetKind::Hard {
+            self.registers = Registers::new();
+        } else {
+            self.registers.stack -= 3;
+        }
+        self.registers.status.interrupt_disable = true;
+    }
 }
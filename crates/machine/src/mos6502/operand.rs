@@ -16,11 +16,7 @@ impl Operand {
     }
 
     pub fn get_byte_as_u16(&self) -> Option<u16> {
-        if let Some(val) = self.get_byte() {
-            Some(val as u16)
-        } else {
-            None
-        }
+        self.get_byte().map(|val| val as u16)
     }
 
     pub fn get_word(&self) -> Option<u16> {
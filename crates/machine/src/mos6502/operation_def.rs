@@ -25,6 +25,7 @@ impl Default for OperationDef {
 }
 
 impl OperationDef {
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> u8 {
         match self.address_mode {
             Implicit | Accumulator => 1,
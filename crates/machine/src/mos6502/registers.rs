@@ -1,4 +1,8 @@
 use super::ProcessorStatus;
+use crate::emulator::{
+    abstractions::{StateReader, StateWriter},
+    EmulatorError,
+};
 use colored::*;
 use std::fmt;
 use std::num::Wrapping;
@@ -45,4 +49,23 @@ impl Registers {
             status: ProcessorStatus::new(),
         }
     }
+
+    pub fn save(&self, w: &mut StateWriter) {
+        w.u16(self.counter)
+            .u8(self.stack.0)
+            .u8(self.accumulator.0)
+            .u8(self.x.0)
+            .u8(self.y.0)
+            .u8(u8::from(&self.status));
+    }
+
+    pub fn load(&mut self, r: &mut StateReader) -> Result<(), EmulatorError> {
+        self.counter = r.u16()?;
+        self.stack = Wrapping(r.u8()?);
+        self.accumulator = Wrapping(r.u8()?);
+        self.x = Wrapping(r.u8()?);
+        self.y = Wrapping(r.u8()?);
+        self.status = ProcessorStatus::from(r.u8()?);
+        Ok(())
+    }
 }
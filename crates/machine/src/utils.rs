@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex, MutexGuard};
 
-pub fn lock<T: ?Sized>(arc: &Arc<Mutex<T>>) -> MutexGuard<T> {
+pub fn lock<T: ?Sized>(arc: &Arc<Mutex<T>>) -> MutexGuard<'_, T> {
     match arc.lock() {
         Ok(guard) => guard,
         Err(poisoned) => poisoned.into_inner(),
@@ -9,7 +9,7 @@ pub fn lock<T: ?Sized>(arc: &Arc<Mutex<T>>) -> MutexGuard<T> {
 
 /// Compares two vectors
 /// source: https://stackoverflow.com/questions/29504514/whats-the-best-way-to-compare-2-vectors-or-strings-element-by-element
-pub fn do_vecs_match<T: PartialEq>(a: &Vec<T>, b: &Vec<T>) -> bool {
+pub fn do_vecs_match<T: PartialEq>(a: &[T], b: &[T]) -> bool {
     let matching = a.iter().zip(b.iter()).filter(|&(a, b)| a == b).count();
     matching == a.len() && matching == b.len()
 }
@@ -22,3 +22,25 @@ pub fn if_else<T>(cond: bool, val1: T, val2: T) -> T {
         val2
     }
 }
+
+/// Simple pseudo-random generator (xorshift64*), so the machines
+/// can be seeded without any external dependencies
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // the state must not be zero
+        XorShift(seed.wrapping_add(0x9e37_79b9_7f4a_7c15).max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}